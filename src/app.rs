@@ -1,45 +1,108 @@
+use crate::config::{Config, SortKey, SortMode};
 use crate::gallery::Gallery;
-use crate::image_loader::{self, LoadedImage};
-use crate::input::{Action, Mode, PanDirection};
-use crate::viewer::Viewer;
-use crate::wayland::{WaylandEvent, WaylandState};
-use std::collections::HashMap;
+use crate::image_loader::{self, LoadedImage, RgbaImage};
+use crate::input::{self, Action, Click, KeyBindings, Mode, PanDirection};
+use crate::render::WindowRect;
+use crate::signal::SignalPipe;
+use crate::status::StatusMode;
+use crate::viewer::{self, Viewer};
+use crate::watch::FileWatcher;
+use crate::wayland::{KeyEvent, PointerButton, WaylandEvent, WaylandState, BTN_LEFT, BTN_RIGHT};
+use crate::window_state;
+use std::collections::{HashMap, HashSet};
 use std::os::fd::{AsRawFd, BorrowedFd};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
 use std::time::{Duration, Instant};
 use wayland_client::Connection;
+use xkbcommon_dl::keysyms;
 
 /// Duration to show transient error messages in the status bar.
 const ERROR_DISPLAY_DURATION: Duration = Duration::from_secs(3);
+/// Decodes slower than this are reported in the image's toast.
+const SLOW_DECODE_THRESHOLD: Duration = Duration::from_secs(1);
 /// Duration to show the sort mode toast overlay.
 const TOAST_DISPLAY_DURATION: Duration = Duration::from_millis(1500);
+/// Time the status bar stays up after activity with `status-mode = auto`.
+const STATUS_DISPLAY_DURATION: Duration = Duration::from_secs(2);
+/// Largest file read just for its metadata (64 MiB).
+const MAX_METADATA_FILE_SIZE: u64 = 64 * 1024 * 1024;
+/// Per-channel difference from the corner color still counted as border
+/// when auto-cropping.
+const AUTO_CROP_TOLERANCE: u8 = 16;
+/// Decoded images kept beyond each end of the visible strip.
+const STRIP_KEEP: usize = 2;
+/// Fit-scaled images kept for recently left images.
+const FIT_CACHE_SIZE: usize = 4;
+/// Length of the zoom from a gallery thumbnail into the viewer.
+const GALLERY_TRANSITION_DURATION: Duration = Duration::from_millis(150);
+
+/// A held key whose action is being repeated in software.
+struct KeyRepeat {
+    keycode: u32,
+    action: Action,
+    next: Instant,
+}
+
+/// A thumbnail zooming from its gallery cell to where the viewer shows
+/// the image (`gallery-transition`).
+struct ZoomTransition {
+    thumb: RgbaImage,
+    /// Window rectangle `(x, y, w, h)` of the thumbnail in the gallery.
+    from: WindowRect,
+    start: Instant,
+}
+
+/// Decoded image, or the reason it failed, from the strip preload worker.
+type StripResult = (PathBuf, Result<LoadedImage, String>);
 
-/// Sort mode for image list ordering.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum SortMode {
-    Name,
-    Size,
-    ExifDate,
-    ModTime,
+/// Background decoder for the strip neighbors just off screen, run like
+/// the gallery's thumbnail worker so scrolling does not wait on them.
+struct StripPreload {
+    work_tx: mpsc::Sender<PathBuf>,
+    result_rx: mpsc::Receiver<StripResult>,
+    /// Paths sent to the worker but not yet received.
+    pending: HashSet<PathBuf>,
 }
 
-impl SortMode {
-    fn next(self) -> Self {
-        match self {
-            SortMode::Name => SortMode::Size,
-            SortMode::Size => SortMode::ExifDate,
-            SortMode::ExifDate => SortMode::ModTime,
-            SortMode::ModTime => SortMode::Name,
+impl StripPreload {
+    fn new() -> Self {
+        let (work_tx, work_rx) = mpsc::channel::<PathBuf>();
+        let (result_tx, result_rx) = mpsc::channel::<StripResult>();
+        thread::spawn(move || {
+            while let Ok(path) = work_rx.recv() {
+                let result = image_loader::load_image(&path);
+                if result_tx.send((path, result)).is_err() {
+                    return; // Main thread dropped receiver, exit
+                }
+            }
+        });
+        Self {
+            work_tx,
+            result_rx,
+            pending: HashSet::new(),
+        }
+    }
+
+    /// Queue `path` for decoding unless it is already queued.
+    fn request(&mut self, path: &Path) {
+        if self.pending.insert(path.to_path_buf()) {
+            let _ = self.work_tx.send(path.to_path_buf());
         }
     }
 
-    fn label(self) -> &'static str {
-        match self {
-            SortMode::Name => "Name",
-            SortMode::Size => "Size",
-            SortMode::ExifDate => "EXIF Date",
-            SortMode::ModTime => "Mod Time",
+    fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Results that arrived since the last call.
+    fn poll(&mut self) -> Vec<StripResult> {
+        let results: Vec<StripResult> = self.result_rx.try_iter().collect();
+        for (path, _) in &results {
+            self.pending.remove(path);
         }
+        results
     }
 }
 
@@ -52,6 +115,11 @@ pub struct App {
     viewer: Viewer,
     gallery: Gallery,
     image_cache: HashMap<usize, LoadedImage>,
+    /// Fit-scaled images of recently left images by index, oldest first, so
+    /// going back and forth does not rescale them.
+    fit_cache: Vec<(usize, viewer::FitScaled)>,
+    /// Manual rotations of cached images in quarter turns clockwise.
+    rotations: HashMap<PathBuf, u32>,
     win_w: u32,
     win_h: u32,
     needs_redraw: bool,
@@ -60,44 +128,165 @@ pub struct App {
     error_message: Option<String>,
     /// Deadline after which the error message should be cleared.
     error_deadline: Option<Instant>,
+    /// File name and reason of the most recent failed decode, shown
+    /// centered once no loadable image is left.
+    last_error: Option<(String, String)>,
     /// Current sort mode.
     sort_mode: SortMode,
+    /// Compare names naturally (`img2` before `img10`).
+    natural_sort: bool,
     /// Toast overlay message (e.g., "Sort: Name").
     toast_message: Option<String>,
     /// Deadline after which the toast should be cleared.
     toast_deadline: Option<Instant>,
+    /// Whether the viewer status bar is always shown, auto-hidden or off.
+    status_mode: StatusMode,
+    /// With `StatusMode::Auto`, when the status bar hides again.
+    status_deadline: Option<Instant>,
     /// Cached file metadata: path -> (size_bytes, mtime_secs).
     meta_cache: HashMap<PathBuf, (u64, u64)>,
-    /// Cached EXIF dates: path -> Option<timestamp_secs>.
-    exif_date_cache: HashMap<PathBuf, Option<u64>>,
+    /// Cached EXIF capture times: path -> Option<unix_millis>.
+    exif_date_cache: HashMap<PathBuf, Option<i64>>,
+    /// Cached header dimensions: path -> Option<(width, height)>.
+    dimensions_cache: HashMap<PathBuf, Option<(u32, u32)>>,
+    /// User key bindings from the config file.
+    bindings: KeyBindings,
+    /// Background color behind images and thumbnails.
+    bg_color: u32,
+    /// Slideshow auto-advance interval (None = disabled).
+    slideshow_interval: Option<Duration>,
+    /// When the slideshow should advance to the next image.
+    slideshow_deadline: Option<Instant>,
+    /// Advance the slideshow to a random image.
+    slideshow_random: bool,
+    /// Source of random images and of the `--shuffle` order.
+    rng: Rng,
+    /// Currently repeating key, if any.
+    key_repeat: Option<KeyRepeat>,
+    /// Last plain left click, for double-click detection.
+    last_click: Option<Click>,
+    /// Keybinding help overlay (`?`) is shown.
+    show_help: bool,
+    /// The gallery filter query is being typed (`/`); keys edit it.
+    filter_editing: bool,
+    /// Zoom percentage being typed after `%`, until Enter or Escape.
+    zoom_input: Option<String>,
+    /// Images that failed to load as strip neighbors; left out of the strip.
+    strip_failed: HashSet<PathBuf>,
+    /// Decodes the strip neighbors off the render path.
+    strip_preload: StripPreload,
+    /// Open the window at the first image's size.
+    size_to_image: bool,
+    /// Save the window state on exit (`--remember-geometry`), with the
+    /// last size the window had outside fullscreen.
+    remember_geometry: bool,
+    windowed_size: Option<(u32, u32)>,
+    /// Reloads the current image when it changes on disk (`--watch`).
+    watcher: Option<FileWatcher>,
+    /// Wakes the event loop on SIGINT/SIGTERM so it exits through Drop.
+    signals: Option<SignalPipe>,
+    /// Images marked with `m`, keyed by path so marks survive re-sorting.
+    marks: HashSet<PathBuf>,
+    /// Zoom into the viewer when opening an image from the gallery.
+    gallery_transition: bool,
+    /// The running gallery-to-viewer zoom, until it hands off to the viewer.
+    transition: Option<ZoomTransition>,
 }
 
 impl App {
-    pub fn new(paths: Vec<PathBuf>, wallpaper_mode: bool) -> Self {
+    pub fn new(paths: Vec<PathBuf>, wallpaper_mode: bool, config: Config) -> Self {
         let conn = Connection::connect_to_env().expect("Failed to connect to Wayland");
-        let state = WaylandState::new(wallpaper_mode);
+        let mut state = WaylandState::new(wallpaper_mode);
+        if let Some((w, h)) = config.geometry {
+            state.set_default_size(w, h);
+        }
+        state.set_start_fullscreen(config.fullscreen);
+        state.set_wallpaper_outputs(config.wallpaper_outputs.clone());
+
+        let mut viewer = Viewer::new();
+        viewer.set_scale_mode(config.scale_mode);
+        viewer.set_background(config.background);
+        viewer.set_proof(config.proof);
+        viewer.set_status_layout(config.status_position, config.status_fields.clone());
+        viewer.set_animations(config.animations);
+        viewer.set_anim_speed(config.anim_speed);
+        viewer.set_elastic_pan(config.elastic_pan);
+        viewer.set_grid_divisions(config.grid.0, config.grid.1);
+        if config.strip && !wallpaper_mode {
+            viewer.set_strip_mode(true);
+        }
+        let mut gallery = Gallery::new();
+        gallery.set_background(config.background);
+        gallery.set_wrap(config.gallery_wrap);
+        let watcher = if config.watch && !wallpaper_mode {
+            FileWatcher::new()
+                .map_err(|e| eprintln!("Warning: --watch unavailable: {}", e))
+                .ok()
+        } else {
+            None
+        };
+        let signals = SignalPipe::install()
+            .map_err(|e| eprintln!("Warning: signal handling unavailable: {}", e))
+            .ok();
 
-        Self {
+        let mut app = Self {
             state,
             conn,
             paths,
             current_index: 0,
             mode: Mode::Viewer,
-            viewer: Viewer::new(),
-            gallery: Gallery::new(),
+            viewer,
+            gallery,
             image_cache: HashMap::new(),
+            fit_cache: Vec::new(),
+            rotations: HashMap::new(),
             win_w: 0,
             win_h: 0,
             needs_redraw: true,
             wallpaper_mode,
             error_message: None,
             error_deadline: None,
-            sort_mode: SortMode::Name,
+            last_error: None,
+            sort_mode: config.sort,
+            natural_sort: config.natural_sort,
             toast_message: None,
             toast_deadline: None,
+            status_mode: config.status_mode,
+            status_deadline: (config.status_mode == StatusMode::Auto)
+                .then(|| Instant::now() + STATUS_DISPLAY_DURATION),
             meta_cache: HashMap::new(),
             exif_date_cache: HashMap::new(),
+            dimensions_cache: HashMap::new(),
+            bindings: config.bindings,
+            bg_color: config.background,
+            slideshow_interval: config.slideshow_interval,
+            slideshow_deadline: None,
+            slideshow_random: config.slideshow_random,
+            rng: Rng::from_time(),
+            key_repeat: None,
+            last_click: None,
+            show_help: false,
+            filter_editing: false,
+            zoom_input: None,
+            strip_failed: HashSet::new(),
+            strip_preload: StripPreload::new(),
+            size_to_image: config.size_to_image,
+            remember_geometry: config.remember_geometry && !wallpaper_mode,
+            windowed_size: config.geometry,
+            watcher,
+            signals,
+            marks: HashSet::new(),
+            gallery_transition: config.gallery_transition && config.animations,
+            transition: None,
+        };
+        // Paths arrive in name (or command-line) order; apply any other
+        // initial sort
+        if app.sort_mode.key == SortKey::Shuffled {
+            shuffle(&mut app.paths, &mut app.rng);
+        } else if app.sort_mode != SortMode::default() {
+            app.sort_paths();
         }
+        app
     }
 
     pub fn run(&mut self) {
@@ -106,12 +295,48 @@ impl App {
             return;
         }
         self.run_viewer();
+        if self.remember_geometry {
+            self.save_window_state();
+        }
+    }
+
+    /// Remember the window size and fullscreen state for the next run.
+    fn save_window_state(&self) {
+        let Some(size) = self.windowed_size else {
+            return;
+        };
+        let state = window_state::WindowState {
+            size,
+            fullscreen: self.state.is_fullscreen(),
+        };
+        if let Err(e) = window_state::save(&state) {
+            eprintln!("Warning: {}", e);
+        }
+    }
+
+    /// Stop the event loop once SIGINT or SIGTERM arrived, so shutdown takes
+    /// the same path as quitting with a key.
+    fn signal_received(&mut self) -> bool {
+        if self.signals.as_ref().is_some_and(|s| s.received()) {
+            self.state.running = false;
+        }
+        !self.state.running
     }
 
     fn run_viewer(&mut self) {
         let mut event_queue = self.conn.new_event_queue();
         let qh = event_queue.handle();
 
+        // Load first image (before the first configure, which may size the
+        // window after it)
+        self.ensure_image_loaded();
+        if self.size_to_image {
+            if let Some(loaded) = self.image_cache.get(&self.current_index) {
+                let (w, h) = first_frame(loaded).dimensions();
+                self.state.set_preferred_size(w, h);
+            }
+        }
+
         // Register globals
         let display = self.conn.display();
         display.get_registry(&qh, ());
@@ -121,18 +346,24 @@ impl App {
             .roundtrip(&mut self.state)
             .expect("Roundtrip failed");
 
-        // Second roundtrip to ensure everything is configured
+        // Second roundtrip to get the output modes, which the initial window
+        // size is clamped to
+        event_queue
+            .roundtrip(&mut self.state)
+            .expect("Roundtrip failed");
+
+        // Create the window and wait for its first configure
+        self.state.create_window(&qh);
         event_queue
             .roundtrip(&mut self.state)
             .expect("Roundtrip failed");
 
-        // Load first image
-        self.ensure_image_loaded();
         if let Some(loaded) = self.image_cache.get(&self.current_index) {
             self.viewer.start_animation(loaded);
         }
-        self.load_exif_for_current();
+        self.load_metadata_for_current();
         self.update_title();
+        self.slideshow_deadline = self.slideshow_interval.map(|d| Instant::now() + d);
 
         // Main event loop using poll
         // SAFETY: The connection fd is valid for the lifetime of self.conn
@@ -172,6 +403,48 @@ impl App {
                     };
                 }
 
+                // Status bar auto-hide deadline
+                if let Some(deadline) = self.status_deadline {
+                    let t = if deadline > now {
+                        deadline.duration_since(now).as_millis() as i32
+                    } else {
+                        0
+                    };
+                    min_timeout = if min_timeout < 0 {
+                        t
+                    } else {
+                        min_timeout.min(t)
+                    };
+                }
+
+                // Hide an idle cursor in fullscreen
+                if let Some(deadline) = self.state.cursor_hide_deadline() {
+                    let t = if deadline > now {
+                        deadline.duration_since(now).as_millis() as i32
+                    } else {
+                        0
+                    };
+                    min_timeout = if min_timeout < 0 {
+                        t
+                    } else {
+                        min_timeout.min(t)
+                    };
+                }
+
+                // Software key repeat
+                if let Some(repeat) = &self.key_repeat {
+                    let t = if repeat.next > now {
+                        repeat.next.duration_since(now).as_millis() as i32
+                    } else {
+                        0
+                    };
+                    min_timeout = if min_timeout < 0 {
+                        t
+                    } else {
+                        min_timeout.min(t)
+                    };
+                }
+
                 if self.mode == Mode::Viewer {
                     if let Some(deadline) = self.viewer.next_frame_deadline() {
                         let t = if deadline > now {
@@ -185,7 +458,19 @@ impl App {
                             min_timeout.min(t)
                         };
                     }
-                    if let Some(deadline) = self.viewer.pan_deadline() {
+                    if let Some(deadline) = self.slideshow_deadline {
+                        let t = if deadline > now {
+                            deadline.duration_since(now).as_millis() as i32
+                        } else {
+                            0
+                        };
+                        min_timeout = if min_timeout < 0 {
+                            t
+                        } else {
+                            min_timeout.min(t)
+                        };
+                    }
+                    if let Some(deadline) = self.viewer.pan_deadline(self.state.next_refresh()) {
                         let t = if deadline > now {
                             deadline.duration_since(now).as_millis() as i32
                         } else {
@@ -197,8 +482,15 @@ impl App {
                             min_timeout.min(t)
                         };
                     }
-                } else if self.mode == Mode::Gallery && self.gallery.has_pending() {
-                    let t = 16; // Poll at ~60fps while thumbnails are being generated
+                }
+                let strip_pending = self.viewer.is_strip_mode() && self.strip_preload.has_pending();
+                if self.transition.is_some()
+                    || (self.mode == Mode::Gallery && self.gallery.has_pending())
+                    || (self.mode == Mode::Viewer && strip_pending)
+                {
+                    // Poll at ~60fps while zooming into the viewer or while
+                    // thumbnails or strip neighbors are being decoded
+                    let t = 16;
                     min_timeout = if min_timeout < 0 {
                         t
                     } else {
@@ -209,9 +501,44 @@ impl App {
                 min_timeout
             };
 
-            // Poll the wayland fd
-            let mut pollfd = rustix::event::PollFd::new(&wl_fd, rustix::event::PollFlags::IN);
-            let _ = rustix::event::poll(std::slice::from_mut(&mut pollfd), timeout_ms);
+            // Poll the wayland fd, the signal pipe, and the watched directory with --watch
+            if let (Some(watcher), Some(path)) =
+                (&mut self.watcher, self.paths.get(self.current_index))
+            {
+                watcher.watch(path);
+            }
+            let mut pollfds = vec![rustix::event::PollFd::new(
+                &wl_fd,
+                rustix::event::PollFlags::IN,
+            )];
+            if let Some(signals) = &self.signals {
+                pollfds.push(rustix::event::PollFd::from_borrowed_fd(
+                    signals.fd(),
+                    rustix::event::PollFlags::IN,
+                ));
+            }
+            if let Some(fd) = self.state.drop_fd() {
+                pollfds.push(rustix::event::PollFd::from_borrowed_fd(
+                    fd,
+                    rustix::event::PollFlags::IN,
+                ));
+            }
+            if let Some(watcher) = &self.watcher {
+                pollfds.push(rustix::event::PollFd::from_borrowed_fd(
+                    watcher.fd(),
+                    rustix::event::PollFlags::IN,
+                ));
+            }
+            let _ = rustix::event::poll(&mut pollfds, timeout_ms);
+            let watch_ready =
+                self.watcher.is_some() && pollfds.last().is_some_and(|p| !p.revents().is_empty());
+            drop(pollfds);
+            if self.signal_received() {
+                break;
+            }
+            if watch_ready && self.watcher.as_mut().is_some_and(|w| w.file_changed()) {
+                self.reload_current();
+            }
 
             // Read and dispatch events
             if let Some(guard) = event_queue.prepare_read() {
@@ -222,6 +549,7 @@ impl App {
             event_queue
                 .dispatch_pending(&mut self.state)
                 .expect("Dispatch failed");
+            self.state.read_drop();
 
             // Process all pending wayland events
             let events: Vec<WaylandEvent> = self.state.events.drain(..).collect();
@@ -230,6 +558,10 @@ impl App {
                     WaylandEvent::Configure { width, height } => {
                         self.win_w = width;
                         self.win_h = height;
+                        if !self.state.is_fullscreen() {
+                            self.windowed_size = Some((width, height));
+                        }
+                        self.fit_cache.clear();
                         self.state.resize_buffers(width, height, &qh);
                         self.needs_redraw = true;
                     }
@@ -237,31 +569,101 @@ impl App {
                         return;
                     }
                     WaylandEvent::Key(key_event) => {
-                        if let Some(action) = crate::input::map_key(&key_event, self.mode) {
+                        // Any key press or release of the repeating key stops repeat
+                        if key_event.pressed
+                            || self
+                                .key_repeat
+                                .as_ref()
+                                .is_some_and(|r| r.keycode == key_event.keycode)
+                        {
+                            self.key_repeat = None;
+                        }
+                        if key_event.pressed {
+                            self.show_status_briefly();
+                        }
+                        if self.filter_editing && self.mode == Mode::Gallery {
+                            if key_event.pressed {
+                                self.handle_filter_key(&key_event);
+                            }
+                            continue;
+                        }
+                        if self.zoom_input.is_some() && self.mode == Mode::Viewer {
+                            if key_event.pressed {
+                                self.handle_zoom_key(&key_event);
+                            }
+                            continue;
+                        }
+                        if let Some(action) = crate::input::map_key_with_bindings(
+                            &key_event,
+                            self.mode,
+                            &self.bindings,
+                        ) {
+                            // Stepped pan (no animations) repeats like other keys
+                            let repeatable = action.is_repeatable()
+                                || (matches!(action, Action::PanStart(_))
+                                    && !self.viewer.animations_enabled());
+                            if key_event.pressed && repeatable {
+                                let (rate, delay) = self.state.repeat_info();
+                                if rate > 0 {
+                                    self.key_repeat = Some(KeyRepeat {
+                                        keycode: key_event.keycode,
+                                        action: action.clone(),
+                                        next: Instant::now() + Duration::from_millis(delay as u64),
+                                    });
+                                }
+                            }
                             let should_quit = self.handle_action(action);
                             if should_quit {
                                 return;
                             }
                         }
                     }
+                    WaylandEvent::KeyboardLeave => {
+                        self.key_repeat = None;
+                    }
+                    WaylandEvent::PointerButton(button) => {
+                        self.handle_pointer_button(button);
+                    }
                     WaylandEvent::FrameCallback => {
                         // Frame was displayed, we can draw again if needed
                         if self.needs_redraw {
                             self.redraw();
                         }
                     }
+                    WaylandEvent::FilesDropped(paths) => {
+                        self.open_dropped(paths);
+                    }
                     WaylandEvent::WallpaperConfigure { .. } => {
                         // Not in wallpaper mode, ignore
                     }
                 }
             }
 
+            // Fire software key repeat
+            if let Some(repeat) = &mut self.key_repeat {
+                let now = Instant::now();
+                if now >= repeat.next {
+                    let (rate, _) = self.state.repeat_info();
+                    let interval = Duration::from_millis(1000 / rate.max(1) as u64);
+                    // Schedule from now so a slow redraw doesn't cause a burst
+                    repeat.next = now + interval;
+                    let action = repeat.action.clone();
+                    if self.handle_action(action) {
+                        return;
+                    }
+                }
+            }
+
             // Poll for completed thumbnails from background worker
             if self.mode == Mode::Gallery {
                 if self.gallery.poll_thumbnails() {
                     self.needs_redraw = true;
                 }
             }
+            // Off-screen strip neighbors need no redraw once decoded
+            if self.mode == Mode::Viewer && self.viewer.is_strip_mode() {
+                self.poll_strip_preload();
+            }
 
             // Handle GIF animation
             if self.mode == Mode::Viewer {
@@ -272,13 +674,44 @@ impl App {
                 }
             }
 
-            // Handle pan animation
+            // Handle pan animation (or strip scrolling)
             if self.mode == Mode::Viewer {
-                if self.viewer.update_pan() {
+                let moving = if self.viewer.is_strip_mode() {
+                    self.viewer.update_strip_scroll()
+                } else {
+                    self.viewer.update_pan()
+                };
+                if moving {
                     self.needs_redraw = true;
                 }
             }
 
+            // Keep the gallery transition going until it hands off. It also
+            // ends here, as the frame that draws it may never run (strip,
+            // image not decoded, mode changed)
+            if let Some(transition) = &self.transition {
+                if self.mode != Mode::Viewer
+                    || transition.start.elapsed() >= GALLERY_TRANSITION_DURATION
+                {
+                    self.transition = None;
+                }
+                self.needs_redraw = true;
+            }
+
+            // Handle slideshow auto-advance
+            if self.mode == Mode::Viewer {
+                if let Some(deadline) = self.slideshow_deadline {
+                    if Instant::now() >= deadline {
+                        let random = self.slideshow_random.then(|| {
+                            random_other(self.current_index, self.paths.len(), &mut self.rng)
+                        });
+                        self.navigate_to(random.flatten().unwrap_or(self.current_index + 1));
+                    }
+                }
+            }
+
+            self.state.hide_idle_cursor();
+
             // Handle error message auto-dismiss
             if let Some(deadline) = self.error_deadline {
                 if Instant::now() >= deadline {
@@ -297,14 +730,23 @@ impl App {
                 }
             }
 
+            // Handle status bar auto-hide
+            if let Some(deadline) = self.status_deadline {
+                if Instant::now() >= deadline {
+                    self.status_deadline = None;
+                    self.needs_redraw = true;
+                }
+            }
+
             // Draw if needed
             if self.needs_redraw && self.win_w > 0 && self.win_h > 0 {
                 self.redraw();
 
-                // If animating (GIF or pan), request next frame callback
+                // If animating (GIF, pan or transition), request next frame callback
                 if self.mode == Mode::Viewer
                     && (self.viewer.next_frame_deadline().is_some()
-                        || self.viewer.is_pan_animating())
+                        || self.viewer.is_pan_animating()
+                        || self.transition.is_some())
                 {
                     self.state.request_frame(&qh);
                 }
@@ -369,8 +811,21 @@ impl App {
             let _ = self.conn.flush();
 
             // Block indefinitely — wallpaper is static
-            let mut pollfd = rustix::event::PollFd::new(&wl_fd, rustix::event::PollFlags::IN);
-            let _ = rustix::event::poll(std::slice::from_mut(&mut pollfd), -1);
+            let mut pollfds = vec![rustix::event::PollFd::new(
+                &wl_fd,
+                rustix::event::PollFlags::IN,
+            )];
+            if let Some(signals) = &self.signals {
+                pollfds.push(rustix::event::PollFd::from_borrowed_fd(
+                    signals.fd(),
+                    rustix::event::PollFlags::IN,
+                ));
+            }
+            let _ = rustix::event::poll(&mut pollfds, -1);
+            drop(pollfds);
+            if self.signal_received() {
+                break;
+            }
 
             if let Some(guard) = event_queue.prepare_read() {
                 if let Ok(_) = guard.read() {
@@ -385,19 +840,30 @@ impl App {
             for event in events {
                 match event {
                     WaylandEvent::WallpaperConfigure {
-                        output_idx,
+                        output_name,
                         width,
                         height,
                     } => {
                         // Resize SHM buffers for this output
                         self.state
-                            .resize_wallpaper_buffers(output_idx, width, height, &qh);
+                            .resize_wallpaper_buffers(output_name, width, height, &qh);
 
-                        // Render wallpaper: scale-to-fill and convert to XRGB
+                        // Render wallpaper: scale-to-fill, transparency over the background
                         let filled = crate::render::scale_to_fill(&frame, width, height);
-                        let pixels = rgba_to_xrgb(&filled);
-
-                        self.state.present_wallpaper(output_idx, &pixels);
+                        let pixels = crate::render::composite_centered(
+                            &filled,
+                            width,
+                            height,
+                            0,
+                            0,
+                            self.bg_color,
+                        );
+
+                        self.state.present_wallpaper(output_name, &pixels);
+                        if image_loader::verbose() {
+                            let label = self.state.output_label(output_name);
+                            eprintln!("Set wallpaper on {} ({}x{})", label, width, height);
+                        }
                     }
                     WaylandEvent::Close => {
                         return;
@@ -408,6 +874,37 @@ impl App {
         }
     }
 
+    /// Double-click toggles fullscreen; Alt+left drag moves the window and
+    /// Alt+right drag resizes it. Viewer mode only.
+    fn handle_pointer_button(&mut self, button: PointerButton) {
+        if self.wallpaper_mode || self.mode != Mode::Viewer || !button.pressed {
+            return;
+        }
+        if button.alt {
+            self.last_click = None;
+            match button.button {
+                BTN_LEFT => self.state.start_move(),
+                BTN_RIGHT => self.state.start_resize(),
+                _ => {}
+            }
+            return;
+        }
+        if button.button != BTN_LEFT {
+            return;
+        }
+        let click = Click {
+            time: Instant::now(),
+            x: button.x,
+            y: button.y,
+        };
+        if input::is_double_click(self.last_click, click) {
+            self.last_click = None;
+            self.state.toggle_fullscreen();
+        } else {
+            self.last_click = Some(click);
+        }
+    }
+
     fn ensure_image_loaded(&mut self) {
         // Try loading the current image; if it fails, remove it and advance.
         // Loop in case multiple consecutive images fail.
@@ -416,9 +913,17 @@ impl App {
             if self.image_cache.contains_key(&idx) {
                 return;
             }
-            match image_loader::load_image(&self.paths[idx]) {
-                Ok(loaded) => {
+            let start = Instant::now();
+            match image_loader::load_image_with_note(&self.paths[idx]) {
+                Ok((loaded, note)) => {
                     self.image_cache.insert(idx, loaded);
+                    self.rotations.remove(&self.paths[idx]);
+                    self.fit_cache.retain(|(i, _)| *i != idx);
+                    self.last_error = None;
+                    if let Some(note) = with_decode_time(note, start.elapsed()) {
+                        self.toast_message = Some(note);
+                        self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
+                    }
                     return;
                 }
                 Err(e) => {
@@ -432,9 +937,11 @@ impl App {
                         self.paths[idx].display(),
                         e
                     );
+                    self.last_error = Some((name.clone(), e));
 
                     // Remove the failed path and adjust indices
                     self.paths.remove(idx);
+                    self.fit_cache.clear();
                     // Shift any cached entries above this index down by one
                     let mut new_cache = HashMap::new();
                     for (k, v) in self.image_cache.drain() {
@@ -465,27 +972,160 @@ impl App {
         }
     }
 
+    /// Switch between viewer and gallery. A zoom percentage being typed
+    /// belongs to the viewer and is dropped.
+    fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.zoom_input = None;
+    }
+
     fn navigate_to(&mut self, index: usize) {
         if self.paths.is_empty() {
             return;
         }
+        // The strip moves between images without going through here, so
+        // its scaled image may belong to another one
+        let strip = self.viewer.is_strip_mode();
+        if let Some(scaled) = self.viewer.take_fit_scaled().filter(|_| !strip) {
+            let left = self.current_index;
+            self.fit_cache.retain(|(i, _)| *i != left);
+            if self.fit_cache.len() >= FIT_CACHE_SIZE {
+                self.fit_cache.remove(0);
+            }
+            self.fit_cache.push((left, scaled));
+        }
         self.current_index = index % self.paths.len();
         self.viewer.reset_view();
+        self.slideshow_deadline = self.slideshow_interval.map(|d| Instant::now() + d);
         // Clear any transient error when user explicitly navigates
         self.error_message = None;
         self.error_deadline = None;
+        self.show_status_briefly();
         self.ensure_image_loaded();
+        let current = self.current_index;
+        if let Some(pos) = self.fit_cache.iter().position(|(i, _)| *i == current) {
+            let (_, scaled) = self.fit_cache.remove(pos);
+            self.viewer.restore_fit_scaled(scaled);
+        }
 
         if let Some(loaded) = self.image_cache.get(&self.current_index) {
             self.viewer.start_animation(loaded);
         }
 
-        self.load_exif_for_current();
+        self.load_metadata_for_current();
         self.update_title();
         self.needs_redraw = true;
     }
 
-    fn load_exif_for_current(&mut self) {
+    /// Decode the current image again from disk, keeping zoom and pan. On
+    /// failure (e.g. a file caught mid-write) the old image stays.
+    fn reload_current(&mut self) {
+        let idx = self.current_index;
+        let Some(path) = self.paths.get(idx).cloned() else {
+            return;
+        };
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("?")
+            .to_string();
+        match image_loader::load_image_with_note(&path) {
+            Ok((loaded, _)) => {
+                self.image_cache.insert(idx, loaded);
+                self.rotations.remove(&path);
+                self.meta_cache.remove(&path);
+                self.exif_date_cache.remove(&path);
+                self.dimensions_cache.remove(&path);
+                self.invalidate_scaled();
+                self.viewer.invalidate_strip_image(&path);
+                self.gallery.invalidate_thumbnail(idx);
+                if let Some(loaded) = self.image_cache.get(&idx) {
+                    self.viewer.start_animation(loaded);
+                }
+                self.load_metadata_for_current();
+                self.toast_message = Some(format!("Reloaded: {}", name));
+                self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
+            }
+            Err(e) => {
+                eprintln!("Warning: failed to reload {}: {}", path.display(), e);
+                self.error_message = Some(format!("Reload failed: {}", name));
+                self.error_deadline = Some(Instant::now() + ERROR_DISPLAY_DURATION);
+            }
+        }
+        self.needs_redraw = true;
+    }
+
+    /// Add files dropped onto the window to the end of the list (skipping ones
+    /// already in it) and show the first of them.
+    fn open_dropped(&mut self, dropped: Vec<PathBuf>) {
+        let sort = !self.sort_mode.is_unsorted();
+        let images = image_loader::image_paths(dropped, self.natural_sort, sort);
+        let Some(first) = images.first() else {
+            self.error_message = Some("No supported images dropped".to_string());
+            self.error_deadline = Some(Instant::now() + ERROR_DISPLAY_DURATION);
+            self.needs_redraw = true;
+            return;
+        };
+        let target = match self.paths.iter().position(|p| p == first) {
+            Some(idx) => idx,
+            None => self.paths.len(),
+        };
+        for path in images {
+            if !self.paths.contains(&path) {
+                self.paths.push(path);
+            }
+        }
+        if self.mode == Mode::Gallery {
+            self.set_mode(Mode::Viewer);
+        }
+        self.navigate_to(target);
+    }
+
+    /// Add the other images in the current image's directory to the list,
+    /// keeping the sort order and caches, and show them in the gallery with
+    /// the current image selected.
+    fn browse_directory(&mut self) {
+        let Some(current) = self.paths.get(self.current_index).cloned() else {
+            return;
+        };
+        let old_paths = self.paths.clone();
+        let known: HashSet<&PathBuf> = old_paths.iter().collect();
+        let mut siblings: Vec<PathBuf> = image_loader::sibling_images(&current)
+            .into_iter()
+            .filter(|p| !known.contains(p))
+            .collect();
+        let added = siblings.len();
+        if added > 0 {
+            if self.sort_mode.is_unsorted() {
+                siblings.sort_by(|a, b| image_loader::compare_file_names(a, b, self.natural_sort));
+            }
+            self.paths.extend(siblings);
+            self.sort_paths();
+            self.remap_indices(&old_paths);
+        }
+
+        self.set_mode(Mode::Gallery);
+        self.gallery.set_selected(self.current_index);
+        self.gallery.refilter(&self.paths);
+        self.viewer.next_frame_time = None;
+        self.slideshow_deadline = None;
+        let dir = current
+            .parent()
+            .filter(|d| !d.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        self.toast_message = Some(format!(
+            "Added {} image{} from {}",
+            added,
+            if added == 1 { "" } else { "s" },
+            dir.display()
+        ));
+        self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
+        self.needs_redraw = true;
+    }
+
+    /// Read EXIF tags and the pixel aspect ratio of the current image.
+    fn load_metadata_for_current(&mut self) {
+        self.viewer.set_pixel_aspect(1.0);
         if let Some(path) = self.paths.get(self.current_index) {
             let ext = path
                 .extension()
@@ -493,23 +1133,18 @@ impl App {
                 .unwrap_or("")
                 .to_ascii_lowercase();
 
-            // Only read EXIF from reasonably-sized files (64 MiB limit for metadata)
+            // Only read EXIF from reasonably-sized files
             let too_large = std::fs::metadata(path)
-                .map(|m| m.len() > 64 * 1024 * 1024)
+                .map(|m| m.len() > MAX_METADATA_FILE_SIZE)
                 .unwrap_or(true);
             if !too_large {
                 if let Ok(data) = std::fs::read(path) {
-                    let tags = match ext.as_str() {
-                        "jpg" | "jpeg" => image_loader::read_exif_tags(&data),
-                        "tiff" | "tif" => image_loader::read_exif_tags_tiff(&data),
-                        "webp" => image_loader::read_exif_tags_webp(&data),
-                        "png" => image_loader::read_exif_tags_png(&data),
-                        "avif" => image_loader::read_exif_tags_avif(&data),
-                        "heic" | "heif" => image_loader::read_exif_tags_heic(&data),
-                        "jxl" => image_loader::read_exif_tags_jxl(&data),
-                        _ => Vec::new(),
-                    };
+                    let tags = image_loader::read_exif_tags_for_ext(&data, &ext);
                     self.viewer.set_exif_data(tags);
+                    self.viewer
+                        .set_exif_rotation(self.rotations.get(path).copied().unwrap_or(0));
+                    self.viewer
+                        .set_pixel_aspect(image_loader::read_pixel_aspect(&data, &ext));
                     return;
                 }
             }
@@ -517,6 +1152,32 @@ impl App {
         }
     }
 
+    /// Drop the scaled copies of the current image after its pixels changed.
+    fn invalidate_scaled(&mut self) {
+        let current = self.current_index;
+        self.fit_cache.retain(|(i, _)| *i != current);
+        self.viewer.invalidate_cache();
+    }
+
+    /// With `StatusMode::Auto`, show the status bar for a few more seconds.
+    fn show_status_briefly(&mut self) {
+        if self.status_mode == StatusMode::Auto {
+            self.status_deadline = Some(Instant::now() + STATUS_DISPLAY_DURATION);
+            self.needs_redraw = true;
+        }
+    }
+
+    /// Whether the viewer draws its status bar now. Pending errors are
+    /// shown whatever the mode.
+    fn status_visible(&self) -> bool {
+        self.error_message.is_some()
+            || match self.status_mode {
+                StatusMode::Always => true,
+                StatusMode::Auto => self.status_deadline.is_some(),
+                StatusMode::Off => false,
+            }
+    }
+
     fn update_title(&self) {
         if let Some(path) = self.paths.get(self.current_index) {
             let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("rimg");
@@ -529,17 +1190,32 @@ impl App {
             return;
         }
 
-        let pixels = match self.mode {
+        let show_status = self.status_visible();
+        let mut pixels = match self.mode {
             Mode::Viewer => {
                 if self.paths.is_empty() {
                     // No valid images remain — show background with error message
-                    let mut buf = vec![crate::render::BG_COLOR; (self.win_w * self.win_h) as usize];
+                    let mut buf = vec![self.bg_color; (self.win_w * self.win_h) as usize];
+                    if let Some((name, reason)) = &self.last_error {
+                        let title = format!("Cannot open {}", name);
+                        crate::viewer::Viewer::draw_hint(
+                            &mut buf,
+                            self.win_w,
+                            self.win_h,
+                            &[&title, reason],
+                        );
+                    }
                     if let Some(ref msg) = self.error_message {
-                        crate::status::draw_status_bar(&mut buf, self.win_w, self.win_h, msg);
+                        let position = self.viewer.status_position();
+                        crate::status::draw_status_bar(
+                            &mut buf, self.win_w, self.win_h, msg, position,
+                        );
                     }
                     buf
+                } else if self.viewer.is_strip_mode() {
+                    self.render_strip()
                 } else if let Some(loaded) = self.image_cache.get(&self.current_index) {
-                    self.viewer.render(
+                    let buf = self.viewer.render(
                         loaded,
                         self.win_w,
                         self.win_h,
@@ -548,13 +1224,28 @@ impl App {
                         self.paths.len(),
                         self.error_message.as_deref(),
                         self.toast_message.as_deref(),
-                    )
+                        show_status,
+                    );
+                    self.transition_frame().unwrap_or(buf)
                 } else {
-                    vec![crate::render::BG_COLOR; (self.win_w * self.win_h) as usize]
+                    vec![self.bg_color; (self.win_w * self.win_h) as usize]
                 }
             }
             Mode::Gallery => {
-                let mut buf = self.gallery.render(&self.paths, self.win_w, self.win_h);
+                let mut buf = self
+                    .gallery
+                    .render(&self.paths, &self.marks, self.win_w, self.win_h);
+                if self.filter_editing || !self.gallery.filter().is_empty() {
+                    let text = format!(
+                        "/{}{} | {} of {}",
+                        self.gallery.filter(),
+                        if self.filter_editing { "_" } else { "" },
+                        self.gallery.shown(self.paths.len()),
+                        self.paths.len()
+                    );
+                    let bottom = crate::status::StatusPosition::Bottom;
+                    crate::status::draw_status_bar(&mut buf, self.win_w, self.win_h, &text, bottom);
+                }
                 if let Some(ref msg) = self.toast_message {
                     crate::viewer::Viewer::draw_toast(&mut buf, self.win_w, self.win_h, msg);
                 }
@@ -562,53 +1253,365 @@ impl App {
             }
         };
 
+        if let (Mode::Viewer, Some(input)) = (self.mode, &self.zoom_input) {
+            if !pixels.is_empty() {
+                let text = format!("Zoom: {}_%", input);
+                let (w, h) = (self.win_w, self.win_h);
+                let position = self.viewer.status_position();
+                crate::status::draw_status_bar(&mut pixels, w, h, &text, position);
+            }
+        }
+
+        if self.show_help && !pixels.is_empty() {
+            let (title, entries) = match self.mode {
+                Mode::Viewer => ("Viewer keys", input::VIEWER_HELP),
+                Mode::Gallery => ("Gallery keys", input::GALLERY_HELP),
+            };
+            crate::viewer::Viewer::draw_help(&mut pixels, self.win_w, self.win_h, title, entries);
+        }
+
         if pixels.is_empty() {
             return;
         }
 
-        self.state.present(&pixels);
-        self.needs_redraw = false;
+        // If all buffers are still held by the compositor, keep needs_redraw
+        // set; the buffer release event wakes the loop for another attempt.
+        self.needs_redraw = !self.state.present(&pixels);
+    }
+
+    /// The frame of a running gallery transition, called right after the
+    /// viewer rendered the image so its place is known. Ends the transition
+    /// once it has run its course.
+    fn transition_frame(&mut self) -> Option<Vec<u32>> {
+        let transition = self.transition.as_ref()?;
+        let progress =
+            transition.start.elapsed().as_secs_f64() / GALLERY_TRANSITION_DURATION.as_secs_f64();
+        let Some(to) = self.viewer.image_rect().filter(|_| progress < 1.0) else {
+            self.transition = None;
+            return None;
+        };
+        let (x, y, w, h) = transition_rect(transition.from, to, progress);
+        let (thumb_w, thumb_h) = transition.thumb.dimensions();
+        let scaled = crate::render::scale_by_factors(
+            &transition.thumb,
+            w as f64 / thumb_w as f64,
+            h as f64 / thumb_h as f64,
+        );
+        let mut buf = vec![self.bg_color; (self.win_w * self.win_h) as usize];
+        crate::render::blit_over(
+            &mut buf,
+            self.win_w,
+            self.win_h,
+            &scaled,
+            x,
+            y,
+            self.bg_color,
+        );
+        Some(buf)
+    }
+
+    /// Bring the strip position into range, switch the current image to the
+    /// one at the window top, and render the images on screen.
+    fn render_strip(&mut self) -> Vec<u32> {
+        let total = self.paths.len();
+        let (current, offset, win_h) = (
+            self.current_index,
+            self.viewer.strip_offset(),
+            self.win_h as f64,
+        );
+        let (idx, offset, end) =
+            viewer::strip_position(|i| self.strip_height(i), total, current, offset, win_h);
+        self.viewer.set_strip_offset(offset);
+        if idx != self.current_index {
+            self.current_index = idx;
+            self.load_metadata_for_current();
+            self.update_title();
+        }
+
+        // Preload the neighbors just off screen and drop far-away images
+        if idx > 0 {
+            self.request_strip_image(idx - 1);
+        }
+        if end < total {
+            self.request_strip_image(end);
+        }
+        let (lo, hi) = (idx.saturating_sub(STRIP_KEEP), end + STRIP_KEEP);
+        self.image_cache.retain(|&k, _| k >= lo && k <= hi);
+
+        let images: Vec<(&Path, &RgbaImage)> = (idx..end)
+            .filter_map(|i| {
+                let loaded = self.image_cache.get(&i)?;
+                Some((self.paths[i].as_path(), first_frame(loaded)))
+            })
+            .collect();
+        self.viewer.render_strip(
+            &images,
+            self.win_w,
+            self.win_h,
+            self.current_index,
+            total,
+            self.error_message.as_deref(),
+            self.toast_message.as_deref(),
+            self.status_visible(),
+        )
+    }
+
+    /// Decode image `idx` into the cache for the strip. Images that fail are
+    /// reported once and skipped from then on.
+    fn load_strip_image(&mut self, idx: usize) {
+        let path = &self.paths[idx];
+        if self.image_cache.contains_key(&idx) || self.strip_failed.contains(path) {
+            return;
+        }
+        let result = image_loader::load_image(path);
+        self.store_strip_image(idx, result);
+    }
+
+    /// Decode image `idx` for the strip in the background; it is added to
+    /// the cache by `poll_strip_preload`.
+    fn request_strip_image(&mut self, idx: usize) {
+        let path = &self.paths[idx];
+        if self.image_cache.contains_key(&idx) || self.strip_failed.contains(path) {
+            return;
+        }
+        self.strip_preload.request(path);
+    }
+
+    /// Cache the strip images decoded in the background. Ones loaded on the
+    /// render path meanwhile, or no longer in the list, are dropped.
+    fn poll_strip_preload(&mut self) {
+        for (path, result) in self.strip_preload.poll() {
+            let Some(idx) = self.paths.iter().position(|p| *p == path) else {
+                continue;
+            };
+            if !self.image_cache.contains_key(&idx) {
+                self.store_strip_image(idx, result);
+            }
+        }
+    }
+
+    fn store_strip_image(&mut self, idx: usize, result: Result<LoadedImage, String>) {
+        let path = &self.paths[idx];
+        match result {
+            Ok(loaded) => {
+                self.rotations.remove(path);
+                self.image_cache.insert(idx, loaded);
+            }
+            Err(e) => {
+                eprintln!("Warning: failed to load {}: {}", path.display(), e);
+                self.strip_failed.insert(path.clone());
+            }
+        }
+    }
+
+    /// Displayed height of image `idx` in the strip (0 if it can't be loaded).
+    fn strip_height(&mut self, idx: usize) -> f64 {
+        self.load_strip_image(idx);
+        match self.image_cache.get(&idx) {
+            Some(loaded) => self.viewer.strip_height(first_frame(loaded), self.win_w) as f64,
+            None => 0.0,
+        }
     }
 
     /// Rotate the current image in the cache (clockwise if `cw`, counterclockwise otherwise).
     fn rotate_current_image(&mut self, cw: bool) {
-        if let Some(loaded) = self.image_cache.remove(&self.current_index) {
-            let rotate_fn = if cw {
-                image_loader::rotate_90
-            } else {
-                image_loader::rotate_270
-            };
-            let rotated = match loaded {
-                LoadedImage::Static(img) => LoadedImage::Static(rotate_fn(img)),
+        if let Some(loaded) = self.image_cache.get_mut(&self.current_index) {
+            // One scratch buffer serves every frame of an animation
+            let mut scratch = Vec::new();
+            match loaded {
+                LoadedImage::Static(img) => image_loader::rotate_quarter(img, cw, &mut scratch),
+                LoadedImage::Animated { frames } => {
+                    for (img, _) in frames {
+                        image_loader::rotate_quarter(img, cw, &mut scratch);
+                    }
+                }
+            }
+            let path = &self.paths[self.current_index];
+            let turns = self.rotations.entry(path.clone()).or_insert(0);
+            *turns = (*turns + if cw { 1 } else { 3 }) % 4;
+            self.viewer.set_exif_rotation(*turns);
+            self.invalidate_scaled();
+            self.viewer
+                .invalidate_strip_image(&self.paths[self.current_index]);
+            self.viewer.rotate_pixel_aspect();
+            self.viewer.zoom_reset();
+            self.needs_redraw = true;
+        }
+    }
+
+    /// Step the display tone (all zero resets it) and show the new values.
+    fn adjust_tone(&mut self, brightness: f64, contrast: f64, gamma: f64) {
+        self.viewer.adjust_tone(brightness, contrast, gamma);
+        self.toast_message = Some(self.viewer.tone_label());
+        self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
+        self.needs_redraw = true;
+    }
+
+    /// Trim uniform borders off the current image in the cache. Animated
+    /// images are cropped to the union of their frames' content.
+    fn auto_crop_current_image(&mut self) {
+        let Some(loaded) = self.image_cache.get(&self.current_index) else {
+            return;
+        };
+        let frames: Vec<&image_loader::RgbaImage> = match loaded {
+            LoadedImage::Static(img) => vec![img],
+            LoadedImage::Animated { frames } => frames.iter().map(|(img, _)| img).collect(),
+        };
+        let (full_w, full_h) = frames[0].dimensions();
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        for frame in &frames {
+            let (x, y, w, h) = image_loader::auto_crop_bounds(frame, AUTO_CROP_TOLERANCE)
+                .unwrap_or((0, 0, full_w, full_h));
+            bounds = Some(match bounds {
+                None => (x, y, x + w, y + h),
+                Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x + w), y1.max(y + h)),
+            });
+        }
+        let Some((x0, y0, x1, y1)) = bounds else {
+            return;
+        };
+        let (w, h) = (x1 - x0, y1 - y0);
+        if (w, h) == (full_w, full_h) {
+            self.toast_message = Some("Nothing to crop".to_string());
+        } else {
+            let cropped = match loaded {
+                LoadedImage::Static(img) => {
+                    LoadedImage::Static(image_loader::crop_region(img, x0, y0, w, h))
+                }
                 LoadedImage::Animated { frames } => LoadedImage::Animated {
                     frames: frames
-                        .into_iter()
-                        .map(|(img, dur)| (rotate_fn(img), dur))
+                        .iter()
+                        .map(|(img, dur)| (image_loader::crop_region(img, x0, y0, w, h), *dur))
                         .collect(),
                 },
             };
-            self.image_cache.insert(self.current_index, rotated);
+            self.image_cache.insert(self.current_index, cropped);
+            self.invalidate_scaled();
+            self.viewer
+                .invalidate_strip_image(&self.paths[self.current_index]);
             self.viewer.zoom_reset();
+            self.toast_message = Some(format!("Cropped to {}x{}", w, h));
+        }
+        self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
+        self.needs_redraw = true;
+    }
+
+    /// Write a copy of the current image with its manual rotation applied
+    /// next to the source. JPEGs are rotated losslessly when their size
+    /// allows it; other formats are saved as PNG.
+    fn save_rotated(&mut self) {
+        let path = &self.paths[self.current_index];
+        let turns = self.rotations.get(path).copied().unwrap_or(0);
+        let Some(loaded) = self.image_cache.get(&self.current_index) else {
+            return;
+        };
+        if turns == 0 {
+            self.toast_message = Some("Not rotated".to_string());
+            self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
             self.needs_redraw = true;
+            return;
+        }
+
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let result = if ext == "jpg" || ext == "jpeg" {
+            std::fs::read(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+                .and_then(|data| image_loader::rotate_jpeg(&data, turns, loaded.first_frame()))
+                .and_then(|(data, lossless)| {
+                    let out = export_path(path, "rotated", &ext);
+                    std::fs::write(&out, data)
+                        .map_err(|e| format!("Failed to write {}: {}", out.display(), e))
+                        .map(|()| (out, lossless))
+                })
+        } else {
+            let out = export_path(path, "rotated", "png");
+            image_loader::save_png(loaded.first_frame(), &out).map(|()| (out, true))
+        };
+
+        match result {
+            Ok((out, lossless)) => {
+                let name = out
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or("?")
+                    .to_string();
+                let how = if lossless { "" } else { " (re-encoded)" };
+                self.toast_message = Some(format!("Saved: {}{}", name, how));
+                self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
+            }
+            Err(e) => {
+                eprintln!("Warning: save failed: {}", e);
+                self.error_message = Some("Save failed".to_string());
+                self.error_deadline = Some(Instant::now() + ERROR_DISPLAY_DURATION);
+            }
+        }
+        self.needs_redraw = true;
+    }
+
+    /// Write the currently visible region of the image to a PNG next to the source.
+    fn export_view(&mut self) {
+        let Some(loaded) = self.image_cache.get(&self.current_index) else {
+            return;
+        };
+        let frame = loaded.frame(self.viewer.current_frame);
+
+        let result = match self.viewer.visible_region(frame, self.win_w, self.win_h) {
+            Some((x, y, w, h)) if w > 0 && h > 0 => {
+                let region = image_loader::crop_region(frame, x, y, w, h);
+                let out = export_path(&self.paths[self.current_index], "view", "png");
+                image_loader::save_png(&region, &out).map(|()| out)
+            }
+            _ => Err("Nothing visible to export".to_string()),
+        };
+
+        match result {
+            Ok(out) => {
+                let name = out
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or("?")
+                    .to_string();
+                self.toast_message = Some(format!("Exported: {}", name));
+                self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
+            }
+            Err(e) => {
+                eprintln!("Warning: export failed: {}", e);
+                self.error_message = Some("Export failed".to_string());
+                self.error_deadline = Some(Instant::now() + ERROR_DISPLAY_DURATION);
+            }
         }
+        self.needs_redraw = true;
     }
 
     /// Handle an action. Returns true if the app should quit.
     fn handle_action(&mut self, action: Action) -> bool {
+        // Any key cuts a running gallery transition short
+        self.transition = None;
         match action {
             Action::Quit => {
                 return true;
             }
             Action::EscapeOrQuit => {
-                if self.mode == Mode::Gallery {
-                    self.mode = Mode::Viewer;
+                if self.show_help {
+                    self.show_help = false;
+                    self.needs_redraw = true;
+                } else if self.mode == Mode::Gallery && !self.gallery.filter().is_empty() {
+                    self.gallery.set_filter("", &self.paths);
+                    self.needs_redraw = true;
+                } else if self.mode == Mode::Gallery {
+                    self.set_mode(Mode::Viewer);
                     self.current_index = self.gallery.selected;
                     self.viewer.reset_view();
+                    self.slideshow_deadline = self.slideshow_interval.map(|d| Instant::now() + d);
                     self.ensure_image_loaded();
                     if let Some(loaded) = self.image_cache.get(&self.current_index) {
                         self.viewer.start_animation(loaded);
                     }
-                    self.load_exif_for_current();
+                    self.load_metadata_for_current();
                     self.update_title();
                     self.needs_redraw = true;
                 } else if self.viewer.is_exif_visible() {
@@ -623,14 +1626,27 @@ impl App {
             }
             Action::ToggleMode => match self.mode {
                 Mode::Viewer => {
-                    self.mode = Mode::Gallery;
+                    self.set_mode(Mode::Gallery);
                     self.gallery.set_selected(self.current_index);
+                    self.gallery.refilter(&self.paths);
                     self.viewer.next_frame_time = None;
+                    self.slideshow_deadline = None;
                     self.needs_redraw = true;
                 }
+                Mode::Gallery if self.gallery.shown(self.paths.len()) == 0 => {}
                 Mode::Gallery => {
-                    self.mode = Mode::Viewer;
+                    // The strip has no single image to zoom into
+                    let transition = (self.gallery_transition && !self.viewer.is_strip_mode())
+                        .then(|| self.gallery.selected_thumbnail(self.win_w))
+                        .flatten()
+                        .map(|(thumb, from)| ZoomTransition {
+                            thumb: thumb.clone(),
+                            from,
+                            start: Instant::now(),
+                        });
+                    self.set_mode(Mode::Viewer);
                     self.navigate_to(self.gallery.selected);
+                    self.transition = transition;
                 }
             },
             Action::NextImage => {
@@ -649,6 +1665,13 @@ impl App {
                 };
                 self.navigate_to(prev);
             }
+            Action::RandomImage => {
+                if let Some(index) =
+                    random_other(self.current_index, self.paths.len(), &mut self.rng)
+                {
+                    self.navigate_to(index);
+                }
+            }
             Action::FirstImage => {
                 self.navigate_to(0);
             }
@@ -677,10 +1700,29 @@ impl App {
                 self.viewer.zoom_actual_size();
                 self.needs_redraw = true;
             }
+            Action::ToggleActualSize => {
+                let mode = self.viewer.toggle_actual_size();
+                self.toast_message = Some(format!("Zoom: {}", mode));
+                self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
+                self.needs_redraw = true;
+            }
+            Action::PanStart(dir @ (PanDirection::Up | PanDirection::Down))
+                if self.viewer.is_strip_mode() =>
+            {
+                self.viewer.strip_scroll_start(dir);
+                // Without animations the offset moved in one step
+                if !self.viewer.animations_enabled() {
+                    self.needs_redraw = true;
+                }
+            }
             Action::PanStart(dir) => {
-                if self.viewer.is_zoomed() {
+                if self.viewer.is_zoomed() && !self.viewer.is_strip_mode() {
                     self.viewer.pan_start(dir);
-                    // No needs_redraw here — update_pan() in the event loop handles it
+                    // With animations, update_pan() in the event loop redraws;
+                    // without them pan_start() jumps immediately.
+                    if !self.viewer.animations_enabled() {
+                        self.needs_redraw = true;
+                    }
                 } else {
                     // When not zoomed, h/l/Left/Right navigate between images
                     match dir {
@@ -716,10 +1758,68 @@ impl App {
             Action::RotateCCW => {
                 self.rotate_current_image(false);
             }
+            Action::ExportView => {
+                self.export_view();
+            }
+            Action::SaveRotated => {
+                self.save_rotated();
+            }
+            Action::Reload => {
+                self.reload_current();
+            }
+            Action::AutoCrop => {
+                self.auto_crop_current_image();
+            }
             Action::ToggleExif => {
                 self.viewer.toggle_exif();
                 self.needs_redraw = true;
             }
+            Action::ToggleHelp => {
+                self.show_help = !self.show_help;
+                self.needs_redraw = true;
+            }
+            Action::ToggleGrid => {
+                self.viewer.toggle_grid();
+                self.needs_redraw = true;
+            }
+            Action::BrightnessUp => self.adjust_tone(0.05, 0.0, 0.0),
+            Action::BrightnessDown => self.adjust_tone(-0.05, 0.0, 0.0),
+            Action::ContrastUp => self.adjust_tone(0.0, 0.1, 0.0),
+            Action::ContrastDown => self.adjust_tone(0.0, -0.1, 0.0),
+            Action::GammaUp => self.adjust_tone(0.0, 0.0, 0.1),
+            Action::GammaDown => self.adjust_tone(0.0, 0.0, -0.1),
+            Action::ResetTone => self.adjust_tone(0.0, 0.0, 0.0),
+            Action::CycleStatus => {
+                self.status_mode = self.status_mode.next();
+                self.status_deadline = None;
+                self.show_status_briefly();
+                self.toast_message = Some(format!("Status bar: {}", self.status_mode.name()));
+                self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
+                self.needs_redraw = true;
+            }
+            Action::AnimFaster | Action::AnimSlower => {
+                let speed = self.viewer.step_anim_speed(action == Action::AnimFaster);
+                self.toast_message = Some(format!("Animation speed: {}x", speed));
+                self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
+                self.needs_redraw = true;
+            }
+            Action::BrowseDirectory => self.browse_directory(),
+            Action::CycleFilter => {
+                let name = self.viewer.cycle_filter();
+                self.toast_message = Some(format!("Filter: {}", name));
+                self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
+                self.needs_redraw = true;
+            }
+            Action::ToggleStrip => {
+                let enabled = !self.viewer.is_strip_mode();
+                self.viewer.set_strip_mode(enabled);
+                if !enabled {
+                    if let Some(loaded) = self.image_cache.get(&self.current_index) {
+                        self.viewer.start_animation(loaded);
+                    }
+                }
+                self.needs_redraw = true;
+            }
             Action::MoveLeft => {
                 self.gallery.move_left(self.paths.len());
                 self.needs_redraw = true;
@@ -737,41 +1837,199 @@ impl App {
                 self.needs_redraw = true;
             }
             Action::GalleryFirst => {
-                self.gallery.go_first();
+                self.gallery.go_first(self.paths.len());
                 self.needs_redraw = true;
             }
             Action::GalleryLast => {
                 self.gallery.go_last(self.paths.len());
                 self.needs_redraw = true;
             }
+            Action::GalleryPageUp => {
+                self.gallery.page_up(self.paths.len(), self.win_h);
+                self.needs_redraw = true;
+            }
+            Action::GalleryPageDown => {
+                self.gallery.page_down(self.paths.len(), self.win_h);
+                self.needs_redraw = true;
+            }
+            Action::GalleryFilter => {
+                self.filter_editing = true;
+                self.needs_redraw = true;
+            }
+            Action::ZoomPercent => {
+                if !self.viewer.is_strip_mode() {
+                    self.zoom_input = Some(String::new());
+                    self.needs_redraw = true;
+                }
+            }
             Action::CycleSort => {
-                self.cycle_sort();
+                self.sort_mode = self.sort_mode.next();
+                self.resort();
+                self.ensure_image_loaded();
+                self.needs_redraw = true;
+            }
+            Action::ToggleSortDirection => {
+                self.sort_mode = self.sort_mode.reversed();
+                // There is no key to sort by, so reverse the order as it is
+                if self.sort_mode.is_unsorted() {
+                    let old_paths = self.paths.clone();
+                    self.paths.reverse();
+                    self.remap_indices(&old_paths);
+                }
+                self.resort();
                 self.ensure_image_loaded();
                 self.needs_redraw = true;
             }
+            Action::ToggleMark => self.toggle_mark(),
         }
         false
     }
 
-    /// Cycle to the next sort mode, re-sort paths, and show a toast.
-    fn cycle_sort(&mut self) {
+    /// Mark or unmark the current image, or the selected one in the gallery.
+    fn toggle_mark(&mut self) {
+        let index = match self.mode {
+            Mode::Viewer => self.current_index,
+            Mode::Gallery if self.gallery.shown(self.paths.len()) > 0 => self.gallery.selected,
+            Mode::Gallery => return,
+        };
+        let Some(path) = self.paths.get(index) else {
+            return;
+        };
+        let label = if self.marks.remove(path) {
+            "Unmarked"
+        } else {
+            self.marks.insert(path.clone());
+            "Marked"
+        };
+        self.toast_message = Some(format!("{} ({} marked)", label, self.marks.len()));
+        self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
+        self.needs_redraw = true;
+    }
+
+    /// Marked images in the current display order.
+    pub fn marked_paths(&self) -> Vec<&Path> {
+        self.paths
+            .iter()
+            .filter(|p| self.marks.contains(*p))
+            .map(PathBuf::as_path)
+            .collect()
+    }
+
+    /// Edit the gallery filter while it is being typed: text is appended,
+    /// Backspace deletes, Enter keeps the filter and Escape clears it.
+    fn handle_filter_key(&mut self, key: &KeyEvent) {
+        let mut query = self.gallery.filter().to_string();
+        match key.keysym {
+            keysyms::Return | keysyms::KP_Enter => self.filter_editing = false,
+            keysyms::Escape => {
+                self.filter_editing = false;
+                query.clear();
+            }
+            keysyms::BackSpace => {
+                query.pop();
+            }
+            sym => match input::keysym_to_char(sym) {
+                Some(c) if !key.ctrl => query.push(c),
+                _ => return,
+            },
+        }
+        self.gallery.set_filter(&query, &self.paths);
+        self.needs_redraw = true;
+    }
+
+    /// Edit the zoom percentage typed after `%`: digits and a decimal point
+    /// are appended, Backspace deletes, Enter zooms and Escape cancels.
+    fn handle_zoom_key(&mut self, key: &KeyEvent) {
+        let Some(input) = &mut self.zoom_input else {
+            return;
+        };
+        match key.keysym {
+            keysyms::Return | keysyms::KP_Enter => {
+                let input = self.zoom_input.take().unwrap_or_default();
+                if !input.is_empty() {
+                    let message = match parse_zoom_percent(&input) {
+                        Some(percent) => {
+                            let Some(loaded) = self.image_cache.get(&self.current_index) else {
+                                return;
+                            };
+                            let frame = first_frame(loaded);
+                            let (w, h) = (self.win_w, self.win_h);
+                            let applied = self.viewer.set_zoom_percent(frame, percent, w, h);
+                            format!("Zoom: {:.0}%", applied)
+                        }
+                        None => format!("Invalid zoom '{}%'", input),
+                    };
+                    self.toast_message = Some(message);
+                    self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
+                }
+            }
+            keysyms::Escape => self.zoom_input = None,
+            keysyms::BackSpace => {
+                input.pop();
+            }
+            sym => match input::keysym_to_char(sym) {
+                Some(c) if (c.is_ascii_digit() || c == '.') && input.len() < 8 => input.push(c),
+                _ => return,
+            },
+        }
+        self.needs_redraw = true;
+    }
+
+    /// Re-sort paths after the sort mode changed, keep the current image
+    /// selected, and show a toast.
+    fn resort(&mut self) {
         if self.paths.is_empty() {
             return;
         }
 
-        // Remember current image path and old index to re-find it after sort
-        let current_path = self.paths.get(self.current_index).cloned();
-        let old_index = self.current_index;
+        let old_paths = self.paths.clone();
+        self.sort_paths();
+        self.remap_indices(&old_paths);
+
+        // Update gallery: select the current image and reapply the filter
+        self.gallery.set_selected(self.current_index);
+        self.gallery.refilter(&self.paths);
+
+        // Show toast
+        self.toast_message = Some(format!("Sort: {}", self.sort_mode.label()));
+        self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
+    }
 
-        self.sort_mode = self.sort_mode.next();
+    /// Follow a reordering of `paths` from `old_paths` (same images) with
+    /// the current index and the caches keyed by index.
+    fn remap_indices(&mut self, old_paths: &[PathBuf]) {
+        let new_index = index_remap(old_paths, &self.paths);
+        if let Some(&index) = new_index.get(self.current_index) {
+            self.current_index = index;
+        }
+        self.image_cache = self
+            .image_cache
+            .drain()
+            .map(|(i, loaded)| (new_index[i], loaded))
+            .collect();
+        for (i, _) in &mut self.fit_cache {
+            *i = new_index[*i];
+        }
+        self.gallery.remap_thumbnails(&new_index);
+    }
 
-        // Sort paths according to the new mode
+    /// Sort `paths` according to the current sort mode and direction.
+    fn sort_paths(&mut self) {
+        let descending = self.sort_mode.descending;
         // We pre-populate caches then sort using them to avoid borrow conflicts.
-        match self.sort_mode {
-            SortMode::Name => {
-                self.paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+        match self.sort_mode.key {
+            SortKey::Unsorted | SortKey::Shuffled => {}
+            SortKey::Name => {
+                let natural = self.natural_sort;
+                if descending {
+                    self.paths
+                        .sort_by(|a, b| image_loader::compare_file_names(b, a, natural));
+                } else {
+                    self.paths
+                        .sort_by(|a, b| image_loader::compare_file_names(a, b, natural));
+                }
             }
-            SortMode::Size => {
+            SortKey::Size => {
                 // Ensure all metadata is cached first
                 for p in &self.paths {
                     if !self.meta_cache.contains_key(p) {
@@ -780,10 +2038,11 @@ impl App {
                     }
                 }
                 let cache = &self.meta_cache;
-                self.paths
-                    .sort_by_cached_key(|p| cache.get(p).map(|m| m.0).unwrap_or(0));
+                sort_by_cached_key_dir(&mut self.paths, descending, |p| {
+                    cache.get(p).map(|m| m.0).unwrap_or(0)
+                });
             }
-            SortMode::ModTime => {
+            SortKey::ModTime => {
                 for p in &self.paths {
                     if !self.meta_cache.contains_key(p) {
                         let meta = read_file_meta(p);
@@ -791,10 +2050,27 @@ impl App {
                     }
                 }
                 let cache = &self.meta_cache;
-                self.paths
-                    .sort_by_cached_key(|p| cache.get(p).map(|m| m.1).unwrap_or(0));
+                sort_by_cached_key_dir(&mut self.paths, descending, |p| {
+                    cache.get(p).map(|m| m.1).unwrap_or(0)
+                });
+            }
+            SortKey::Megapixels => {
+                for p in &self.paths {
+                    if !self.dimensions_cache.contains_key(p) {
+                        let dims = image_loader::image_dimensions(p);
+                        self.dimensions_cache.insert(p.clone(), dims);
+                    }
+                }
+                let cache = &self.dimensions_cache;
+                // Images whose header gives no size sort as empty
+                sort_by_cached_key_dir(&mut self.paths, descending, |p| {
+                    cache
+                        .get(p)
+                        .and_then(|d| *d)
+                        .map_or(0, |(w, h)| w as u64 * h as u64)
+                });
             }
-            SortMode::ExifDate => {
+            SortKey::ExifDate => {
                 // Pre-populate both metadata and EXIF date caches
                 for p in &self.paths {
                     if !self.meta_cache.contains_key(p) {
@@ -802,55 +2078,139 @@ impl App {
                         self.meta_cache.insert(p.clone(), meta);
                     }
                     if !self.exif_date_cache.contains_key(p) {
-                        let ext = p
-                            .extension()
-                            .and_then(|e| e.to_str())
-                            .unwrap_or("")
-                            .to_ascii_lowercase();
-                        let exif_ts = if ext == "jpg" || ext == "jpeg" {
-                            parse_exif_date_original(p)
-                        } else {
+                        let too_large = self.meta_cache[p].0 > MAX_METADATA_FILE_SIZE;
+                        let exif_ts = if too_large {
                             None
+                        } else {
+                            parse_exif_date_original(p)
                         };
                         self.exif_date_cache.insert(p.clone(), exif_ts);
                     }
                 }
                 let meta_cache = &self.meta_cache;
                 let exif_cache = &self.exif_date_cache;
-                self.paths.sort_by_cached_key(|p| {
-                    exif_cache
-                        .get(p)
-                        .and_then(|v| *v)
-                        .unwrap_or_else(|| meta_cache.get(p).map(|m| m.1).unwrap_or(0))
+                // Compare in milliseconds; files without EXIF fall back to mtime
+                sort_by_cached_key_dir(&mut self.paths, descending, |p| {
+                    exif_cache.get(p).and_then(|v| *v).unwrap_or_else(|| {
+                        meta_cache.get(p).map(|m| m.1 as i64 * 1000).unwrap_or(0)
+                    })
                 });
             }
         }
+    }
+}
 
-        // Re-find the current image in the sorted list
-        if let Some(ref path) = current_path {
-            if let Some(pos) = self.paths.iter().position(|p| p == path) {
-                self.current_index = pos;
-            }
-        }
+/// The frame shown for an image outside of animation playback.
+fn first_frame(loaded: &LoadedImage) -> &RgbaImage {
+    match loaded {
+        LoadedImage::Static(img) => img,
+        LoadedImage::Animated { frames } => &frames[0].0,
+    }
+}
 
-        // Remap the cached image from old index to new index
-        if old_index != self.current_index {
-            if let Some(loaded) = self.image_cache.remove(&old_index) {
-                self.image_cache.clear();
-                self.image_cache.insert(self.current_index, loaded);
-            }
-        }
+/// The load note of an image, followed by its decode time when that took
+/// at least `SLOW_DECODE_THRESHOLD`.
+fn with_decode_time(note: Option<String>, elapsed: Duration) -> Option<String> {
+    if elapsed < SLOW_DECODE_THRESHOLD {
+        return note;
+    }
+    let slow = format!("Decoded in {:.1}s", elapsed.as_secs_f64());
+    Some(match note {
+        Some(note) => format!("{} | {}", note, slow),
+        None => slow,
+    })
+}
 
-        // Update gallery: reset selection and invalidate stale thumbnail cache
-        self.gallery.set_selected(self.current_index);
-        self.gallery.invalidate_thumbnails();
+/// Parse a typed zoom percentage such as `150` or `12.5` (a trailing `%`
+/// is allowed); it must be positive.
+fn parse_zoom_percent(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let percent: f64 = s.strip_suffix('%').unwrap_or(s).trim().parse().ok()?;
+    (percent.is_finite() && percent > 0.0).then_some(percent)
+}
 
-        // Show toast
-        self.toast_message = Some(format!("Sort: {}", self.sort_mode.label()));
-        self.toast_deadline = Some(Instant::now() + TOAST_DISPLAY_DURATION);
+/// Rectangle `progress` (0 to 1) of the way from `from` to `to`, eased
+/// out so the zoom slows down as it lands.
+fn transition_rect(from: WindowRect, to: WindowRect, progress: f64) -> WindowRect {
+    let t = 1.0 - (1.0 - progress.clamp(0.0, 1.0)).powi(3);
+    let lerp = |a: f64, b: f64| a + (b - a) * t;
+    (
+        lerp(from.0 as f64, to.0 as f64).round() as i32,
+        lerp(from.1 as f64, to.1 as f64).round() as i32,
+        (lerp(from.2 as f64, to.2 as f64).round() as u32).max(1),
+        (lerp(from.3 as f64, to.3 as f64).round() as u32).max(1),
+    )
+}
+
+/// `sort_by_cached_key`, with the comparison reversed when `descending`.
+/// Equal keys keep their relative order either way.
+fn sort_by_cached_key_dir<K: Ord>(
+    paths: &mut [PathBuf],
+    descending: bool,
+    key: impl Fn(&PathBuf) -> K,
+) {
+    if descending {
+        paths.sort_by_cached_key(|p| std::cmp::Reverse(key(p)));
+    } else {
+        paths.sort_by_cached_key(key);
     }
 }
 
+/// Xorshift64 generator for random navigation; not for anything that
+/// needs good randomness.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Xorshift gets stuck at zero
+        Self(seed.max(1))
+    }
+
+    /// Seeded from the clock and the process id.
+    fn from_time() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Self::new(nanos ^ ((std::process::id() as u64) << 32))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number in `0..n` (`n` > 0).
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// Shuffle `items` in place (Fisher-Yates).
+fn shuffle<T>(items: &mut [T], rng: &mut Rng) {
+    for i in (1..items.len()).rev() {
+        items.swap(i, rng.below(i + 1));
+    }
+}
+
+/// A random index in `0..len` other than `current`, or `None` with fewer
+/// than two images.
+fn random_other(current: usize, len: usize, rng: &mut Rng) -> Option<usize> {
+    if len < 2 {
+        return None;
+    }
+    let index = rng.below(len - 1);
+    Some(if index >= current { index + 1 } else { index })
+}
+
+/// For each path in `old`, its index in `new`, which must contain all of
+/// them exactly once.
+fn index_remap(old: &[PathBuf], new: &[PathBuf]) -> Vec<usize> {
+    let positions: HashMap<&PathBuf, usize> = new.iter().enumerate().map(|(i, p)| (p, i)).collect();
+    old.iter().map(|p| positions[p]).collect()
+}
+
 /// Read file size and modification time. Returns (size_bytes, mtime_secs).
 fn read_file_meta(path: &PathBuf) -> (u64, u64) {
     match std::fs::metadata(path) {
@@ -868,23 +2228,45 @@ fn read_file_meta(path: &PathBuf) -> (u64, u64) {
     }
 }
 
-/// Parse EXIF DateTimeOriginal from a JPEG file, returning a Unix timestamp.
-fn parse_exif_date_original(path: &PathBuf) -> Option<u64> {
+/// Parse the EXIF capture time from any format with EXIF support,
+/// returning Unix milliseconds.
+fn parse_exif_date_original(path: &PathBuf) -> Option<i64> {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
     let data = std::fs::read(path).ok()?;
-    let tags = image_loader::read_exif_tags(&data);
-    for (label, value) in &tags {
-        if label == "Date Original" || label == "Date/Time" {
-            // EXIF date format: "YYYY:MM:DD HH:MM:SS"
-            return parse_exif_datetime(value);
-        }
+    let tags = image_loader::read_exif_tags_for_ext(&data, &ext);
+    exif_capture_time(&tags)
+}
+
+/// Capture time in Unix milliseconds from EXIF tags: DateTimeOriginal refined
+/// by SubSecTimeOriginal and OffsetTimeOriginal, else the IFD0 DateTime.
+/// Without an offset the camera's local time is taken as UTC.
+fn exif_capture_time(tags: &[(String, String)]) -> Option<i64> {
+    let tag = |name: &str| {
+        tags.iter()
+            .find(|(label, _)| label == name)
+            .map(|(_, value)| value.as_str())
+    };
+    if let Some(secs) = tag("Date Original").and_then(parse_exif_datetime) {
+        let millis = tag("SubSec Original")
+            .and_then(parse_exif_subsec)
+            .unwrap_or(0);
+        let offset = tag("Offset Original")
+            .and_then(parse_exif_offset)
+            .unwrap_or(0);
+        return Some((secs - offset) * 1000 + millis);
     }
-    None
+    tag("Date/Time")
+        .and_then(parse_exif_datetime)
+        .map(|secs| secs * 1000)
 }
 
-/// Parse "YYYY:MM:DD HH:MM:SS" into a rough Unix timestamp (seconds since epoch).
-fn parse_exif_datetime(s: &str) -> Option<u64> {
-    // "2024:01:15 14:30:00" -> parse as approximate seconds
-    let parts: Vec<&str> = s.split(|c: char| c == ':' || c == ' ').collect();
+/// Parse "YYYY:MM:DD HH:MM:SS" into seconds since the Unix epoch.
+fn parse_exif_datetime(s: &str) -> Option<i64> {
+    let parts: Vec<&str> = s.trim().split([':', ' ']).collect();
     if parts.len() < 6 {
         return None;
     }
@@ -897,22 +2279,202 @@ fn parse_exif_datetime(s: &str) -> Option<u64> {
     if year < 1970 || month == 0 || month > 12 || day == 0 || day > 31 {
         return None;
     }
-    // Approximate days since epoch (good enough for sorting)
-    let days_approx = (year - 1970) * 365 + (year - 1969) / 4 + (month - 1) * 30 + day;
-    Some(days_approx * 86400 + hour * 3600 + min * 60 + sec)
+    if hour > 23 || min > 59 || sec > 60 {
+        return None;
+    }
+    let days = crate::status::date_to_days(year, month, day);
+    Some((days * 86400 + hour * 3600 + min * 60 + sec) as i64)
 }
 
-/// Convert an RgbaImage to a Vec<u32> XRGB8888 pixel buffer.
-fn rgba_to_xrgb(img: &crate::image_loader::RgbaImage) -> Vec<u32> {
-    let raw = img.as_raw();
-    let (w, h) = img.dimensions();
-    let mut buf = Vec::with_capacity((w * h) as usize);
-    for i in 0..(w * h) as usize {
-        let idx = i * 4;
-        let r = raw[idx] as u32;
-        let g = raw[idx + 1] as u32;
-        let b = raw[idx + 2] as u32;
-        buf.push((r << 16) | (g << 8) | b);
-    }
-    buf
+/// Parse SubSecTime digits ("5" = 0.5 s, "123" = 0.123 s) into milliseconds.
+fn parse_exif_subsec(s: &str) -> Option<i64> {
+    let digits = s.trim_end_matches([' ', '\0']).trim();
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let ms: String = digits.chars().chain("000".chars()).take(3).collect();
+    ms.parse().ok()
+}
+
+/// Parse an OffsetTime value ("+02:00", "-05:30") into seconds east of UTC.
+fn parse_exif_offset(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let (sign, rest) = match s.as_bytes().first()? {
+        b'+' => (1, &s[1..]),
+        b'-' => (-1, &s[1..]),
+        _ => return None,
+    };
+    let (h, m) = rest.split_once(':')?;
+    let h: i64 = h.parse().ok()?;
+    let m: i64 = m.parse().ok()?;
+    if h > 14 || m > 59 {
+        return None;
+    }
+    Some(sign * (h * 3600 + m * 60))
+}
+
+/// Pick an unused `<stem>-<suffix>.<ext>` (or `<stem>-<suffix>-N.<ext>`)
+/// path next to `src`.
+fn export_path(src: &Path, suffix: &str, ext: &str) -> PathBuf {
+    let dir = src.parent().unwrap_or_else(|| Path::new("."));
+    let stem = src
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "image".to_string());
+    let mut candidate = dir.join(format!("{}-{}.{}", stem, suffix, ext));
+    let mut n = 1;
+    while candidate.exists() {
+        n += 1;
+        candidate = dir.join(format!("{}-{}-{}.{}", stem, suffix, n, ext));
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(l, v)| (l.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_strip_preload_queues_each_path_once() {
+        let path = Path::new("/nonexistent/strip.png");
+        let mut preload = StripPreload::new();
+        preload.request(path);
+        preload.request(path);
+        assert!(preload.has_pending());
+        let start = Instant::now();
+        let mut results = Vec::new();
+        while results.is_empty() && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(1));
+            results = preload.poll();
+        }
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, path);
+        assert!(results[0].1.is_err());
+        assert!(!preload.has_pending());
+    }
+
+    #[test]
+    fn test_with_decode_time() {
+        let note = || Some("downscaled".to_string());
+        assert_eq!(with_decode_time(None, Duration::from_millis(999)), None);
+        assert_eq!(with_decode_time(note(), Duration::from_millis(200)), note());
+        assert_eq!(
+            with_decode_time(None, Duration::from_millis(1250)).as_deref(),
+            Some("Decoded in 1.2s")
+        );
+        assert_eq!(
+            with_decode_time(note(), Duration::from_secs(2)).as_deref(),
+            Some("downscaled | Decoded in 2.0s")
+        );
+    }
+
+    #[test]
+    fn test_parse_zoom_percent() {
+        assert_eq!(parse_zoom_percent("150"), Some(150.0));
+        assert_eq!(parse_zoom_percent("12.5%"), Some(12.5));
+        assert_eq!(parse_zoom_percent("0"), None);
+        assert_eq!(parse_zoom_percent("."), None);
+        assert_eq!(parse_zoom_percent(""), None);
+    }
+
+    #[test]
+    fn test_transition_rect() {
+        let from = (100, 50, 200, 150);
+        let to = (-20, 0, 1000, 750);
+        assert_eq!(transition_rect(from, to, 0.0), from);
+        assert_eq!(transition_rect(from, to, 1.0), to);
+        assert_eq!(transition_rect(from, to, 2.0), to);
+        // Eased out: more than half way at the midpoint
+        let (x, y, w, h) = transition_rect(from, to, 0.5);
+        assert_eq!((x, y, w, h), (-5, 6, 900, 675));
+        // Never collapses to nothing
+        let empty = (0, 0, 0, 0);
+        assert_eq!(transition_rect(empty, empty, 0.5), (0, 0, 1, 1));
+    }
+
+    #[test]
+    fn test_shuffle_and_random_other() {
+        let mut rng = Rng::new(42);
+        let mut items: Vec<u32> = (0..50).collect();
+        shuffle(&mut items, &mut rng);
+        assert_ne!(items, (0..50).collect::<Vec<_>>());
+        items.sort();
+        assert_eq!(items, (0..50).collect::<Vec<_>>());
+
+        // Never the current image, and every other one comes up
+        let mut seen = [false; 5];
+        for _ in 0..200 {
+            let index = random_other(2, 5, &mut rng).unwrap();
+            assert_ne!(index, 2);
+            seen[index] = true;
+        }
+        assert_eq!(seen, [true, true, false, true, true]);
+        assert_eq!(random_other(0, 1, &mut rng), None);
+        assert_eq!(random_other(0, 0, &mut rng), None);
+        assert_eq!(Rng::new(0).next(), Rng::new(1).next());
+    }
+
+    #[test]
+    fn test_index_remap_after_merge() {
+        let paths = |names: &[&str]| -> Vec<PathBuf> { names.iter().map(PathBuf::from).collect() };
+        let old = paths(&["d/b.png", "d/d.png", "x.png"]);
+        let new = paths(&["d/a.png", "d/b.png", "d/c.png", "d/d.png", "x.png"]);
+        assert_eq!(index_remap(&old, &new), [1, 3, 4]);
+        let reversed: Vec<PathBuf> = new.iter().rev().cloned().collect();
+        assert_eq!(index_remap(&old, &reversed), [3, 1, 0]);
+    }
+
+    #[test]
+    fn test_parse_exif_datetime_known_dates() {
+        assert_eq!(parse_exif_datetime("1970:01:01 00:00:00"), Some(0));
+        // 2000-02-29 12:00:00 UTC
+        assert_eq!(
+            parse_exif_datetime("2000:02:29 12:00:00"),
+            Some(951_825_600)
+        );
+        // 2024-01-15 14:30:00 UTC
+        assert_eq!(
+            parse_exif_datetime("2024:01:15 14:30:00"),
+            Some(1_705_329_000)
+        );
+        assert_eq!(parse_exif_datetime("0000:00:00 00:00:00"), None);
+        assert_eq!(parse_exif_datetime("2024:01:15"), None);
+    }
+
+    #[test]
+    fn test_parse_exif_subsec_and_offset() {
+        assert_eq!(parse_exif_subsec("5"), Some(500));
+        assert_eq!(parse_exif_subsec("123456"), Some(123));
+        assert_eq!(parse_exif_subsec("07 "), Some(70));
+        assert_eq!(parse_exif_subsec("x"), None);
+        assert_eq!(parse_exif_offset("+02:00"), Some(7200));
+        assert_eq!(parse_exif_offset("-05:30"), Some(-19800));
+        assert_eq!(parse_exif_offset("   :  "), None);
+    }
+
+    #[test]
+    fn test_exif_capture_time_refined() {
+        let t = exif_capture_time(&tags(&[
+            ("Date Original", "2024:01:15 14:30:00"),
+            ("SubSec Original", "25"),
+            ("Offset Original", "+01:00"),
+        ]));
+        assert_eq!(t, Some((1_705_329_000 - 3600) * 1000 + 250));
+        // Same wall-clock time in a later timezone sorts earlier
+        let east = exif_capture_time(&tags(&[
+            ("Date Original", "2024:01:15 14:30:00"),
+            ("Offset Original", "+09:00"),
+        ]));
+        assert!(east < t);
+        // Falls back to IFD0 DateTime
+        let t = exif_capture_time(&tags(&[("Date/Time", "2024:01:15 14:30:00")]));
+        assert_eq!(t, Some(1_705_329_000_000));
+    }
 }
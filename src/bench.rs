@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::gallery::THUMB_SIZE;
+use crate::image_loader::{self, LoadedImage, RgbaImage};
+use crate::render;
+
+/// Window size the resize benchmark scales images to fit.
+const RESIZE_BENCH_SIZE: (u32, u32) = (3840, 2160);
+
+/// Decode `path` once as the viewer or the gallery would, returning the
+/// number of decoded pixels (all frames of an animation).
+fn decode(path: &Path, thumbnails: bool) -> Result<u64, String> {
+    let pixels = |w: u32, h: u32| w as u64 * h as u64;
+    if thumbnails {
+        let thumb = image_loader::load_image_thumbnail(path, THUMB_SIZE)?;
+        return Ok(pixels(thumb.width, thumb.height));
+    }
+    Ok(match image_loader::load_image(path)? {
+        LoadedImage::Static(img) => pixels(img.width, img.height),
+        LoadedImage::Animated { frames, .. } => frames
+            .iter()
+            .map(|(img, _)| pixels(img.width, img.height))
+            .sum(),
+    })
+}
+
+/// Decode throughput in megapixels per second.
+fn megapixels_per_second(pixels: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs <= 0.0 {
+        return 0.0;
+    }
+    pixels as f64 / 1_000_000.0 / secs
+}
+
+/// Decode every path without opening a window, printing the time and
+/// throughput of each file and a total. Returns the number of failures.
+pub fn decode_only(paths: &[PathBuf], thumbnails: bool) -> usize {
+    let mut failed = 0;
+    let mut total_pixels = 0;
+    let mut total_time = Duration::ZERO;
+    for path in paths {
+        let start = Instant::now();
+        let result = decode(path, thumbnails);
+        let elapsed = start.elapsed();
+        total_time += elapsed;
+        match result {
+            Ok(pixels) => {
+                total_pixels += pixels;
+                println!(
+                    "{}: {:.1} ms, {:.1} MP/s",
+                    path.display(),
+                    elapsed.as_secs_f64() * 1000.0,
+                    megapixels_per_second(pixels, elapsed)
+                );
+            }
+            Err(e) => {
+                eprintln!("Error: {}: {}", path.display(), e);
+                failed += 1;
+            }
+        }
+    }
+    println!(
+        "{} files ({} failed) in {:.1} ms, {:.1} MP/s",
+        paths.len(),
+        failed,
+        total_time.as_secs_f64() * 1000.0,
+        megapixels_per_second(total_pixels, total_time)
+    );
+    failed
+}
+
+/// Time one bilinear resize of `img` to `dst_w`x`dst_h` on `threads`.
+fn time_resize(img: &RgbaImage, dst_w: u32, dst_h: u32, threads: usize) -> Duration {
+    let start = Instant::now();
+    let out = render::resize_rgba_threaded(img, dst_w, dst_h, threads);
+    let elapsed = start.elapsed();
+    debug_assert_eq!(out.dimensions(), (dst_w, dst_h));
+    elapsed
+}
+
+/// Scale the first frame of every path to fit a 4K window, once on one
+/// thread and once with the threads the viewer would use, printing both
+/// times and the speedup. Returns the number of files that failed to load.
+pub fn resize_only(paths: &[PathBuf]) -> usize {
+    let mut failed = 0;
+    for path in paths {
+        let img = match image_loader::load_image(path) {
+            Ok(LoadedImage::Static(img)) => img,
+            Ok(LoadedImage::Animated { mut frames }) => frames.swap_remove(0).0,
+            Err(e) => {
+                eprintln!("Error: {}: {}", path.display(), e);
+                failed += 1;
+                continue;
+            }
+        };
+        let (max_w, max_h) = RESIZE_BENCH_SIZE;
+        // Shrunk to fit like the viewer; smaller images are not enlarged
+        let (dst_w, dst_h) = if img.width > max_w || img.height > max_h {
+            render::fit_size(img.width, img.height, max_w, max_h)
+        } else {
+            (img.width, img.height)
+        };
+        let threads = render::resize_threads(dst_w, dst_h);
+        let single = time_resize(&img, dst_w, dst_h, 1);
+        let parallel = time_resize(&img, dst_w, dst_h, threads);
+        println!(
+            "{}: {}x{} -> {}x{}, 1 thread {:.1} ms, {} threads {:.1} ms ({:.1}x)",
+            path.display(),
+            img.width,
+            img.height,
+            dst_w,
+            dst_h,
+            single.as_secs_f64() * 1000.0,
+            threads,
+            parallel.as_secs_f64() * 1000.0,
+            single.as_secs_f64() / parallel.as_secs_f64().max(1e-9)
+        );
+    }
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_counts_pixels_and_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("a.png");
+        let img = RgbaImage::from_raw(3, 2, vec![255; 3 * 2 * 4]).unwrap();
+        image_loader::save_png(&img, &src).unwrap();
+
+        assert_eq!(decode(&src, false), Ok(6));
+        // Thumbnails are scaled to the gallery cell
+        assert_eq!(decode(&src, true), Ok(200 * 133));
+        let paths = vec![src, dir.path().join("missing.png")];
+        assert_eq!(decode_only(&paths, false), 1);
+
+        let mp = megapixels_per_second(2_000_000, Duration::from_millis(500));
+        assert!((mp - 4.0).abs() < 1e-9);
+        assert_eq!(megapixels_per_second(1, Duration::ZERO), 0.0);
+    }
+
+    #[test]
+    fn test_resize_only_reports_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("a.png");
+        let img = RgbaImage::from_raw(4, 2, vec![255; 4 * 2 * 4]).unwrap();
+        image_loader::save_png(&img, &src).unwrap();
+        let paths = vec![src, dir.path().join("missing.png")];
+        assert_eq!(resize_only(&paths), 1);
+    }
+}
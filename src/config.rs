@@ -0,0 +1,755 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::image_loader::{self, OrientationPolicy};
+use crate::input::{self, KeyBindings};
+use crate::render;
+use crate::status::{self, StatusField, StatusMode, StatusPosition};
+use crate::viewer;
+
+/// How images are scaled when first displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleMode {
+    /// Shrink large images to fit, never upscale small ones.
+    Fit,
+    /// Scale every image to fill the window (upscaling small images).
+    FitWindow,
+    /// Show images at 1:1 pixel size.
+    Actual,
+}
+
+/// Key the image list is sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Size,
+    ExifDate,
+    ModTime,
+    /// Pixel count (width times height) read from the image header.
+    Megapixels,
+    /// Command-line order (`--no-sort`); left for good by cycling.
+    Unsorted,
+    /// Random order (`--shuffle`); left for good by cycling.
+    Shuffled,
+}
+
+/// Sort mode for image list ordering: a key plus a direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortMode {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Default for SortMode {
+    fn default() -> Self {
+        Self {
+            key: SortKey::Name,
+            descending: false,
+        }
+    }
+}
+
+impl SortMode {
+    /// Images in the order given on the command line (`--no-sort`).
+    pub const UNSORTED: Self = Self {
+        key: SortKey::Unsorted,
+        descending: false,
+    };
+
+    /// Images in random order (`--shuffle`).
+    pub const SHUFFLED: Self = Self {
+        key: SortKey::Shuffled,
+        descending: false,
+    };
+
+    /// Whether the list is kept in an order of its own rather than sorted.
+    pub fn is_unsorted(self) -> bool {
+        matches!(self.key, SortKey::Unsorted | SortKey::Shuffled)
+    }
+
+    /// Cycle to the next sort key, keeping the direction.
+    pub fn next(self) -> Self {
+        let key = match self.key {
+            SortKey::Name => SortKey::Size,
+            SortKey::Size => SortKey::ExifDate,
+            SortKey::ExifDate => SortKey::ModTime,
+            SortKey::ModTime => SortKey::Megapixels,
+            SortKey::Megapixels | SortKey::Unsorted | SortKey::Shuffled => SortKey::Name,
+        };
+        Self { key, ..self }
+    }
+
+    pub fn reversed(self) -> Self {
+        Self {
+            descending: !self.descending,
+            ..self
+        }
+    }
+
+    /// Toast label, e.g. "Size ↓".
+    pub fn label(self) -> String {
+        let name = match self.key {
+            SortKey::Name => "Name",
+            SortKey::Size => "Size",
+            SortKey::ExifDate => "EXIF Date",
+            SortKey::ModTime => "Mod Time",
+            SortKey::Megapixels => "Megapixels",
+            SortKey::Unsorted => "Unsorted",
+            SortKey::Shuffled => "Shuffled",
+        };
+        let arrow = if self.descending {
+            '\u{2193}'
+        } else {
+            '\u{2191}'
+        };
+        format!("{} {}", name, arrow)
+    }
+
+    /// Parse `name|size|date|mtime|megapixels`, optionally followed by `:asc` or `:desc`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (key, dir) = match s.split_once(':') {
+            Some((key, dir)) => (key, Some(dir)),
+            None => (s, None),
+        };
+        let key = match key {
+            "name" => SortKey::Name,
+            "size" => SortKey::Size,
+            "date" => SortKey::ExifDate,
+            "mtime" => SortKey::ModTime,
+            "megapixels" => SortKey::Megapixels,
+            _ => return None,
+        };
+        let descending = match dir {
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(_) => return None,
+        };
+        Some(Self { key, descending })
+    }
+}
+
+/// User configuration. Every field has a default, so a missing or partial
+/// config file is fine.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Extra key bindings that take precedence over the built-in ones.
+    pub bindings: KeyBindings,
+    pub scale_mode: ScaleMode,
+    /// Letterbox/background color as XRGB8888.
+    pub background: u32,
+    /// Whether `background` was set in the config; PNG `bKGD` colors are
+    /// only used when it was not.
+    pub custom_background: bool,
+    /// Auto-advance interval in the viewer; `None` disables the slideshow.
+    pub slideshow_interval: Option<Duration>,
+    /// Advance the slideshow to a random image instead of the next one.
+    pub slideshow_random: bool,
+    /// Smooth panning and animated image playback (`--no-animations` clears it).
+    pub animations: bool,
+    /// Let panning overshoot the image edges and spring back on release.
+    pub elastic_pan: bool,
+    /// Bounds for the display duration of animation frames.
+    pub min_frame_duration: Duration,
+    pub max_frame_duration: Duration,
+    /// Columns and rows of the composition grid overlay.
+    pub grid: (u32, u32),
+    /// Initial sort order (`--sort` overrides it).
+    pub sort: SortMode,
+    /// Sort names naturally (`img2` before `img10`) instead of byte-wise.
+    pub natural_sort: bool,
+    /// Start in vertical strip mode (`--strip` sets it).
+    pub strip: bool,
+    /// Initial window size when the compositor leaves it to us
+    /// (`--geometry` overrides it); `None` uses the built-in default.
+    pub geometry: Option<(u32, u32)>,
+    /// Open the window at the first image's size (`--size-to-image`).
+    pub size_to_image: bool,
+    /// Restore the window size and fullscreen state of the last run and
+    /// save them on exit (`--remember-geometry` sets it).
+    pub remember_geometry: bool,
+    /// Open the window fullscreen; only set from the remembered state.
+    pub fullscreen: bool,
+    /// Outputs wallpaper mode is limited to, by connector name; only set
+    /// from `--output`.
+    pub wallpaper_outputs: Vec<String>,
+    /// Animation playback speed multiplier; only set from `--anim-speed`.
+    pub anim_speed: f64,
+    /// Which orientation source to apply (`--orientation` overrides it).
+    pub orientation: OrientationPolicy,
+    /// Decode JPEGs over the pixel limit at a reduced size instead of
+    /// skipping them (`--downscale-oversized` sets it).
+    pub downscale_oversized: bool,
+    /// Also limit decoded images to the available memory
+    /// (`--memory-limit` sets it).
+    pub memory_limit: bool,
+    /// Show images on white with crop marks (`--proof` sets it).
+    pub proof: bool,
+    /// Reload the current image when it changes on disk (`--watch` sets it).
+    pub watch: bool,
+    /// Apply PNG `gAMA` gamma correction (`--no-gamma` clears it).
+    pub gamma: bool,
+    /// Floyd–Steinberg dithering for images deeper than 8 bits (`--dither`).
+    pub dither: bool,
+    /// Window edge the status bar is drawn at.
+    pub status_position: StatusPosition,
+    /// Fields of the status bar, in display order.
+    pub status_fields: Vec<StatusField>,
+    /// Status bar always shown, auto-hidden or off (cycled with `t`).
+    pub status_mode: StatusMode,
+    /// Gallery left/right wrap around between the first and last thumbnail.
+    pub gallery_wrap: bool,
+    /// Zoom the selected thumbnail up to the image when opening it from
+    /// the gallery, instead of switching at once.
+    pub gallery_transition: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bindings: KeyBindings::default(),
+            scale_mode: ScaleMode::Fit,
+            background: render::BG_COLOR,
+            custom_background: false,
+            slideshow_interval: None,
+            slideshow_random: false,
+            animations: true,
+            elastic_pan: false,
+            min_frame_duration: image_loader::DEFAULT_MIN_FRAME_DURATION,
+            max_frame_duration: image_loader::DEFAULT_MAX_FRAME_DURATION,
+            grid: (3, 3),
+            sort: SortMode::default(),
+            natural_sort: true,
+            strip: false,
+            geometry: None,
+            size_to_image: false,
+            remember_geometry: false,
+            fullscreen: false,
+            wallpaper_outputs: Vec::new(),
+            anim_speed: 1.0,
+            orientation: OrientationPolicy::Auto,
+            downscale_oversized: false,
+            memory_limit: false,
+            proof: false,
+            watch: false,
+            gamma: true,
+            dither: false,
+            status_position: StatusPosition::Bottom,
+            status_fields: status::DEFAULT_STATUS_FIELDS.to_vec(),
+            status_mode: StatusMode::Always,
+            gallery_wrap: false,
+            gallery_transition: false,
+        }
+    }
+}
+
+/// Locate and load the config file. `cli_path` is the `--config` argument.
+/// A missing file yields the defaults silently.
+pub fn load(cli_path: Option<&Path>) -> Config {
+    let path = match resolve_path(
+        env::var_os("RIMG_CONFIG"),
+        cli_path,
+        env::var_os("XDG_CONFIG_HOME"),
+        env::var_os("HOME"),
+    ) {
+        Some(p) => p,
+        None => return Config::default(),
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(text) => parse(&text, &path),
+        Err(_) => Config::default(),
+    }
+}
+
+/// Pick the config path: `$RIMG_CONFIG`, then `--config`, then
+/// `$XDG_CONFIG_HOME/rimg/config`, then `~/.config/rimg/config`.
+fn resolve_path(
+    env_config: Option<OsString>,
+    cli_path: Option<&Path>,
+    xdg_config_home: Option<OsString>,
+    home: Option<OsString>,
+) -> Option<PathBuf> {
+    if let Some(p) = env_config.filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(p));
+    }
+    if let Some(p) = cli_path {
+        return Some(p.to_path_buf());
+    }
+    // Per the XDG spec, relative values of XDG_CONFIG_HOME are ignored
+    if let Some(dir) = xdg_config_home
+        .map(PathBuf::from)
+        .filter(|d| d.is_absolute())
+    {
+        return Some(dir.join("rimg").join("config"));
+    }
+    home.filter(|h| !h.is_empty())
+        .map(|h| PathBuf::from(h).join(".config").join("rimg").join("config"))
+}
+
+/// Parse config file contents. The format is one `key = value` per line,
+/// with `#` starting a comment (full-line, or ` # ` after a value). Unknown keys and bad values are reported
+/// on stderr and skipped.
+///
+/// ```text
+/// scale = fit | fit-window | actual
+/// background = #1a1a1a
+/// slideshow = 5          # seconds, 0 disables
+/// slideshow-random = true
+/// animations = false
+/// elastic-pan = true
+/// min-frame-duration = 20     # milliseconds
+/// max-frame-duration = 10000
+/// grid = 3x3
+/// sort = size:desc
+/// natural-sort = true
+/// strip = false
+/// geometry = 1280x720
+/// size-to-image = true
+/// remember-geometry = true
+/// orientation = auto | container | exif | none
+/// downscale-oversized = true
+/// memory-limit = true
+/// proof = false
+/// watch = true
+/// gamma = false
+/// dither = true
+/// status-position = top | bottom
+/// status-fields = name,dimensions,size,date,index,zoom,format
+/// status-mode = always | auto | off
+/// gallery-wrap = true
+/// gallery-transition = true
+/// bind x = export-view
+/// ```
+fn parse(text: &str, path: &Path) -> Config {
+    let mut config = Config::default();
+
+    for (lineno, raw) in text.lines().enumerate() {
+        let line = strip_comment(raw).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            warn(path, lineno, "expected `key = value`");
+            continue;
+        };
+        let key = key.trim();
+        let value = value.trim();
+
+        if let Some(keyname) = key.strip_prefix("bind ") {
+            let keysym = input::keysym_from_name(keyname.trim());
+            let action = input::action_from_name(value);
+            match (keysym, action) {
+                (Some(sym), Some(action)) => config.bindings.insert(sym, action),
+                (None, _) => warn(path, lineno, &format!("unknown key '{}'", keyname.trim())),
+                (_, None) => warn(path, lineno, &format!("unknown action '{}'", value)),
+            }
+            continue;
+        }
+
+        match key {
+            "scale" => match value {
+                "fit" => config.scale_mode = ScaleMode::Fit,
+                "fit-window" => config.scale_mode = ScaleMode::FitWindow,
+                "actual" => config.scale_mode = ScaleMode::Actual,
+                _ => warn(path, lineno, &format!("unknown scale mode '{}'", value)),
+            },
+            "background" => match parse_hex_color(value) {
+                Some(color) => {
+                    config.background = color;
+                    config.custom_background = true;
+                }
+                None => warn(path, lineno, &format!("invalid color '{}'", value)),
+            },
+            "slideshow" => match value.parse::<f64>() {
+                Ok(0.0) => config.slideshow_interval = None,
+                Ok(secs) if secs > 0.0 && secs.is_finite() => {
+                    config.slideshow_interval = Some(Duration::from_secs_f64(secs));
+                }
+                _ => warn(
+                    path,
+                    lineno,
+                    &format!("invalid slideshow interval '{}'", value),
+                ),
+            },
+            "slideshow-random" => match parse_bool(value) {
+                Some(enabled) => config.slideshow_random = enabled,
+                None => warn(path, lineno, &format!("invalid boolean '{}'", value)),
+            },
+            "animations" => match parse_bool(value) {
+                Some(enabled) => config.animations = enabled,
+                None => warn(path, lineno, &format!("invalid boolean '{}'", value)),
+            },
+            "sort" => match SortMode::parse(value) {
+                Some(sort) => config.sort = sort,
+                None => warn(path, lineno, &format!("invalid sort order '{}'", value)),
+            },
+            "natural-sort" => match parse_bool(value) {
+                Some(enabled) => config.natural_sort = enabled,
+                None => warn(path, lineno, &format!("invalid boolean '{}'", value)),
+            },
+            "strip" => match parse_bool(value) {
+                Some(enabled) => config.strip = enabled,
+                None => warn(path, lineno, &format!("invalid boolean '{}'", value)),
+            },
+            "size-to-image" => match parse_bool(value) {
+                Some(enabled) => config.size_to_image = enabled,
+                None => warn(path, lineno, &format!("invalid boolean '{}'", value)),
+            },
+            "remember-geometry" => match parse_bool(value) {
+                Some(enabled) => config.remember_geometry = enabled,
+                None => warn(path, lineno, &format!("invalid boolean '{}'", value)),
+            },
+            "orientation" => match OrientationPolicy::parse(value) {
+                Some(policy) => config.orientation = policy,
+                None => warn(path, lineno, &format!("invalid orientation '{}'", value)),
+            },
+            "downscale-oversized" => match parse_bool(value) {
+                Some(enabled) => config.downscale_oversized = enabled,
+                None => warn(path, lineno, &format!("invalid boolean '{}'", value)),
+            },
+            "memory-limit" => match parse_bool(value) {
+                Some(enabled) => config.memory_limit = enabled,
+                None => warn(path, lineno, &format!("invalid boolean '{}'", value)),
+            },
+            "min-frame-duration" => match value.parse::<u64>() {
+                Ok(ms) => config.min_frame_duration = Duration::from_millis(ms),
+                Err(_) => warn(path, lineno, &format!("invalid duration '{}'", value)),
+            },
+            "max-frame-duration" => match value.parse::<u64>() {
+                Ok(ms) if ms > 0 => config.max_frame_duration = Duration::from_millis(ms),
+                _ => warn(path, lineno, &format!("invalid duration '{}'", value)),
+            },
+            "elastic-pan" => match parse_bool(value) {
+                Some(enabled) => config.elastic_pan = enabled,
+                None => warn(path, lineno, &format!("invalid boolean '{}'", value)),
+            },
+            "proof" => match parse_bool(value) {
+                Some(enabled) => config.proof = enabled,
+                None => warn(path, lineno, &format!("invalid boolean '{}'", value)),
+            },
+            "watch" => match parse_bool(value) {
+                Some(enabled) => config.watch = enabled,
+                None => warn(path, lineno, &format!("invalid boolean '{}'", value)),
+            },
+            "gamma" => match parse_bool(value) {
+                Some(enabled) => config.gamma = enabled,
+                None => warn(path, lineno, &format!("invalid boolean '{}'", value)),
+            },
+            "dither" => match parse_bool(value) {
+                Some(enabled) => config.dither = enabled,
+                None => warn(path, lineno, &format!("invalid boolean '{}'", value)),
+            },
+            "gallery-wrap" => match parse_bool(value) {
+                Some(enabled) => config.gallery_wrap = enabled,
+                None => warn(path, lineno, &format!("invalid boolean '{}'", value)),
+            },
+            "gallery-transition" => match parse_bool(value) {
+                Some(enabled) => config.gallery_transition = enabled,
+                None => warn(path, lineno, &format!("invalid boolean '{}'", value)),
+            },
+            "status-position" => match StatusPosition::parse(value) {
+                Some(position) => config.status_position = position,
+                None => warn(
+                    path,
+                    lineno,
+                    &format!("invalid status position '{}'", value),
+                ),
+            },
+            "status-fields" => match StatusField::parse_list(value) {
+                Some(fields) => config.status_fields = fields,
+                None => warn(path, lineno, &format!("invalid status fields '{}'", value)),
+            },
+            "status-mode" => match StatusMode::parse(value) {
+                Some(mode) => config.status_mode = mode,
+                None => warn(path, lineno, &format!("invalid status mode '{}'", value)),
+            },
+            "geometry" => match parse_geometry(value) {
+                Some(size) => config.geometry = Some(size),
+                None => warn(path, lineno, &format!("invalid geometry '{}'", value)),
+            },
+            "grid" => match parse_grid(value) {
+                Some(grid) => config.grid = grid,
+                None => warn(path, lineno, &format!("invalid grid '{}'", value)),
+            },
+            _ => warn(path, lineno, &format!("unknown key '{}'", key)),
+        }
+    }
+
+    config
+}
+
+/// Remove a `#` comment: either the whole line, or a trailing ` # ...`.
+/// A `#` followed directly by text (as in `#1a1a1a`) is kept.
+fn strip_comment(line: &str) -> &str {
+    if line.trim_start().starts_with('#') {
+        return "";
+    }
+    let bytes = line.as_bytes();
+    for (i, &b) in bytes.iter().enumerate() {
+        if b == b'#'
+            && i > 0
+            && bytes[i - 1].is_ascii_whitespace()
+            && bytes.get(i + 1).is_none_or(|c| c.is_ascii_whitespace())
+        {
+            return &line[..i];
+        }
+    }
+    line
+}
+
+fn warn(path: &Path, lineno: usize, msg: &str) {
+    eprintln!("Warning: {}:{}: {}", path.display(), lineno + 1, msg);
+}
+
+pub fn parse_bool(s: &str) -> Option<bool> {
+    match s {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse a `COLSxROWS` grid size such as `3x3` (each 1-32).
+fn parse_grid(s: &str) -> Option<(u32, u32)> {
+    let (cols, rows) = s.split_once(['x', 'X'])?;
+    let cols: u32 = cols.trim().parse().ok()?;
+    let rows: u32 = rows.trim().parse().ok()?;
+    ((1..=32).contains(&cols) && (1..=32).contains(&rows)).then_some((cols, rows))
+}
+
+/// Parse a `WIDTHxHEIGHT` window size such as `1280x720` (each 1-16384).
+pub fn parse_geometry(s: &str) -> Option<(u32, u32)> {
+    let (w, h) = s.split_once(['x', 'X'])?;
+    let w: u32 = w.trim().parse().ok()?;
+    let h: u32 = h.trim().parse().ok()?;
+    ((1..=16384).contains(&w) && (1..=16384).contains(&h)).then_some((w, h))
+}
+
+/// Parse an animation speed multiplier such as `2` or `0.5` (0.125-8).
+pub fn parse_anim_speed(s: &str) -> Option<f64> {
+    let speed: f64 = s.trim().parse().ok()?;
+    (viewer::ANIM_SPEED_MIN..=viewer::ANIM_SPEED_MAX)
+        .contains(&speed)
+        .then_some(speed)
+}
+
+/// Parse a `#rrggbb` (or bare `rrggbb`) color into XRGB8888.
+pub fn parse_hex_color(s: &str) -> Option<u32> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Action;
+    use xkbcommon_dl::keysyms;
+
+    fn parse_str(text: &str) -> Config {
+        parse(text, Path::new("test"))
+    }
+
+    #[test]
+    fn test_resolve_env_wins() {
+        let p = resolve_path(
+            Some("/env/cfg".into()),
+            Some(Path::new("/cli/cfg")),
+            Some("/xdg".into()),
+            Some("/home/u".into()),
+        );
+        assert_eq!(p, Some(PathBuf::from("/env/cfg")));
+    }
+
+    #[test]
+    fn test_resolve_cli_before_xdg() {
+        let p = resolve_path(
+            None,
+            Some(Path::new("/cli/cfg")),
+            Some("/xdg".into()),
+            Some("/home/u".into()),
+        );
+        assert_eq!(p, Some(PathBuf::from("/cli/cfg")));
+    }
+
+    #[test]
+    fn test_resolve_xdg_then_home() {
+        let p = resolve_path(None, None, Some("/xdg".into()), Some("/home/u".into()));
+        assert_eq!(p, Some(PathBuf::from("/xdg/rimg/config")));
+        // Relative XDG_CONFIG_HOME is ignored
+        let p = resolve_path(None, None, Some("rel".into()), Some("/home/u".into()));
+        assert_eq!(p, Some(PathBuf::from("/home/u/.config/rimg/config")));
+        assert_eq!(resolve_path(None, None, None, None), None);
+    }
+
+    #[test]
+    fn test_parse_defaults() {
+        let config = parse_str("");
+        assert_eq!(config.scale_mode, ScaleMode::Fit);
+        assert_eq!(config.background, render::BG_COLOR);
+        assert!(!config.custom_background);
+        assert!(config.gamma);
+        assert!(!config.elastic_pan);
+        assert_eq!(config.min_frame_duration, Duration::from_millis(10));
+        assert_eq!(config.max_frame_duration, Duration::from_secs(60));
+        assert!(!config.remember_geometry);
+        assert!(!config.memory_limit);
+        assert!(!config.gallery_wrap);
+        assert!(!config.gallery_transition);
+        assert_eq!(config.status_mode, StatusMode::Always);
+        assert_eq!(config.slideshow_interval, None);
+        assert!(!config.slideshow_random);
+    }
+
+    #[test]
+    fn test_parse_values() {
+        let config = parse_str(
+            "# comment\n\
+             scale = fit-window\n\
+             background = #102030\n\
+             slideshow = 2.5  # seconds\n\
+             slideshow-random = yes\n\
+             animations = no\n\
+             elastic-pan = on\n\
+             min-frame-duration = 20\n\
+             max-frame-duration = 5000 # ms\n\
+             remember-geometry = yes\n\
+             natural-sort = off\n\
+             orientation = exif\n\
+             downscale-oversized = yes\n\
+             memory-limit = on\n\
+             proof = on\n\
+             watch = true\n\
+             gamma = false\n\
+             dither = true\n\
+             status-position = top\n\
+             status-fields = name, zoom\n\
+             status-mode = auto\n\
+             gallery-wrap = yes\n\
+             gallery-transition = on\n\
+             bind x = next\n",
+        );
+        assert_eq!(config.scale_mode, ScaleMode::FitWindow);
+        assert_eq!(config.background, 0x00102030);
+        assert!(config.custom_background);
+        assert_eq!(config.slideshow_interval, Some(Duration::from_millis(2500)));
+        assert!(config.slideshow_random);
+        assert!(!config.animations);
+        assert!(config.elastic_pan);
+        assert_eq!(config.min_frame_duration, Duration::from_millis(20));
+        assert_eq!(config.max_frame_duration, Duration::from_secs(5));
+        assert!(config.remember_geometry);
+        assert!(!config.natural_sort);
+        assert_eq!(config.orientation, OrientationPolicy::Exif);
+        assert!(config.downscale_oversized);
+        assert!(config.memory_limit);
+        assert!(config.proof);
+        assert!(config.watch);
+        assert!(!config.gamma);
+        assert!(config.dither);
+        assert_eq!(config.status_position, StatusPosition::Top);
+        assert_eq!(
+            config.status_fields,
+            vec![StatusField::Name, StatusField::Zoom]
+        );
+        assert_eq!(config.status_mode, StatusMode::Auto);
+        assert!(config.gallery_wrap);
+        assert!(config.gallery_transition);
+        assert_eq!(config.bindings.get(keysyms::x), Some(&Action::NextImage));
+    }
+
+    #[test]
+    fn test_parse_invalid_values_ignored() {
+        let config = parse_str("scale = huge\nbackground = red\nslideshow = -1\nbogus\n");
+        let durations = parse_str("min-frame-duration = -5\nmax-frame-duration = 0\n");
+        assert_eq!(durations.min_frame_duration, Duration::from_millis(10));
+        assert_eq!(durations.max_frame_duration, Duration::from_secs(60));
+        assert_eq!(config.scale_mode, ScaleMode::Fit);
+        assert_eq!(config.background, render::BG_COLOR);
+        assert_eq!(config.slideshow_interval, None);
+    }
+
+    #[test]
+    fn test_parse_grid() {
+        assert_eq!(parse_grid("3x3"), Some((3, 3)));
+        assert_eq!(parse_grid("4X2"), Some((4, 2)));
+        assert_eq!(parse_grid("0x3"), None);
+        assert_eq!(parse_grid("3"), None);
+        assert_eq!(parse_str("grid = 5x4").grid, (5, 4));
+    }
+
+    #[test]
+    fn test_parse_geometry() {
+        assert_eq!(parse_geometry("1280x720"), Some((1280, 720)));
+        assert_eq!(parse_geometry("640X480"), Some((640, 480)));
+        assert_eq!(parse_geometry("0x480"), None);
+        assert_eq!(parse_geometry("99999x480"), None);
+        assert_eq!(parse_geometry("1280"), None);
+        assert_eq!(parse_str("geometry = 1024x768").geometry, Some((1024, 768)));
+    }
+
+    #[test]
+    fn test_parse_anim_speed() {
+        assert_eq!(parse_anim_speed("2"), Some(2.0));
+        assert_eq!(parse_anim_speed("0.125"), Some(0.125));
+        assert_eq!(parse_anim_speed("16"), None);
+        assert_eq!(parse_anim_speed("0"), None);
+        assert_eq!(parse_anim_speed("NaN"), None);
+        assert_eq!(parse_anim_speed("fast"), None);
+    }
+
+    #[test]
+    fn test_sort_mode_parse() {
+        let size_desc = SortMode {
+            key: SortKey::Size,
+            descending: true,
+        };
+        assert_eq!(SortMode::parse("size:desc"), Some(size_desc));
+        assert_eq!(SortMode::parse("name"), Some(SortMode::default()));
+        assert_eq!(
+            SortMode::parse("mtime:asc").map(|m| m.key),
+            Some(SortKey::ModTime)
+        );
+        let megapixels = SortMode::parse("megapixels:desc").unwrap();
+        assert_eq!(megapixels.key, SortKey::Megapixels);
+        assert!(megapixels.descending);
+        assert_eq!(megapixels.label(), "Megapixels \u{2193}");
+        assert_eq!(SortMode::parse("date:up"), None);
+        assert_eq!(SortMode::parse("color"), None);
+        assert_eq!(parse_str("sort = size:desc").sort, size_desc);
+    }
+
+    #[test]
+    fn test_sort_mode_cycle_keeps_direction() {
+        let mode = SortMode::default().reversed().next();
+        assert_eq!(mode.key, SortKey::Size);
+        assert!(mode.descending);
+        assert_eq!(mode.label(), "Size \u{2193}");
+        assert_eq!(SortMode::default().label(), "Name \u{2191}");
+        // Cycling leaves the command-line order and never comes back to it
+        let mut mode = SortMode::UNSORTED.next();
+        assert_eq!(mode, SortMode::default());
+        for _ in 0..5 {
+            mode = mode.next();
+            assert_ne!(mode.key, SortKey::Unsorted);
+        }
+        assert_eq!(mode, SortMode::default());
+        assert_eq!(SortMode::parse("unsorted"), None);
+        assert_eq!(SortMode::SHUFFLED.next(), SortMode::default());
+        assert!(SortMode::SHUFFLED.is_unsorted() && !SortMode::default().is_unsorted());
+    }
+
+    #[test]
+    fn test_parse_hex_color() {
+        assert_eq!(parse_hex_color("#ff0080"), Some(0x00FF0080));
+        assert_eq!(parse_hex_color("000000"), Some(0));
+        assert_eq!(parse_hex_color("#fff"), None);
+        assert_eq!(parse_hex_color("#gggggg"), None);
+    }
+}
@@ -0,0 +1,153 @@
+use std::path::{Path, PathBuf};
+
+use crate::font;
+use crate::gallery::{GridLayout, PLACEHOLDER_COLOR, THUMB_SIZE};
+use crate::image_loader::{self, RgbaImage, MAX_PIXEL_COUNT};
+use crate::render;
+
+/// Default number of columns for `--contact-sheet` without `--cols`.
+pub const DEFAULT_COLS: usize = 6;
+/// Space below each thumbnail for its file name.
+const CAPTION_H: u32 = font::GLYPH_H + 4;
+/// Caption text color (same as the status bar).
+const CAPTION_COLOR: u32 = 0x00DDDDDD;
+
+/// Lay out thumbnails of `paths` in a grid with file name captions.
+/// Images that fail to load get a placeholder cell.
+pub fn render_contact_sheet(
+    paths: &[PathBuf],
+    cols: usize,
+    bg_color: u32,
+) -> Result<RgbaImage, String> {
+    if paths.is_empty() {
+        return Err("no images for contact sheet".to_string());
+    }
+    let layout = GridLayout::with_cols(cols.min(paths.len()), CAPTION_H);
+    let (w, h) = (layout.width(), layout.height(paths.len()));
+    if w as u64 * h as u64 > MAX_PIXEL_COUNT {
+        return Err(format!(
+            "contact sheet too large: {}x{} ({} images)",
+            w,
+            h,
+            paths.len()
+        ));
+    }
+
+    let mut buf = vec![bg_color; (w * h) as usize];
+    let max_chars = (THUMB_SIZE / font::GLYPH_W) as usize;
+    for (i, path) in paths.iter().enumerate() {
+        let (x, y) = layout.cell_origin(i);
+        match image_loader::load_image_thumbnail(path, THUMB_SIZE) {
+            Ok(thumb) => {
+                render::blit_thumbnail(&mut buf, w, h, &thumb, x, y, THUMB_SIZE, THUMB_SIZE)
+            }
+            Err(e) => {
+                eprintln!("Warning: {}: {}", path.display(), e);
+                render::fill_rect(&mut buf, w, x, y, THUMB_SIZE, THUMB_SIZE, PLACEHOLDER_COLOR);
+            }
+        }
+
+        let name = caption(path, max_chars);
+        let text_w = name.chars().count() as u32 * font::GLYPH_W;
+        let text_x = x + THUMB_SIZE.saturating_sub(text_w) / 2;
+        font::draw_string(
+            &mut buf,
+            w,
+            h,
+            &name,
+            text_x,
+            y + THUMB_SIZE + 2,
+            CAPTION_COLOR,
+        );
+    }
+
+    Ok(render::xrgb_to_rgba(&buf, w, h))
+}
+
+/// Render a contact sheet of `paths` and write it to `out` as PNG.
+pub fn write_contact_sheet(
+    paths: &[PathBuf],
+    out: &Path,
+    cols: usize,
+    bg_color: u32,
+) -> Result<(), String> {
+    let img = render_contact_sheet(paths, cols, bg_color)?;
+    image_loader::save_png(&img, out)
+}
+
+/// File name shortened to `max_chars`, keeping both ends (and so the
+/// extension) visible: `very_long_name.jpg` -> `very_l..me.jpg`.
+fn caption(path: &Path, max_chars: usize) -> String {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let chars: Vec<char> = name.chars().collect();
+    if chars.len() <= max_chars {
+        return name;
+    }
+    let keep = max_chars.saturating_sub(2);
+    let head = keep / 2;
+    let tail = keep - head;
+    let start: String = chars[..head].iter().collect();
+    let end: String = chars[chars.len() - tail..].iter().collect();
+    format!("{}..{}", start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_caption_truncates_middle() {
+        assert_eq!(caption(Path::new("/x/short.jpg"), 25), "short.jpg");
+        let c = caption(Path::new("a_very_long_file_name_here.jpg"), 12);
+        assert_eq!(c, "a_ver..e.jpg");
+        assert_eq!(c.len(), 12);
+    }
+
+    #[test]
+    fn test_contact_sheet_layout_and_placeholders() {
+        let paths: Vec<PathBuf> = (0..5)
+            .map(|i| PathBuf::from(format!("/nonexistent/{}.png", i)))
+            .collect();
+        let sheet = render_contact_sheet(&paths, 3, 0).unwrap();
+        let layout = GridLayout::with_cols(3, CAPTION_H);
+        assert_eq!(sheet.dimensions(), (layout.width(), layout.height(5)));
+
+        // Missing files are drawn as placeholders
+        let (x, y) = layout.cell_origin(4);
+        let off = ((y * sheet.width + x) * 4) as usize;
+        assert_eq!(&sheet.as_raw()[off..off + 3], &[0x33, 0x33, 0x33]);
+    }
+
+    #[test]
+    fn test_contact_sheet_writes_png() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("red.png");
+        let mut img = RgbaImage::new(4, 4);
+        for px in img.data.chunks_exact_mut(4) {
+            px.copy_from_slice(&[255, 0, 0, 255]);
+        }
+        image_loader::save_png(&img, &src).unwrap();
+
+        let out = dir.path().join("sheet.png");
+        write_contact_sheet(&[src], &out, DEFAULT_COLS, 0).unwrap();
+        let sheet = image_loader::load_image(&out).unwrap();
+        let image_loader::LoadedImage::Static(sheet) = sheet else {
+            panic!("expected a static image");
+        };
+        let layout = GridLayout::with_cols(1, CAPTION_H);
+        assert_eq!(sheet.dimensions(), (layout.width(), layout.height(1)));
+        // The image sits centered in its cell
+        let (x, y) = layout.cell_origin(0);
+        let (cx, cy) = (x + THUMB_SIZE / 2, y + THUMB_SIZE / 2);
+        let off = ((cy * sheet.width + cx) * 4) as usize;
+        assert_eq!(&sheet.as_raw()[off..off + 4], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_contact_sheet_empty() {
+        assert!(render_contact_sheet(&[], 3, 0).is_err());
+    }
+}
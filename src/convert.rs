@@ -0,0 +1,162 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::image_loader::{self, RgbaImage};
+
+/// Quality of JPEGs written by `--convert jpg`.
+const JPEG_QUALITY: i32 = 90;
+
+/// Output format of `--convert`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConvertFormat {
+    Png,
+    Jpeg,
+}
+
+impl ConvertFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Some(ConvertFormat::Png),
+            "jpg" | "jpeg" => Some(ConvertFormat::Jpeg),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ConvertFormat::Png => "png",
+            ConvertFormat::Jpeg => "jpg",
+        }
+    }
+}
+
+/// Where the converted copy of `input` goes: next to it with the new
+/// extension, or `<name>-converted.<ext>` if that would be the input itself.
+fn output_path(input: &Path, format: ConvertFormat) -> PathBuf {
+    let out = input.with_extension(format.extension());
+    if out != input {
+        return out;
+    }
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    input.with_file_name(format!("{}-converted.{}", stem, format.extension()))
+}
+
+/// Composite an image over an XRGB `bg_color`, making it opaque.
+fn flatten(img: &mut RgbaImage, bg_color: u32) {
+    let bg = [
+        (bg_color >> 16) as u8 as u32,
+        (bg_color >> 8) as u8 as u32,
+        bg_color as u8 as u32,
+    ];
+    for px in img.data.chunks_exact_mut(4) {
+        let a = px[3] as u32;
+        for c in 0..3 {
+            px[c] = ((px[c] as u32 * a + bg[c] * (255 - a) + 127) / 255) as u8;
+        }
+        px[3] = 255;
+    }
+}
+
+/// Decode `input` (applying its orientation, first frame of animations)
+/// and write it in `format`. Returns the output path. An existing file
+/// at that path is never overwritten.
+pub fn convert_file(input: &Path, format: ConvertFormat, bg_color: u32) -> Result<PathBuf, String> {
+    let loaded = image_loader::load_image(input)?;
+    let data = match format {
+        ConvertFormat::Png => image_loader::encode_png(loaded.first_frame())?,
+        ConvertFormat::Jpeg => {
+            let mut img = loaded.first_frame().clone();
+            flatten(&mut img, bg_color);
+            image_loader::encode_jpeg(&img, JPEG_QUALITY)?
+        }
+    };
+    let out = output_path(input, format);
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&out)
+        .map_err(|e| match e.kind() {
+            ErrorKind::AlreadyExists => format!("{} already exists", out.display()),
+            _ => format!("Failed to write {}: {}", out.display(), e),
+        })?;
+    file.write_all(&data)
+        .map_err(|e| format!("Failed to write {}: {}", out.display(), e))?;
+    Ok(out)
+}
+
+/// Convert every path, reporting each result on stderr. Returns the
+/// number of files that failed.
+pub fn convert_all(paths: &[PathBuf], format: ConvertFormat, bg_color: u32) -> usize {
+    let mut failed = 0;
+    for path in paths {
+        match convert_file(path, format, bg_color) {
+            Ok(out) => eprintln!("{} -> {}", path.display(), out.display()),
+            Err(e) => {
+                eprintln!("Error: {}: {}", path.display(), e);
+                failed += 1;
+            }
+        }
+    }
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_path() {
+        let jpg = Path::new("/p/photo.JPG");
+        assert_eq!(
+            output_path(jpg, ConvertFormat::Png),
+            Path::new("/p/photo.png")
+        );
+        assert_eq!(
+            output_path(Path::new("/p/a.png"), ConvertFormat::Png),
+            Path::new("/p/a-converted.png")
+        );
+        assert_eq!(ConvertFormat::parse("JPEG"), Some(ConvertFormat::Jpeg));
+        assert_eq!(ConvertFormat::parse("gif"), None);
+    }
+
+    #[test]
+    fn test_flatten_over_background() {
+        let mut img = RgbaImage::from_raw(2, 1, vec![255, 0, 0, 255, 255, 0, 0, 0]).unwrap();
+        flatten(&mut img, 0x000000ff);
+        assert_eq!(img.as_raw(), &[255, 0, 0, 255, 0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_convert_png_keeps_pixels_and_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("a.png");
+        let img = RgbaImage::from_raw(2, 1, vec![1, 2, 3, 255, 4, 5, 6, 128]).unwrap();
+        image_loader::save_png(&img, &src).unwrap();
+
+        let paths = vec![src.clone(), dir.path().join("missing.png")];
+        assert_eq!(convert_all(&paths, ConvertFormat::Png, 0), 1);
+
+        let out = dir.path().join("a-converted.png");
+        let image_loader::LoadedImage::Static(copy) = image_loader::load_image(&out).unwrap()
+        else {
+            panic!("expected a static image");
+        };
+        assert_eq!(copy.as_raw(), img.as_raw());
+        assert!(src.exists());
+    }
+
+    #[test]
+    fn test_convert_keeps_existing_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("a.png");
+        let img = RgbaImage::from_raw(1, 1, vec![1, 2, 3, 255]).unwrap();
+        image_loader::save_png(&img, &src).unwrap();
+        let out = dir.path().join("a-converted.png");
+        fs::write(&out, b"keep").unwrap();
+
+        let err = convert_file(&src, ConvertFormat::Png, 0).unwrap_err();
+        assert!(err.contains("already exists"), "{}", err);
+        assert_eq!(fs::read(&out).unwrap(), b"keep");
+    }
+}
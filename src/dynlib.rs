@@ -0,0 +1,174 @@
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+
+const RTLD_NOW: c_int = 2;
+
+extern "C" {
+    fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    fn dlerror() -> *mut c_char;
+}
+
+/// An opened shared library. The optional decoders (SVG, AVIF, HEIC, JPEG XL)
+/// resolve their C functions through this at runtime, so a binary built with
+/// them still starts on a machine lacking one of the libraries.
+/// Never closed: the resolved function pointers live for the whole process.
+pub struct Library {
+    handle: *mut c_void,
+}
+
+// dlopen handles and dlsym lookups are thread-safe.
+unsafe impl Send for Library {}
+unsafe impl Sync for Library {}
+
+impl Library {
+    /// Open the first library in `names` that loads.
+    /// `display` names the library in the error, e.g. "libheif".
+    pub fn open(display: &str, names: &[&str]) -> Result<Library, String> {
+        let mut last_err = String::new();
+        for name in names {
+            let c_name = CString::new(*name).map_err(|e| e.to_string())?;
+            let handle = unsafe { dlopen(c_name.as_ptr(), RTLD_NOW) };
+            if !handle.is_null() {
+                return Ok(Library { handle });
+            }
+            last_err = last_error().unwrap_or_else(|| format!("cannot open {}", name));
+        }
+        Err(format!("{} not available ({})", display, last_err))
+    }
+
+    /// Address of the exported symbol `name`, or `None` if it is missing.
+    pub fn symbol(&self, name: &str) -> Option<*mut c_void> {
+        let c_name = CString::new(name).ok()?;
+        let ptr = unsafe { dlsym(self.handle, c_name.as_ptr()) };
+        (!ptr.is_null()).then_some(ptr)
+    }
+}
+
+fn last_error() -> Option<String> {
+    let msg = unsafe { dlerror() };
+    if msg.is_null() {
+        return None;
+    }
+    Some(
+        unsafe { CStr::from_ptr(msg) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+/// Look up `name` in each of `libs`, failing with `display` in the message.
+pub fn find_symbol(libs: &[Library], display: &str, name: &str) -> Result<*mut c_void, String> {
+    libs.iter()
+        .find_map(|lib| lib.symbol(name))
+        .ok_or_else(|| format!("{} not available (missing symbol {})", display, name))
+}
+
+/// Declare C functions that are resolved from shared libraries on first use.
+///
+/// Generates `load()`, which opens the libraries and resolves every symbol
+/// (returning "<name> not available" on failure), `api()` for raw access to
+/// the function pointers, and an `unsafe fn` wrapper per function so call
+/// sites read like a plain `extern "C"` block. Callers must `load()` before
+/// using the wrappers.
+macro_rules! dynamic_extern {
+    (
+        $display:literal;
+        $(
+            [$($soname:literal),+ $(,)?] {
+                $(
+                    pub fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?;
+                )*
+            }
+        )+
+    ) => {
+        #[allow(non_snake_case, dead_code)]
+        pub struct Api {
+            $($(pub $name: unsafe extern "C" fn($($ty),*) $(-> $ret)?,)*)+
+        }
+
+        static API: std::sync::OnceLock<Result<Api, String>> = std::sync::OnceLock::new();
+
+        /// Resolved function table, loading the libraries on first call.
+        pub fn api() -> Result<&'static Api, String> {
+            API.get_or_init(|| {
+                let libs = [$(
+                    $crate::dynlib::Library::open($display, &[$($soname),+])?,
+                )+];
+                Ok(Api {
+                    $($($name: unsafe {
+                        std::mem::transmute::<
+                            *mut std::os::raw::c_void,
+                            unsafe extern "C" fn($($ty),*) $(-> $ret)?,
+                        >($crate::dynlib::find_symbol(&libs, $display, stringify!($name))?)
+                    },)*)+
+                })
+            })
+            .as_ref()
+            .map_err(|e| e.clone())
+        }
+
+        /// Load the library, or report why it is not available.
+        #[allow(dead_code)]
+        pub fn load() -> Result<(), String> {
+            api().map(|_| ())
+        }
+
+        $($(
+            #[allow(non_snake_case, dead_code, clippy::too_many_arguments)]
+            pub unsafe fn $name($($arg: $ty),*) $(-> $ret)? {
+                match api() {
+                    Ok(api) => (api.$name)($($arg),*),
+                    Err(e) => panic!("{} called before load(): {}", stringify!($name), e),
+                }
+            }
+        )*)+
+    };
+}
+
+pub(crate) use dynamic_extern;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_library() {
+        let err = Library::open(
+            "libnothere",
+            &["libnothere-rimg.so.0", "libnothere-rimg.so"],
+        )
+        .err()
+        .unwrap();
+        assert!(err.starts_with("libnothere not available"), "{}", err);
+    }
+
+    #[test]
+    fn test_missing_symbol() {
+        let libs = [Library::open("libc", &["libc.so.6"]).unwrap()];
+        assert!(find_symbol(&libs, "libc", "malloc").is_ok());
+        let err = find_symbol(&libs, "libc", "rimg_no_such_symbol").unwrap_err();
+        assert!(
+            err.contains("missing symbol rimg_no_such_symbol"),
+            "{}",
+            err
+        );
+    }
+
+    mod missing {
+        super::super::dynamic_extern! {
+            "libmissing";
+            ["libmissing-rimg.so.1"] {
+                pub fn missing_fn(x: i32) -> i32;
+            }
+        }
+    }
+
+    #[test]
+    fn test_dynamic_extern_reports_missing_library() {
+        let err = missing::load().unwrap_err();
+        assert!(err.starts_with("libmissing not available"), "{}", err);
+        // The failure is cached, not retried
+        assert_eq!(missing::load().unwrap_err(), err);
+    }
+}
@@ -200,20 +200,33 @@ pub const FONT_DATA: &[u8] = &[
     0x00, 0x00, 0x76, 0xDC, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 ];
 
+/// U+2191 '↑' (upwards arrow), used in the sort toast.
+const ARROW_UP: [u8; 16] = [
+    0x00, 0x00, 0x18, 0x3C, 0x7E, 0xDB, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00,
+];
+/// U+2193 '↓' (downwards arrow).
+const ARROW_DOWN: [u8; 16] = [
+    0x00, 0x00, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0xDB, 0x7E, 0x3C, 0x18, 0x00, 0x00, 0x00, 0x00,
+];
+
 /// Draw a single character onto an XRGB buffer at (px, py).
 /// `color` is XRGB format (0x00RRGGBB).
 pub fn draw_char(buf: &mut [u32], buf_w: u32, buf_h: u32, ch: char, px: u32, py: u32, color: u32) {
-    let c = ch as u32;
-    if c < 0x20 || c > 0x7E {
-        return; // Not in our font range
-    }
-    let glyph_offset = ((c - 0x20) * GLYPH_H) as usize;
-    if glyph_offset + GLYPH_H as usize > FONT_DATA.len() {
-        return;
-    }
+    let glyph: &[u8] = match ch {
+        '\u{2191}' => &ARROW_UP,
+        '\u{2193}' => &ARROW_DOWN,
+        ' '..='~' => {
+            let glyph_offset = ((ch as u32 - 0x20) * GLYPH_H) as usize;
+            match FONT_DATA.get(glyph_offset..glyph_offset + GLYPH_H as usize) {
+                Some(g) => g,
+                None => return,
+            }
+        }
+        _ => return, // Not in our font range
+    };
 
     for row in 0..GLYPH_H {
-        let byte = FONT_DATA[glyph_offset + row as usize];
+        let byte = glyph[row as usize];
         let dy = py + row;
         if dy >= buf_h {
             break;
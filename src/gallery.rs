@@ -2,20 +2,89 @@ use crate::image_loader;
 use crate::image_loader::RgbaImage;
 use crate::render;
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::thread;
 
 /// Thumbnail size in pixels.
-const THUMB_SIZE: u32 = 200;
+pub(crate) const THUMB_SIZE: u32 = 200;
 /// Gap between thumbnails.
 const GAP: u32 = 10;
 /// Padding from window edges.
 const PADDING: u32 = 10;
+/// Width of the selection frame drawn around the selected thumbnail.
+const BORDER_W: u32 = 2;
 /// Selection corner radius (matches exif info overlay).
 const SELECTION_RADIUS: u32 = 6;
 /// Placeholder color (dark gray).
-const PLACEHOLDER_COLOR: u32 = 0x00333333;
+pub(crate) const PLACEHOLDER_COLOR: u32 = 0x00333333;
+/// Size of the badge drawn in the top-right corner of marked thumbnails.
+const MARK_BADGE_SIZE: u32 = 16;
+/// Inset of the mark badge from the thumbnail corner.
+const MARK_BADGE_INSET: u32 = 6;
+/// Mark badge fill (amber) and its dark outline.
+const MARK_BADGE_COLOR: u32 = 0x00f0b020;
+const MARK_BADGE_OUTLINE: u32 = 0x00202020;
+
+/// Thumbnail grid geometry, shared by the gallery and contact sheets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridLayout {
+    pub cols: usize,
+    /// Left edge of the first column.
+    pub x_offset: u32,
+    /// Extra space below each thumbnail, e.g. for a caption.
+    pub caption_h: u32,
+}
+
+impl GridLayout {
+    /// As many columns as fit in `width`, centered horizontally.
+    pub fn fit_width(width: u32, caption_h: u32) -> Self {
+        let cell = THUMB_SIZE + GAP;
+        let usable = width.saturating_sub(PADDING * 2 + GAP);
+        let cols = ((usable / cell) as usize).max(1);
+        let x_offset = PADDING + (width.saturating_sub(PADDING * 2 + cols as u32 * cell - GAP)) / 2;
+        Self {
+            cols,
+            x_offset,
+            caption_h,
+        }
+    }
+
+    /// Exactly `cols` columns, starting at the left padding.
+    pub fn with_cols(cols: usize, caption_h: u32) -> Self {
+        Self {
+            cols: cols.max(1),
+            x_offset: PADDING,
+            caption_h,
+        }
+    }
+
+    /// Height of one row including the gap below it.
+    pub fn row_height(&self) -> u32 {
+        THUMB_SIZE + self.caption_h + GAP
+    }
+
+    /// Canvas width needed for `cols` columns plus padding.
+    pub fn width(&self) -> u32 {
+        PADDING * 2 + self.cols as u32 * (THUMB_SIZE + GAP) - GAP
+    }
+
+    /// Canvas height needed for `total` cells plus padding.
+    pub fn height(&self, total: usize) -> u32 {
+        let rows = total.div_ceil(self.cols).max(1) as u32;
+        PADDING * 2 + rows * self.row_height() - GAP
+    }
+
+    /// Top-left corner of cell `index`, before any scrolling.
+    pub fn cell_origin(&self, index: usize) -> (u32, u32) {
+        let col = (index % self.cols) as u32;
+        let row = (index / self.cols) as u32;
+        (
+            self.x_offset + col * (THUMB_SIZE + GAP),
+            PADDING + row * self.row_height(),
+        )
+    }
+}
 
 pub struct Gallery {
     /// Selected index in the image list.
@@ -24,6 +93,9 @@ pub struct Gallery {
     scroll_y: u32,
     /// Cached thumbnails.
     thumbnails: HashMap<usize, RgbaImage>,
+    /// Header-only display sizes for placeholders, by path; `None` where
+    /// the header gave none.
+    placeholder_sizes: HashMap<PathBuf, Option<(u32, u32)>>,
     /// Number of columns in the current layout.
     cols: usize,
     /// Sender to dispatch thumbnail generation requests to the worker.
@@ -32,6 +104,17 @@ pub struct Gallery {
     result_rx: mpsc::Receiver<(usize, RgbaImage)>,
     /// Indices sent to worker but not yet received.
     pending: HashSet<usize>,
+    /// Background color behind the grid.
+    bg_color: u32,
+    /// Filter query (`/`); empty shows every image.
+    filter: String,
+    /// Indices of the images matching `filter`, in display order; `None`
+    /// when no filter is set. Navigation works on positions in this list
+    /// while `selected` and the thumbnail cache keep the image indices.
+    view: Option<Vec<usize>>,
+    /// Left on the first thumbnail selects the last one and right on the
+    /// last selects the first (`gallery-wrap`).
+    wrap: bool,
 }
 
 impl Gallery {
@@ -59,81 +142,234 @@ impl Gallery {
             selected: 0,
             scroll_y: 0,
             thumbnails: HashMap::new(),
+            placeholder_sizes: HashMap::new(),
             cols: 1,
             work_tx,
             result_rx,
             pending: HashSet::new(),
+            bg_color: render::BG_COLOR,
+            filter: String::new(),
+            view: None,
+            wrap: false,
         }
     }
 
+    pub fn set_background(&mut self, color: u32) {
+        self.bg_color = color;
+    }
+
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    /// Thumbnail of the selected image and the window rectangle
+    /// `(x, y, w, h)` it was drawn at in a `win_w` wide window, if loaded.
+    pub fn selected_thumbnail(&self, win_w: u32) -> Option<(&RgbaImage, render::WindowRect)> {
+        let thumb = self.thumbnails.get(&self.selected)?;
+        let (tw, th) = thumb.dimensions();
+        let (x, y) = GridLayout::fit_width(win_w, 0).cell_origin(self.position());
+        let x = x + (THUMB_SIZE.saturating_sub(tw)) / 2;
+        let y = (y + (THUMB_SIZE.saturating_sub(th)) / 2) as i32 - self.scroll_y as i32;
+        Some((thumb, (x as i32, y, tw, th)))
+    }
+
     /// Set selected index (when switching from viewer).
     pub fn set_selected(&mut self, index: usize) {
         self.selected = index;
     }
 
-    /// Clear cached thumbnails so they are re-generated from current paths order.
-    pub fn invalidate_thumbnails(&mut self) {
-        self.thumbnails.clear();
+    /// Move cached thumbnails to new indices after the list was re-sorted
+    /// or extended: the image at index `i` is now at `new_index[i]`.
+    pub fn remap_thumbnails(&mut self, new_index: &[usize]) {
+        self.thumbnails = self
+            .thumbnails
+            .drain()
+            .filter_map(|(i, thumb)| Some((*new_index.get(i)?, thumb)))
+            .collect();
+        // Results still in flight carry the old indices
         self.pending.clear();
     }
 
+    /// Drop the thumbnail of one image, e.g. after it changed on disk.
+    pub fn invalidate_thumbnail(&mut self, index: usize) {
+        self.thumbnails.remove(&index);
+        self.pending.remove(&index);
+    }
+
     fn cell_size() -> u32 {
         THUMB_SIZE + GAP
     }
 
-    fn calc_cols(&self, win_w: u32) -> usize {
-        let usable = win_w.saturating_sub(PADDING * 2 + GAP);
-        ((usable / Self::cell_size()) as usize).max(1)
+    /// Show only images whose file name matches `query` (see
+    /// `matches_filter`); an empty query shows all of them. The selection
+    /// moves to the first match if it is filtered out.
+    pub fn set_filter(&mut self, query: &str, paths: &[PathBuf]) {
+        self.filter = query.to_string();
+        self.refilter(paths);
+    }
+
+    /// Apply the filter again, e.g. after the paths were re-sorted.
+    pub fn refilter(&mut self, paths: &[PathBuf]) {
+        self.view = if self.filter.trim().is_empty() {
+            None
+        } else {
+            let matches = (0..paths.len()).filter(|&i| matches_filter(&paths[i], &self.filter));
+            Some(matches.collect())
+        };
+        if let Some(view) = &self.view {
+            if !view.contains(&self.selected) {
+                self.selected = view.first().copied().unwrap_or(self.selected);
+            }
+        }
+        self.scroll_y = 0;
     }
 
-    /// Move selection left.
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    /// Number of images shown out of `total`.
+    pub fn shown(&self, total: usize) -> usize {
+        self.view.as_ref().map_or(total, |v| v.len())
+    }
+
+    /// Image index at display position `pos`.
+    fn index_at(&self, pos: usize) -> usize {
+        self.view.as_ref().map_or(pos, |v| v[pos])
+    }
+
+    /// Display position of the selected image (0 if it is filtered out).
+    fn position(&self) -> usize {
+        match &self.view {
+            Some(view) => view.iter().position(|&i| i == self.selected).unwrap_or(0),
+            None => self.selected,
+        }
+    }
+
+    /// Select the image at display position `pos`.
+    fn select_position(&mut self, pos: usize) {
+        self.selected = self.index_at(pos);
+    }
+
+    /// Move selection left, continuing at the end of the previous row.
     pub fn move_left(&mut self, total: usize) {
-        if total == 0 {
+        let shown = self.shown(total);
+        if shown == 0 {
             return;
         }
-        if self.selected > 0 {
-            self.selected -= 1;
+        let pos = self.position();
+        if pos > 0 {
+            self.select_position(pos - 1);
+        } else if self.wrap {
+            self.select_position(shown - 1);
         }
     }
 
-    /// Move selection right.
+    /// Move selection right, continuing at the start of the next row.
     pub fn move_right(&mut self, total: usize) {
-        if total == 0 {
-            return;
-        }
-        if self.selected + 1 < total {
-            self.selected += 1;
+        let shown = self.shown(total);
+        let pos = self.position();
+        if pos + 1 < shown {
+            self.select_position(pos + 1);
+        } else if self.wrap && shown > 0 {
+            self.select_position(0);
         }
     }
 
     /// Move selection up one row.
-    pub fn move_up(&mut self, _total: usize) {
-        if self.selected >= self.cols {
-            self.selected -= self.cols;
+    pub fn move_up(&mut self, total: usize) {
+        let pos = self.position();
+        if pos >= self.cols && self.shown(total) > 0 {
+            self.select_position(pos - self.cols);
         }
     }
 
-    /// Move selection down one row.
+    /// Move selection down one row. Above a shorter last row, a column
+    /// that row lacks moves to its last thumbnail.
     pub fn move_down(&mut self, total: usize) {
-        if total == 0 {
-            return;
-        }
-        if self.selected + self.cols < total {
-            self.selected += self.cols;
+        let shown = self.shown(total);
+        let pos = self.position();
+        if pos + self.cols < shown {
+            self.select_position(pos + self.cols);
+        } else if pos / self.cols < shown.saturating_sub(1) / self.cols {
+            self.select_position(shown - 1);
         }
     }
 
     /// Jump to first.
-    pub fn go_first(&mut self) {
-        self.selected = 0;
+    pub fn go_first(&mut self, total: usize) {
+        if self.shown(total) > 0 {
+            self.select_position(0);
+        }
         self.scroll_y = 0;
     }
 
     /// Jump to last.
     pub fn go_last(&mut self, total: usize) {
-        if total > 0 {
-            self.selected = total - 1;
+        let shown = self.shown(total);
+        if shown > 0 {
+            self.select_position(shown - 1);
+        }
+    }
+
+    /// Number of whole rows that fit in a window of height `win_h`.
+    fn rows_per_page(win_h: u32) -> usize {
+        (win_h.saturating_sub(PADDING) / Self::cell_size()).max(1) as usize
+    }
+
+    /// Move selection up by a page of rows, staying in the same column,
+    /// and scroll the view by the same amount.
+    pub fn page_up(&mut self, total: usize, win_h: u32) {
+        if self.shown(total) == 0 {
+            return;
+        }
+        let rows = Self::rows_per_page(win_h);
+        let step = rows * self.cols;
+        let pos = self.position();
+        if pos >= step {
+            self.select_position(pos - step);
+        } else {
+            self.select_position(pos % self.cols);
         }
+        self.scroll_y = self
+            .scroll_y
+            .saturating_sub(rows as u32 * Self::cell_size());
+    }
+
+    /// Move selection down by a page of rows and scroll the view by the
+    /// same amount. Near the end it lands on the last row, in the same
+    /// column if that row has one.
+    pub fn page_down(&mut self, total: usize, win_h: u32) {
+        let shown = self.shown(total);
+        if shown == 0 {
+            return;
+        }
+        let rows = Self::rows_per_page(win_h);
+        let step = rows * self.cols;
+        let pos = self.position();
+        if pos + step < shown {
+            self.select_position(pos + step);
+        } else {
+            let last_row_start = (shown - 1) / self.cols * self.cols;
+            let col = pos % self.cols;
+            self.select_position((last_row_start + col).min(shown - 1));
+        }
+        let content_h = GridLayout::with_cols(self.cols, 0).height(shown);
+        let max_scroll = content_h.saturating_sub(win_h);
+        self.scroll_y = (self.scroll_y + rows as u32 * Self::cell_size()).min(max_scroll);
+    }
+
+    /// Size of the placeholder drawn until the thumbnail of `path` arrives:
+    /// the thumbnail size if the image header tells the proportions (probed
+    /// once per path), else the whole cell.
+    fn placeholder_size(&mut self, path: &Path) -> (u32, u32) {
+        let size = *self
+            .placeholder_sizes
+            .entry(path.to_path_buf())
+            .or_insert_with(|| image_loader::probe_display_size(path));
+        size.map_or((THUMB_SIZE, THUMB_SIZE), |(w, h)| {
+            render::fit_size(w, h, THUMB_SIZE, THUMB_SIZE)
+        })
     }
 
     /// Returns true if there are thumbnail requests pending in the worker.
@@ -153,36 +389,46 @@ impl Gallery {
         received
     }
 
-    /// Ensure the selected thumbnail is visible by adjusting scroll.
-    fn ensure_visible(&mut self, win_h: u32) {
-        let row = self.selected / self.cols;
-        let cell = Self::cell_size();
-        let y_top = PADDING + row as u32 * cell;
-        let y_bottom = y_top + cell;
-
-        if y_top < self.scroll_y {
-            self.scroll_y = y_top.saturating_sub(PADDING);
+    /// Ensure the selected thumbnail, including its selection frame, is
+    /// visible by adjusting scroll, and keep the scroll within the grid of
+    /// `total` images. A window too short for a whole thumbnail shows its top.
+    fn ensure_visible(&mut self, total: usize, win_h: u32) {
+        let row = self.position() / self.cols;
+        let y_top = PADDING + row as u32 * Self::cell_size();
+        let y_bottom = y_top + THUMB_SIZE;
+
+        // Leave the padding around the frame, like at the grid edges
+        if y_bottom + BORDER_W > self.scroll_y + win_h {
+            self.scroll_y = (y_bottom + PADDING).saturating_sub(win_h);
         }
-        if y_bottom > self.scroll_y + win_h {
-            self.scroll_y = y_bottom.saturating_sub(win_h) + PADDING;
+        if y_top < self.scroll_y + BORDER_W {
+            self.scroll_y = y_top.saturating_sub(PADDING);
         }
+        let content_h = GridLayout::with_cols(self.cols, 0).height(self.shown(total));
+        self.scroll_y = self.scroll_y.min(content_h.saturating_sub(win_h));
     }
 
-    /// Render the gallery into an XRGB pixel buffer.
-    pub fn render(&mut self, paths: &[PathBuf], win_w: u32, win_h: u32) -> Vec<u32> {
+    /// Render the gallery into an XRGB pixel buffer. Thumbnails of images
+    /// in `marks` get a corner badge.
+    pub fn render(
+        &mut self,
+        paths: &[PathBuf],
+        marks: &HashSet<PathBuf>,
+        win_w: u32,
+        win_h: u32,
+    ) -> Vec<u32> {
         if win_w == 0 || win_h == 0 {
             return vec![];
         }
 
-        self.cols = self.calc_cols(win_w);
-        self.ensure_visible(win_h);
+        let layout = GridLayout::fit_width(win_w, 0);
+        self.cols = layout.cols;
+        self.ensure_visible(paths.len(), win_h);
 
-        let total = paths.len();
+        let total = self.shown(paths.len());
         let cell = Self::cell_size();
-        let grid_x_offset =
-            PADDING + (win_w.saturating_sub(PADDING * 2 + self.cols as u32 * cell - GAP)) / 2;
 
-        let mut buf = vec![render::BG_COLOR; (win_w * win_h) as usize];
+        let mut buf = vec![self.bg_color; (win_w * win_h) as usize];
 
         // Determine visible range
         let first_visible_row = (self.scroll_y / cell) as usize;
@@ -196,7 +442,8 @@ impl Gallery {
 
         // Dispatch missing thumbnails to background worker
         let mut batch = Vec::new();
-        for i in load_start..load_end {
+        for pos in load_start..load_end {
+            let i = self.index_at(pos);
             if !self.thumbnails.contains_key(&i) && !self.pending.contains(&i) {
                 batch.push((i, paths[i].clone()));
                 self.pending.insert(i);
@@ -207,12 +454,10 @@ impl Gallery {
         }
 
         // Draw thumbnails
-        for i in first_visible..last_visible.min(total) {
-            let col = i % self.cols;
-            let row = i / self.cols;
-
-            let x = grid_x_offset + col as u32 * cell;
-            let y = (PADDING + row as u32 * cell) as i32 - self.scroll_y as i32;
+        for pos in first_visible..last_visible.min(total) {
+            let i = self.index_at(pos);
+            let (x, cell_y) = layout.cell_origin(pos);
+            let y = cell_y as i32 - self.scroll_y as i32;
 
             if y + cell as i32 <= 0 || y >= win_h as i32 {
                 continue; // Off screen
@@ -222,10 +467,10 @@ impl Gallery {
 
             // Draw selection background with border (same style as EXIF info overlay)
             if i == self.selected {
-                let bx = x.saturating_sub(2);
-                let by = dy.saturating_sub(2);
-                let bw = THUMB_SIZE + 4;
-                let bh = THUMB_SIZE + 4;
+                let bx = x.saturating_sub(BORDER_W);
+                let by = dy.saturating_sub(BORDER_W);
+                let bw = THUMB_SIZE + 2 * BORDER_W;
+                let bh = THUMB_SIZE + 2 * BORDER_W;
                 // Draw dark overlay first (same as EXIF info overlay)
                 render::draw_overlay_rounded(
                     &mut buf,
@@ -255,23 +500,61 @@ impl Gallery {
                     &mut buf, win_w, win_h, thumb, x, dy, THUMB_SIZE, THUMB_SIZE,
                 );
             } else {
-                // Placeholder
+                // Placeholder, in the shape the thumbnail will have
+                let (w, h) = self.placeholder_size(&paths[i]);
                 render::fill_rect(
                     &mut buf,
                     win_w,
-                    x,
-                    dy,
-                    THUMB_SIZE,
-                    THUMB_SIZE,
+                    x + (THUMB_SIZE - w) / 2,
+                    dy + (THUMB_SIZE - h) / 2,
+                    w,
+                    h,
                     PLACEHOLDER_COLOR,
                 );
             }
+
+            if !marks.is_empty() && marks.contains(&paths[i]) {
+                let size = MARK_BADGE_SIZE;
+                let bx = x + THUMB_SIZE - MARK_BADGE_INSET - size;
+                let by = dy + MARK_BADGE_INSET;
+                render::fill_rect(&mut buf, win_w, bx, by, size, size, MARK_BADGE_OUTLINE);
+                render::fill_rect(
+                    &mut buf,
+                    win_w,
+                    bx + 2,
+                    by + 2,
+                    size - 4,
+                    size - 4,
+                    MARK_BADGE_COLOR,
+                );
+            }
         }
 
         buf
     }
 }
 
+/// Whether the file name of `path` matches every whitespace-separated
+/// token of `query`: `ext:png` (or `ext:jpg,jpeg`) matches the extension,
+/// anything else is a substring of the name. Case is ignored.
+pub fn matches_filter(path: &Path, query: &str) -> bool {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    query.split_whitespace().all(|token| {
+        let token = token.to_lowercase();
+        match token.strip_prefix("ext:") {
+            Some(exts) => exts.split(',').any(|e| e.trim_start_matches('.') == ext),
+            None => name.contains(&token),
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -282,6 +565,29 @@ mod tests {
         g
     }
 
+    #[test]
+    fn test_grid_layout_fit_width() {
+        // 3 cells of 210px plus padding fit in 700px, centered
+        let layout = GridLayout::fit_width(700, 0);
+        assert_eq!(layout.cols, 3);
+        assert_eq!(layout.x_offset, PADDING + (700 - layout.width()) / 2);
+        assert_eq!(GridLayout::fit_width(50, 0).cols, 1);
+    }
+
+    #[test]
+    fn test_grid_layout_with_cols() {
+        let layout = GridLayout::with_cols(2, 20);
+        assert_eq!(layout.width(), PADDING * 2 + 2 * THUMB_SIZE + GAP);
+        assert_eq!(
+            layout.height(3),
+            PADDING * 2 + 2 * (THUMB_SIZE + 20 + GAP) - GAP
+        );
+        assert_eq!(
+            layout.cell_origin(3),
+            (PADDING + THUMB_SIZE + GAP, PADDING + THUMB_SIZE + 20 + GAP)
+        );
+    }
+
     #[test]
     fn test_move_right_basic() {
         let mut g = gallery_with_cols(3);
@@ -330,6 +636,41 @@ mod tests {
         assert_eq!(g.selected, 7); // stays
     }
 
+    #[test]
+    fn test_move_down_into_short_last_row() {
+        let mut g = gallery_with_cols(4);
+        g.selected = 7; // row 1, col 3; the last row holds 8 and 9
+        g.move_down(10);
+        assert_eq!(g.selected, 9);
+        g.move_down(10);
+        assert_eq!(g.selected, 9); // last row: stays
+    }
+
+    #[test]
+    fn test_wrap_at_boundaries() {
+        let mut g = gallery_with_cols(3);
+        g.set_wrap(true);
+        g.selected = 0;
+        g.move_left(10);
+        assert_eq!(g.selected, 9);
+        g.move_right(10);
+        assert_eq!(g.selected, 0);
+        // Rows still flow into each other; vertical moves do not wrap
+        g.selected = 3;
+        g.move_left(10);
+        assert_eq!(g.selected, 2);
+        g.move_up(10);
+        assert_eq!(g.selected, 2);
+        g.selected = 9;
+        g.move_down(10);
+        assert_eq!(g.selected, 9);
+        // Nothing shown: nothing to wrap to
+        g.selected = 0;
+        g.move_left(0);
+        g.move_right(0);
+        assert_eq!(g.selected, 0);
+    }
+
     #[test]
     fn test_move_up_basic() {
         let mut g = gallery_with_cols(3);
@@ -351,7 +692,7 @@ mod tests {
         let mut g = gallery_with_cols(3);
         g.selected = 7;
         g.scroll_y = 100;
-        g.go_first();
+        g.go_first(10);
         assert_eq!(g.selected, 0);
         assert_eq!(g.scroll_y, 0);
     }
@@ -371,6 +712,169 @@ mod tests {
         assert_eq!(g.selected, 0); // unchanged
     }
 
+    #[test]
+    fn test_render_marks_badge() {
+        let paths: Vec<PathBuf> = ["a.png", "b.png"].iter().map(PathBuf::from).collect();
+        let marks: HashSet<PathBuf> = [paths[1].clone()].into_iter().collect();
+        let mut g = Gallery::new();
+        let win_w = 700;
+        let buf = g.render(&paths, &marks, win_w, 400);
+
+        let layout = GridLayout::fit_width(win_w, 0);
+        let badge_at = |pos: usize| {
+            let (x, y) = layout.cell_origin(pos);
+            let bx = x + THUMB_SIZE - MARK_BADGE_INSET - MARK_BADGE_SIZE / 2;
+            let by = y + MARK_BADGE_INSET + MARK_BADGE_SIZE / 2;
+            buf[(by * win_w + bx) as usize]
+        };
+        assert_eq!(badge_at(1), MARK_BADGE_COLOR);
+        assert_ne!(badge_at(0), MARK_BADGE_COLOR);
+    }
+
+    #[test]
+    fn test_matches_filter() {
+        let p = Path::new("/photos/Holiday_2023.JPG");
+        assert!(matches_filter(p, "holiday"));
+        assert!(matches_filter(p, "2023 ext:jpg"));
+        assert!(matches_filter(p, "ext:png,jpg"));
+        assert!(!matches_filter(p, "ext:png"));
+        assert!(!matches_filter(p, "holiday 2024"));
+        // The directory is not part of the match
+        assert!(!matches_filter(p, "photos"));
+        assert!(matches_filter(p, ""));
+    }
+
+    #[test]
+    fn test_filter_navigation() {
+        let paths: Vec<PathBuf> = ["a.png", "b.jpg", "c.png", "d.png", "e.jpg", "f.png"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let mut g = gallery_with_cols(2);
+        g.selected = 1; // b.jpg is filtered out: the first match is selected
+        g.set_filter("ext:png", &paths);
+        assert_eq!(g.shown(paths.len()), 4);
+        assert_eq!(g.selected, 0);
+        g.move_right(paths.len());
+        assert_eq!(g.selected, 2);
+        g.move_down(paths.len());
+        assert_eq!(g.selected, 5);
+        g.move_right(paths.len());
+        assert_eq!(g.selected, 5); // last match
+        g.go_first(paths.len());
+        assert_eq!(g.selected, 0);
+        g.go_last(paths.len());
+        assert_eq!(g.selected, 5);
+
+        // Nothing matches: navigation is a no-op
+        g.set_filter("zzz", &paths);
+        assert_eq!(g.shown(paths.len()), 0);
+        g.move_right(paths.len());
+        g.page_down(paths.len(), 1000);
+        assert_eq!(g.selected, 5);
+
+        // Clearing shows everything again and keeps the selection
+        g.set_filter("", &paths);
+        assert_eq!(g.shown(paths.len()), paths.len());
+        assert_eq!(g.selected, 5);
+    }
+
+    #[test]
+    fn test_rows_per_page() {
+        let cell = Gallery::cell_size();
+        assert_eq!(Gallery::rows_per_page(PADDING + 3 * cell), 3);
+        assert_eq!(Gallery::rows_per_page(PADDING + 3 * cell - 1), 2);
+        // Always at least one row
+        assert_eq!(Gallery::rows_per_page(0), 1);
+    }
+
+    #[test]
+    fn test_page_down_and_up() {
+        let cell = Gallery::cell_size();
+        let win_h = PADDING + 2 * cell; // 2 rows per page
+        let mut g = gallery_with_cols(3);
+        g.selected = 1;
+        g.page_down(20, win_h);
+        assert_eq!(g.selected, 7);
+        assert_eq!(g.scroll_y, 2 * cell);
+        g.page_down(20, win_h);
+        assert_eq!(g.selected, 13);
+        // Past the end: the last row (18, 19) keeps the column if it can
+        g.page_down(20, win_h);
+        assert_eq!(g.selected, 19);
+        g.selected = 17;
+        g.page_down(20, win_h);
+        assert_eq!(g.selected, 19);
+        // Scrolling stops at the end of the grid
+        let max_scroll = GridLayout::with_cols(3, 0).height(20) - win_h;
+        assert_eq!(g.scroll_y, max_scroll);
+
+        g.selected = 13;
+        g.page_up(20, win_h);
+        assert_eq!(g.selected, 7);
+        assert_eq!(g.scroll_y, max_scroll - 2 * cell);
+        g.page_up(20, win_h);
+        assert_eq!(g.selected, 1);
+        g.selected = 4;
+        g.page_up(20, win_h);
+        assert_eq!(g.selected, 1); // top row, same column
+        assert_eq!(g.scroll_y, 0);
+    }
+
+    #[test]
+    fn test_ensure_visible() {
+        let cell = Gallery::cell_size();
+        let win_h = PADDING + 2 * cell; // 2 whole rows
+        let content_h = GridLayout::with_cols(3, 0).height(20); // 7 rows
+        let mut g = gallery_with_cols(3);
+
+        // Visible rows leave the scroll alone
+        g.selected = 4;
+        g.ensure_visible(20, win_h);
+        assert_eq!(g.scroll_y, 0);
+
+        // Scrolling down puts the frame and the padding below it in view
+        g.selected = 6;
+        g.ensure_visible(20, win_h);
+        let frame_bottom = PADDING + 2 * cell + THUMB_SIZE + BORDER_W;
+        assert_eq!(g.scroll_y, frame_bottom + PADDING - BORDER_W - win_h);
+        assert!(frame_bottom <= g.scroll_y + win_h);
+
+        // The last row ends flush with the grid, without overscrolling
+        g.selected = 19;
+        g.ensure_visible(20, win_h);
+        assert_eq!(g.scroll_y, content_h - win_h);
+
+        // Back up to a row whose frame is cut off at the top
+        g.selected = 13;
+        g.scroll_y = PADDING + 4 * cell - 1;
+        g.ensure_visible(20, win_h);
+        assert_eq!(g.scroll_y, 4 * cell);
+        g.selected = 0;
+        g.ensure_visible(20, win_h);
+        assert_eq!(g.scroll_y, 0);
+
+        // A stale scroll past the end, e.g. after the window grew, is clamped
+        g.selected = 19;
+        g.scroll_y = content_h;
+        g.ensure_visible(20, win_h + cell);
+        assert_eq!(g.scroll_y, content_h - win_h - cell);
+        g.ensure_visible(20, content_h + 50);
+        assert_eq!(g.scroll_y, 0);
+
+        // Too short for a whole thumbnail: its top wins
+        g.selected = 3;
+        g.ensure_visible(20, 100);
+        assert_eq!(g.scroll_y, cell);
+    }
+
+    #[test]
+    fn test_page_down_empty() {
+        let mut g = gallery_with_cols(3);
+        g.page_down(0, 500);
+        assert_eq!(g.selected, 0);
+    }
+
     #[test]
     fn test_move_empty() {
         let mut g = gallery_with_cols(3);
@@ -1,16 +1,38 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::ffi::CString;
 use std::fs;
+use std::io::{Read, Seek, SeekFrom};
 use std::os::raw::{c_char, c_int, c_uchar, c_uint, c_void};
 use std::path::{Path, PathBuf};
-use std::time::Duration;
-
-/// Supported image extensions (lowercase).
-const SUPPORTED_EXTENSIONS: &[&str] = &[
-    "jpg", "jpeg", "png", "gif", "webp", "bmp", "tiff", "tif", "svg", "avif", "heic", "heif", "jxl",
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering as AtomicOrdering};
+use std::time::{Duration, Instant};
+
+/// Supported image extensions (lowercase). SVG, AVIF, HEIC/HEIF and JPEG XL
+/// depend on the `svg`, `avif`, `heif` and `jxl` build features.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "jpg",
+    "jpeg",
+    "png",
+    "gif",
+    "webp",
+    "bmp",
+    "tiff",
+    "tif",
+    #[cfg(feature = "svg")]
+    "svg",
+    #[cfg(feature = "avif")]
+    "avif",
+    #[cfg(feature = "heif")]
+    "heic",
+    #[cfg(feature = "heif")]
+    "heif",
+    #[cfg(feature = "jxl")]
+    "jxl",
 ];
 
 /// Maximum pixel count to prevent excessive memory allocation (256 megapixels).
-const MAX_PIXEL_COUNT: u64 = 256 * 1024 * 1024;
+pub(crate) const MAX_PIXEL_COUNT: u64 = 256 * 1024 * 1024;
 
 /// Maximum file size to read into memory (512 MiB).
 const MAX_FILE_SIZE: u64 = 512 * 1024 * 1024;
@@ -62,6 +84,27 @@ impl RgbaImage {
     pub fn as_raw(&self) -> &[u8] {
         &self.data
     }
+
+    /// Copy of the `w`x`h` region starting at (`x`, `y`), or `None` if it
+    /// does not lie entirely inside the image. See `crop_region` for a
+    /// variant that clips instead.
+    pub fn crop(&self, x: u32, y: u32, w: u32, h: u32) -> Option<RgbaImage> {
+        if x.checked_add(w)? > self.width || y.checked_add(h)? > self.height {
+            return None;
+        }
+        let stride = self.width as usize * 4;
+        let row_len = w as usize * 4;
+        let mut data = Vec::with_capacity(row_len * h as usize);
+        for row in y as usize..(y + h) as usize {
+            let start = row * stride + x as usize * 4;
+            data.extend_from_slice(&self.data[start..start + row_len]);
+        }
+        Some(RgbaImage {
+            data,
+            width: w,
+            height: h,
+        })
+    }
 }
 
 /// A loaded image — either static or animated.
@@ -78,6 +121,26 @@ impl LoadedImage {
             LoadedImage::Animated { frames, .. } => &frames[0].0,
         }
     }
+
+    /// Frame count and the duration of one loop of an animation; `None` for
+    /// static images.
+    pub fn animation_length(&self) -> Option<(usize, Duration)> {
+        match self {
+            LoadedImage::Static(_) => None,
+            LoadedImage::Animated { frames } => {
+                Some((frames.len(), frames.iter().map(|(_, d)| *d).sum()))
+            }
+        }
+    }
+
+    /// Frame `index` of an animation (clamped to the last frame); static
+    /// images have only the one.
+    pub fn frame(&self, index: usize) -> &RgbaImage {
+        match self {
+            LoadedImage::Static(img) => img,
+            LoadedImage::Animated { frames } => &frames[index.min(frames.len() - 1)].0,
+        }
+    }
 }
 
 /// Read a file into memory with a size limit to prevent excessive allocation.
@@ -95,13 +158,206 @@ fn read_file_limited(path: &Path) -> Result<Vec<u8>, String> {
     fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
 }
 
-/// Validate image dimensions against maximum pixel count.
+static DOWNSCALE_OVERSIZED: AtomicBool = AtomicBool::new(false);
+
+/// Decode images over `MAX_PIXEL_COUNT` at a reduced resolution where the
+/// format allows it (JPEG), instead of refusing them.
+pub fn set_downscale_oversized(enabled: bool) {
+    DOWNSCALE_OVERSIZED.store(enabled, AtomicOrdering::Relaxed);
+}
+
+fn downscale_oversized() -> bool {
+    DOWNSCALE_OVERSIZED.load(AtomicOrdering::Relaxed)
+}
+
+static MEMORY_LIMIT: AtomicBool = AtomicBool::new(false);
+
+/// Also limit decoded images to what fits in the memory currently
+/// available (`--memory-limit`), on top of `MAX_PIXEL_COUNT`.
+pub fn set_memory_limit(enabled: bool) {
+    MEMORY_LIMIT.store(enabled, AtomicOrdering::Relaxed);
+}
+
+fn memory_limit() -> bool {
+    MEMORY_LIMIT.load(AtomicOrdering::Relaxed)
+}
+
+/// Memory budgeted per decoded pixel with `--memory-limit`: the RGBA
+/// buffer, the decoder's own copy and the scaled copy for display.
+const BYTES_PER_DECODED_PIXEL: u64 = 12;
+
+/// The `MemAvailable` value of `/proc/meminfo` text, in bytes.
+fn parse_mem_available(meminfo: &str) -> Option<u64> {
+    let value = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))?;
+    let kib: u64 = value.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    kib.checked_mul(1024)
+}
+
+/// Pixel limit for `available` bytes of free memory, if known.
+fn limit_for_memory(available: Option<u64>) -> u64 {
+    match available {
+        Some(bytes) => (bytes / BYTES_PER_DECODED_PIXEL).min(MAX_PIXEL_COUNT),
+        None => MAX_PIXEL_COUNT,
+    }
+}
+
+/// Most pixels an image may decode to: `MAX_PIXEL_COUNT`, lowered with
+/// `--memory-limit` to what fits in the memory available right now.
+fn pixel_limit() -> u64 {
+    let available = memory_limit()
+        .then(|| fs::read_to_string("/proc/meminfo").ok())
+        .flatten()
+        .and_then(|meminfo| parse_mem_available(&meminfo));
+    limit_for_memory(available)
+}
+
+/// How `limit` reads in errors: "max N pixels", noting when it comes from
+/// the available memory.
+fn describe_limit(limit: u64) -> String {
+    if limit < MAX_PIXEL_COUNT {
+        format!("max {} pixels for the available memory", limit)
+    } else {
+        format!("max {} pixels", limit)
+    }
+}
+
+static PNG_GAMMA: AtomicBool = AtomicBool::new(true);
+
+/// Gamma-correct PNGs that carry a `gAMA` chunk. Off shows the stored
+/// sample values unchanged (`--no-gamma`).
+pub fn set_png_gamma(enabled: bool) {
+    PNG_GAMMA.store(enabled, AtomicOrdering::Relaxed);
+}
+
+fn png_gamma() -> bool {
+    PNG_GAMMA.load(AtomicOrdering::Relaxed)
+}
+
+static PNG_BACKGROUND: AtomicBool = AtomicBool::new(true);
+
+/// Composite transparent PNGs over their `bKGD` color. Turned off when the
+/// user picked a background, which then shows through instead.
+pub fn set_png_background(enabled: bool) {
+    PNG_BACKGROUND.store(enabled, AtomicOrdering::Relaxed);
+}
+
+fn png_background() -> bool {
+    PNG_BACKGROUND.load(AtomicOrdering::Relaxed)
+}
+
+/// Shortest animation frame duration by default; shorter nonzero delays
+/// are raised to it.
+pub const DEFAULT_MIN_FRAME_DURATION: Duration = Duration::from_millis(10);
+/// Longest animation frame duration by default, so an absurd delay does
+/// not freeze playback.
+pub const DEFAULT_MAX_FRAME_DURATION: Duration = Duration::from_secs(60);
+/// Duration of frames with a zero delay, meant to run as fast as
+/// possible: one frame at 60 Hz.
+const ZERO_DELAY_FRAME_MS: u64 = 16;
+
+static MIN_FRAME_MS: AtomicU64 = AtomicU64::new(DEFAULT_MIN_FRAME_DURATION.as_millis() as u64);
+static MAX_FRAME_MS: AtomicU64 = AtomicU64::new(DEFAULT_MAX_FRAME_DURATION.as_millis() as u64);
+
+/// Bounds for animation frame durations (`min-frame-duration` and
+/// `max-frame-duration`). A maximum below the minimum is raised to it.
+pub fn set_frame_duration_limits(min: Duration, max: Duration) {
+    let min = min.as_millis() as u64;
+    MIN_FRAME_MS.store(min, AtomicOrdering::Relaxed);
+    MAX_FRAME_MS.store((max.as_millis() as u64).max(min), AtomicOrdering::Relaxed);
+}
+
+/// Shortest animation frame duration (`min-frame-duration`).
+pub fn min_frame_duration() -> Duration {
+    Duration::from_millis(MIN_FRAME_MS.load(AtomicOrdering::Relaxed))
+}
+
+/// Display duration of an animation frame whose file says `ms`
+/// milliseconds, within the configured limits.
+fn frame_duration(ms: u64) -> Duration {
+    clamp_frame_duration(
+        ms,
+        MIN_FRAME_MS.load(AtomicOrdering::Relaxed),
+        MAX_FRAME_MS.load(AtomicOrdering::Relaxed),
+    )
+}
+
+/// A zero delay becomes `ZERO_DELAY_FRAME_MS`; the result is then kept
+/// within `min..=max` milliseconds.
+fn clamp_frame_duration(ms: u64, min: u64, max: u64) -> Duration {
+    let ms = if ms == 0 { ZERO_DELAY_FRAME_MS } else { ms };
+    Duration::from_millis(ms.max(min).min(max.max(min)))
+}
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Print per-image decode times to stderr (`--verbose`).
+pub fn set_verbose(enabled: bool) {
+    VERBOSE.store(enabled, AtomicOrdering::Relaxed);
+}
+
+pub fn verbose() -> bool {
+    VERBOSE.load(AtomicOrdering::Relaxed)
+}
+
+/// Default upper bound on decoder worker threads.
+#[cfg(any(feature = "avif", feature = "jxl"))]
+const MAX_DECODE_THREADS: usize = 8;
+
+/// Worker threads for the AVIF and JPEG XL decoders: `$RIMG_DECODE_THREADS`
+/// if set, otherwise the available parallelism up to `MAX_DECODE_THREADS`.
+/// Read once.
+#[cfg(any(feature = "avif", feature = "jxl"))]
+fn decode_threads() -> usize {
+    static THREADS: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+    *THREADS.get_or_init(|| {
+        let threads = match std::env::var("RIMG_DECODE_THREADS") {
+            Ok(v) if !v.trim().is_empty() => parse_decode_threads(&v)
+                .inspect_err(|e| eprintln!("Warning: {}", e))
+                .ok(),
+            _ => None,
+        };
+        threads.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map_or(1, |n| n.get())
+                .min(MAX_DECODE_THREADS)
+        })
+    })
+}
+
+/// Parse a `RIMG_DECODE_THREADS` value: a positive thread count.
+#[cfg(any(feature = "avif", feature = "jxl"))]
+fn parse_decode_threads(value: &str) -> Result<usize, String> {
+    match value.trim().parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "RIMG_DECODE_THREADS must be a positive number, got '{}'",
+            value
+        )),
+    }
+}
+
+/// Status note for an image decoded below its full size.
+fn downscale_note(w: u32, h: u32, scaled_w: u32, scaled_h: u32) -> String {
+    format!(
+        "Downscaled to fit limit: {}x{} -> {}x{}",
+        w, h, scaled_w, scaled_h
+    )
+}
+
+/// Validate image dimensions against the pixel limit.
 fn validate_dimensions(width: u32, height: u32, format: &str) -> Result<(), String> {
     let pixels = width as u64 * height as u64;
-    if pixels > MAX_PIXEL_COUNT {
+    let limit = pixel_limit();
+    if pixels > limit {
         return Err(format!(
-            "{} image too large: {}x{} ({} pixels, max {})",
-            format, width, height, pixels, MAX_PIXEL_COUNT
+            "{} image too large: {}x{} ({} pixels, {})",
+            format,
+            width,
+            height,
+            pixels,
+            describe_limit(limit)
         ));
     }
     if width == 0 || height == 0 {
@@ -113,21 +369,354 @@ fn validate_dimensions(width: u32, height: u32, format: &str) -> Result<(), Stri
     Ok(())
 }
 
-/// Collect image paths from CLI arguments.
-pub fn collect_paths(args: &[String]) -> Vec<PathBuf> {
-    let mut paths = Vec::new();
+/// Collect image paths from CLI arguments, sorted by file name
+/// (naturally, unless `natural_sort` is false).
+pub fn collect_paths(args: &[String], natural_sort: bool, sort: bool) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
     for arg in args {
         let p = PathBuf::from(arg);
+        // Expand patterns the shell left alone (e.g. from a launcher), but
+        // never reinterpret an argument that names a real file
+        let expanded = if !p.exists() && arg.starts_with("file://") {
+            match path_from_file_uri(arg) {
+                Some(p) => vec![p],
+                None => {
+                    eprintln!("Warning: not a local file URI: '{}'", arg);
+                    continue;
+                }
+            }
+        } else if !p.exists() && has_glob_meta(arg) {
+            let matches = expand_glob(arg);
+            if matches.is_empty() {
+                eprintln!("Warning: no files match '{}'", arg);
+            }
+            matches
+        } else {
+            vec![p]
+        };
+        candidates.extend(expanded);
+    }
+    image_paths(candidates, natural_sort, sort)
+}
+
+/// The supported images among `candidates`, with directories scanned
+/// recursively, sorted by file name unless `sort` is false (then in the
+/// given order, directories in the order they are read).
+pub fn image_paths(candidates: Vec<PathBuf>, natural_sort: bool, sort: bool) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    for p in candidates {
         if p.is_dir() {
             scan_directory(&p, &mut paths, 0);
         } else if is_supported_image(&p) {
             paths.push(p);
+        } else if let Some(err) = p
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(|e| not_compiled_in(&ascii_lower(e)))
+        {
+            eprintln!("Warning: {}: {}", p.display(), err);
         }
     }
-    paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    if sort {
+        paths.sort_by(|a, b| compare_file_names(a, b, natural_sort));
+    }
     paths
 }
 
+// ============================================================
+// file:// URIs (from file managers and "open with")
+// ============================================================
+
+/// Convert a `file:///path` or `file://localhost/path` URI to a path,
+/// percent-decoding it byte-wise (so non-UTF-8 names survive). Returns
+/// `None` for other hosts or a missing path.
+pub fn path_from_file_uri(uri: &str) -> Option<PathBuf> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    let rest = uri.strip_prefix("file://")?;
+    let slash = rest.find('/')?;
+    let host = &rest[..slash];
+    if !host.is_empty() && !host.eq_ignore_ascii_case("localhost") {
+        return None;
+    }
+    // A query or fragment is not part of the path
+    let path = rest[slash..].split(['?', '#']).next().unwrap_or("");
+
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = |b: u8| (b as char).to_digit(16);
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                out.push((hi * 16 + lo) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    Some(PathBuf::from(OsString::from_vec(out)))
+}
+
+/// Local paths in a `text/uri-list` (as sent by drag and drop): one URI per
+/// line, `#` lines are comments. Non-file URIs are skipped.
+pub fn parse_uri_list(text: &str) -> Vec<PathBuf> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(path_from_file_uri)
+        .collect()
+}
+
+// ============================================================
+// Glob expansion for unexpanded CLI patterns
+// ============================================================
+
+fn has_glob_meta(s: &str) -> bool {
+    s.contains(['*', '?', '[', '{'])
+}
+
+/// Expand a shell-style pattern (`*`, `?`, `[a-z]`, `[!x]`, `{a,b}`) against
+/// the filesystem, relative to the current directory unless absolute.
+/// Wildcards do not match a leading `.`, as in the shell.
+fn expand_glob(pattern: &str) -> Vec<PathBuf> {
+    let mut out = Vec::new();
+    for pat in expand_braces(pattern) {
+        let (mut current, rest) = match pat.strip_prefix('/') {
+            Some(rest) => (vec![PathBuf::from("/")], rest.to_string()),
+            None => (vec![PathBuf::new()], pat),
+        };
+        for component in rest.split('/').filter(|c| !c.is_empty()) {
+            let mut next = Vec::new();
+            for base in &current {
+                if !has_glob_meta(component) {
+                    let p = base.join(component);
+                    if p.exists() {
+                        next.push(p);
+                    }
+                    continue;
+                }
+                let dir = if base.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    base.as_path()
+                };
+                let Ok(entries) = fs::read_dir(dir) else {
+                    continue;
+                };
+                let mut names: Vec<_> = entries
+                    .flatten()
+                    .map(|e| e.file_name())
+                    .filter(|n| glob_match(component.as_bytes(), n.as_encoded_bytes()))
+                    .collect();
+                names.sort();
+                next.extend(names.into_iter().map(|n| base.join(n)));
+            }
+            current = next;
+        }
+        out.extend(current.into_iter().filter(|p| !p.as_os_str().is_empty()));
+    }
+    out.dedup();
+    out
+}
+
+/// Expand the first `{a,b,...}` group (recursively, so nested and multiple
+/// groups work). A group without a comma is kept literally.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let bytes = pattern.as_bytes();
+    let Some(open) = bytes.iter().position(|&b| b == b'{') else {
+        return vec![pattern.to_string()];
+    };
+    // Find the matching close brace and the top-level commas
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut close = None;
+    for (i, &b) in bytes.iter().enumerate().skip(open) {
+        match b {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(i);
+                    break;
+                }
+            }
+            b',' if depth == 1 => commas.push(i),
+            _ => {}
+        }
+    }
+    let Some(close) = close else {
+        return vec![pattern.to_string()];
+    };
+    let (prefix, suffix) = (&pattern[..open], &pattern[close + 1..]);
+    if commas.is_empty() {
+        // Literal braces: keep them and expand any later group
+        return expand_braces(suffix)
+            .into_iter()
+            .map(|s| format!("{}{}{}", prefix, &pattern[open..=close], s))
+            .collect();
+    }
+    let mut bounds = vec![open];
+    bounds.extend(&commas);
+    bounds.push(close);
+    bounds
+        .windows(2)
+        .flat_map(|w| expand_braces(&format!("{}{}{}", prefix, &pattern[w[0] + 1..w[1]], suffix)))
+        .collect()
+}
+
+/// Match a single path component against a glob pattern.
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    // Hidden files only match a pattern that starts with a literal dot
+    if name.first() == Some(&b'.') && pattern.first() != Some(&b'.') {
+        return false;
+    }
+    glob_match_from(pattern, name)
+}
+
+fn glob_match_from(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Backtracking point for the last `*`: (pattern index after it, name index)
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p + 1, n));
+                p += 1;
+                continue;
+            }
+            Some(b'?') => Some(p + 1),
+            Some(b'[') => match match_class(&pattern[p..], name[n]) {
+                Some((true, len)) => Some(p + len),
+                Some((false, _)) => None,
+                // Unterminated class: treat '[' literally
+                None => (name[n] == b'[').then_some(p + 1),
+            },
+            Some(&c) => (c == name[n]).then_some(p + 1),
+            None => None,
+        };
+        match (step, star) {
+            (Some(next), _) => {
+                p = next;
+                n += 1;
+            }
+            (None, Some((sp, sn))) => {
+                // Let the last `*` swallow one more byte and retry
+                p = sp;
+                n = sn + 1;
+                star = Some((sp, sn + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Match `c` against the bracket expression at the start of `class`.
+/// Returns (matched, length of the expression), or None if unterminated.
+fn match_class(class: &[u8], c: u8) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = matches!(class.get(i), Some(b'!') | Some(b'^'));
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let lo = *class.get(i)?;
+        if lo == b']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+        if class.get(i + 1) == Some(&b'-') && class.get(i + 2).is_some_and(|&b| b != b']') {
+            let hi = class[i + 2];
+            matched |= lo <= c && c <= hi;
+            i += 3;
+        } else {
+            matched |= lo == c;
+            i += 1;
+        }
+    }
+}
+
+/// Compare two paths by file name, naturally or by raw bytes.
+pub fn compare_file_names(a: &Path, b: &Path, natural: bool) -> Ordering {
+    if !natural {
+        return a.file_name().cmp(&b.file_name());
+    }
+    fn name(p: &Path) -> &[u8] {
+        p.file_name()
+            .map(|n| n.as_encoded_bytes())
+            .unwrap_or_default()
+    }
+    natural_cmp(name(a), name(b))
+}
+
+/// Natural ordering: digit runs compare by numeric value (`img2` < `img10`),
+/// everything else compares ASCII case-insensitively. Ties (`a` vs `A`,
+/// `7` vs `007`) are broken by the first byte difference so the order is total.
+pub fn natural_cmp(a: &[u8], b: &[u8]) -> Ordering {
+    let (mut i, mut j) = (0, 0);
+    let mut tie = Ordering::Equal;
+    while i < a.len() && j < b.len() {
+        if a[i].is_ascii_digit() && b[j].is_ascii_digit() {
+            let (start_i, start_j) = (i, j);
+            while i < a.len() && a[i].is_ascii_digit() {
+                i += 1;
+            }
+            while j < b.len() && b[j].is_ascii_digit() {
+                j += 1;
+            }
+            let (run_a, run_b) = (&a[start_i..i], &b[start_j..j]);
+            // Compare by value without parsing, so long runs cannot overflow
+            let trim = |r: &[u8]| -> usize { r.iter().take_while(|&&d| d == b'0').count() };
+            let (num_a, num_b) = (&run_a[trim(run_a)..], &run_b[trim(run_b)..]);
+            let ord = num_a.len().cmp(&num_b.len()).then_with(|| num_a.cmp(num_b));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            if tie == Ordering::Equal {
+                tie = run_a.len().cmp(&run_b.len());
+            }
+        } else {
+            let (ca, cb) = (a[i].to_ascii_lowercase(), b[j].to_ascii_lowercase());
+            if ca != cb {
+                return ca.cmp(&cb);
+            }
+            if tie == Ordering::Equal {
+                tie = a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+    }
+    (a.len() - i).cmp(&(b.len() - j)).then(tie)
+}
+
+/// The supported images in the directory of `path`, including `path`
+/// itself, unsorted. Subdirectories are not scanned. Each result is the
+/// parent of `path` joined with a file name, so it compares equal to
+/// `path` however that was spelled.
+pub fn sibling_images(path: &Path) -> Vec<PathBuf> {
+    let parent = path.parent().unwrap_or(Path::new(""));
+    let dir = if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| parent.join(entry.file_name()))
+        .filter(|p| !p.is_symlink() && p.is_file() && is_supported_image(p))
+        .collect()
+}
+
 fn scan_directory(dir: &Path, out: &mut Vec<PathBuf>, depth: u32) {
     if depth >= MAX_DIR_DEPTH {
         return;
@@ -163,44 +752,356 @@ fn is_supported_image(path: &Path) -> bool {
 
 /// Load an image from disk.
 pub fn load_image(path: &Path) -> Result<LoadedImage, String> {
+    load_image_with_note(path).map(|(img, _)| img)
+}
+
+/// Like `load_image`, also returning a note when the image was decoded at a
+/// reduced resolution to stay under the pixel limit.
+pub fn load_image_with_note(path: &Path) -> Result<(LoadedImage, Option<String>), String> {
     let ext = ascii_lower(path.extension().and_then(|e| e.to_str()).unwrap_or(""));
 
-    match ext.as_str() {
-        "jpg" | "jpeg" => load_jpeg(path),
+    let mut note = None;
+    let start = Instant::now();
+    let result = match ext.as_str() {
+        "jpg" | "jpeg" => load_jpeg(path, &mut note),
         "png" => load_png(path),
         "webp" => load_webp(path),
         "gif" => load_gif(path),
         "bmp" => load_bmp(path),
         "tiff" | "tif" => load_tiff(path),
-        "svg" => load_svg(path),
+        #[cfg(feature = "svg")]
+        "svg" => load_svg(path, &mut note),
+        #[cfg(feature = "avif")]
         "avif" => load_avif(path),
+        #[cfg(feature = "heif")]
         "heic" | "heif" => load_heic(path),
+        #[cfg(feature = "jxl")]
         "jxl" => load_jxl(path),
-        _ => Err(format!("Unsupported format: {}", ext)),
+        _ => Err(not_compiled_in(&ext).unwrap_or_else(|| format!("Unsupported format: {}", ext))),
+    };
+    if verbose() {
+        let ms = start.elapsed().as_millis();
+        match &result {
+            Ok(img) => {
+                let (w, h) = img.first_frame().dimensions();
+                eprintln!("{}: decoded {}x{} in {} ms", path.display(), w, h, ms);
+            }
+            Err(_) => eprintln!("{}: failed after {} ms", path.display(), ms),
+        }
     }
+    Ok((result?, note))
+}
+
+/// Known formats whose decoder was left out of the build, as
+/// (extension, format name, build feature).
+const DISABLED_FORMATS: &[(&str, &str, &str)] = &[
+    #[cfg(not(feature = "svg"))]
+    ("svg", "SVG", "svg"),
+    #[cfg(not(feature = "avif"))]
+    ("avif", "AVIF", "avif"),
+    #[cfg(not(feature = "heif"))]
+    ("heic", "HEIC/HEIF", "heif"),
+    #[cfg(not(feature = "heif"))]
+    ("heif", "HEIC/HEIF", "heif"),
+    #[cfg(not(feature = "jxl"))]
+    ("jxl", "JPEG XL", "jxl"),
+];
+
+/// Error for a (lowercase) extension whose decoder is not compiled in.
+fn not_compiled_in(ext: &str) -> Option<String> {
+    DISABLED_FORMATS
+        .iter()
+        .find(|(e, _, _)| *e == ext)
+        .map(|(_, name, feature)| {
+            format!(
+                "{} support not compiled in (rebuild with the '{}' feature)",
+                name, feature
+            )
+        })
 }
 
 // ============================================================
 // JPEG via system libturbojpeg
 // ============================================================
 
-fn load_jpeg(path: &Path) -> Result<LoadedImage, String> {
+fn load_jpeg(path: &Path, note: &mut Option<String>) -> Result<LoadedImage, String> {
     let data = read_file_limited(path)?;
 
-    let image = turbojpeg::decompress(&data, turbojpeg::PixelFormat::RGBA)
+    let mut decompressor = turbojpeg::Decompressor::new()
+        .map_err(|e| format!("Failed to create decompressor: {}", e))?;
+    let header = decompressor
+        .read_header(&data)
         .map_err(|e| format!("Failed to decode JPEG {}: {}", path.display(), e))?;
 
-    validate_dimensions(image.width as u32, image.height as u32, "JPEG")?;
+    let (w, h) = (header.width as u32, header.height as u32);
+    let factor = match jpeg_limit_scale(header.width, header.height) {
+        Some(factor) if factor != turbojpeg::ScalingFactor::ONE => {
+            if !downscale_oversized() {
+                return Err(format!(
+                    "JPEG image too large: {}x{} ({}, try --downscale-oversized)",
+                    w,
+                    h,
+                    describe_limit(pixel_limit())
+                ));
+            }
+            let scaled = header.scaled(factor);
+            *note = Some(downscale_note(
+                w,
+                h,
+                scaled.width as u32,
+                scaled.height as u32,
+            ));
+            factor
+        }
+        // Fits as is, or too large even at 1/8 (rejected when decoding)
+        _ => turbojpeg::ScalingFactor::ONE,
+    };
 
-    let mut img = RgbaImage::from_raw(image.width as u32, image.height as u32, image.pixels)
-        .ok_or_else(|| "JPEG pixel buffer size mismatch".to_string())?;
+    let img = decompress_jpeg_scaled(&mut decompressor, &data, &header, factor)
+        .map_err(|e| format!("Failed to decode JPEG {}: {}", path.display(), e))?;
 
-    // Apply EXIF orientation
-    if let Some(orientation) = read_exif_orientation(&data) {
-        img = apply_orientation(img, orientation);
+    Ok(LoadedImage::Static(orient_jpeg(img, &data)))
+}
+
+/// Apply the EXIF orientation of JPEG `data` to its decoded pixels. Both the
+/// full and the thumbnail decoder go through here, so a gallery thumbnail
+/// always stands the same way up as the image in the viewer.
+fn orient_jpeg(img: RgbaImage, data: &[u8]) -> RgbaImage {
+    match read_exif_orientation(data).filter(|_| exif_orientation_enabled()) {
+        Some(orientation) => apply_orientation(img, orientation),
+        None => img,
     }
+}
 
-    Ok(LoadedImage::Static(img))
+/// Quality used when a rotated JPEG cannot be transformed losslessly.
+const JPEG_REENCODE_QUALITY: i32 = 92;
+
+/// DCT scaling factors libjpeg-turbo supports on every version, largest first.
+const JPEG_SCALING_FACTORS: [turbojpeg::ScalingFactor; 4] = [
+    turbojpeg::ScalingFactor::ONE,
+    turbojpeg::ScalingFactor::ONE_HALF,
+    turbojpeg::ScalingFactor::ONE_QUARTER,
+    turbojpeg::ScalingFactor::ONE_EIGHTH,
+];
+
+/// Largest DCT scaling factor that brings a `width`x`height` JPEG under
+/// the pixel limit, or `None` if even the smallest one does not.
+fn jpeg_limit_scale(width: usize, height: usize) -> Option<turbojpeg::ScalingFactor> {
+    let limit = pixel_limit();
+    JPEG_SCALING_FACTORS
+        .into_iter()
+        .find(|sf| sf.scale(width) as u64 * sf.scale(height) as u64 <= limit)
+}
+
+/// Decode a JPEG whose header was already read, scaled by `factor`.
+fn decompress_jpeg_scaled(
+    decompressor: &mut turbojpeg::Decompressor,
+    data: &[u8],
+    header: &turbojpeg::DecompressHeader,
+    factor: turbojpeg::ScalingFactor,
+) -> Result<RgbaImage, String> {
+    if factor != turbojpeg::ScalingFactor::ONE {
+        decompressor
+            .set_scaling_factor(factor)
+            .map_err(|e| format!("Failed to set scaling factor: {}", e))?;
+    }
+
+    let scaled_header = header.scaled(factor);
+    let w = scaled_header.width;
+    let h = scaled_header.height;
+    validate_dimensions(w as u32, h as u32, "JPEG")?;
+    let pitch = w * 4;
+
+    let mut image = turbojpeg::Image {
+        pixels: vec![0u8; h * pitch],
+        width: w,
+        pitch,
+        height: h,
+        format: turbojpeg::PixelFormat::RGBA,
+    };
+    decompressor
+        .decompress(data, image.as_deref_mut())
+        .map_err(|e| e.to_string())?;
+
+    RgbaImage::from_raw(w as u32, h as u32, image.pixels)
+        .ok_or_else(|| "JPEG pixel buffer size mismatch".to_string())
+}
+
+/// Encode an RGBA image as a 4:2:0 JPEG at `quality` (1-100). Alpha is
+/// dropped, so transparent images should be flattened first.
+pub fn encode_jpeg(img: &RgbaImage, quality: i32) -> Result<Vec<u8>, String> {
+    if img.width == 0 || img.height == 0 {
+        return Err("Cannot encode an empty image".to_string());
+    }
+    let mut compressor =
+        turbojpeg::Compressor::new().map_err(|e| format!("Failed to create compressor: {}", e))?;
+    compressor
+        .set_quality(quality)
+        .and_then(|()| compressor.set_subsamp(turbojpeg::Subsamp::Sub2x2))
+        .map_err(|e| format!("Failed to configure compressor: {}", e))?;
+    let image = turbojpeg::Image {
+        pixels: img.as_raw(),
+        width: img.width as usize,
+        pitch: img.width as usize * 4,
+        height: img.height as usize,
+        format: turbojpeg::PixelFormat::RGBX,
+    };
+    compressor
+        .compress_to_vec(image)
+        .map_err(|e| format!("JPEG encode error: {}", e))
+}
+
+/// Rotate a JPEG by `turns` quarter turns clockwise in the DCT domain,
+/// without decoding it. Fails when a partial MCU block on the edge would
+/// have to move; the caller then has to re-encode. EXIF and other markers
+/// are kept, with the orientation tag reset to normal.
+pub fn transform_jpeg_lossless(data: &[u8], turns: u32) -> Result<Vec<u8>, String> {
+    let op = match turns % 4 {
+        0 => turbojpeg::TransformOp::None,
+        1 => turbojpeg::TransformOp::Rot90,
+        2 => turbojpeg::TransformOp::Rot180,
+        _ => turbojpeg::TransformOp::Rot270,
+    };
+    let mut transform = turbojpeg::Transform::op(op);
+    transform.perfect = true;
+    let mut out = turbojpeg::Transformer::new()
+        .and_then(|mut t| t.transform_to_vec(&transform, data))
+        .map_err(|e| format!("Lossless JPEG transform failed: {}", e))?;
+    set_jpeg_orientation_normal(&mut out);
+    Ok(out)
+}
+
+/// Quarter turns clockwise applied by an EXIF orientation that is a pure
+/// rotation (1, 3, 6 or 8); `None` for the mirrored ones.
+fn orientation_turns(orientation: u32) -> Option<u32> {
+    match orientation {
+        1 => Some(0),
+        6 => Some(1),
+        3 => Some(2),
+        8 => Some(3),
+        _ => None,
+    }
+}
+
+/// Total quarter turns to apply to the stored pixels of a JPEG so that it
+/// shows as displayed after `turns` manual rotations, counting the EXIF
+/// orientation when it was applied on load. `None` if it mirrors.
+fn jpeg_save_turns(data: &[u8], turns: u32) -> Option<u32> {
+    let stored = match read_exif_orientation(data).filter(|_| exif_orientation_enabled()) {
+        Some(orientation) => orientation_turns(orientation)?,
+        None => 0,
+    };
+    Some((stored + turns) % 4)
+}
+
+/// Encode a JPEG file rotated by `turns` manual quarter turns. Uses a
+/// lossless transform when possible and otherwise re-encodes `displayed`
+/// (the decoded image as shown). Returns the data and whether it was lossless.
+pub fn rotate_jpeg(
+    data: &[u8],
+    turns: u32,
+    displayed: &RgbaImage,
+) -> Result<(Vec<u8>, bool), String> {
+    if let Some(total) = jpeg_save_turns(data, turns) {
+        match transform_jpeg_lossless(data, total) {
+            Ok(out) => return Ok((out, true)),
+            Err(e) if verbose() => eprintln!("{}; re-encoding", e),
+            Err(_) => {}
+        }
+    }
+    Ok((encode_jpeg(displayed, JPEG_REENCODE_QUALITY)?, false))
+}
+
+// ============================================================
+// Bit depth reduction (16-bit PNG, 10/12-bit AVIF and HEIC)
+// ============================================================
+
+static DITHER: AtomicBool = AtomicBool::new(false);
+
+/// Reduce images deeper than 8 bits with Floyd–Steinberg error diffusion
+/// (`--dither`). Slower than the default ordered dither, and AVIF and HEIC
+/// are only decoded at full depth for it.
+pub fn set_dither(enabled: bool) {
+    DITHER.store(enabled, AtomicOrdering::Relaxed);
+}
+
+fn dither() -> bool {
+    DITHER.load(AtomicOrdering::Relaxed)
+}
+
+/// 4x4 Bayer matrix for ordered dithering, thresholds in sixteenths.
+const BAYER_4X4: [[u32; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Reduces rows of RGBA samples in `0..=max` to 8 bits, top to bottom, so
+/// decoders can feed it a row at a time. The color channels are dithered,
+/// which breaks up the banding plain rounding leaves in smooth gradients;
+/// alpha is rounded. The samples are already gamma-encoded, so the dither
+/// steps are perceptually even.
+struct DepthReducer {
+    max: u32,
+    y: usize,
+    /// Floyd–Steinberg error (RGB per pixel) for the current and the next
+    /// row; `None` uses the ordered dither.
+    diffusion: Option<(Vec<f32>, Vec<f32>)>,
+}
+
+impl DepthReducer {
+    fn new(width: usize, max: u32) -> Self {
+        Self::with_diffusion(width, max, dither())
+    }
+
+    fn with_diffusion(width: usize, max: u32, diffuse: bool) -> Self {
+        let diffusion = diffuse.then(|| (vec![0.0; width * 3], vec![0.0; width * 3]));
+        Self {
+            max: max.max(1),
+            y: 0,
+            diffusion,
+        }
+    }
+
+    /// Reduce the next row of `row.len() / 4` pixels into `out`.
+    fn reduce_row(&mut self, row: &[u16], out: &mut [u8]) {
+        let max = self.max;
+        let pixels = row.chunks_exact(4).zip(out.chunks_exact_mut(4));
+        match &mut self.diffusion {
+            None => {
+                let thresholds = &BAYER_4X4[self.y % 4];
+                for (x, (px, dst)) in pixels.enumerate() {
+                    // (2t + 1) / 32 of one 8-bit step, in source units
+                    let offset = (2 * thresholds[x % 4] + 1) * max;
+                    for c in 0..3 {
+                        dst[c] = ((px[c] as u32 * 255 * 32 + offset) / (max * 32)) as u8;
+                    }
+                    dst[3] = ((px[3] as u32 * 255 + max / 2) / max) as u8;
+                }
+            }
+            Some((errors, next)) => {
+                let scale = 255.0 / max as f32;
+                let width = errors.len() / 3;
+                for (x, (px, dst)) in pixels.enumerate() {
+                    for c in 0..3 {
+                        let i = x * 3 + c;
+                        let value = px[c] as f32 * scale + errors[i];
+                        let q = value.round().clamp(0.0, 255.0);
+                        let e = value - q;
+                        dst[c] = q as u8;
+                        if x + 1 < width {
+                            errors[i + 3] += e * 7.0 / 16.0;
+                            next[i + 3] += e / 16.0;
+                        }
+                        if x > 0 {
+                            next[i - 3] += e * 3.0 / 16.0;
+                        }
+                        next[i] += e * 5.0 / 16.0;
+                    }
+                    dst[3] = ((px[3] as u32 * 255 + max / 2) / max) as u8;
+                }
+                std::mem::swap(errors, next);
+                next.fill(0.0);
+            }
+        }
+        self.y += 1;
+    }
 }
 
 // ============================================================
@@ -216,13 +1117,28 @@ mod libpng {
     pub type png_infop = *mut c_void;
     pub type png_bytep = *mut c_uchar;
     pub type png_bytepp = *mut png_bytep;
+
+    #[repr(C)]
+    pub struct png_color_16 {
+        pub index: c_uchar,
+        pub red: u16,
+        pub green: u16,
+        pub blue: u16,
+        pub gray: u16,
+    }
     // jmp_buf is 200 bytes on x86_64 Linux
     pub type jmp_buf = [u8; 200];
 
     pub const PNG_COLOR_TYPE_PALETTE: c_uchar = 3;
     pub const PNG_COLOR_TYPE_GRAY: c_uchar = 0;
     pub const PNG_COLOR_TYPE_GRAY_ALPHA: c_uchar = 4;
-    pub const PNG_COLOR_TYPE_RGB: c_uchar = 2;
+    pub const PNG_COLOR_TYPE_RGB_ALPHA: c_int = 6;
+    pub const PNG_COLOR_MASK_ALPHA: c_uchar = 4;
+    pub const PNG_INTERLACE_NONE: c_int = 0;
+    pub const PNG_INFO_TRNS: c_uint = 0x0010;
+    pub const PNG_BACKGROUND_GAMMA_FILE: c_int = 2;
+    pub const PNG_COMPRESSION_TYPE_DEFAULT: c_int = 0;
+    pub const PNG_FILTER_TYPE_DEFAULT: c_int = 0;
 
     extern "C" {
         pub fn setjmp(buf: *mut jmp_buf) -> c_int;
@@ -259,11 +1175,30 @@ mod libpng {
         pub fn png_set_expand(png_ptr: png_structp);
         pub fn png_set_gray_to_rgb(png_ptr: png_structp);
         pub fn png_set_add_alpha(png_ptr: png_structp, filler: c_uint, flags: c_int);
-        pub fn png_set_strip_16(png_ptr: png_structp);
         pub fn png_set_palette_to_rgb(png_ptr: png_structp);
         pub fn png_set_tRNS_to_alpha(png_ptr: png_structp);
+        pub fn png_get_valid(png_ptr: png_structp, info_ptr: png_infop, flag: c_uint) -> c_uint;
+        pub fn png_get_gAMA(
+            png_ptr: png_structp,
+            info_ptr: png_infop,
+            file_gamma: *mut f64,
+        ) -> c_uint;
+        pub fn png_set_gamma(png_ptr: png_structp, screen_gamma: f64, override_file_gamma: f64);
+        pub fn png_get_bKGD(
+            png_ptr: png_structp,
+            info_ptr: png_infop,
+            background: *mut *mut png_color_16,
+        ) -> c_uint;
+        pub fn png_set_background(
+            png_ptr: png_structp,
+            background_color: *const png_color_16,
+            background_gamma_code: c_int,
+            need_expand: c_int,
+            background_gamma: f64,
+        );
         pub fn png_read_update_info(png_ptr: png_structp, info_ptr: png_infop);
         pub fn png_read_image(png_ptr: png_structp, row_pointers: png_bytepp);
+        pub fn png_read_row(png_ptr: png_structp, row: png_bytep, display_row: png_bytep);
         pub fn png_read_end(png_ptr: png_structp, info_ptr: png_infop);
         pub fn png_set_longjmp_fn(
             png_ptr: png_structp,
@@ -276,6 +1211,34 @@ mod libpng {
             read_fn: unsafe extern "C" fn(png_structp, png_bytep, usize),
         );
         pub fn png_get_io_ptr(png_ptr: png_structp) -> *mut c_void;
+
+        pub fn png_create_write_struct(
+            ver: *const c_char,
+            error_ptr: *mut c_void,
+            error_fn: Option<unsafe extern "C" fn(png_structp, *const c_char)>,
+            warn_fn: Option<unsafe extern "C" fn(png_structp, *const c_char)>,
+        ) -> png_structp;
+        pub fn png_destroy_write_struct(png_ptr: *mut png_structp, info_ptr: *mut png_infop);
+        pub fn png_set_write_fn(
+            png_ptr: png_structp,
+            io_ptr: *mut c_void,
+            write_fn: unsafe extern "C" fn(png_structp, png_bytep, usize),
+            flush_fn: Option<unsafe extern "C" fn(png_structp)>,
+        );
+        pub fn png_set_IHDR(
+            png_ptr: png_structp,
+            info_ptr: png_infop,
+            width: c_uint,
+            height: c_uint,
+            bit_depth: c_int,
+            color_type: c_int,
+            interlace: c_int,
+            compression: c_int,
+            filter: c_int,
+        );
+        pub fn png_write_info(png_ptr: png_structp, info_ptr: png_infop);
+        pub fn png_write_image(png_ptr: png_structp, row_pointers: png_bytepp);
+        pub fn png_write_end(png_ptr: png_structp, info_ptr: png_infop);
     }
 }
 
@@ -298,6 +1261,9 @@ unsafe extern "C" fn png_read_callback(
     state.offset += to_read;
 }
 
+/// Display gamma that `gAMA` chunks are corrected for (sRGB-like).
+const PNG_SCREEN_GAMMA: f64 = 2.2;
+
 fn load_png(path: &Path) -> Result<LoadedImage, String> {
     let data = read_file_limited(path)?;
 
@@ -364,6 +1330,7 @@ fn load_png(path: &Path) -> Result<LoadedImage, String> {
         let mut height: c_uint = 0;
         let mut bit_depth: c_int = 0;
         let mut color_type: c_int = 0;
+        let mut interlace: c_int = 0;
         libpng::png_get_IHDR(
             png_ptr,
             info_ptr,
@@ -371,13 +1338,13 @@ fn load_png(path: &Path) -> Result<LoadedImage, String> {
             &mut height,
             &mut bit_depth,
             &mut color_type,
-            std::ptr::null_mut(),
+            &mut interlace,
             std::ptr::null_mut(),
             std::ptr::null_mut(),
         );
 
         // Validate dimensions before allocating buffers
-        if width == 0 || height == 0 || (width as u64) * (height as u64) > MAX_PIXEL_COUNT {
+        if width == 0 || height == 0 || (width as u64) * (height as u64) > pixel_limit() {
             let mut pp = png_ptr;
             let mut ip = info_ptr;
             libpng::png_destroy_read_struct(&mut pp, &mut ip, std::ptr::null_mut());
@@ -389,40 +1356,111 @@ fn load_png(path: &Path) -> Result<LoadedImage, String> {
             ));
         }
 
-        // Set transforms to get RGBA output
+        // Set transforms to get RGBA output, in the order the libpng manual
+        // lists them: expand to 8 bits and tRNS to alpha, gamma and
+        // background, then gray to RGB and the alpha filler
         let ct = color_type as c_uchar;
+        let has_trns = libpng::png_get_valid(png_ptr, info_ptr, libpng::PNG_INFO_TRNS) != 0;
         if ct == libpng::PNG_COLOR_TYPE_PALETTE {
             libpng::png_set_palette_to_rgb(png_ptr);
         }
         if ct == libpng::PNG_COLOR_TYPE_GRAY && bit_depth < 8 {
             libpng::png_set_expand(png_ptr);
         }
+        if has_trns {
+            libpng::png_set_tRNS_to_alpha(png_ptr);
+        }
+        // Gamma-correct images with a gAMA chunk for an sRGB-like display
+        let mut file_gamma: f64 = 0.0;
+        if png_gamma() && libpng::png_get_gAMA(png_ptr, info_ptr, &mut file_gamma) != 0 {
+            libpng::png_set_gamma(png_ptr, PNG_SCREEN_GAMMA, file_gamma);
+        }
+        // Composite transparency over the bKGD color; this drops the alpha
+        // channel, which the filler below puts back as opaque
+        let has_alpha = ct & libpng::PNG_COLOR_MASK_ALPHA != 0 || has_trns;
+        let mut bkgd: *mut libpng::png_color_16 = std::ptr::null_mut();
+        let composite = has_alpha
+            && png_background()
+            && libpng::png_get_bKGD(png_ptr, info_ptr, &mut bkgd) != 0
+            && !bkgd.is_null();
+        if composite && ct == libpng::PNG_COLOR_TYPE_GRAY && bit_depth < 8 {
+            // libpng leaves a low-depth gray bKGD unscaled when expanding,
+            // so pass it already at 8 bits
+            let max = (1u16 << bit_depth) - 1;
+            let gray = (*bkgd).gray.min(max) * (255 / max);
+            let expanded = libpng::png_color_16 {
+                index: 0,
+                red: gray,
+                green: gray,
+                blue: gray,
+                gray,
+            };
+            libpng::png_set_background(
+                png_ptr,
+                &expanded,
+                libpng::PNG_BACKGROUND_GAMMA_FILE,
+                0, // already in the output bit depth
+                1.0,
+            );
+        } else if composite {
+            libpng::png_set_background(
+                png_ptr,
+                bkgd,
+                libpng::PNG_BACKGROUND_GAMMA_FILE,
+                1, // bKGD is in the file's color type and bit depth
+                1.0,
+            );
+        }
         if ct == libpng::PNG_COLOR_TYPE_GRAY || ct == libpng::PNG_COLOR_TYPE_GRAY_ALPHA {
             libpng::png_set_gray_to_rgb(png_ptr);
         }
-        // Expand tRNS chunk to alpha
-        libpng::png_set_tRNS_to_alpha(png_ptr);
-        // Add alpha channel if missing
-        if ct == libpng::PNG_COLOR_TYPE_RGB
-            || ct == libpng::PNG_COLOR_TYPE_GRAY
-            || ct == libpng::PNG_COLOR_TYPE_PALETTE
-        {
-            libpng::png_set_add_alpha(png_ptr, 0xFF, 1); // filler after RGB
-        }
-        if bit_depth == 16 {
-            libpng::png_set_strip_16(png_ptr);
+        // Add an opaque alpha channel where the output has none
+        if composite || !has_alpha {
+            // Opaque filler after RGB; libpng uses the low byte at 8 bits
+            libpng::png_set_add_alpha(png_ptr, 0xFFFF, 1);
         }
 
         libpng::png_read_update_info(png_ptr, info_ptr);
 
-        // Allocate row pointers
         let stride = (width * 4) as usize;
         let mut rgba_data = vec![0u8; stride * height as usize];
-        let mut row_ptrs: Vec<*mut c_uchar> = (0..height as usize)
-            .map(|row| rgba_data.as_mut_ptr().add(row * stride))
-            .collect();
-
-        libpng::png_read_image(png_ptr, row_ptrs.as_mut_ptr());
+        let mut reducer = DepthReducer::new(width as usize, u16::MAX as u32);
+        let mut samples: Vec<u16> = Vec::with_capacity(stride);
+        let mut reduce_row = |row: &[u8], out: &mut [u8]| {
+            samples.clear();
+            samples.extend(
+                row.chunks_exact(2)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]])),
+            );
+            reducer.reduce_row(&samples, out);
+        };
+        if bit_depth != 16 {
+            let mut row_ptrs: Vec<*mut c_uchar> = (0..height as usize)
+                .map(|row| rgba_data.as_mut_ptr().add(row * stride))
+                .collect();
+            libpng::png_read_image(png_ptr, row_ptrs.as_mut_ptr());
+        } else if interlace == libpng::PNG_INTERLACE_NONE {
+            // 16-bit samples are reduced to 8 bits here rather than with
+            // png_set_strip_16, which drops the low byte. Without
+            // interlacing one 16-bit row at a time is enough.
+            let mut row = vec![0u8; stride * 2];
+            for out in rgba_data.chunks_exact_mut(stride) {
+                libpng::png_read_row(png_ptr, row.as_mut_ptr(), std::ptr::null_mut());
+                reduce_row(&row, out);
+            }
+        } else {
+            let mut rgba16 = vec![0u8; stride * 2 * height as usize];
+            let mut row_ptrs: Vec<*mut c_uchar> = (0..height as usize)
+                .map(|row| rgba16.as_mut_ptr().add(row * stride * 2))
+                .collect();
+            libpng::png_read_image(png_ptr, row_ptrs.as_mut_ptr());
+            let rows = rgba16
+                .chunks_exact(stride * 2)
+                .zip(rgba_data.chunks_exact_mut(stride));
+            for (row, out) in rows {
+                reduce_row(row, out);
+            }
+        }
         libpng::png_read_end(png_ptr, info_ptr);
 
         let mut pp = png_ptr;
@@ -433,7 +1471,9 @@ fn load_png(path: &Path) -> Result<LoadedImage, String> {
             .ok_or_else(|| "PNG pixel buffer size mismatch".to_string())?;
 
         // Apply EXIF orientation from PNG eXIf chunk
-        if let Some(orientation) = read_exif_orientation_png(&data) {
+        if let Some(orientation) =
+            read_exif_orientation_png(&data).filter(|_| exif_orientation_enabled())
+        {
             img = apply_orientation(img, orientation);
         }
 
@@ -441,12 +1481,118 @@ fn load_png(path: &Path) -> Result<LoadedImage, String> {
     }
 }
 
+// ============================================================
+// PNG encoding via system libpng16
+// ============================================================
+
+unsafe extern "C" fn png_write_callback(
+    png_ptr: libpng::png_structp,
+    data: libpng::png_bytep,
+    length: usize,
+) {
+    let out = &mut *(libpng::png_get_io_ptr(png_ptr) as *mut Vec<u8>);
+    out.extend_from_slice(std::slice::from_raw_parts(data, length));
+}
+
+/// Encode an RGBA image as an 8-bit RGBA PNG in memory.
+pub fn encode_png(img: &RgbaImage) -> Result<Vec<u8>, String> {
+    if img.width == 0 || img.height == 0 {
+        return Err("Cannot encode an empty image".to_string());
+    }
+    if img.data.len() != (img.width as usize) * (img.height as usize) * 4 {
+        return Err("PNG encode: pixel buffer size mismatch".to_string());
+    }
+
+    unsafe {
+        let ver = c"1.6.0".as_ptr();
+        let png_ptr = libpng::png_create_write_struct(ver, std::ptr::null_mut(), None, None);
+        if png_ptr.is_null() {
+            return Err("png_create_write_struct failed".to_string());
+        }
+
+        let info_ptr = libpng::png_create_info_struct(png_ptr);
+        if info_ptr.is_null() {
+            let mut pp = png_ptr;
+            libpng::png_destroy_write_struct(&mut pp, std::ptr::null_mut());
+            return Err("png_create_info_struct failed".to_string());
+        }
+
+        // Same setjmp-based error handling as load_png; the output and
+        // row pointer Vecs are leaked if libpng longjmps mid-write.
+        let jmpbuf = libpng::png_set_longjmp_fn(
+            png_ptr,
+            libpng::longjmp,
+            std::mem::size_of::<libpng::jmp_buf>(),
+        );
+        if jmpbuf.is_null() {
+            let mut pp = png_ptr;
+            let mut ip = info_ptr;
+            libpng::png_destroy_write_struct(&mut pp, &mut ip);
+            return Err("png_set_longjmp_fn failed".to_string());
+        }
+
+        if libpng::setjmp(jmpbuf) != 0 {
+            let mut pp = png_ptr;
+            let mut ip = info_ptr;
+            libpng::png_destroy_write_struct(&mut pp, &mut ip);
+            return Err("PNG encode error".to_string());
+        }
+
+        let mut out: Vec<u8> = Vec::new();
+        libpng::png_set_write_fn(
+            png_ptr,
+            &mut out as *mut Vec<u8> as *mut c_void,
+            png_write_callback,
+            None,
+        );
+
+        libpng::png_set_IHDR(
+            png_ptr,
+            info_ptr,
+            img.width,
+            img.height,
+            8,
+            libpng::PNG_COLOR_TYPE_RGB_ALPHA,
+            libpng::PNG_INTERLACE_NONE,
+            libpng::PNG_COMPRESSION_TYPE_DEFAULT,
+            libpng::PNG_FILTER_TYPE_DEFAULT,
+        );
+        libpng::png_write_info(png_ptr, info_ptr);
+
+        let stride = (img.width * 4) as usize;
+        // libpng takes non-const row pointers but does not modify them when writing
+        let mut row_ptrs: Vec<*mut c_uchar> = (0..img.height as usize)
+            .map(|row| img.data.as_ptr().add(row * stride) as *mut c_uchar)
+            .collect();
+
+        libpng::png_write_image(png_ptr, row_ptrs.as_mut_ptr());
+        libpng::png_write_end(png_ptr, info_ptr);
+
+        let mut pp = png_ptr;
+        let mut ip = info_ptr;
+        libpng::png_destroy_write_struct(&mut pp, &mut ip);
+
+        Ok(out)
+    }
+}
+
+/// Encode an RGBA image as PNG and write it to `path`.
+pub fn save_png(img: &RgbaImage, path: &Path) -> Result<(), String> {
+    let data = encode_png(img)?;
+    fs::write(path, data).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
 // ============================================================
 // WebP via system libwebp
 // ============================================================
 
+/// Output colorspace for both WebP decode paths: straight (non-premultiplied)
+/// RGBA, as everything downstream expects. `MODE_rgbA` would be premultiplied.
+const WEBP_COLOR_MODE: libwebp_sys::WEBP_CSP_MODE = libwebp_sys::MODE_RGBA;
+
 fn load_webp(path: &Path) -> Result<LoadedImage, String> {
     let data = read_file_limited(path)?;
+    validate_webp_riff(&data).map_err(|e| format!("Invalid WebP {}: {}", path.display(), e))?;
 
     // Check if the WebP is animated using WebPGetFeatures
     let mut features: libwebp_sys::WebPBitstreamFeatures = unsafe { std::mem::zeroed() };
@@ -459,21 +1605,9 @@ fn load_webp(path: &Path) -> Result<LoadedImage, String> {
         return load_webp_animated(&data, path);
     }
 
-    // Static WebP: decode with WebPDecodeRGBA
-    let mut width: std::ffi::c_int = 0;
-    let mut height: std::ffi::c_int = 0;
-
-    let ptr =
-        unsafe { libwebp_sys::WebPDecodeRGBA(data.as_ptr(), data.len(), &mut width, &mut height) };
-
-    if ptr.is_null() {
-        return Err(format!("Failed to decode WebP {}", path.display()));
-    }
-
+    // Static WebP: decode straight RGBA directly into the image buffer
+    let (width, height) = (features.width, features.height);
     if width <= 0 || height <= 0 {
-        unsafe {
-            libwebp_sys::WebPFree(ptr as *mut std::ffi::c_void);
-        }
         return Err(format!(
             "Invalid WebP dimensions: {}x{} in {}",
             width,
@@ -481,33 +1615,137 @@ fn load_webp(path: &Path) -> Result<LoadedImage, String> {
             path.display()
         ));
     }
-
-    let w = width as u32;
-    let h = height as u32;
-    validate_dimensions(w, h, "WebP").map_err(|e| {
-        unsafe {
-            libwebp_sys::WebPFree(ptr as *mut std::ffi::c_void);
-        }
-        e
-    })?;
-
-    let len = w as u64 * h as u64 * 4;
-    let rgba_data = unsafe { std::slice::from_raw_parts(ptr, len as usize).to_vec() };
-    unsafe {
-        libwebp_sys::WebPFree(ptr as *mut std::ffi::c_void);
+    let (w, h) = (width as u32, height as u32);
+    validate_dimensions(w, h, "WebP")?;
+
+    let mut img = RgbaImage::new(w, h);
+    let status = unsafe {
+        let mut config: libwebp_sys::WebPDecoderConfig = std::mem::zeroed();
+        if libwebp_sys::WebPInitDecoderConfig(&mut config) == 0 {
+            return Err("WebPInitDecoderConfig failed".to_string());
+        }
+        config.output.colorspace = WEBP_COLOR_MODE;
+        config.output.is_external_memory = 1;
+        config.output.u.RGBA = libwebp_sys::WebPRGBABuffer {
+            rgba: img.data.as_mut_ptr(),
+            stride: width * 4,
+            size: img.data.len(),
+        };
+        let status = libwebp_sys::WebPDecode(data.as_ptr(), data.len(), &mut config);
+        libwebp_sys::WebPFreeDecBuffer(&mut config.output);
+        status
+    };
+    if status == libwebp_sys::VP8_STATUS_NOT_ENOUGH_DATA {
+        // Truncated file (e.g. a partial download): keep whatever rows the
+        // incremental decoder gets out of it
+        let rows = decode_webp_partial(&data, &mut img)
+            .ok_or_else(|| format!("Failed to decode truncated WebP {}", path.display()))?;
+        eprintln!(
+            "Warning: {}: truncated WebP, decoded {} of {} rows",
+            path.display(),
+            rows,
+            h
+        );
+    } else if status != libwebp_sys::VP8_STATUS_OK {
+        return Err(format!("Failed to decode WebP {}", path.display()));
     }
 
-    let mut img = RgbaImage::from_raw(w, h, rgba_data)
-        .ok_or_else(|| "WebP pixel buffer size mismatch".to_string())?;
-
     // Apply EXIF orientation from WebP EXIF chunk
-    if let Some(orientation) = read_exif_orientation_webp(&data) {
+    if let Some(orientation) =
+        read_exif_orientation_webp(&data).filter(|_| exif_orientation_enabled())
+    {
         img = apply_orientation(img, orientation);
     }
 
     Ok(LoadedImage::Static(img))
 }
 
+/// Feed a truncated static WebP to the incremental decoder, writing into
+/// `img`. Rows the data does not reach are left transparent. Returns the
+/// number of decoded rows, or None when not even one row could be decoded.
+fn decode_webp_partial(data: &[u8], img: &mut RgbaImage) -> Option<u32> {
+    let (w, h) = img.dimensions();
+    let mut last_y: std::ffi::c_int = 0;
+    unsafe {
+        let idec = libwebp_sys::WebPINewRGB(
+            WEBP_COLOR_MODE,
+            img.data.as_mut_ptr(),
+            img.data.len(),
+            (w * 4) as std::ffi::c_int,
+        );
+        if idec.is_null() {
+            return None;
+        }
+        let status = libwebp_sys::WebPIAppend(idec, data.as_ptr(), data.len());
+        let ok = matches!(
+            status,
+            libwebp_sys::VP8_STATUS_OK | libwebp_sys::VP8_STATUS_SUSPENDED
+        ) && !libwebp_sys::WebPIDecGetRGB(
+            idec,
+            &mut last_y,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+        .is_null();
+        libwebp_sys::WebPIDelete(idec);
+        if !ok {
+            return None;
+        }
+    }
+    let rows = (last_y.max(0) as u32).min(h);
+    if rows == 0 {
+        return None;
+    }
+    // The decoder may have started on the next rows; clear them so the
+    // missing part is uniformly transparent
+    let start = rows as usize * w as usize * 4;
+    img.data[start..].fill(0);
+    Some(rows)
+}
+
+/// End of the RIFF payload: the declared RIFF size, clamped to the buffer so a
+/// truncated file still yields its leading chunks.
+fn webp_riff_end(data: &[u8]) -> usize {
+    let riff_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    riff_size.saturating_add(8).min(data.len())
+}
+
+/// Cheap structural check of the RIFF container before libwebp sees the data.
+/// A file shorter than its declared RIFF size is accepted: that is a truncated
+/// download, which the static decoder renders partially.
+fn validate_webp_riff(data: &[u8]) -> Result<(), String> {
+    if data.len() < 20 {
+        return Err("truncated RIFF header".to_string());
+    }
+    if &data[0..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return Err("missing RIFF/WEBP signature".to_string());
+    }
+    let riff_size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as u64;
+    // "WEBP" plus at least one chunk header
+    if riff_size < 12 {
+        return Err(format!("RIFF size {} is too small", riff_size));
+    }
+    let fourcc = &data[12..16];
+    if fourcc != b"VP8 " && fourcc != b"VP8L" && fourcc != b"VP8X" {
+        return Err(format!(
+            "unexpected first chunk '{}'",
+            String::from_utf8_lossy(fourcc).escape_debug()
+        ));
+    }
+    let chunk_size = u32::from_le_bytes([data[16], data[17], data[18], data[19]]) as u64;
+    // The chunk starts 12 bytes into the RIFF payload and must end inside it
+    if 12 + chunk_size > riff_size {
+        return Err(format!(
+            "{} chunk of {} bytes exceeds RIFF size {}",
+            String::from_utf8_lossy(fourcc).trim_end(),
+            chunk_size,
+            riff_size
+        ));
+    }
+    Ok(())
+}
+
 /// Decode an animated WebP using the WebPAnimDecoder API.
 fn load_webp_animated(data: &[u8], path: &Path) -> Result<LoadedImage, String> {
     unsafe {
@@ -516,7 +1754,7 @@ fn load_webp_animated(data: &[u8], path: &Path) -> Result<LoadedImage, String> {
         if libwebp_sys::WebPAnimDecoderOptionsInit(&mut options) == 0 {
             return Err("WebPAnimDecoderOptionsInit failed".to_string());
         }
-        options.color_mode = libwebp_sys::MODE_RGBA;
+        options.color_mode = WEBP_COLOR_MODE;
         options.use_threads = 0;
 
         // Create WebPData
@@ -565,13 +1803,13 @@ fn load_webp_animated(data: &[u8], path: &Path) -> Result<LoadedImage, String> {
             }
 
             // Frame duration = delta between consecutive cumulative timestamps
-            let delay_ms = ((timestamp - prev_timestamp) as u64).max(10);
+            let delay_ms = (timestamp as i64 - prev_timestamp as i64).max(0) as u64;
             prev_timestamp = timestamp;
 
             // Copy the RGBA buffer (it's owned by the decoder, valid until next GetNext or Delete)
             let rgba_data = std::slice::from_raw_parts(buf, frame_size).to_vec();
             if let Some(img) = RgbaImage::from_raw(canvas_w, canvas_h, rgba_data) {
-                frames.push((img, Duration::from_millis(delay_ms)));
+                frames.push((img, frame_duration(delay_ms)));
             }
         }
 
@@ -721,7 +1959,7 @@ fn load_gif(path: &Path) -> Result<LoadedImage, String> {
         }
 
         // Validate canvas dimensions to prevent overflow in allocation
-        if (canvas_w as u64) * (canvas_h as u64) > MAX_PIXEL_COUNT {
+        if (canvas_w as u64) * (canvas_h as u64) > pixel_limit() {
             libgif::DGifCloseFile(gif, std::ptr::null_mut());
             return Err(format!(
                 "GIF canvas too large: {}x{} in {}",
@@ -772,7 +2010,7 @@ fn load_gif(path: &Path) -> Result<LoadedImage, String> {
             libgif::DGifSavedExtensionToGCB(gif, i as c_int, &mut gcb);
 
             let transparent = gcb.TransparentColor;
-            let delay_ms = ((gcb.DelayTime as u64) * 10).max(10);
+            let delay_ms = gcb.DelayTime.max(0) as u64 * 10;
 
             // Map palette indices to RGBA and composite onto canvas
             for row in 0..fh {
@@ -806,7 +2044,7 @@ fn load_gif(path: &Path) -> Result<LoadedImage, String> {
                 width: canvas_w,
                 height: canvas_h,
             };
-            frames.push((img, Duration::from_millis(delay_ms)));
+            frames.push((img, frame_duration(delay_ms)));
         }
 
         libgif::DGifCloseFile(gif, std::ptr::null_mut());
@@ -833,9 +2071,23 @@ fn load_bmp(path: &Path) -> Result<LoadedImage, String> {
     decode_bmp(&data, &path.display().to_string())
 }
 
+/// Size of the OS/2 BITMAPCOREHEADER; BITMAPINFOHEADER and its successors
+/// are 40 bytes or more.
+const BMP_CORE_HEADER_SIZE: usize = 12;
+
+/// Row of the pixel data holding image row `y`. BMPs are stored bottom-up
+/// unless the header height is negative.
+fn source_row(y: u32, h: u32, top_down: bool) -> usize {
+    if top_down {
+        y as usize
+    } else {
+        (h - 1 - y) as usize
+    }
+}
+
 /// Decode a BMP image from raw bytes. Separated from load_bmp for testability.
 fn decode_bmp(data: &[u8], path_display: &str) -> Result<LoadedImage, String> {
-    if data.len() < 54 {
+    if data.len() < 14 + BMP_CORE_HEADER_SIZE {
         return Err("File too small to be BMP".to_string());
     }
 
@@ -845,20 +2097,34 @@ fn decode_bmp(data: &[u8], path_display: &str) -> Result<LoadedImage, String> {
 
     let data_offset = u32::from_le_bytes([data[10], data[11], data[12], data[13]]) as usize;
     let dib_header_size = u32::from_le_bytes([data[14], data[15], data[16], data[17]]) as usize;
-    let width = i32::from_le_bytes([data[18], data[19], data[20], data[21]]);
-    let height = i32::from_le_bytes([data[22], data[23], data[24], data[25]]);
-    let bits_per_pixel = u16::from_le_bytes([data[28], data[29]]);
-    let compression = if data.len() >= 34 {
-        u32::from_le_bytes([data[30], data[31], data[32], data[33]])
+    // OS/2 BITMAPCOREHEADER: 16-bit dimensions, always bottom-up, no
+    // compression and a palette of 3-byte BGR triples
+    let core = dib_header_size == BMP_CORE_HEADER_SIZE;
+    if !core && data.len() < 54 {
+        return Err("File too small to be BMP".to_string());
+    }
+    let (width, height, bits_per_pixel, compression) = if core {
+        (
+            u16::from_le_bytes([data[18], data[19]]) as i32,
+            u16::from_le_bytes([data[20], data[21]]) as i32,
+            u16::from_le_bytes([data[24], data[25]]),
+            0,
+        )
     } else {
-        0
+        (
+            i32::from_le_bytes([data[18], data[19], data[20], data[21]]),
+            i32::from_le_bytes([data[22], data[23], data[24], data[25]]),
+            u16::from_le_bytes([data[28], data[29]]),
+            u32::from_le_bytes([data[30], data[31], data[32], data[33]]),
+        )
     };
 
     if width <= 0 || height == 0 {
         return Err("Invalid BMP dimensions".to_string());
     }
 
-    let (w, h) = (width as u32, height.unsigned_abs() as u32);
+    let (w, h) = (width as u32, height.unsigned_abs());
+    let top_down = height < 0;
     validate_dimensions(w, h, "BMP")?;
 
     // Use u64 arithmetic to prevent overflow in row_size and expected_size calculations
@@ -880,11 +2146,7 @@ fn decode_bmp(data: &[u8], path_display: &str) -> Result<LoadedImage, String> {
         24 => {
             for y in 0..h {
                 for x in 0..w {
-                    let src_row = if height > 0 {
-                        (h - 1 - y) as usize
-                    } else {
-                        y as usize
-                    };
+                    let src_row = source_row(y, h, top_down);
                     let src_idx = data_offset + (src_row * row_size) + (x as usize * 3);
                     let dst = ((y * w + x) * 4) as usize;
                     rgba_data[dst] = data[src_idx + 2];
@@ -897,11 +2159,7 @@ fn decode_bmp(data: &[u8], path_display: &str) -> Result<LoadedImage, String> {
         32 => {
             for y in 0..h {
                 for x in 0..w {
-                    let src_row = if height > 0 {
-                        (h - 1 - y) as usize
-                    } else {
-                        y as usize
-                    };
+                    let src_row = source_row(y, h, top_down);
                     let src_idx = data_offset + (src_row * row_size) + (x as usize * 4);
                     let dst = ((y * w + x) * 4) as usize;
                     rgba_data[dst] = data[src_idx + 2];
@@ -911,7 +2169,7 @@ fn decode_bmp(data: &[u8], path_display: &str) -> Result<LoadedImage, String> {
                 }
             }
         }
-        1 | 4 | 8 => {
+        1 | 2 | 4 | 8 => {
             // Reject RLE compression
             if compression == 1 {
                 return Err(format!(
@@ -928,7 +2186,7 @@ fn decode_bmp(data: &[u8], path_display: &str) -> Result<LoadedImage, String> {
 
             // Parse color table
             let max_colors: u32 = 1 << bits_per_pixel;
-            let clr_used = if data.len() >= 50 {
+            let clr_used = if !core {
                 let v = u32::from_le_bytes([data[46], data[47], data[48], data[49]]);
                 if v == 0 {
                     max_colors
@@ -936,7 +2194,10 @@ fn decode_bmp(data: &[u8], path_display: &str) -> Result<LoadedImage, String> {
                     v
                 }
             } else {
-                max_colors
+                // Core headers have no count: the palette fills the gap
+                // up to the pixel data, as many writers store fewer entries
+                let gap = data_offset.saturating_sub(14 + BMP_CORE_HEADER_SIZE) / 3;
+                (gap as u32).min(max_colors)
             };
             if clr_used > max_colors {
                 return Err(format!(
@@ -946,25 +2207,22 @@ fn decode_bmp(data: &[u8], path_display: &str) -> Result<LoadedImage, String> {
             }
 
             let color_table_offset = 14 + dib_header_size;
-            let color_table_end = color_table_offset + clr_used as usize * 4;
+            let entry_size = if core { 3 } else { 4 };
+            let color_table_end = color_table_offset + clr_used as usize * entry_size;
             if color_table_end > data.len() {
                 return Err("BMP color table truncated".to_string());
             }
 
-            // Read BGRA color table entries
+            // Read BGR(A) color table entries
             let mut palette = Vec::with_capacity(clr_used as usize);
             for i in 0..clr_used as usize {
-                let off = color_table_offset + i * 4;
-                palette.push([data[off + 2], data[off + 1], data[off], 255]); // BGR_ -> RGBA
+                let off = color_table_offset + i * entry_size;
+                palette.push([data[off + 2], data[off + 1], data[off], 255]); // BGR -> RGBA
             }
 
             // Decode indexed pixels
             for y in 0..h {
-                let src_row = if height > 0 {
-                    (h - 1 - y) as usize
-                } else {
-                    y as usize
-                };
+                let src_row = source_row(y, h, top_down);
                 let row_start = data_offset + src_row * row_size;
 
                 match bits_per_pixel {
@@ -991,6 +2249,17 @@ fn decode_bmp(data: &[u8], path_display: &str) -> Result<LoadedImage, String> {
                             }
                         }
                     }
+                    2 => {
+                        for x in 0..w {
+                            let byte = data[row_start + (x as usize / 4)];
+                            let shift = 6 - 2 * (x % 4); // high bits = leftmost pixel
+                            let idx = ((byte >> shift) & 3) as usize;
+                            let dst = ((y * w + x) * 4) as usize;
+                            if idx < palette.len() {
+                                rgba_data[dst..dst + 4].copy_from_slice(&palette[idx]);
+                            }
+                        }
+                    }
                     1 => {
                         for x in 0..w {
                             let byte = data[row_start + (x as usize / 8)];
@@ -1068,7 +2337,7 @@ fn load_tiff(path: &Path) -> Result<LoadedImage, String> {
         }
 
         // Validate dimensions before allocation
-        if w == 0 || h == 0 || (w as u64) * (h as u64) > MAX_PIXEL_COUNT {
+        if w == 0 || h == 0 || (w as u64) * (h as u64) > pixel_limit() {
             libtiff::TIFFClose(tif);
             return Err(format!(
                 "TIFF dimensions invalid or too large: {}x{} in {}",
@@ -1118,6 +2387,7 @@ fn load_tiff(path: &Path) -> Result<LoadedImage, String> {
 // SVG via system librsvg + cairo
 // ============================================================
 
+#[cfg(feature = "svg")]
 #[allow(non_camel_case_types)]
 mod librsvg {
     use std::os::raw::{c_char, c_int, c_uchar, c_void};
@@ -1137,53 +2407,54 @@ mod librsvg {
         pub height: f64,
     }
 
-    #[link(name = "rsvg-2")]
-    extern "C" {
-        pub fn rsvg_handle_new_from_file(
-            file_name: *const c_char,
-            error: *mut *mut GError,
-        ) -> *mut RsvgHandle;
-        pub fn rsvg_handle_get_intrinsic_size_in_pixels(
-            handle: *mut RsvgHandle,
-            out_width: *mut f64,
-            out_height: *mut f64,
-        ) -> c_int;
-        pub fn rsvg_handle_render_document(
-            handle: *mut RsvgHandle,
-            cr: *mut cairo_t,
-            viewport: *const RsvgRectangle,
-            error: *mut *mut GError,
-        ) -> c_int;
-        pub fn rsvg_handle_set_dpi(handle: *mut RsvgHandle, dpi: f64);
-    }
-
-    #[link(name = "gobject-2.0")]
-    extern "C" {
-        pub fn g_object_unref(object: *mut c_void);
-    }
-
-    #[link(name = "glib-2.0")]
-    extern "C" {
-        pub fn g_error_free(error: *mut GError);
-    }
-
-    #[link(name = "cairo")]
-    extern "C" {
-        pub fn cairo_image_surface_create(
-            format: c_int,
-            width: c_int,
-            height: c_int,
-        ) -> *mut cairo_surface_t;
-        pub fn cairo_create(target: *mut cairo_surface_t) -> *mut cairo_t;
-        pub fn cairo_destroy(cr: *mut cairo_t);
-        pub fn cairo_surface_destroy(surface: *mut cairo_surface_t);
-        pub fn cairo_surface_flush(surface: *mut cairo_surface_t);
-        pub fn cairo_image_surface_get_data(surface: *mut cairo_surface_t) -> *mut c_uchar;
-        pub fn cairo_image_surface_get_stride(surface: *mut cairo_surface_t) -> c_int;
+    crate::dynlib::dynamic_extern! {
+        "librsvg";
+        ["librsvg-2.so.2"] {
+            pub fn rsvg_handle_new_from_file(
+                file_name: *const c_char,
+                error: *mut *mut GError,
+            ) -> *mut RsvgHandle;
+            pub fn rsvg_handle_get_intrinsic_size_in_pixels(
+                handle: *mut RsvgHandle,
+                out_width: *mut f64,
+                out_height: *mut f64,
+            ) -> c_int;
+            pub fn rsvg_handle_render_document(
+                handle: *mut RsvgHandle,
+                cr: *mut cairo_t,
+                viewport: *const RsvgRectangle,
+                error: *mut *mut GError,
+            ) -> c_int;
+            pub fn rsvg_handle_set_dpi(handle: *mut RsvgHandle, dpi: f64);
+        }
+
+        ["libgobject-2.0.so.0"] {
+            pub fn g_object_unref(object: *mut c_void);
+        }
+
+        ["libglib-2.0.so.0"] {
+            pub fn g_error_free(error: *mut GError);
+        }
+
+        ["libcairo.so.2"] {
+            pub fn cairo_image_surface_create(
+                format: c_int,
+                width: c_int,
+                height: c_int,
+            ) -> *mut cairo_surface_t;
+            pub fn cairo_create(target: *mut cairo_surface_t) -> *mut cairo_t;
+            pub fn cairo_destroy(cr: *mut cairo_t);
+            pub fn cairo_surface_destroy(surface: *mut cairo_surface_t);
+            pub fn cairo_surface_flush(surface: *mut cairo_surface_t);
+            pub fn cairo_image_surface_get_data(surface: *mut cairo_surface_t) -> *mut c_uchar;
+            pub fn cairo_image_surface_get_stride(surface: *mut cairo_surface_t) -> c_int;
+        }
     }
 }
 
-fn load_svg(path: &Path) -> Result<LoadedImage, String> {
+#[cfg(feature = "svg")]
+fn load_svg(path: &Path, note: &mut Option<String>) -> Result<LoadedImage, String> {
+    librsvg::load()?;
     let c_path = CString::new(path.to_str().ok_or_else(|| "Invalid path".to_string())?)
         .map_err(|_| "Path contains null byte".to_string())?;
 
@@ -1211,19 +2482,24 @@ fn load_svg(path: &Path) -> Result<LoadedImage, String> {
             h = 1024.0;
         }
 
-        // Clamp SVG dimensions to prevent excessive memory allocation
+        // Clamp the render size to prevent excessive memory allocation
         let max_svg_dim = 16384.0; // 16K pixels per side
-        if w > max_svg_dim || h > max_svg_dim {
-            let scale = (max_svg_dim / w).min(max_svg_dim / h);
-            w *= scale;
-            h *= scale;
+        let limit = pixel_limit();
+        let scale = (max_svg_dim / w)
+            .min(max_svg_dim / h)
+            .min((limit as f64 / (w * h)).sqrt());
+        if scale < 1.0 {
+            let (full_w, full_h) = (w.ceil() as u32, h.ceil() as u32);
+            w = (w * scale).floor().max(1.0);
+            h = (h * scale).floor().max(1.0);
+            *note = Some(downscale_note(full_w, full_h, w as u32, h as u32));
         }
 
         let pw = w.ceil() as c_int;
         let ph = h.ceil() as c_int;
 
         // Validate pixel count
-        if (pw as u64) * (ph as u64) > MAX_PIXEL_COUNT {
+        if (pw as u64) * (ph as u64) > limit {
             librsvg::g_object_unref(handle);
             return Err(format!(
                 "SVG dimensions too large: {}x{} in {}",
@@ -1323,12 +2599,17 @@ fn load_svg(path: &Path) -> Result<LoadedImage, String> {
 // AVIF via system libavif
 // ============================================================
 
+#[cfg(feature = "avif")]
 #[allow(non_camel_case_types)]
 mod libavif {
-    use std::os::raw::{c_int, c_uint, c_void};
+    use std::ffi::CStr;
+    use std::os::raw::{c_char, c_int, c_uint, c_void};
+    use std::sync::OnceLock;
 
     pub const AVIF_RESULT_OK: c_int = 0;
-    pub const AVIF_RGB_FORMAT_RGBA: c_int = 0;
+    pub const AVIF_RGB_FORMAT_RGBA: c_int = 1;
+    /// `imageSizeLimit` of a new decoder (AVIF_DEFAULT_IMAGE_SIZE_LIMIT).
+    pub const AVIF_DEFAULT_IMAGE_SIZE_LIMIT: u32 = 16384 * 16384;
 
     #[repr(C)]
     pub struct avifImageTiming {
@@ -1361,44 +2642,81 @@ mod libavif {
         pub ignore_alpha: c_int,
         pub alpha_premultiplied: c_int,
         pub is_float: c_int,
-        pub max_threads: c_int,
-        pub pixels: *mut u8,
-        pub row_bytes: c_uint,
+        // libavif 1.x continues with maxThreads, pixels and rowBytes;
+        // 0.x has no maxThreads. Read them through `pixels`/`row_bytes`.
+        tail: [usize; 3],
     }
 
-    #[link(name = "avif")]
-    extern "C" {
-        pub fn avifDecoderCreate() -> *mut avifDecoder;
-        pub fn avifDecoderDestroy(decoder: *mut avifDecoder);
-        pub fn avifDecoderSetIOMemory(
-            decoder: *mut avifDecoder,
-            data: *const u8,
-            size: usize,
-        ) -> c_int;
-        pub fn avifDecoderParse(decoder: *mut avifDecoder) -> c_int;
-        pub fn avifDecoderNextImage(decoder: *mut avifDecoder) -> c_int;
-        pub fn avifDecoderNthImageTiming(
-            decoder: *const avifDecoder,
-            frame_index: c_uint,
-            out_timing: *mut avifImageTiming,
-        ) -> c_int;
-        pub fn avifRGBImageSetDefaults(rgb: *mut avifRGBImage, image: *const avifImage);
-        pub fn avifRGBImageAllocatePixels(rgb: *mut avifRGBImage) -> c_int;
-        pub fn avifRGBImageFreePixels(rgb: *mut avifRGBImage);
-        pub fn avifImageYUVToRGB(image: *const avifImage, rgb: *mut avifRGBImage) -> c_int;
-    }
-}
-
-/// Read avifDecoder->image (offset depends on the struct layout).
-/// avifDecoder struct: first field is codecChoice (enum = c_int), then maxThreads (c_int),
-/// requestedSource (enum = c_int), allowProgressive (c_int), allowIncremental (c_int),
-/// ignoreExif (c_int), ignoreXMP (c_int), imageSizeLimit (u32), imageDimensionLimit (u32),
-/// imageCountLimit (u32), strictFlags (u32), then image (*mut avifImage).
-/// That's 10 * 4 + 4 = 44 bytes on most platforms, but we need to account for pointer alignment.
-/// Actually we should read from the struct pointer directly. Let's use a more reliable approach.
-///
-/// Helper to read decoder fields by casting to known offsets.
-/// We define a repr(C) partial mirror of the decoder struct for safe field access.
+    impl avifRGBImage {
+        /// Byte offset of `pixels`, which is followed by `rowBytes`.
+        fn pixels_offset() -> usize {
+            let tail = std::mem::offset_of!(avifRGBImage, tail);
+            if is_legacy() {
+                tail
+            } else {
+                tail + std::mem::size_of::<usize>()
+            }
+        }
+
+        pub fn pixels(&self) -> *mut u8 {
+            unsafe {
+                let base = (self as *const Self as *const u8).add(Self::pixels_offset());
+                *(base as *const *mut u8)
+            }
+        }
+
+        pub fn row_bytes(&self) -> usize {
+            unsafe {
+                let base = (self as *const Self as *const u8).add(Self::pixels_offset());
+                *(base.add(std::mem::size_of::<usize>()) as *const u32) as usize
+            }
+        }
+    }
+
+    /// Whether the loaded library is libavif 0.x, whose `avifRGBImage`
+    /// lacks `maxThreads` and whose `avifRGBImageAllocatePixels` returns
+    /// nothing.
+    fn is_legacy() -> bool {
+        static LEGACY: OnceLock<bool> = OnceLock::new();
+        *LEGACY.get_or_init(|| {
+            let version = unsafe { CStr::from_ptr(avifVersion()) };
+            version.to_bytes().starts_with(b"0.")
+        })
+    }
+
+    crate::dynlib::dynamic_extern! {
+        "libavif";
+        ["libavif.so.16", "libavif.so.15"] {
+            pub fn avifDecoderCreate() -> *mut avifDecoder;
+            pub fn avifDecoderDestroy(decoder: *mut avifDecoder);
+            pub fn avifDecoderSetIOMemory(
+                decoder: *mut avifDecoder,
+                data: *const u8,
+                size: usize,
+            ) -> c_int;
+            pub fn avifDecoderParse(decoder: *mut avifDecoder) -> c_int;
+            pub fn avifDecoderNextImage(decoder: *mut avifDecoder) -> c_int;
+            pub fn avifDecoderNthImageTiming(
+                decoder: *const avifDecoder,
+                frame_index: c_uint,
+                out_timing: *mut avifImageTiming,
+            ) -> c_int;
+            pub fn avifRGBImageSetDefaults(rgb: *mut avifRGBImage, image: *const avifImage);
+            // Returns avifResult in 1.x and void in 0.x; check `pixels()`
+            pub fn avifRGBImageAllocatePixels(rgb: *mut avifRGBImage);
+            pub fn avifRGBImageFreePixels(rgb: *mut avifRGBImage);
+            pub fn avifImageYUVToRGB(image: *const avifImage, rgb: *mut avifRGBImage) -> c_int;
+            pub fn avifVersion() -> *const c_char;
+        }
+    }
+}
+
+/// Leading fields of `struct avifDecoder`, which libavif exposes only as a
+/// public struct without accessor functions. The layout is the same in
+/// libavif.so.15 and .16, and `avif_set_max_threads` and
+/// `avif_decoder_image` check it at runtime before a field is written or
+/// `image` is used.
+#[cfg(feature = "avif")]
 #[repr(C)]
 struct AvifDecoderPartial {
     codec_choice: c_int,
@@ -1418,7 +2736,88 @@ struct AvifDecoderPartial {
     image_count: c_int,
 }
 
+/// Set `decoder->maxThreads` of a decoder fresh from avifDecoderCreate,
+/// once the fields read through `AvifDecoderPartial` hold libavif's
+/// defaults (one thread, the default size limit, no image yet).
+#[cfg(feature = "avif")]
+unsafe fn avif_set_max_threads(
+    decoder: *mut libavif::avifDecoder,
+    threads: c_int,
+) -> Result<(), String> {
+    let dec = &mut *(decoder as *mut AvifDecoderPartial);
+    let fresh = dec.max_threads == 1
+        && dec.image_size_limit == libavif::AVIF_DEFAULT_IMAGE_SIZE_LIMIT
+        && dec.image.is_null();
+    if !fresh {
+        return Err("Unsupported libavif version (unexpected decoder layout)".to_string());
+    }
+    dec.max_threads = threads;
+    Ok(())
+}
+
+/// `decoder->image` of a decoder that was just parsed (`index` -1) or
+/// advanced to frame `index`. If the fields read through
+/// `AvifDecoderPartial` do not look like such a decoder, the installed
+/// libavif has another layout and this fails instead of passing garbage on.
+#[cfg(feature = "avif")]
+unsafe fn avif_decoder_image(
+    decoder: *const libavif::avifDecoder,
+    index: c_int,
+) -> Result<*mut libavif::avifImage, String> {
+    let dec = &*(decoder as *const AvifDecoderPartial);
+    let plausible = !dec.image.is_null()
+        && dec.image_count >= 1
+        && dec.image_index == index
+        && index < dec.image_count;
+    if !plausible {
+        return Err("Unsupported libavif version (unexpected decoder layout)".to_string());
+    }
+    if index >= 0 {
+        // avifImage starts with its width and height
+        let size = &*(dec.image as *const [u32; 2]);
+        if size[0] == 0 || size[1] == 0 {
+            return Err("Unsupported libavif version (unexpected image layout)".to_string());
+        }
+    }
+    Ok(dec.image)
+}
+
+/// RGB output depth for an AVIF image of `image_depth` bits: 8, unless
+/// `--dither` asks for deeper images at full depth.
+#[cfg(feature = "avif")]
+fn avif_rgb_depth(image_depth: c_uint) -> c_uint {
+    if dither() && image_depth > 8 {
+        image_depth
+    } else {
+        8
+    }
+}
+
+/// Copy converted AVIF pixels into a tightly packed 8-bit RGBA buffer,
+/// reducing deeper samples with `DepthReducer`.
+#[cfg(feature = "avif")]
+unsafe fn avif_rgb_pixels(rgb: &libavif::avifRGBImage) -> Vec<u8> {
+    let (w, h) = (rgb.width as usize, rgb.height as usize);
+    let (src, row_bytes) = (rgb.pixels(), rgb.row_bytes());
+    let mut pixels = vec![0u8; w * h * 4];
+    if rgb.depth > 8 {
+        let mut reducer = DepthReducer::new(w, (1u32 << rgb.depth) - 1);
+        for (y, out) in pixels.chunks_exact_mut(w * 4).enumerate() {
+            // Deep samples are native-endian u16
+            let row = src.add(y * row_bytes) as *const u16;
+            reducer.reduce_row(std::slice::from_raw_parts(row, w * 4), out);
+        }
+        return pixels;
+    }
+    for (y, out) in pixels.chunks_exact_mut(w * 4).enumerate() {
+        std::ptr::copy_nonoverlapping(src.add(y * row_bytes), out.as_mut_ptr(), w * 4);
+    }
+    pixels
+}
+
+#[cfg(feature = "avif")]
 fn load_avif(path: &Path) -> Result<LoadedImage, String> {
+    libavif::load()?;
     let data = read_file_limited(path)?;
 
     unsafe {
@@ -1426,6 +2825,11 @@ fn load_avif(path: &Path) -> Result<LoadedImage, String> {
         if decoder.is_null() {
             return Err("Failed to create AVIF decoder".to_string());
         }
+        let threads = decode_threads().min(c_int::MAX as usize) as c_int;
+        if let Err(e) = avif_set_max_threads(decoder, threads) {
+            libavif::avifDecoderDestroy(decoder);
+            return Err(e);
+        }
 
         let result = libavif::avifDecoderSetIOMemory(decoder, data.as_ptr(), data.len());
         if result != libavif::AVIF_RESULT_OK {
@@ -1439,8 +2843,11 @@ fn load_avif(path: &Path) -> Result<LoadedImage, String> {
             return Err(format!("Failed to parse AVIF {}", path.display()));
         }
 
-        let dec = &*(decoder as *const AvifDecoderPartial);
-        let image_count = dec.image_count;
+        if let Err(e) = avif_decoder_image(decoder, -1) {
+            libavif::avifDecoderDestroy(decoder);
+            return Err(e);
+        }
+        let image_count = (*(decoder as *const AvifDecoderPartial)).image_count;
         let is_animated = image_count > 1;
 
         if is_animated {
@@ -1456,16 +2863,21 @@ fn load_avif(path: &Path) -> Result<LoadedImage, String> {
                     ));
                 }
 
-                let dec = &*(decoder as *const AvifDecoderPartial);
-                let image = dec.image;
+                let image = match avif_decoder_image(decoder, i) {
+                    Ok(image) => image,
+                    Err(e) => {
+                        libavif::avifDecoderDestroy(decoder);
+                        return Err(e);
+                    }
+                };
 
                 let mut rgb: libavif::avifRGBImage = std::mem::zeroed();
                 libavif::avifRGBImageSetDefaults(&mut rgb, image);
                 rgb.format = libavif::AVIF_RGB_FORMAT_RGBA;
-                rgb.depth = 8;
+                rgb.depth = avif_rgb_depth(rgb.depth);
 
-                let res = libavif::avifRGBImageAllocatePixels(&mut rgb);
-                if res != libavif::AVIF_RESULT_OK {
+                libavif::avifRGBImageAllocatePixels(&mut rgb);
+                if rgb.pixels().is_null() {
                     libavif::avifDecoderDestroy(decoder);
                     return Err(format!(
                         "Failed to allocate AVIF RGB pixels for {}",
@@ -1491,19 +2903,7 @@ fn load_avif(path: &Path) -> Result<LoadedImage, String> {
                     e
                 })?;
 
-                let pixel_count = (w as usize) * (h as usize) * 4;
-                let mut pixels = vec![0u8; pixel_count];
-                let src_ptr = rgb.pixels;
-                let row_bytes = rgb.row_bytes as usize;
-                for y in 0..h as usize {
-                    let src_offset = y * row_bytes;
-                    let dst_offset = y * (w as usize) * 4;
-                    std::ptr::copy_nonoverlapping(
-                        src_ptr.add(src_offset),
-                        pixels.as_mut_ptr().add(dst_offset),
-                        (w as usize) * 4,
-                    );
-                }
+                let pixels = avif_rgb_pixels(&rgb);
                 libavif::avifRGBImageFreePixels(&mut rgb);
 
                 let img = RgbaImage::from_raw(w, h, pixels)
@@ -1512,8 +2912,8 @@ fn load_avif(path: &Path) -> Result<LoadedImage, String> {
                 // Get frame timing
                 let mut timing: libavif::avifImageTiming = std::mem::zeroed();
                 libavif::avifDecoderNthImageTiming(decoder, i as c_uint, &mut timing);
-                let duration_ms = (timing.duration * 1000.0) as u64;
-                let duration = Duration::from_millis(duration_ms.max(10));
+                // Negative or NaN durations saturate to 0
+                let duration = frame_duration((timing.duration * 1000.0) as u64);
 
                 frames.push((img, duration));
             }
@@ -1533,16 +2933,21 @@ fn load_avif(path: &Path) -> Result<LoadedImage, String> {
                 return Err(format!("Failed to decode AVIF {}", path.display()));
             }
 
-            let dec = &*(decoder as *const AvifDecoderPartial);
-            let image = dec.image;
+            let image = match avif_decoder_image(decoder, 0) {
+                Ok(image) => image,
+                Err(e) => {
+                    libavif::avifDecoderDestroy(decoder);
+                    return Err(e);
+                }
+            };
 
             let mut rgb: libavif::avifRGBImage = std::mem::zeroed();
             libavif::avifRGBImageSetDefaults(&mut rgb, image);
             rgb.format = libavif::AVIF_RGB_FORMAT_RGBA;
-            rgb.depth = 8;
+            rgb.depth = avif_rgb_depth(rgb.depth);
 
-            let res = libavif::avifRGBImageAllocatePixels(&mut rgb);
-            if res != libavif::AVIF_RESULT_OK {
+            libavif::avifRGBImageAllocatePixels(&mut rgb);
+            if rgb.pixels().is_null() {
                 libavif::avifDecoderDestroy(decoder);
                 return Err(format!(
                     "Failed to allocate AVIF RGB pixels for {}",
@@ -1568,19 +2973,7 @@ fn load_avif(path: &Path) -> Result<LoadedImage, String> {
                 e
             })?;
 
-            let pixel_count = (w as usize) * (h as usize) * 4;
-            let mut pixels = vec![0u8; pixel_count];
-            let src_ptr = rgb.pixels;
-            let row_bytes = rgb.row_bytes as usize;
-            for y in 0..h as usize {
-                let src_offset = y * row_bytes;
-                let dst_offset = y * (w as usize) * 4;
-                std::ptr::copy_nonoverlapping(
-                    src_ptr.add(src_offset),
-                    pixels.as_mut_ptr().add(dst_offset),
-                    (w as usize) * 4,
-                );
-            }
+            let pixels = avif_rgb_pixels(&rgb);
             libavif::avifRGBImageFreePixels(&mut rgb);
 
             // Extract EXIF orientation before destroying decoder
@@ -1589,11 +2982,14 @@ fn load_avif(path: &Path) -> Result<LoadedImage, String> {
             // raw AVIF container.
             libavif::avifDecoderDestroy(decoder);
 
+            // libavif composes grid images itself; make sure this one did
+            check_grid_decoded(isobmff_grid_size(&data), w, h, path)?;
+
             let mut img = RgbaImage::from_raw(w, h, pixels)
                 .ok_or_else(|| "AVIF pixel buffer size mismatch".to_string())?;
 
-            // Apply EXIF orientation from raw AVIF data
-            if let Some(orientation) = read_exif_orientation_avif(&data) {
+            // Apply the container transforms or EXIF orientation
+            if let Some(orientation) = isobmff_orientation(&data) {
                 img = apply_orientation(img, orientation);
             }
 
@@ -1708,13 +3104,18 @@ pub fn read_exif_tags_avif(data: &[u8]) -> Vec<(String, String)> {
 // HEIC/HEIF via system libheif
 // ============================================================
 
+#[cfg(feature = "heif")]
 #[allow(non_camel_case_types)]
 mod libheif {
     use std::os::raw::{c_char, c_int, c_void};
 
     pub const HEIF_ERROR_OK: c_int = 0;
     pub const HEIF_COLORSPACE_RGB: c_int = 1;
+    pub const HEIF_COLORSPACE_YCBCR: c_int = 0;
+    pub const HEIF_CHROMA_UNDEFINED: c_int = 99;
     pub const HEIF_CHROMA_INTERLEAVED_RGBA: c_int = 11;
+    pub const HEIF_CHROMA_INTERLEAVED_RRGGBBAA_LE: c_int = 15;
+    pub const HEIF_CHANNEL_Y: c_int = 0;
     pub const HEIF_CHANNEL_INTERLEAVED: c_int = 10;
 
     #[repr(C)]
@@ -1727,65 +3128,113 @@ mod libheif {
     pub type heif_context = c_void;
     pub type heif_image_handle = c_void;
     pub type heif_image = c_void;
-    pub type heif_decoding_options = c_void;
+
+    /// Leading fields of `struct heif_decoding_options`; always allocated
+    /// by libheif, which fills in the rest.
+    #[repr(C)]
+    pub struct heif_decoding_options {
+        pub version: u8,
+        pub ignore_transformations: u8,
+    }
 
     pub type heif_item_id = u32;
 
-    #[link(name = "heif")]
-    extern "C" {
-        pub fn heif_context_alloc() -> *mut heif_context;
-        pub fn heif_context_free(ctx: *mut heif_context);
-        pub fn heif_context_read_from_memory_without_copy(
-            ctx: *mut heif_context,
-            mem: *const u8,
-            size: usize,
-            options: *const c_void,
-        ) -> heif_error;
-        pub fn heif_context_get_primary_image_handle(
-            ctx: *mut heif_context,
-            handle: *mut *mut heif_image_handle,
-        ) -> heif_error;
-        pub fn heif_image_handle_release(handle: *mut heif_image_handle);
-        pub fn heif_image_handle_get_width(handle: *const heif_image_handle) -> c_int;
-        pub fn heif_image_handle_get_height(handle: *const heif_image_handle) -> c_int;
-        pub fn heif_decode_image(
-            handle: *const heif_image_handle,
-            out_img: *mut *mut heif_image,
-            colorspace: c_int,
-            chroma: c_int,
-            options: *const heif_decoding_options,
-        ) -> heif_error;
-        pub fn heif_image_get_plane_readonly(
-            image: *const heif_image,
-            channel: c_int,
-            out_stride: *mut c_int,
-        ) -> *const u8;
-        pub fn heif_image_release(image: *mut heif_image);
-
-        // EXIF metadata
-        pub fn heif_image_handle_get_number_of_metadata_blocks(
-            handle: *const heif_image_handle,
-            type_filter: *const c_char,
-        ) -> c_int;
-        pub fn heif_image_handle_get_list_of_metadata_block_IDs(
-            handle: *const heif_image_handle,
-            type_filter: *const c_char,
-            ids: *mut heif_item_id,
-            count: c_int,
-        ) -> c_int;
-        pub fn heif_image_handle_get_metadata_size(
-            handle: *const heif_image_handle,
-            metadata_id: heif_item_id,
-        ) -> usize;
-        pub fn heif_image_handle_get_metadata(
-            handle: *const heif_image_handle,
-            metadata_id: heif_item_id,
-            out_data: *mut u8,
-        ) -> heif_error;
+    crate::dynlib::dynamic_extern! {
+        "libheif";
+        ["libheif.so.1"] {
+            pub fn heif_context_alloc() -> *mut heif_context;
+            pub fn heif_context_free(ctx: *mut heif_context);
+            pub fn heif_context_read_from_memory_without_copy(
+                ctx: *mut heif_context,
+                mem: *const u8,
+                size: usize,
+                options: *const c_void,
+            ) -> heif_error;
+            pub fn heif_context_get_primary_image_handle(
+                ctx: *mut heif_context,
+                handle: *mut *mut heif_image_handle,
+            ) -> heif_error;
+            pub fn heif_image_handle_release(handle: *mut heif_image_handle);
+            pub fn heif_image_handle_get_width(handle: *const heif_image_handle) -> c_int;
+            pub fn heif_image_handle_get_height(handle: *const heif_image_handle) -> c_int;
+            pub fn heif_image_handle_get_luma_bits_per_pixel(
+                handle: *const heif_image_handle,
+            ) -> c_int;
+            pub fn heif_image_handle_has_alpha_channel(handle: *const heif_image_handle) -> c_int;
+
+            // Auxiliary images (alpha, depth)
+            pub fn heif_image_handle_get_number_of_auxiliary_images(
+                handle: *const heif_image_handle,
+                aux_filter: c_int,
+            ) -> c_int;
+            pub fn heif_image_handle_get_list_of_auxiliary_image_IDs(
+                handle: *const heif_image_handle,
+                aux_filter: c_int,
+                ids: *mut heif_item_id,
+                count: c_int,
+            ) -> c_int;
+            pub fn heif_image_handle_get_auxiliary_image_handle(
+                main_image_handle: *const heif_image_handle,
+                auxiliary_id: heif_item_id,
+                out_auxiliary_handle: *mut *mut heif_image_handle,
+            ) -> heif_error;
+            pub fn heif_image_handle_get_auxiliary_type(
+                handle: *const heif_image_handle,
+                out_type: *mut *const c_char,
+            ) -> heif_error;
+            pub fn heif_image_handle_free_auxiliary_types(
+                handle: *const heif_image_handle,
+                out_type: *mut *const c_char,
+            );
+            pub fn heif_decode_image(
+                handle: *const heif_image_handle,
+                out_img: *mut *mut heif_image,
+                colorspace: c_int,
+                chroma: c_int,
+                options: *const heif_decoding_options,
+            ) -> heif_error;
+            pub fn heif_image_get_plane_readonly(
+                image: *const heif_image,
+                channel: c_int,
+                out_stride: *mut c_int,
+            ) -> *const u8;
+            pub fn heif_image_release(image: *mut heif_image);
+            pub fn heif_image_get_width(image: *const heif_image, channel: c_int) -> c_int;
+            pub fn heif_image_get_height(image: *const heif_image, channel: c_int) -> c_int;
+            pub fn heif_image_get_bits_per_pixel_range(
+                image: *const heif_image,
+                channel: c_int,
+            ) -> c_int;
+            pub fn heif_decoding_options_alloc() -> *mut heif_decoding_options;
+            pub fn heif_decoding_options_free(options: *mut heif_decoding_options);
+
+            // EXIF metadata
+            pub fn heif_image_handle_get_number_of_metadata_blocks(
+                handle: *const heif_image_handle,
+                type_filter: *const c_char,
+            ) -> c_int;
+            pub fn heif_image_handle_get_list_of_metadata_block_IDs(
+                handle: *const heif_image_handle,
+                type_filter: *const c_char,
+                ids: *mut heif_item_id,
+                count: c_int,
+            ) -> c_int;
+            pub fn heif_image_handle_get_metadata_size(
+                handle: *const heif_image_handle,
+                metadata_id: heif_item_id,
+            ) -> usize;
+            pub fn heif_image_handle_get_metadata(
+                handle: *const heif_image_handle,
+                metadata_id: heif_item_id,
+                out_data: *mut u8,
+            ) -> heif_error;
+        }
     }
 }
 
+#[cfg(feature = "heif")]
 fn load_heic(path: &Path) -> Result<LoadedImage, String> {
+    libheif::load()?;
     let data = read_file_limited(path)?;
 
     unsafe {
@@ -1815,28 +3264,53 @@ fn load_heic(path: &Path) -> Result<LoadedImage, String> {
             ));
         }
 
-        let w = libheif::heif_image_handle_get_width(handle) as u32;
-        let h = libheif::heif_image_handle_get_height(handle) as u32;
-        validate_dimensions(w, h, "HEIC").map_err(|e| {
+        let handle_w = libheif::heif_image_handle_get_width(handle) as u32;
+        let handle_h = libheif::heif_image_handle_get_height(handle) as u32;
+        validate_dimensions(handle_w, handle_h, "HEIC").map_err(|e| {
             libheif::heif_image_handle_release(handle);
             libheif::heif_context_free(ctx);
             e
         })?;
 
+        // libheif applies the container transforms (irot/imir) unless told
+        // to ignore them
+        let policy = orientation_policy();
+        let ignore_container = matches!(policy, OrientationPolicy::Exif | OrientationPolicy::Off);
+        let options = libheif::heif_decoding_options_alloc();
+        if !options.is_null() {
+            (*options).ignore_transformations = ignore_container as u8;
+        }
+
+        // Deeper images are decoded at full depth only for `--dither`
+        let deep = dither() && libheif::heif_image_handle_get_luma_bits_per_pixel(handle) > 8;
+        let chroma = if deep {
+            libheif::HEIF_CHROMA_INTERLEAVED_RRGGBBAA_LE
+        } else {
+            libheif::HEIF_CHROMA_INTERLEAVED_RGBA
+        };
         let mut img_ptr: *mut libheif::heif_image = std::ptr::null_mut();
         let err = libheif::heif_decode_image(
             handle,
             &mut img_ptr,
             libheif::HEIF_COLORSPACE_RGB,
-            libheif::HEIF_CHROMA_INTERLEAVED_RGBA,
-            std::ptr::null(),
+            chroma,
+            options,
         );
         if err.code != libheif::HEIF_ERROR_OK {
+            if !options.is_null() {
+                libheif::heif_decoding_options_free(options);
+            }
             libheif::heif_image_handle_release(handle);
             libheif::heif_context_free(ctx);
             return Err(format!("Failed to decode HEIC {}", path.display()));
         }
 
+        // The handle reports the transformed size; the decoded image may
+        // differ when transforms were skipped
+        let channel = libheif::HEIF_CHANNEL_INTERLEAVED;
+        let w = libheif::heif_image_get_width(img_ptr, channel).max(0) as u32;
+        let h = libheif::heif_image_get_height(img_ptr, channel).max(0) as u32;
+
         let mut stride: c_int = 0;
         let plane = libheif::heif_image_get_plane_readonly(
             img_ptr,
@@ -1844,6 +3318,9 @@ fn load_heic(path: &Path) -> Result<LoadedImage, String> {
             &mut stride,
         );
         if plane.is_null() {
+            if !options.is_null() {
+                libheif::heif_decoding_options_free(options);
+            }
             libheif::heif_image_release(img_ptr);
             libheif::heif_image_handle_release(handle);
             libheif::heif_context_free(ctx);
@@ -1856,14 +3333,41 @@ fn load_heic(path: &Path) -> Result<LoadedImage, String> {
         let stride = stride as usize;
         let pixel_count = (w as usize) * (h as usize) * 4;
         let mut pixels = vec![0u8; pixel_count];
-        for y in 0..h as usize {
-            let src_offset = y * stride;
-            let dst_offset = y * (w as usize) * 4;
-            std::ptr::copy_nonoverlapping(
-                plane.add(src_offset),
-                pixels.as_mut_ptr().add(dst_offset),
-                (w as usize) * 4,
-            );
+        if deep {
+            let bits = libheif::heif_image_get_bits_per_pixel_range(img_ptr, channel).clamp(9, 16);
+            let mut reducer = DepthReducer::new(w as usize, (1u32 << bits) - 1);
+            let mut samples: Vec<u16> = Vec::with_capacity(w as usize * 4);
+            for (y, out) in pixels.chunks_exact_mut(w as usize * 4).enumerate() {
+                let row = std::slice::from_raw_parts(plane.add(y * stride), w as usize * 8);
+                samples.clear();
+                samples.extend(
+                    row.chunks_exact(2)
+                        .map(|b| u16::from_le_bytes([b[0], b[1]])),
+                );
+                reducer.reduce_row(&samples, out);
+            }
+        } else {
+            for y in 0..h as usize {
+                let src_offset = y * stride;
+                let dst_offset = y * (w as usize) * 4;
+                std::ptr::copy_nonoverlapping(
+                    plane.add(src_offset),
+                    pixels.as_mut_ptr().add(dst_offset),
+                    (w as usize) * 4,
+                );
+            }
+        }
+
+        // The alpha plane lives in a separate auxiliary image. libheif
+        // merges it into RGBA output, but fill it in ourselves if the
+        // decode came back opaque anyway.
+        if libheif::heif_image_handle_has_alpha_channel(handle) != 0
+            && pixels.chunks_exact(4).all(|px| px[3] == 255)
+        {
+            merge_heif_alpha(handle, options, &mut pixels, w, h);
+        }
+        if !options.is_null() {
+            libheif::heif_decoding_options_free(options);
         }
 
         // Extract EXIF metadata before releasing handle
@@ -1873,19 +3377,138 @@ fn load_heic(path: &Path) -> Result<LoadedImage, String> {
         libheif::heif_image_handle_release(handle);
         libheif::heif_context_free(ctx);
 
-        let img = RgbaImage::from_raw(w, h, pixels)
+        // libheif composes grid images from the primary handle. With the
+        // container transforms applied the result matches the handle size,
+        // otherwise the grid's stored size.
+        let grid = isobmff_grid_size(&data);
+        let expected = grid.map(|size| {
+            if ignore_container {
+                size
+            } else {
+                (handle_w, handle_h)
+            }
+        });
+        check_grid_decoded(expected, w, h, path)?;
+
+        let mut img = RgbaImage::from_raw(w, h, pixels)
             .ok_or_else(|| "HEIC pixel buffer size mismatch".to_string())?;
 
-        // libheif applies geometric transforms (rotation/mirror) by default
-        // (ignore_transformations=false in decoding options), so we do NOT apply
-        // EXIF orientation ourselves. The EXIF data is kept for tag display only.
-        let _ = exif_data;
+        let container = isobmff_container_orientation(&data);
+        if ignore_container || container.is_none() {
+            let exif = exif_data.and_then(|exif| parse_tiff_orientation(&exif, 0));
+            if let Some(orientation) = policy.resolve(None, exif) {
+                img = apply_orientation(img, orientation);
+            }
+        }
 
         Ok(LoadedImage::Static(img))
     }
 }
 
+/// Auxiliary type URNs marking an alpha plane (HEVC and generic MPEG).
+#[cfg(feature = "heif")]
+const HEIF_ALPHA_AUX_TYPES: [&[u8]; 2] = [
+    b"urn:mpeg:hevc:2015:auxid:1",
+    b"urn:mpeg:mpegB:cicp:systems:auxiliary:alpha",
+];
+
+/// Decode the alpha auxiliary image of `handle` and copy it into the alpha
+/// bytes of the interleaved RGBA `pixels`. Returns false when there is no
+/// alpha auxiliary or its size does not match the primary image.
+#[cfg(feature = "heif")]
+unsafe fn merge_heif_alpha(
+    handle: *const libheif::heif_image_handle,
+    options: *const libheif::heif_decoding_options,
+    pixels: &mut [u8],
+    w: u32,
+    h: u32,
+) -> bool {
+    let count = libheif::heif_image_handle_get_number_of_auxiliary_images(handle, 0);
+    if count <= 0 {
+        return false;
+    }
+    let mut ids = vec![0 as libheif::heif_item_id; count as usize];
+    let count = libheif::heif_image_handle_get_list_of_auxiliary_image_IDs(
+        handle,
+        0,
+        ids.as_mut_ptr(),
+        count,
+    );
+    ids.truncate(count.max(0) as usize);
+
+    for id in ids {
+        let mut aux: *mut libheif::heif_image_handle = std::ptr::null_mut();
+        let err = libheif::heif_image_handle_get_auxiliary_image_handle(handle, id, &mut aux);
+        if err.code != libheif::HEIF_ERROR_OK {
+            continue;
+        }
+        let mut aux_type: *const c_char = std::ptr::null();
+        let err = libheif::heif_image_handle_get_auxiliary_type(aux, &mut aux_type);
+        let is_alpha = err.code == libheif::HEIF_ERROR_OK
+            && !aux_type.is_null()
+            && HEIF_ALPHA_AUX_TYPES.contains(&std::ffi::CStr::from_ptr(aux_type).to_bytes());
+        if !aux_type.is_null() {
+            libheif::heif_image_handle_free_auxiliary_types(aux, &mut aux_type);
+        }
+        let merged = is_alpha && copy_heif_alpha(aux, options, pixels, w, h);
+        libheif::heif_image_handle_release(aux);
+        if merged {
+            return true;
+        }
+    }
+    false
+}
+
+/// Decode an alpha auxiliary handle into `pixels`' alpha bytes. Only the
+/// luma plane is read, so it is decoded as YCbCr in whatever chroma format
+/// the encoder used.
+#[cfg(feature = "heif")]
+unsafe fn copy_heif_alpha(
+    aux: *const libheif::heif_image_handle,
+    options: *const libheif::heif_decoding_options,
+    pixels: &mut [u8],
+    w: u32,
+    h: u32,
+) -> bool {
+    let mut img: *mut libheif::heif_image = std::ptr::null_mut();
+    let err = libheif::heif_decode_image(
+        aux,
+        &mut img,
+        libheif::HEIF_COLORSPACE_YCBCR,
+        libheif::HEIF_CHROMA_UNDEFINED,
+        options,
+    );
+    if err.code != libheif::HEIF_ERROR_OK {
+        return false;
+    }
+    let channel = libheif::HEIF_CHANNEL_Y;
+    let aux_w = libheif::heif_image_get_width(img, channel).max(0) as u32;
+    let aux_h = libheif::heif_image_get_height(img, channel).max(0) as u32;
+    let mut stride: c_int = 0;
+    let plane = libheif::heif_image_get_plane_readonly(img, channel, &mut stride);
+    let ok = !plane.is_null() && (aux_w, aux_h) == (w, h);
+    if ok {
+        // Deeper alpha planes come as 16-bit little-endian samples
+        let bits = libheif::heif_image_get_bits_per_pixel_range(img, channel).clamp(8, 16);
+        let bytes = if bits > 8 { 2 } else { 1 };
+        let stride = stride as usize;
+        for (y, out) in pixels.chunks_exact_mut(w as usize * 4).enumerate() {
+            let row = std::slice::from_raw_parts(plane.add(y * stride), w as usize * bytes);
+            for (px, sample) in out.chunks_exact_mut(4).zip(row.chunks_exact(bytes)) {
+                px[3] = if bytes == 2 {
+                    (u16::from_le_bytes([sample[0], sample[1]]) >> (bits - 8)) as u8
+                } else {
+                    sample[0]
+                };
+            }
+        }
+    }
+    libheif::heif_image_release(img);
+    ok
+}
+
 /// Extract raw EXIF data from a HEIF image handle via libheif metadata API.
+#[cfg(feature = "heif")]
 unsafe fn extract_heif_exif(handle: *const libheif::heif_image_handle) -> Option<Vec<u8>> {
     let exif_filter = b"Exif\0".as_ptr() as *const c_char;
     let count = libheif::heif_image_handle_get_number_of_metadata_blocks(handle, exif_filter);
@@ -1939,6 +3562,7 @@ pub fn read_exif_tags_heic(data: &[u8]) -> Vec<(String, String)> {
 // JPEG XL via system libjxl
 // ============================================================
 
+#[cfg(feature = "jxl")]
 #[allow(non_camel_case_types)]
 mod libjxl {
     use std::os::raw::c_void;
@@ -2038,57 +3662,64 @@ mod libjxl {
         pub layer_info: JxlLayerInfo,
     }
 
-    #[link(name = "jxl")]
-    extern "C" {
-        pub fn JxlDecoderCreate(memory_manager: *const c_void) -> *mut JxlDecoder;
-        pub fn JxlDecoderDestroy(dec: *mut JxlDecoder);
-        pub fn JxlDecoderSubscribeEvents(dec: *mut JxlDecoder, events_wanted: i32) -> u32;
-        pub fn JxlDecoderSetInput(dec: *mut JxlDecoder, data: *const u8, size: usize) -> u32;
-        pub fn JxlDecoderCloseInput(dec: *mut JxlDecoder);
-        pub fn JxlDecoderProcessInput(dec: *mut JxlDecoder) -> u32;
-        pub fn JxlDecoderGetBasicInfo(dec: *const JxlDecoder, info: *mut JxlBasicInfo) -> u32;
-        pub fn JxlDecoderGetFrameHeader(dec: *const JxlDecoder, header: *mut JxlFrameHeader)
-            -> u32;
-        pub fn JxlDecoderImageOutBufferSize(
-            dec: *const JxlDecoder,
-            format: *const JxlPixelFormat,
-            size: *mut usize,
-        ) -> u32;
-        pub fn JxlDecoderSetImageOutBuffer(
-            dec: *mut JxlDecoder,
-            format: *const JxlPixelFormat,
-            buffer: *mut u8,
-            size: usize,
-        ) -> u32;
-        pub fn JxlDecoderSetParallelRunner(
-            dec: *mut JxlDecoder,
-            parallel_runner: *const c_void,
-            parallel_runner_opaque: *mut c_void,
-        ) -> u32;
-    }
-
-    #[link(name = "jxl_threads")]
-    extern "C" {
-        pub fn JxlThreadParallelRunnerCreate(
-            memory_manager: *const c_void,
-            num_worker_threads: usize,
-        ) -> *mut c_void;
-        pub fn JxlThreadParallelRunnerDestroy(runner_opaque: *mut c_void);
-        pub fn JxlThreadParallelRunnerDefaultNumWorkerThreads() -> usize;
+    crate::dynlib::dynamic_extern! {
+        "libjxl";
+        ["libjxl.so.0.11", "libjxl.so.0.10", "libjxl.so.0.9", "libjxl.so.0.8"] {
+            pub fn JxlDecoderCreate(memory_manager: *const c_void) -> *mut JxlDecoder;
+            pub fn JxlDecoderDestroy(dec: *mut JxlDecoder);
+            pub fn JxlDecoderSubscribeEvents(dec: *mut JxlDecoder, events_wanted: i32) -> u32;
+            pub fn JxlDecoderSetInput(dec: *mut JxlDecoder, data: *const u8, size: usize) -> u32;
+            pub fn JxlDecoderCloseInput(dec: *mut JxlDecoder);
+            pub fn JxlDecoderProcessInput(dec: *mut JxlDecoder) -> u32;
+            pub fn JxlDecoderGetBasicInfo(dec: *const JxlDecoder, info: *mut JxlBasicInfo) -> u32;
+            pub fn JxlDecoderGetFrameHeader(dec: *const JxlDecoder, header: *mut JxlFrameHeader)
+                -> u32;
+            pub fn JxlDecoderImageOutBufferSize(
+                dec: *const JxlDecoder,
+                format: *const JxlPixelFormat,
+                size: *mut usize,
+            ) -> u32;
+            pub fn JxlDecoderSetImageOutBuffer(
+                dec: *mut JxlDecoder,
+                format: *const JxlPixelFormat,
+                buffer: *mut u8,
+                size: usize,
+            ) -> u32;
+            pub fn JxlDecoderSetParallelRunner(
+                dec: *mut JxlDecoder,
+                parallel_runner: *const c_void,
+                parallel_runner_opaque: *mut c_void,
+            ) -> u32;
+        }
 
-        // The actual runner function — used as a function pointer
-        pub fn JxlThreadParallelRunner(
-            runner_opaque: *mut c_void,
-            jpegxl_opaque: *mut c_void,
-            init: *mut c_void,
-            func: *mut c_void,
-            start_range: u32,
-            end_range: u32,
-        ) -> i32;
+        [
+            "libjxl_threads.so.0.11",
+            "libjxl_threads.so.0.10",
+            "libjxl_threads.so.0.9",
+            "libjxl_threads.so.0.8",
+        ] {
+            pub fn JxlThreadParallelRunnerCreate(
+                memory_manager: *const c_void,
+                num_worker_threads: usize,
+            ) -> *mut c_void;
+            pub fn JxlThreadParallelRunnerDestroy(runner_opaque: *mut c_void);
+
+            // The actual runner function — used as a function pointer
+            pub fn JxlThreadParallelRunner(
+                runner_opaque: *mut c_void,
+                jpegxl_opaque: *mut c_void,
+                init: *mut c_void,
+                func: *mut c_void,
+                start_range: u32,
+                end_range: u32,
+            ) -> i32;
+        }
     }
 }
 
+#[cfg(feature = "jxl")]
 fn load_jxl(path: &Path) -> Result<LoadedImage, String> {
+    let api = libjxl::api()?;
     let data = read_file_limited(path)?;
 
     unsafe {
@@ -2098,12 +3729,11 @@ fn load_jxl(path: &Path) -> Result<LoadedImage, String> {
         }
 
         // Set up thread parallel runner
-        let num_threads = libjxl::JxlThreadParallelRunnerDefaultNumWorkerThreads();
-        let runner = libjxl::JxlThreadParallelRunnerCreate(std::ptr::null(), num_threads);
+        let runner = libjxl::JxlThreadParallelRunnerCreate(std::ptr::null(), decode_threads());
         if !runner.is_null() {
             libjxl::JxlDecoderSetParallelRunner(
                 dec,
-                libjxl::JxlThreadParallelRunner as *const c_void,
+                api.JxlThreadParallelRunner as *const c_void,
                 runner,
             );
         }
@@ -2203,7 +3833,7 @@ fn load_jxl(path: &Path) -> Result<LoadedImage, String> {
                         // We push a placeholder that we'll update
                         frames.push((
                             RgbaImage::new(1, 1), // placeholder
-                            Duration::from_millis(duration_ms.max(10)),
+                            frame_duration(duration_ms),
                         ));
                     }
                 }
@@ -2282,12 +3912,9 @@ fn load_jxl(path: &Path) -> Result<LoadedImage, String> {
             // Apply orientation to all frames
             let orientation = info.orientation;
             if orientation >= 2 && orientation <= 8 {
+                let mut scratch = Vec::new();
                 for frame in &mut frames {
-                    let rotated = apply_orientation(
-                        std::mem::replace(&mut frame.0, RgbaImage::new(1, 1)),
-                        orientation,
-                    );
-                    frame.0 = rotated;
+                    orient_in_place(&mut frame.0, orientation, &mut scratch);
                 }
             }
             Ok(LoadedImage::Animated { frames })
@@ -2297,6 +3924,7 @@ fn load_jxl(path: &Path) -> Result<LoadedImage, String> {
     }
 }
 
+#[cfg(feature = "jxl")]
 unsafe fn cleanup_jxl(dec: *mut libjxl::JxlDecoder, runner: *mut c_void) {
     libjxl::JxlDecoderDestroy(dec);
     if !runner.is_null() {
@@ -2412,60 +4040,375 @@ fn load_jpeg_thumbnail(path: &Path, thumb_size: u32) -> Result<RgbaImage, String
     let mut decompressor = turbojpeg::Decompressor::new()
         .map_err(|e| format!("Failed to create decompressor: {}", e))?;
 
-    let header = decompressor
-        .read_header(&data)
-        .map_err(|e| format!("Failed to read JPEG header {}: {}", path.display(), e))?;
+    let header = decompressor
+        .read_header(&data)
+        .map_err(|e| format!("Failed to read JPEG header {}: {}", path.display(), e))?;
+
+    // Pick the best DCT scaling factor: smallest where both scaled dims >= thumb_size
+    let thumb_size = thumb_size as usize;
+    let best = JPEG_SCALING_FACTORS
+        .into_iter()
+        .rev()
+        .find(|sf| sf.scale(header.width) >= thumb_size && sf.scale(header.height) >= thumb_size)
+        .unwrap_or(turbojpeg::ScalingFactor::ONE);
+
+    let img = decompress_jpeg_scaled(&mut decompressor, &data, &header, best)
+        .map_err(|e| format!("Failed to decode JPEG {}: {}", path.display(), e))?;
+
+    let img = orient_jpeg(img, &data);
+    Ok(crate::render::generate_thumbnail(&img, thumb_size as u32))
+}
+
+// ============================================================
+// Header-only size probes
+// ============================================================
+
+/// Bytes read from the start of a file to find its size and orientation;
+/// covers a maximal JPEG EXIF segment.
+const HEADER_PROBE_LEN: u64 = 128 * 1024;
+
+/// Largest `meta` box read to find the size of an AVIF/HEIF image.
+const MAX_META_PROBE_LEN: u64 = 4 * 1024 * 1024;
+
+/// Read up to `len` bytes from the start of the file at `path`.
+fn read_file_head(path: &Path, len: u64) -> Option<Vec<u8>> {
+    read_file_at(path, 0, len)
+}
+
+/// Read up to `len` bytes at `offset` in the file at `path`.
+fn read_file_at(path: &Path, offset: u64, len: u64) -> Option<Vec<u8>> {
+    let mut file = fs::File::open(path).ok()?;
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    let mut data = Vec::new();
+    file.take(len).read_to_end(&mut data).ok()?;
+    Some(data)
+}
+
+/// Width and height of the image at `path` as stored, read from its header
+/// without decoding it. EXIF and container orientation are not applied.
+/// `None` for formats without a size in the header (SVG) or an unreadable
+/// header.
+pub fn image_dimensions(path: &Path) -> Option<(u32, u32)> {
+    let ext = ascii_lower(path.extension().and_then(|e| e.to_str()).unwrap_or(""));
+    let read = |offset: u64, len: u64| read_file_at(path, offset, len);
+    let size = match ext.as_str() {
+        // The size may sit anywhere in these files; seek to it
+        "tiff" | "tif" => tiff_dimensions(&read),
+        "avif" | "heic" | "heif" => isobmff_dimensions(&read),
+        _ => {
+            let data = read_file_head(path, HEADER_PROBE_LEN)?;
+            match ext.as_str() {
+                "jpg" | "jpeg" => jpeg_dimensions(&data),
+                "png" => png_dimensions(&data),
+                "gif" => gif_dimensions(&data),
+                "bmp" => bmp_dimensions(&data),
+                "webp" => webp_dimensions(&data),
+                "jxl" => jxl_dimensions(&data),
+                _ => None,
+            }
+        }
+    };
+    size.filter(|&(w, h)| w > 0 && h > 0)
+}
+
+/// Width and height from the first SOF marker of a JPEG.
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    if data.len() < 4 || data[0] != 0xFF || data[1] != 0xD8 {
+        return None;
+    }
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return None;
+        }
+        let marker = data[pos + 1];
+        // Fill bytes and markers without a length
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            pos += 2;
+            continue;
+        }
+        if marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+        let seg_len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        // SOF0-SOF15, except DHT, JPG and DAC which share the range
+        if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+            let sof = data.get(pos + 5..pos + 9)?;
+            let height = u16::from_be_bytes([sof[0], sof[1]]) as u32;
+            let width = u16::from_be_bytes([sof[2], sof[3]]) as u32;
+            return (width > 0 && height > 0).then_some((width, height));
+        }
+        pos += 2 + seg_len;
+    }
+    None
+}
+
+/// Logical screen size of a GIF.
+fn gif_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    if data.len() < 10 || (&data[0..6] != b"GIF87a" && &data[0..6] != b"GIF89a") {
+        return None;
+    }
+    let width = u16::from_le_bytes([data[6], data[7]]) as u32;
+    let height = u16::from_le_bytes([data[8], data[9]]) as u32;
+    Some((width, height))
+}
+
+/// Size from the DIB header of a BMP; bottom-up and top-down alike.
+fn bmp_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    if data.len() < 14 + BMP_CORE_HEADER_SIZE || &data[0..2] != b"BM" {
+        return None;
+    }
+    let dib_header_size = u32::from_le_bytes([data[14], data[15], data[16], data[17]]) as usize;
+    if dib_header_size == BMP_CORE_HEADER_SIZE {
+        let width = u16::from_le_bytes([data[18], data[19]]) as u32;
+        let height = u16::from_le_bytes([data[20], data[21]]) as u32;
+        return Some((width, height));
+    }
+    let d = data.get(18..26)?;
+    let width = i32::from_le_bytes([d[0], d[1], d[2], d[3]]);
+    let height = i32::from_le_bytes([d[4], d[5], d[6], d[7]]);
+    (width > 0).then_some((width as u32, height.unsigned_abs()))
+}
+
+/// Canvas size of a WebP from its first chunk: the VP8X canvas, or the
+/// frame header of a simple lossy (VP8) or lossless (VP8L) file.
+fn webp_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    validate_webp_riff(data).ok()?;
+    let payload = &data[20..];
+    let u24 = |b: &[u8]| b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16;
+    match &data[12..16] {
+        b"VP8X" => {
+            let p = payload.get(..10)?;
+            Some((u24(&p[4..7]) + 1, u24(&p[7..10]) + 1))
+        }
+        b"VP8L" => {
+            let p = payload.get(..5)?;
+            if p[0] != 0x2F {
+                return None;
+            }
+            let bits = u32::from_le_bytes([p[1], p[2], p[3], p[4]]);
+            Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+        }
+        _ => {
+            // VP8: 3-byte frame tag, start code, then 14-bit sizes
+            let p = payload.get(..10)?;
+            if p[3..6] != [0x9D, 0x01, 0x2A] {
+                return None;
+            }
+            let width = u16::from_le_bytes([p[6], p[7]]) & 0x3FFF;
+            let height = u16::from_le_bytes([p[8], p[9]]) & 0x3FFF;
+            Some((width as u32, height as u32))
+        }
+    }
+}
+
+/// Size from the first IFD of a TIFF, using `read(offset, len)` to fetch
+/// the header and the IFD wherever it is in the file.
+fn tiff_dimensions(read: &dyn Fn(u64, u64) -> Option<Vec<u8>>) -> Option<(u32, u32)> {
+    let header = read(0, 8)?;
+    let le = match header.get(..4)? {
+        b"II\x2a\0" => true,
+        b"MM\0\x2a" => false,
+        _ => return None,
+    };
+    let u16_at = |b: &[u8], at: usize| {
+        let v = [b[at], b[at + 1]];
+        if le {
+            u16::from_le_bytes(v)
+        } else {
+            u16::from_be_bytes(v)
+        }
+    };
+    let u32_at = |b: &[u8], at: usize| {
+        let v = [b[at], b[at + 1], b[at + 2], b[at + 3]];
+        if le {
+            u32::from_le_bytes(v)
+        } else {
+            u32::from_be_bytes(v)
+        }
+    };
+    let ifd = u32_at(&header, 4) as u64;
+    let count = u16_at(read(ifd, 2)?.get(..2)?, 0) as u64;
+    let entries = read(ifd + 2, count * 12)?;
+    let (mut width, mut height) = (None, None);
+    for entry in entries.chunks_exact(12) {
+        // ImageWidth and ImageLength, stored as SHORT (3) or LONG (4)
+        let value = match u16_at(entry, 2) {
+            3 => u16_at(entry, 8) as u32,
+            4 => u32_at(entry, 8),
+            _ => continue,
+        };
+        match u16_at(entry, 0) {
+            0x0100 => width = Some(value),
+            0x0101 => height = Some(value),
+            _ => {}
+        }
+    }
+    Some((width?, height?))
+}
+
+/// Size of the primary item of an AVIF/HEIF file from its `ispe` property.
+/// Walks the top-level boxes with `read(offset, len)` to find `meta`, which
+/// some files put after the image data.
+fn isobmff_dimensions(read: &dyn Fn(u64, u64) -> Option<Vec<u8>>) -> Option<(u32, u32)> {
+    let mut pos = 0u64;
+    loop {
+        let header = read(pos, 16)?;
+        if header.len() < 8 {
+            return None;
+        }
+        let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let (header_len, size) = match size {
+            0 => (8, None), // extends to the end of the file
+            1 => {
+                let ext = header.get(8..16)?;
+                let ext = u64::from_be_bytes([
+                    ext[0], ext[1], ext[2], ext[3], ext[4], ext[5], ext[6], ext[7],
+                ]);
+                (16, Some(ext))
+            }
+            n => (8, Some(n)),
+        };
+        if &header[4..8] == b"meta" {
+            let len = size.map_or(MAX_META_PROBE_LEN, |s| s.saturating_sub(header_len));
+            let meta = read(pos + header_len, len.min(MAX_META_PROBE_LEN))?;
+            let meta = meta.get(4..)?; // skip version/flags
+            return isobmff_item_size(meta, isobmff_primary_item(meta)?);
+        }
+        match size {
+            Some(size) if size >= header_len => pos = pos.checked_add(size)?,
+            _ => return None,
+        }
+    }
+}
+
+/// Size from the SizeHeader at the start of a JPEG XL codestream, bare or
+/// in the first `jxlc`/`jxlp` box of a container.
+fn jxl_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let codestream = if data.starts_with(&[0xFF, 0x0A]) {
+        data
+    } else {
+        if data.get(4..8) != Some(b"JXL ") {
+            return None;
+        }
+        // The codestream box usually runs past the bytes read; its start
+        // is all that is needed
+        let mut pos = 0;
+        loop {
+            let header = data.get(pos..pos + 8)?;
+            let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+            let (header_len, end) = match size {
+                0 => (8, data.len()),
+                1 => {
+                    let ext = data.get(pos + 8..pos + 16)?;
+                    let ext = u64::from_be_bytes([
+                        ext[0], ext[1], ext[2], ext[3], ext[4], ext[5], ext[6], ext[7],
+                    ]);
+                    (16, pos.saturating_add(usize::try_from(ext).ok()?))
+                }
+                n => (8, pos.saturating_add(n as usize)),
+            };
+            if end < pos + header_len {
+                return None;
+            }
+            let payload = data.get(pos + header_len..end.min(data.len()))?;
+            match &header[4..8] {
+                b"jxlc" => break payload,
+                b"jxlp" => break payload.get(4..)?, // after the part index
+                _ => pos = end,
+            }
+        }
+    };
+    if !codestream.starts_with(&[0xFF, 0x0A]) {
+        return None;
+    }
+
+    let mut bits = JxlBits {
+        data: codestream,
+        pos: 16,
+    };
+    let small = bits.read(1)? == 1;
+    let height = bits.dimension(small)?;
+    let width = match bits.read(3)? {
+        0 => bits.dimension(small)?,
+        // Width from a fixed aspect ratio
+        ratio => {
+            let ratios = [(1, 1), (12, 10), (4, 3), (3, 2), (16, 9), (5, 4), (2, 1)];
+            let (num, den) = ratios[ratio as usize - 1];
+            (height as u64 * num / den) as u32
+        }
+    };
+    Some((width, height))
+}
 
-    // Pick the best DCT scaling factor: smallest where both scaled dims >= thumb_size
-    let scaling_factors = [
-        turbojpeg::ScalingFactor::ONE_EIGHTH,
-        turbojpeg::ScalingFactor::ONE_QUARTER,
-        turbojpeg::ScalingFactor::ONE_HALF,
-        turbojpeg::ScalingFactor::ONE,
-    ];
+/// Reader for the bit-packed fields of a JPEG XL header, which are stored
+/// least significant bit first.
+struct JxlBits<'a> {
+    data: &'a [u8],
+    /// Position in bits.
+    pos: usize,
+}
 
-    let mut best = turbojpeg::ScalingFactor::ONE;
-    for &sf in &scaling_factors {
-        let sw = sf.scale(header.width);
-        let sh = sf.scale(header.height);
-        if sw >= thumb_size as usize && sh >= thumb_size as usize {
-            best = sf;
-            break;
+impl JxlBits<'_> {
+    fn read(&mut self, n: usize) -> Option<u32> {
+        let mut value = 0;
+        for i in 0..n {
+            let byte = *self.data.get(self.pos / 8)?;
+            value |= ((byte >> (self.pos % 8)) as u32 & 1) << i;
+            self.pos += 1;
         }
+        Some(value)
     }
 
-    if best != turbojpeg::ScalingFactor::ONE {
-        decompressor
-            .set_scaling_factor(best)
-            .map_err(|e| format!("Failed to set scaling factor: {}", e))?;
+    /// A SizeHeader dimension: a multiple of 8 up to 256 in small headers,
+    /// else a value of 9, 13, 18 or 30 bits picked by a 2-bit selector.
+    fn dimension(&mut self, small: bool) -> Option<u32> {
+        if small {
+            return Some((self.read(5)? + 1) * 8);
+        }
+        let n = [9, 13, 18, 30][self.read(2)? as usize];
+        Some(self.read(n)? + 1)
     }
+}
 
-    let scaled_header = header.scaled(best);
-    let w = scaled_header.width;
-    let h = scaled_header.height;
-    let pitch = w * 4;
+/// Width and height from the IHDR chunk of a PNG.
+fn png_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    if data.len() < 24 || &data[0..8] != b"\x89PNG\r\n\x1a\n" || &data[12..16] != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes([data[16], data[17], data[18], data[19]]);
+    let height = u32::from_be_bytes([data[20], data[21], data[22], data[23]]);
+    (width > 0 && height > 0).then_some((width, height))
+}
 
-    let mut image = turbojpeg::Image {
-        pixels: vec![0u8; h * pitch],
-        width: w,
-        pitch,
-        height: h,
-        format: turbojpeg::PixelFormat::RGBA,
+/// Size an image will be displayed at, read from its header alone, with
+/// EXIF orientation applied. `None` for formats whose orientation is not
+/// found as cheaply (only JPEG, PNG, GIF and BMP are probed) or an
+/// unreadable header. Cheap enough to size gallery placeholders.
+pub fn probe_display_size(path: &Path) -> Option<(u32, u32)> {
+    let ext = ascii_lower(path.extension().and_then(|e| e.to_str()).unwrap_or(""));
+    let (size, orientation) = match ext.as_str() {
+        "jpg" | "jpeg" => {
+            let data = read_file_head(path, HEADER_PROBE_LEN)?;
+            (jpeg_dimensions(&data)?, read_exif_orientation(&data))
+        }
+        "png" => {
+            let data = read_file_head(path, HEADER_PROBE_LEN)?;
+            (png_dimensions(&data)?, read_exif_orientation_png(&data))
+        }
+        "gif" | "bmp" => (image_dimensions(path)?, None),
+        _ => return None,
     };
-
-    decompressor
-        .decompress(&data, image.as_deref_mut())
-        .map_err(|e| format!("Failed to decode JPEG {}: {}", path.display(), e))?;
-
-    let mut img = RgbaImage::from_raw(w as u32, h as u32, image.pixels)
-        .ok_or_else(|| "JPEG pixel buffer size mismatch".to_string())?;
-
-    // Apply EXIF orientation
-    if let Some(orientation) = read_exif_orientation(&data) {
-        img = apply_orientation(img, orientation);
+    let (w, h) = size;
+    match orientation.filter(|_| exif_orientation_enabled()) {
+        // Orientations 5-8 include a quarter turn
+        Some(5..=8) => Some((h, w)),
+        _ => Some((w, h)),
     }
-
-    Ok(crate::render::generate_thumbnail(&img, thumb_size))
 }
 
 // ============================================================
@@ -2475,6 +4418,26 @@ fn load_jpeg_thumbnail(path: &Path, thumb_size: u32) -> Result<RgbaImage, String
 /// Parse EXIF orientation tag from raw JPEG data.
 /// Looks for APP1 marker, parses TIFF header, walks IFD0 for tag 0x0112.
 fn read_exif_orientation(data: &[u8]) -> Option<u32> {
+    parse_tiff_orientation(data, jpeg_exif_tiff_start(data)?)
+}
+
+/// Reset the EXIF orientation tag of a JPEG to 1 (normal), if it has one.
+fn set_jpeg_orientation_normal(data: &mut [u8]) {
+    let Some(tiff_start) = jpeg_exif_tiff_start(data) else {
+        return;
+    };
+    if let Some((off, le)) = find_tiff_orientation(data, tiff_start) {
+        let one = if le {
+            1u16.to_le_bytes()
+        } else {
+            1u16.to_be_bytes()
+        };
+        data[off..off + 2].copy_from_slice(&one);
+    }
+}
+
+/// Offset of the TIFF header inside the EXIF APP1 segment of a JPEG.
+fn jpeg_exif_tiff_start(data: &[u8]) -> Option<usize> {
     // JPEG must start with SOI (0xFFD8)
     if data.len() < 4 || data[0] != 0xFF || data[1] != 0xD8 {
         return None;
@@ -2498,8 +4461,7 @@ fn read_exif_orientation(data: &[u8]) -> Option<u32> {
                 pos += 2 + seg_len;
                 continue;
             }
-            let tiff_start = seg_start + 6;
-            return parse_tiff_orientation(data, tiff_start);
+            return Some(seg_start + 6);
         }
         if marker == 0xDA {
             break; // SOS — no more markers before image data
@@ -2510,6 +4472,19 @@ fn read_exif_orientation(data: &[u8]) -> Option<u32> {
 }
 
 fn parse_tiff_orientation(data: &[u8], tiff_offset: usize) -> Option<u32> {
+    let (off, le) = find_tiff_orientation(data, tiff_offset)?;
+    let bytes = [data[off], data[off + 1]];
+    let value = if le {
+        u16::from_le_bytes(bytes)
+    } else {
+        u16::from_be_bytes(bytes)
+    };
+    Some(value as u32)
+}
+
+/// Locate the IFD0 orientation value (tag 0x0112) of the TIFF structure at
+/// `tiff_offset`: its absolute offset in `data` and whether it is little-endian.
+fn find_tiff_orientation(data: &[u8], tiff_offset: usize) -> Option<(usize, bool)> {
     if tiff_offset + 8 > data.len() {
         return None;
     }
@@ -2566,30 +4541,402 @@ fn parse_tiff_orientation(data: &[u8], tiff_offset: usize) -> Option<u32> {
         let tag = read_u16(entry_off)?;
         if tag == 0x0112 {
             // Orientation tag — value is in offset field for SHORT type
-            let value = read_u16(entry_off + 8)?;
-            return Some(value as u32);
+            read_u16(entry_off + 8)?;
+            return Some((tiff_offset + entry_off + 8, le));
+        }
+    }
+    None
+}
+
+// ============================================================
+// Pixel aspect ratio
+// ============================================================
+
+/// Read the pixel aspect ratio (pixel width / pixel height) of an image as
+/// displayed, from JFIF density (JPEG), `pHYs` (PNG) or `pasp` (AVIF/HEIF).
+/// EXIF orientations that swap the axes are taken into account.
+/// Returns 1.0 (square pixels) when the format carries no such data.
+pub fn read_pixel_aspect(data: &[u8], ext: &str) -> f64 {
+    let (aspect, orientation) = match ext {
+        "jpg" | "jpeg" => (jfif_pixel_aspect(data), read_exif_orientation(data)),
+        "png" => (png_pixel_aspect(data), read_exif_orientation_png(data)),
+        // pasp refers to the stored image, before irot/imir
+        "avif" | "heic" | "heif" => (isobmff_pixel_aspect(data), isobmff_orientation(data)),
+        _ => (None, None),
+    };
+    let aspect = aspect.unwrap_or(1.0);
+    match orientation.filter(|_| exif_orientation_enabled()) {
+        Some(5..=8) => 1.0 / aspect,
+        _ => aspect,
+    }
+}
+
+/// Ratio `num / den`, rejecting zero and implausible values.
+fn sane_aspect(num: u32, den: u32) -> Option<f64> {
+    if num == 0 || den == 0 {
+        return None;
+    }
+    let aspect = num as f64 / den as f64;
+    (0.1..=10.0).contains(&aspect).then_some(aspect)
+}
+
+/// JPEG: X/Y density from a JFIF APP0 segment directly after SOI.
+fn jfif_pixel_aspect(data: &[u8]) -> Option<f64> {
+    if data.len() < 18 || data[0..4] != [0xFF, 0xD8, 0xFF, 0xE0] || &data[6..11] != b"JFIF\0" {
+        return None;
+    }
+    let x_density = u16::from_be_bytes([data[14], data[15]]) as u32;
+    let y_density = u16::from_be_bytes([data[16], data[17]]) as u32;
+    sane_aspect(y_density, x_density)
+}
+
+/// PNG: pixels-per-unit from the `pHYs` chunk.
+fn png_pixel_aspect(data: &[u8]) -> Option<f64> {
+    if data.len() < 8 || &data[0..4] != b"\x89PNG" {
+        return None;
+    }
+    let mut pos = 8;
+    while pos + 8 <= data.len() {
+        let len =
+            u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let chunk_type = &data[pos + 4..pos + 8];
+        let body = pos + 8;
+        if chunk_type == b"IDAT" || body.checked_add(len)? > data.len() {
+            break;
+        }
+        if chunk_type == b"pHYs" && len >= 9 {
+            let d = &data[body..body + 8];
+            let x_ppu = u32::from_be_bytes([d[0], d[1], d[2], d[3]]);
+            let y_ppu = u32::from_be_bytes([d[4], d[5], d[6], d[7]]);
+            return sane_aspect(y_ppu, x_ppu);
+        }
+        pos = body + len + 4; // skip CRC
+    }
+    None
+}
+
+/// AVIF/HEIF: `pasp` property in meta/iprp/ipco.
+fn isobmff_pixel_aspect(data: &[u8]) -> Option<f64> {
+    let meta = find_isobmff_box(data, b"meta")?;
+    let iprp = find_isobmff_box(meta.get(4..)?, b"iprp")?; // skip version/flags
+    let ipco = find_isobmff_box(iprp, b"ipco")?;
+    let pasp = find_isobmff_box(ipco, b"pasp")?;
+    if pasp.len() < 8 {
+        return None;
+    }
+    let h_spacing = u32::from_be_bytes([pasp[0], pasp[1], pasp[2], pasp[3]]);
+    let v_spacing = u32::from_be_bytes([pasp[4], pasp[5], pasp[6], pasp[7]]);
+    sane_aspect(h_spacing, v_spacing)
+}
+
+/// Find the first ISOBMFF box of the given type at this nesting level and
+/// return its payload.
+fn find_isobmff_box<'a>(data: &'a [u8], box_type: &[u8; 4]) -> Option<&'a [u8]> {
+    isobmff_boxes(data)
+        .into_iter()
+        .find(|(t, _)| t == box_type)
+        .map(|(_, payload)| payload)
+}
+
+/// Type and payload of the ISOBMFF boxes at this nesting level, up to the
+/// first malformed one.
+fn isobmff_boxes(data: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut boxes = Vec::new();
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let size =
+            u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let (header, size) = match size {
+            0 => (8, data.len() - pos), // box extends to end
+            1 => {
+                let Some(ext) = data.get(pos + 8..pos + 16) else {
+                    break;
+                };
+                let ext = u64::from_be_bytes([
+                    ext[0], ext[1], ext[2], ext[3], ext[4], ext[5], ext[6], ext[7],
+                ]);
+                match usize::try_from(ext) {
+                    Ok(ext) => (16, ext),
+                    Err(_) => break,
+                }
+            }
+            n => (8, n),
+        };
+        if size < header || pos.saturating_add(size) > data.len() {
+            break;
         }
+        let box_type = [data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]];
+        boxes.push((box_type, &data[pos + header..pos + size]));
+        pos += size;
+    }
+    boxes
+}
+
+/// Big-endian integer of 1-4 bytes at `pos`.
+fn read_be(data: &[u8], pos: usize, len: usize) -> Option<u32> {
+    let bytes = data.get(pos..pos.checked_add(len)?)?;
+    Some(bytes.iter().fold(0, |v, &b| v << 8 | b as u32))
+}
+
+/// Output size of an AVIF/HEIF file whose primary item is a `grid`: a
+/// derived image that composes several coded tiles into one canvas, as
+/// cameras do for large photos. The size comes from the grid item's `ispe`
+/// property. `None` when the primary item is not a grid.
+#[cfg(any(feature = "avif", feature = "heif"))]
+fn isobmff_grid_size(data: &[u8]) -> Option<(u32, u32)> {
+    let meta = find_isobmff_box(data, b"meta")?.get(4..)?; // skip version/flags
+    let primary = isobmff_primary_item(meta)?;
+
+    // iinf: one infe box (version 2 or 3) per item with its type
+    let iinf = find_isobmff_box(meta, b"iinf")?;
+    let entries = iinf.get(if *iinf.first()? == 0 { 6 } else { 8 }..)?;
+    let is_grid = isobmff_boxes(entries).into_iter().any(|(t, infe)| {
+        let id_len = match infe.first() {
+            Some(2) => 2,
+            Some(3) => 4,
+            _ => return false,
+        };
+        t == *b"infe"
+            && read_be(infe, 4, id_len) == Some(primary)
+            && infe.get(id_len + 6..id_len + 10) == Some(b"grid")
+    });
+    if !is_grid {
+        return None;
+    }
+    isobmff_item_size(meta, primary)
+}
+
+/// ID of the primary item, from the `pitm` box in the `meta` payload
+/// (after its version and flags).
+fn isobmff_primary_item(meta: &[u8]) -> Option<u32> {
+    let pitm = find_isobmff_box(meta, b"pitm")?;
+    read_be(pitm, 4, if *pitm.first()? == 0 { 2 } else { 4 })
+}
+
+/// Size of `item` from the `ispe` property associated with it in `ipma`.
+fn isobmff_item_size(meta: &[u8], item: u32) -> Option<(u32, u32)> {
+    let iprp = find_isobmff_box(meta, b"iprp")?;
+    let properties = isobmff_boxes(find_isobmff_box(iprp, b"ipco")?);
+    let ipma = find_isobmff_box(iprp, b"ipma")?;
+    let (version, flags) = (*ipma.first()?, *ipma.get(3)?);
+    let (id_len, index_len) = (if version < 1 { 2 } else { 4 }, 1 + (flags & 1) as usize);
+    let mut pos = 8;
+    for _ in 0..read_be(ipma, 4, 4)? {
+        let id = read_be(ipma, pos, id_len)?;
+        let count = read_be(ipma, pos + id_len, 1)? as usize;
+        pos += id_len + 1;
+        if id == item {
+            // Property indices are 1-based; the top bit marks essential ones
+            return (0..count).find_map(|i| {
+                let index = read_be(ipma, pos + i * index_len, index_len)?;
+                let mask = if index_len == 1 { 0x7F } else { 0x7FFF };
+                let (t, ispe) = properties.get((index & mask).checked_sub(1)? as usize)?;
+                (t == b"ispe").then_some((read_be(ispe, 4, 4)?, read_be(ispe, 8, 4)?))
+            });
+        }
+        pos += count * index_len;
     }
     None
 }
 
+/// Fail when a grid image (see `isobmff_grid_size`) was decoded at another
+/// size than `expected`, which means the decoder returned one tile instead
+/// of the composed canvas.
+#[cfg(any(feature = "avif", feature = "heif"))]
+fn check_grid_decoded(
+    expected: Option<(u32, u32)>,
+    w: u32,
+    h: u32,
+    path: &Path,
+) -> Result<(), String> {
+    match expected {
+        Some((gw, gh)) if (gw, gh) != (w, h) => Err(format!(
+            "Decoded a {}x{} tile instead of the {}x{} grid image {}",
+            w,
+            h,
+            gw,
+            gh,
+            path.display()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Orientation from the `irot`/`imir` properties of an AVIF/HEIF file, or
+/// `None` when it has neither. Like `isobmff_pixel_aspect`, this looks at the
+/// first property of each kind and assumes rotation before mirroring.
+fn isobmff_container_orientation(data: &[u8]) -> Option<u32> {
+    let meta = find_isobmff_box(data, b"meta")?;
+    let iprp = find_isobmff_box(meta.get(4..)?, b"iprp")?; // skip version/flags
+    let ipco = find_isobmff_box(iprp, b"ipco")?;
+    // irot: anti-clockwise rotation in 90 degree steps
+    let irot = find_isobmff_box(ipco, b"irot").and_then(|b| b.first().map(|a| a & 3));
+    // imir: mirror about the vertical (0) or horizontal (1) axis
+    let imir = find_isobmff_box(ipco, b"imir").and_then(|b| b.first().map(|a| a & 1));
+    if irot.is_none() && imir.is_none() {
+        return None;
+    }
+    let turns = (4 - irot.unwrap_or(0) as u32) % 4;
+    Some(match imir {
+        None => orientation_from_transform(false, turns),
+        // Mirroring after the rotation, expressed as mirroring first
+        Some(0) => orientation_from_transform(true, 4 - turns),
+        Some(_) => orientation_from_transform(true, 6 - turns),
+    })
+}
+
+/// Orientation applied to an AVIF/HEIF image under the current policy.
+fn isobmff_orientation(data: &[u8]) -> Option<u32> {
+    orientation_policy().resolve(
+        isobmff_container_orientation(data),
+        read_exif_orientation_avif(data),
+    )
+}
+
+// ============================================================
+// Orientation policy
+// ============================================================
+
+/// Which orientation source to honor. AVIF and HEIF files can carry both
+/// container transforms (`irot`/`imir`) and an EXIF orientation tag, and
+/// files in the wild disagree on which one is authoritative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrientationPolicy {
+    /// Container transforms if the file has any, otherwise the EXIF tag.
+    Auto,
+    /// Only the container transforms.
+    Container,
+    /// Only the EXIF tag; container transforms are ignored.
+    Exif,
+    /// No orientation correction for any format.
+    Off,
+}
+
+static ORIENTATION_POLICY: AtomicU8 = AtomicU8::new(OrientationPolicy::Auto as u8);
+
+impl OrientationPolicy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "auto" => Some(Self::Auto),
+            "container" => Some(Self::Container),
+            "exif" => Some(Self::Exif),
+            "none" => Some(Self::Off),
+            _ => None,
+        }
+    }
+
+    /// Orientation to apply given the container and EXIF orientations.
+    fn resolve(self, container: Option<u32>, exif: Option<u32>) -> Option<u32> {
+        match self {
+            Self::Auto => container.or(exif),
+            Self::Container => container,
+            Self::Exif => exif,
+            Self::Off => None,
+        }
+    }
+}
+
+/// Set the orientation policy for all subsequent loads.
+pub fn set_orientation_policy(policy: OrientationPolicy) {
+    ORIENTATION_POLICY.store(policy as u8, AtomicOrdering::Relaxed);
+}
+
+fn orientation_policy() -> OrientationPolicy {
+    match ORIENTATION_POLICY.load(AtomicOrdering::Relaxed) {
+        x if x == OrientationPolicy::Container as u8 => OrientationPolicy::Container,
+        x if x == OrientationPolicy::Exif as u8 => OrientationPolicy::Exif,
+        x if x == OrientationPolicy::Off as u8 => OrientationPolicy::Off,
+        _ => OrientationPolicy::Auto,
+    }
+}
+
+/// Whether EXIF orientation is applied to formats without container
+/// transforms (JPEG, PNG, WebP).
+fn exif_orientation_enabled() -> bool {
+    orientation_policy() != OrientationPolicy::Off
+}
+
 // ============================================================
 // EXIF orientation transforms
 // ============================================================
 
-fn apply_orientation(img: RgbaImage, orientation: u32) -> RgbaImage {
+fn apply_orientation(mut img: RgbaImage, orientation: u32) -> RgbaImage {
+    orient_in_place(&mut img, orientation, &mut Vec::new());
+    img
+}
+
+/// Apply an EXIF orientation to `img`. Quarter turns go through `scratch`
+/// (see `rotate_quarter`); the other transforms need no extra buffer.
+fn orient_in_place(img: &mut RgbaImage, orientation: u32, scratch: &mut Vec<u8>) {
     match orientation {
-        2 => flip_h(img),
-        3 => rotate_180(img),
-        4 => flip_v(img),
-        5 => flip_h(rotate_90(img)),
-        6 => rotate_90(img),
-        7 => flip_h(rotate_270(img)),
-        8 => rotate_270(img),
-        _ => img,
+        2 => flip_h_in_place(img),
+        3 => rotate_180_in_place(img),
+        4 => flip_v_in_place(img),
+        5 => {
+            rotate_quarter(img, true, scratch);
+            flip_h_in_place(img);
+        }
+        6 => rotate_quarter(img, true, scratch),
+        7 => {
+            rotate_quarter(img, false, scratch);
+            flip_h_in_place(img);
+        }
+        8 => rotate_quarter(img, false, scratch),
+        _ => {}
+    }
+}
+
+/// Rotate `img` a quarter turn clockwise (`cw`) or counterclockwise. The
+/// result is written to `scratch`, which then receives the old pixel
+/// buffer, so rotating every frame of an animation with the same scratch
+/// allocates once instead of once per frame.
+pub fn rotate_quarter(img: &mut RgbaImage, cw: bool, scratch: &mut Vec<u8>) {
+    let (w, h) = (img.width as usize, img.height as usize);
+    scratch.resize(img.data.len(), 0);
+    for y in 0..h {
+        for x in 0..w {
+            let (dst_x, dst_y) = if cw { (h - 1 - y, x) } else { (y, w - 1 - x) };
+            let src = (y * w + x) * 4;
+            let dst = (dst_y * h + dst_x) * 4;
+            scratch[dst..dst + 4].copy_from_slice(&img.data[src..src + 4]);
+        }
+    }
+    std::mem::swap(&mut img.data, scratch);
+    (img.width, img.height) = (img.height, img.width);
+}
+
+pub(crate) fn rotate_180_in_place(img: &mut RgbaImage) {
+    // Reversing the bytes reverses the pixel order and each pixel's channels
+    img.data.reverse();
+    for px in img.data.chunks_exact_mut(4) {
+        px.reverse();
+    }
+}
+
+pub(crate) fn flip_h_in_place(img: &mut RgbaImage) {
+    if img.width == 0 {
+        return;
+    }
+    for row in img.data.chunks_exact_mut(img.width as usize * 4) {
+        row.reverse();
+        for px in row.chunks_exact_mut(4) {
+            px.reverse();
+        }
+    }
+}
+
+pub(crate) fn flip_v_in_place(img: &mut RgbaImage) {
+    let (row, h) = (img.width as usize * 4, img.height as usize);
+    for y in 0..h / 2 {
+        let (top, bottom) = img.data.split_at_mut((h - 1 - y) * row);
+        top[y * row..(y + 1) * row].swap_with_slice(&mut bottom[..row]);
     }
 }
 
+// Allocating reference versions of the transforms above, kept to check
+// them against
+
+#[cfg(test)]
 pub fn rotate_90(img: RgbaImage) -> RgbaImage {
     let (w, h) = (img.width, img.height);
     let mut out = RgbaImage::new(h, w);
@@ -2605,6 +4952,7 @@ pub fn rotate_90(img: RgbaImage) -> RgbaImage {
     out
 }
 
+#[cfg(test)]
 pub(crate) fn rotate_180(img: RgbaImage) -> RgbaImage {
     let (w, h) = (img.width, img.height);
     let mut out = RgbaImage::new(w, h);
@@ -2618,6 +4966,7 @@ pub(crate) fn rotate_180(img: RgbaImage) -> RgbaImage {
     out
 }
 
+#[cfg(test)]
 pub fn rotate_270(img: RgbaImage) -> RgbaImage {
     let (w, h) = (img.width, img.height);
     let mut out = RgbaImage::new(h, w);
@@ -2633,6 +4982,7 @@ pub fn rotate_270(img: RgbaImage) -> RgbaImage {
     out
 }
 
+#[cfg(test)]
 pub(crate) fn flip_h(img: RgbaImage) -> RgbaImage {
     let (w, h) = (img.width, img.height);
     let mut out = RgbaImage::new(w, h);
@@ -2646,6 +4996,52 @@ pub(crate) fn flip_h(img: RgbaImage) -> RgbaImage {
     out
 }
 
+/// Copy the `w`x`h` region starting at (`x`, `y`) out of `img`.
+/// The region is clipped to the image bounds.
+pub fn crop_region(img: &RgbaImage, x: u32, y: u32, w: u32, h: u32) -> RgbaImage {
+    let x = x.min(img.width);
+    let y = y.min(img.height);
+    let w = w.min(img.width - x);
+    let h = h.min(img.height - y);
+    img.crop(x, y, w, h)
+        .expect("clipped region is inside the image")
+}
+
+/// Bounds `(x, y, w, h)` of `img` without its uniform border: rows and
+/// columns that are fully transparent or within `tolerance` (per channel) of
+/// the top-left corner pixel. `None` if there is no border to trim or the
+/// whole image is uniform.
+pub fn auto_crop_bounds(img: &RgbaImage, tolerance: u8) -> Option<(u32, u32, u32, u32)> {
+    let (w, h) = img.dimensions();
+    if w == 0 || h == 0 {
+        return None;
+    }
+    let corner = &img.data[0..4];
+    let is_border = |x: u32, y: u32| {
+        let i = ((y * w + x) * 4) as usize;
+        let px = &img.data[i..i + 4];
+        px[3] == 0
+            || px
+                .iter()
+                .zip(corner)
+                .all(|(&a, &b)| a.abs_diff(b) <= tolerance)
+    };
+    let row_is_border = |y: u32| (0..w).all(|x| is_border(x, y));
+
+    let top = (0..h).find(|&y| !row_is_border(y))?;
+    let bottom = (top..h).rev().find(|&y| !row_is_border(y))?;
+    let col_is_border = |x: u32| (top..=bottom).all(|y| is_border(x, y));
+    let left = (0..w).find(|&x| !col_is_border(x))?;
+    let right = (left..w).rev().find(|&x| !col_is_border(x))?;
+
+    let bounds = (left, top, right - left + 1, bottom - top + 1);
+    if bounds == (0, 0, w, h) {
+        None
+    } else {
+        Some(bounds)
+    }
+}
+
 // ============================================================
 // Full EXIF tag reader
 // ============================================================
@@ -2674,8 +5070,10 @@ pub fn read_exif_tags(data: &[u8]) -> Vec<(String, String)> {
                 pos += 2 + seg_len;
                 continue;
             }
+            // Offsets must not reach past the APP1 segment into image data
+            let seg_end = (pos + 2 + seg_len).min(data.len());
             let tiff_start = seg_start + 6;
-            return parse_all_exif_tags(data, tiff_start);
+            return parse_all_exif_tags(&data[..seg_end.max(tiff_start)], tiff_start);
         }
         if marker == 0xDA {
             break;
@@ -2685,6 +5083,21 @@ pub fn read_exif_tags(data: &[u8]) -> Vec<(String, String)> {
     Vec::new()
 }
 
+/// Read EXIF tags from raw file data, picking the container parser by
+/// (lowercase) file extension. Unknown formats yield no tags.
+pub fn read_exif_tags_for_ext(data: &[u8], ext: &str) -> Vec<(String, String)> {
+    match ext {
+        "jpg" | "jpeg" => read_exif_tags(data),
+        "tiff" | "tif" => read_exif_tags_tiff(data),
+        "webp" => read_exif_tags_webp(data),
+        "png" => read_exif_tags_png(data),
+        "avif" => read_exif_tags_avif(data),
+        "heic" | "heif" => read_exif_tags_heic(data),
+        "jxl" => read_exif_tags_jxl(data),
+        _ => Vec::new(),
+    }
+}
+
 /// Read EXIF tags from raw TIFF data.
 /// TIFF files ARE TIFF structures, so the header is at byte 0.
 pub fn read_exif_tags_tiff(data: &[u8]) -> Vec<(String, String)> {
@@ -2724,18 +5137,19 @@ fn extract_webp_exif(data: &[u8]) -> Option<Vec<u8>> {
         return None;
     }
 
-    // Walk RIFF chunks starting at offset 12
+    // Walk RIFF chunks starting at offset 12, staying inside the declared size
+    let end = webp_riff_end(data);
     let mut pos = 12;
-    while pos + 8 <= data.len() {
+    while pos + 8 <= end {
         let fourcc = &data[pos..pos + 4];
         let chunk_size =
             u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]])
                 as usize;
         let payload_start = pos + 8;
-        let payload_end = payload_start + chunk_size;
+        let payload_end = payload_start.checked_add(chunk_size)?;
 
         if fourcc == b"EXIF" {
-            if payload_end > data.len() {
+            if payload_end > end {
                 return None;
             }
             let payload = &data[payload_start..payload_end];
@@ -2747,8 +5161,7 @@ fn extract_webp_exif(data: &[u8]) -> Option<Vec<u8>> {
         }
 
         // Chunks are padded to even size
-        let padded_size = (chunk_size + 1) & !1;
-        pos = payload_start + padded_size;
+        pos = payload_end.checked_add(chunk_size & 1)?;
     }
     None
 }
@@ -2841,35 +5254,80 @@ fn parse_all_exif_tags(data: &[u8], tiff_offset: usize) -> Vec<(String, String)>
     };
 
     let mut tags = Vec::new();
-    let mut exif_ifd_offset: Option<usize> = None;
-    let mut gps_ifd_offset: Option<usize> = None;
+    // Offsets of the IFDs already read, so circular pointers cannot loop.
+    // IFDs lie past the header, and only so many are followed.
+    let mut visited = HashSet::new();
+    let mut visit = |offset: Option<usize>| {
+        offset.filter(|&o| {
+            (8..d.len()).contains(&o) && visited.len() < MAX_EXIF_IFDS && visited.insert(o)
+        })
+    };
 
     // Parse IFD0
-    parse_ifd_tags(
-        d,
-        ifd_offset,
-        le,
-        &IFD0_TAGS,
-        &mut tags,
-        &mut exif_ifd_offset,
-        &mut gps_ifd_offset,
-    );
+    let Some(ifd_offset) = visit(Some(ifd_offset)) else {
+        return tags;
+    };
+    let ifd0 = parse_ifd_tags(d, ifd_offset, le, IFD0_TAGS, &mut tags);
 
-    // Parse EXIF sub-IFD
-    if let Some(offset) = exif_ifd_offset {
-        parse_ifd_tags(d, offset, le, &EXIF_TAGS, &mut tags, &mut None, &mut None);
+    // Parse EXIF sub-IFD and its Interoperability IFD
+    if let Some(offset) = visit(ifd0.exif) {
+        let exif = parse_ifd_tags(d, offset, le, EXIF_TAGS, &mut tags);
+        if let Some(offset) = visit(exif.interop) {
+            parse_ifd_tags(d, offset, le, INTEROP_TAGS, &mut tags);
+        }
     }
 
     // Parse GPS IFD
-    if let Some(offset) = gps_ifd_offset {
+    if let Some(offset) = visit(ifd0.gps) {
         parse_gps_tags(d, offset, le, &mut tags);
     }
 
+    // Follow the chain after IFD0: IFD1 describes the embedded thumbnail
+    let mut thumbnail = None;
+    let mut next = visit(ifd0.next);
+    while let Some(offset) = next {
+        let links = parse_ifd_tags(d, offset, le, &[], &mut tags);
+        thumbnail = thumbnail.or(links.thumbnail);
+        next = visit(links.next);
+    }
+    if let Some((offset, len)) = thumbnail {
+        let is_jpeg = d
+            .get(offset..offset.saturating_add(len))
+            .is_some_and(|t| t.starts_with(&[0xFF, 0xD8]));
+        if is_jpeg {
+            tags.push((
+                "Thumbnail".to_string(),
+                format!("JPEG, {}", crate::status::format_file_size(len as u64)),
+            ));
+        }
+    }
+
     tags
 }
 
+/// Most IFDs read from one EXIF block (IFD0, EXIF, Interop, GPS and the
+/// IFD1... chain).
+const MAX_EXIF_IFDS: usize = 16;
+
+/// Other IFDs and data referenced by an IFD.
+#[derive(Debug, Default)]
+struct IfdLinks {
+    /// EXIF sub-IFD (tag 0x8769).
+    exif: Option<usize>,
+    /// GPS IFD (tag 0x8825).
+    gps: Option<usize>,
+    /// Interoperability IFD (tag 0xA005, in the EXIF sub-IFD).
+    interop: Option<usize>,
+    /// Next IFD in the chain (IFD1 after IFD0).
+    next: Option<usize>,
+    /// Offset and length of a JPEG thumbnail (tags 0x0201/0x0202, in IFD1).
+    thumbnail: Option<(usize, usize)>,
+}
+
 /// Known IFD0 tags
 const IFD0_TAGS: &[(u16, &str)] = &[
+    (0x0102, "Bits per Sample"),
+    (0x010E, "Description"),
     (0x010F, "Make"),
     (0x0110, "Model"),
     (0x0112, "Orientation"),
@@ -2878,9 +5336,13 @@ const IFD0_TAGS: &[(u16, &str)] = &[
     (0x0131, "Software"),
     (0x0132, "Date/Time"),
     (0x013B, "Artist"),
+    (0x0212, "YCbCr Subsampling"),
     (0x8298, "Copyright"),
 ];
 
+/// Known Interoperability IFD tags
+const INTEROP_TAGS: &[(u16, &str)] = &[(0x0001, "Interoperability")];
+
 /// Known EXIF sub-IFD tags
 const EXIF_TAGS: &[(u16, &str)] = &[
     (0x829A, "Exposure Time"),
@@ -2888,10 +5350,13 @@ const EXIF_TAGS: &[(u16, &str)] = &[
     (0x8827, "ISO"),
     (0x9003, "Date Original"),
     (0x9004, "Date Digitized"),
+    (0x9011, "Offset Original"),
     (0x9204, "Exposure Bias"),
     (0x9207, "Metering Mode"),
     (0x9209, "Flash"),
     (0x920A, "Focal Length"),
+    (0x9286, "User Comment"),
+    (0x9291, "SubSec Original"),
     (0xA001, "Color Space"),
     (0xA002, "Width"),
     (0xA003, "Height"),
@@ -2904,15 +5369,18 @@ const EXIF_TAGS: &[(u16, &str)] = &[
 /// 6=SBYTE, 7=UNDEFINED, 8=SSHORT, 9=SLONG, 10=SRATIONAL
 const TYPE_SIZES: &[usize] = &[0, 1, 1, 2, 4, 8, 1, 1, 2, 4, 8];
 
+/// Most elements of a numeric array tag shown in the EXIF overlay.
+const MAX_TAG_VALUES: usize = 8;
+
+/// Read the `known_tags` of the IFD at `ifd_offset` into `tags` and
+/// return its pointers to other IFDs.
 fn parse_ifd_tags(
     d: &[u8],
     ifd_offset: usize,
     le: bool,
     known_tags: &[(u16, &str)],
     tags: &mut Vec<(String, String)>,
-    exif_ifd: &mut Option<usize>,
-    gps_ifd: &mut Option<usize>,
-) {
+) -> IfdLinks {
     let read_u16 = |off: usize| -> Option<u16> {
         if off + 2 > d.len() {
             return None;
@@ -2935,14 +5403,17 @@ fn parse_ifd_tags(
         })
     };
 
+    let mut links = IfdLinks::default();
     if ifd_offset + 2 > d.len() {
-        return;
+        return links;
     }
     let entry_count = match read_u16(ifd_offset) {
         Some(v) => v as usize,
-        None => return,
+        None => return links,
     };
     let entries_start = ifd_offset + 2;
+    let mut thumb_offset = None;
+    let mut thumb_len = None;
 
     for i in 0..entry_count {
         let entry_off = entries_start + i * 12;
@@ -2962,18 +5433,17 @@ fn parse_ifd_tags(
             None => continue,
         };
 
-        // EXIF sub-IFD pointer
-        if tag == 0x8769 {
-            if let Some(offset) = read_u32(entry_off + 8) {
-                *exif_ifd = Some(offset as usize);
-            }
-            continue;
-        }
-        // GPS IFD pointer
-        if tag == 0x8825 {
-            if let Some(offset) = read_u32(entry_off + 8) {
-                *gps_ifd = Some(offset as usize);
-            }
+        // Pointers to other IFDs and to the thumbnail
+        let pointer = match tag {
+            0x8769 => Some(&mut links.exif),
+            0x8825 => Some(&mut links.gps),
+            0xA005 => Some(&mut links.interop),
+            0x0201 => Some(&mut thumb_offset),
+            0x0202 => Some(&mut thumb_len),
+            _ => None,
+        };
+        if let Some(pointer) = pointer {
+            *pointer = read_u32(entry_off + 8).map(|v| v as usize);
             continue;
         }
 
@@ -2985,11 +5455,48 @@ fn parse_ifd_tags(
 
         let value = read_tag_value(d, entry_off + 8, dtype, count, le, tag);
         if let Some(v) = value {
-            if !v.is_empty() {
+            // Cameras often fill descriptions and comments with spaces
+            if !v.trim().is_empty() {
                 tags.push((label.to_string(), v));
             }
         }
     }
+
+    // A zero offset ends the chain
+    links.next = read_u32(entries_start + entry_count * 12)
+        .filter(|&v| v != 0)
+        .map(|v| v as usize);
+    links.thumbnail = thumb_offset.zip(thumb_len);
+    links
+}
+
+/// Where the value of an IFD entry starts, given the offset of its value
+/// field. Values of up to 4 bytes are stored inline; larger ones must lie
+/// entirely within the TIFF block, past its header.
+fn entry_value_offset(
+    d: &[u8],
+    value_off: usize,
+    dtype: usize,
+    count: usize,
+    le: bool,
+) -> Option<usize> {
+    let total_bytes = TYPE_SIZES.get(dtype)?.checked_mul(count)?;
+    if total_bytes <= 4 {
+        return Some(value_off);
+    }
+    let bytes: [u8; 4] = d
+        .get(value_off..value_off.checked_add(4)?)?
+        .try_into()
+        .ok()?;
+    let data_off = if le {
+        u32::from_le_bytes(bytes)
+    } else {
+        u32::from_be_bytes(bytes)
+    } as usize;
+    if data_off < 8 || data_off.checked_add(total_bytes)? > d.len() {
+        return None;
+    }
+    Some(data_off)
 }
 
 fn read_tag_value(
@@ -3033,17 +5540,19 @@ fn read_tag_value(
         })
     };
 
-    // Determine if value is inline or at an offset
-    let type_size = if dtype < TYPE_SIZES.len() {
-        TYPE_SIZES[dtype]
-    } else {
-        return None;
-    };
-    let total_bytes = type_size * count;
-    let data_off = if total_bytes <= 4 {
-        value_off // inline
-    } else {
-        read_u32_at(value_off)? as usize // offset into TIFF data
+    let data_off = entry_value_offset(d, value_off, dtype, count, le)?;
+
+    // Numeric values are arrays of `count` elements of `size` bytes; show
+    // the first MAX_TAG_VALUES of them, comma-separated
+    let list = |size: usize, format: &dyn Fn(usize) -> Option<String>| -> Option<String> {
+        let shown = count.min(MAX_TAG_VALUES);
+        let mut values = (0..shown)
+            .map(|i| format(data_off + i * size))
+            .collect::<Option<Vec<_>>>()?;
+        if count > shown {
+            values.push("...".to_string());
+        }
+        Some(values.join(", "))
     };
 
     match dtype {
@@ -3068,45 +5577,141 @@ fn read_tag_value(
             Some(s)
         }
         // SHORT
-        3 => {
-            let val = read_u16_at(data_off)? as u32;
-            Some(format_tag_short(tag, val))
+        3 => list(2, &|off| {
+            Some(format_tag_short(tag, read_u16_at(off)? as u32))
+        }),
+        // UNDEFINED: only UserComment is shown
+        7 if tag == 0x9286 => {
+            let bytes = d.get(data_off..data_off.checked_add(count)?)?;
+            decode_user_comment(bytes, le)
         }
         // LONG
-        4 => {
-            let val = read_u32_at(data_off)?;
-            Some(format!("{}", val))
-        }
+        4 => list(4, &|off| Some(read_u32_at(off)?.to_string())),
         // RATIONAL (unsigned)
-        5 => {
-            let num = read_u32_at(data_off)?;
-            let den = read_u32_at(data_off + 4)?;
+        5 => list(8, &|off| {
+            let (num, den) = (read_u32_at(off)?, read_u32_at(off + 4)?);
             Some(format_rational(tag, num, den))
-        }
+        }),
         // SRATIONAL (signed)
-        10 => {
-            let num = read_i32_at(data_off)?;
-            let den = read_i32_at(data_off + 4)?;
+        10 => list(8, &|off| {
+            let (num, den) = (read_i32_at(off)?, read_i32_at(off + 4)?);
             Some(format_srational(tag, num, den))
-        }
+        }),
         _ => None,
     }
 }
 
+/// Longest UserComment shown in the EXIF overlay, in characters.
+const MAX_USER_COMMENT_CHARS: usize = 64;
+
+/// Decode an EXIF UserComment: an 8-byte character code (`ASCII`,
+/// `UNICODE`, `JIS` or all zero for undefined) followed by the text.
+/// UNICODE is UTF-16 in the TIFF byte order unless it starts with a BOM;
+/// undefined text is taken as UTF-8. JIS text is not decoded. Control
+/// characters become `?`, and long comments are shortened.
+fn decode_user_comment(bytes: &[u8], le: bool) -> Option<String> {
+    if bytes.len() < 8 {
+        return None;
+    }
+    let (code, text) = bytes.split_at(8);
+    let decoded = match code {
+        b"ASCII\0\0\0" => text.iter().map(|&b| b as char).collect::<String>(),
+        b"UNICODE\0" => {
+            let (text, le) = match text {
+                [0xFF, 0xFE, rest @ ..] => (rest, true),
+                [0xFE, 0xFF, rest @ ..] => (rest, false),
+                _ => (text, le),
+            };
+            let units = text.chunks_exact(2).map(|c| {
+                if le {
+                    u16::from_le_bytes([c[0], c[1]])
+                } else {
+                    u16::from_be_bytes([c[0], c[1]])
+                }
+            });
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        }
+        [0, 0, 0, 0, 0, 0, 0, 0] => String::from_utf8_lossy(text).into_owned(),
+        _ => return None,
+    };
+    // The text may be NUL-terminated or padded
+    let decoded = decoded.split('\0').next().unwrap_or("").trim();
+    let mut out: String = decoded
+        .chars()
+        .take(MAX_USER_COMMENT_CHARS)
+        .map(|c| if c.is_control() { '?' } else { c })
+        .collect();
+    if decoded.chars().count() > MAX_USER_COMMENT_CHARS {
+        out.push_str("...");
+    }
+    Some(out)
+}
+
+/// Display names of the EXIF orientation values 1-8.
+const ORIENTATION_NAMES: [&str; 8] = [
+    "Normal",
+    "Flipped horizontally",
+    "Rotated 180",
+    "Flipped vertically",
+    "Transposed",
+    "Rotated 90 CW",
+    "Transversed",
+    "Rotated 270 CW",
+];
+
+/// Display name of an EXIF orientation value.
+pub fn orientation_name(orientation: u32) -> Option<&'static str> {
+    let idx = orientation.checked_sub(1)? as usize;
+    ORIENTATION_NAMES.get(idx).copied()
+}
+
+/// Orientation tag value for a display name from the EXIF overlay.
+pub fn orientation_from_name(name: &str) -> Option<u32> {
+    let idx = ORIENTATION_NAMES.iter().position(|&n| n == name)?;
+    Some(idx as u32 + 1)
+}
+
+/// Each orientation value 1-8 as (mirror horizontally first, then this many
+/// quarter turns clockwise).
+const ORIENTATION_TRANSFORMS: [(bool, u32); 8] = [
+    (false, 0),
+    (true, 0),
+    (false, 2),
+    (true, 2),
+    (true, 3),
+    (false, 1),
+    (true, 1),
+    (false, 3),
+];
+
+/// Orientation value of a horizontal mirror (optional) followed by
+/// `quarter_turns` clockwise rotations.
+fn orientation_from_transform(mirror: bool, quarter_turns: u32) -> u32 {
+    let target = (mirror, quarter_turns % 4);
+    ORIENTATION_TRANSFORMS
+        .iter()
+        .position(|&t| t == target)
+        .unwrap() as u32
+        + 1
+}
+
+/// Orientation that results from displaying an image with `orientation`
+/// and then rotating it by `quarter_turns` 90 degree steps clockwise.
+pub fn rotated_orientation(orientation: u32, quarter_turns: u32) -> u32 {
+    match ORIENTATION_TRANSFORMS.get(orientation.wrapping_sub(1) as usize) {
+        Some(&(mirror, k)) => orientation_from_transform(mirror, k + quarter_turns),
+        None => orientation,
+    }
+}
+
 fn format_tag_short(tag: u16, val: u32) -> String {
     match tag {
         // Orientation
-        0x0112 => match val {
-            1 => "Normal".to_string(),
-            2 => "Flipped horizontally".to_string(),
-            3 => "Rotated 180".to_string(),
-            4 => "Flipped vertically".to_string(),
-            5 => "Transposed".to_string(),
-            6 => "Rotated 90 CW".to_string(),
-            7 => "Transversed".to_string(),
-            8 => "Rotated 270 CW".to_string(),
-            _ => format!("{}", val),
-        },
+        0x0112 => orientation_name(val)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{}", val)),
         // MeteringMode
         0x9207 => match val {
             0 => "Unknown".to_string(),
@@ -3282,19 +5887,9 @@ fn parse_gps_tags(d: &[u8], ifd_offset: usize, le: bool, tags: &mut Vec<(String,
             None => continue,
         };
 
-        let type_size = if dtype < TYPE_SIZES.len() {
-            TYPE_SIZES[dtype]
-        } else {
-            continue;
-        };
-        let total_bytes = type_size * count;
-        let data_off = if total_bytes <= 4 {
-            entry_off + 8
-        } else {
-            match read_u32(entry_off + 8) {
-                Some(v) => v as usize,
-                None => continue,
-            }
+        let data_off = match entry_value_offset(d, entry_off + 8, dtype, count, le) {
+            Some(v) => v,
+            None => continue,
         };
 
         match tag {
@@ -3375,6 +5970,7 @@ fn read_gps_coord(d: &[u8], off: usize, le: bool) -> Option<(f64, f64, f64)> {
     Some((deg_n / deg_d, min_n / min_d, sec_n / sec_d))
 }
 
+#[cfg(test)]
 pub(crate) fn flip_v(img: RgbaImage) -> RgbaImage {
     let (w, h) = (img.width, img.height);
     let mut out = RgbaImage::new(w, h);
@@ -3426,89 +6022,878 @@ mod tests {
         ]
     }
 
-    // ========== Transform tests ==========
+    // ========== Natural sort tests ==========
+
+    fn natural_sorted(names: &[&str]) -> Vec<String> {
+        let mut v: Vec<&str> = names.to_vec();
+        v.sort_by(|a, b| natural_cmp(a.as_bytes(), b.as_bytes()));
+        v.into_iter().map(String::from).collect()
+    }
+
+    #[cfg(any(feature = "avif", feature = "jxl"))]
+    #[test]
+    fn test_parse_decode_threads() {
+        assert_eq!(parse_decode_threads("4"), Ok(4));
+        assert_eq!(parse_decode_threads(" 2\n"), Ok(2));
+        assert!(parse_decode_threads("0").is_err());
+        assert!(parse_decode_threads("many").is_err());
+    }
+
+    #[test]
+    fn test_natural_sort_embedded_numbers() {
+        assert_eq!(
+            natural_sorted(&["IMG_10.jpg", "IMG_2.jpg", "IMG_1.jpg", "IMG_2b.jpg"]),
+            ["IMG_1.jpg", "IMG_2.jpg", "IMG_2b.jpg", "IMG_10.jpg"]
+        );
+        assert_eq!(
+            natural_sorted(&["a10b2", "a10b10", "a9b99"]),
+            ["a9b99", "a10b2", "a10b10"]
+        );
+    }
+
+    #[test]
+    fn test_natural_sort_mixed_case() {
+        assert_eq!(
+            natural_sorted(&["beta.png", "Alpha.png", "alpha.png", "Gamma.png"]),
+            ["Alpha.png", "alpha.png", "beta.png", "Gamma.png"]
+        );
+    }
+
+    #[test]
+    fn test_natural_sort_leading_zeros() {
+        assert_eq!(
+            natural_sorted(&["img010", "img9", "img007", "img7"]),
+            ["img7", "img007", "img9", "img010"]
+        );
+        // Digit runs longer than u64 compare without overflow
+        assert_eq!(
+            natural_cmp(b"x99999999999999999999999", b"x100000000000000000000000"),
+            Ordering::Less
+        );
+    }
+
+    // ========== Glob expansion tests ==========
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"*.jpg", b"photo.jpg"));
+        assert!(!glob_match(b"*.jpg", b"photo.jpeg"));
+        assert!(glob_match(b"img?.png", b"img1.png"));
+        assert!(!glob_match(b"img?.png", b"img10.png"));
+        assert!(glob_match(b"img[0-9][!a].*", b"img1b.webp"));
+        assert!(!glob_match(b"img[0-9]*", b"imgx.png"));
+        assert!(glob_match(b"*a*b*", b"xxaxxbxx"));
+        assert!(!glob_match(b"*", b".hidden"));
+        assert!(glob_match(b".*", b".hidden"));
+    }
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(expand_braces("*.{jpg,png}"), ["*.jpg", "*.png"]);
+        assert_eq!(expand_braces("{a,b{1,2}}/x"), ["a/x", "b1/x", "b2/x"]);
+        assert_eq!(expand_braces("{a}.jpg"), ["{a}.jpg"]);
+        assert_eq!(expand_braces("no-braces"), ["no-braces"]);
+    }
+
+    #[test]
+    fn test_expand_glob_in_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.jpg", "b.png", "c.txt", ".hidden.jpg"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub").join("d.jpg"), b"").unwrap();
+
+        let base = dir.path().to_str().unwrap();
+        let names = |pattern: &str| -> Vec<String> {
+            expand_glob(&format!("{}/{}", base, pattern))
+                .iter()
+                .map(|p| p.strip_prefix(base).unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(names("*.{jpg,png}"), ["a.jpg", "b.png"]);
+        assert_eq!(names("*/*.jpg"), ["sub/d.jpg"]);
+        assert!(names("*.gif").is_empty());
+    }
+
+    #[test]
+    fn test_path_from_file_uri() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let p = |uri: &str| path_from_file_uri(uri);
+        assert_eq!(
+            p("file:///tmp/My%20Photos/a.jpg"),
+            Some(PathBuf::from("/tmp/My Photos/a.jpg"))
+        );
+        assert_eq!(
+            p("file://localhost/tmp/a.jpg"),
+            Some(PathBuf::from("/tmp/a.jpg"))
+        );
+        assert_eq!(
+            p("file://LOCALHOST/tmp/a.jpg"),
+            Some(PathBuf::from("/tmp/a.jpg"))
+        );
+        // UTF-8 and non-UTF-8 bytes are decoded as-is
+        assert_eq!(
+            p("file:///tmp/caf%C3%A9.png"),
+            Some(PathBuf::from("/tmp/café.png"))
+        );
+        let latin1 = p("file:///tmp/caf%E9.png").unwrap();
+        assert_eq!(latin1.as_os_str().as_bytes(), b"/tmp/caf\xe9.png");
+        // Malformed escapes are kept literally; query and fragment dropped
+        assert_eq!(
+            p("file:///tmp/100%.jpg"),
+            Some(PathBuf::from("/tmp/100%.jpg"))
+        );
+        assert_eq!(
+            p("file:///tmp/a%zz.jpg?x=1"),
+            Some(PathBuf::from("/tmp/a%zz.jpg"))
+        );
+        // Remote hosts and missing paths are rejected
+        assert_eq!(p("file://server/share/a.jpg"), None);
+        assert_eq!(p("file://"), None);
+        assert_eq!(p("/tmp/a.jpg"), None);
+    }
+
+    #[test]
+    fn test_parse_uri_list() {
+        let list = "# dropped from a file manager\r\n\
+                    file:///tmp/a%20b.jpg\r\n\
+                    https://example.com/c.png\r\n\
+                    file://localhost/tmp/d.png\r\n";
+        assert_eq!(
+            parse_uri_list(list),
+            [PathBuf::from("/tmp/a b.jpg"), PathBuf::from("/tmp/d.png")]
+        );
+        assert!(parse_uri_list("").is_empty());
+    }
+
+    #[test]
+    fn test_collect_paths_file_uri() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("two words.png");
+        fs::write(&file, b"").unwrap();
+        let uri = format!("file://{}", file.to_str().unwrap().replace(' ', "%20"));
+        assert_eq!(collect_paths(&[uri], true, true), [file]);
+    }
+
+    #[test]
+    fn test_collect_paths_keeps_argument_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let names = ["c.png", "a.jpg", "b.gif"];
+        for name in names {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let args: Vec<String> = names
+            .iter()
+            .map(|n| dir.path().join(n).to_string_lossy().into_owned())
+            .collect();
+        let order = |sort| -> Vec<String> {
+            collect_paths(&args, true, sort)
+                .iter()
+                .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+        assert_eq!(order(false), names);
+        assert_eq!(order(true), ["a.jpg", "b.gif", "c.png"]);
+    }
+
+    #[test]
+    fn test_sibling_images() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.png", "b.jpg", "notes.txt", "sub/c.png"] {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+        let mut found = sibling_images(&dir.path().join("a.png"));
+        found.sort();
+        assert_eq!(found, [dir.path().join("a.png"), dir.path().join("b.jpg")]);
+        assert!(sibling_images(&dir.path().join("gone/x.png")).is_empty());
+    }
+
+    #[test]
+    fn test_compare_file_names_lexicographic() {
+        let (a, b) = (Path::new("/d/IMG_10.jpg"), Path::new("/c/IMG_2.jpg"));
+        assert_eq!(compare_file_names(a, b, false), Ordering::Less);
+        assert_eq!(compare_file_names(a, b, true), Ordering::Greater);
+    }
+
+    // ========== Transform tests ==========
+
+    #[test]
+    fn test_rotate_90() {
+        let img = make_2x3_image(); // 2x3
+        let out = rotate_90(img);
+        assert_eq!(out.dimensions(), (3, 2)); // width=old_h, height=old_w
+                                              // Original layout:
+                                              //   R G     Rotated 90 CW:
+                                              //   B W     Y B R
+                                              //   Y C     C W G
+        assert_eq!(pixel_at(&out, 0, 0), [255, 255, 0, 255]); // Y
+        assert_eq!(pixel_at(&out, 1, 0), [0, 0, 255, 255]); // B
+        assert_eq!(pixel_at(&out, 2, 0), [255, 0, 0, 255]); // R
+        assert_eq!(pixel_at(&out, 0, 1), [0, 255, 255, 255]); // C
+        assert_eq!(pixel_at(&out, 1, 1), [255, 255, 255, 255]); // W
+        assert_eq!(pixel_at(&out, 2, 1), [0, 255, 0, 255]); // G
+    }
+
+    #[test]
+    fn test_rotate_180() {
+        let img = make_2x3_image();
+        let out = rotate_180(img);
+        assert_eq!(out.dimensions(), (2, 3));
+        // 180: reverse all pixels
+        //   C Y
+        //   W B
+        //   G R
+        assert_eq!(pixel_at(&out, 0, 0), [0, 255, 255, 255]); // C
+        assert_eq!(pixel_at(&out, 1, 0), [255, 255, 0, 255]); // Y
+        assert_eq!(pixel_at(&out, 0, 1), [255, 255, 255, 255]); // W
+        assert_eq!(pixel_at(&out, 1, 1), [0, 0, 255, 255]); // B
+        assert_eq!(pixel_at(&out, 0, 2), [0, 255, 0, 255]); // G
+        assert_eq!(pixel_at(&out, 1, 2), [255, 0, 0, 255]); // R
+    }
+
+    #[test]
+    fn test_rotate_270() {
+        let img = make_2x3_image();
+        let out = rotate_270(img);
+        assert_eq!(out.dimensions(), (3, 2));
+        // 270 CW (= 90 CCW):
+        //   G W C
+        //   R B Y
+        assert_eq!(pixel_at(&out, 0, 0), [0, 255, 0, 255]); // G
+        assert_eq!(pixel_at(&out, 1, 0), [255, 255, 255, 255]); // W
+        assert_eq!(pixel_at(&out, 2, 0), [0, 255, 255, 255]); // C
+        assert_eq!(pixel_at(&out, 0, 1), [255, 0, 0, 255]); // R
+        assert_eq!(pixel_at(&out, 1, 1), [0, 0, 255, 255]); // B
+        assert_eq!(pixel_at(&out, 2, 1), [255, 255, 0, 255]); // Y
+    }
+
+    #[test]
+    fn test_flip_h() {
+        // Use a 2x2 image
+        let mut img = RgbaImage::new(2, 2);
+        img.data[0..4].copy_from_slice(&[255, 0, 0, 255]); // (0,0)=R
+        img.data[4..8].copy_from_slice(&[0, 255, 0, 255]); // (1,0)=G
+        img.data[8..12].copy_from_slice(&[0, 0, 255, 255]); // (0,1)=B
+        img.data[12..16].copy_from_slice(&[255, 255, 0, 255]); // (1,1)=Y
+
+        let out = flip_h(img);
+        assert_eq!(out.dimensions(), (2, 2));
+        assert_eq!(pixel_at(&out, 0, 0), [0, 255, 0, 255]); // G (was right)
+        assert_eq!(pixel_at(&out, 1, 0), [255, 0, 0, 255]); // R (was left)
+        assert_eq!(pixel_at(&out, 0, 1), [255, 255, 0, 255]); // Y
+        assert_eq!(pixel_at(&out, 1, 1), [0, 0, 255, 255]); // B
+    }
+
+    #[test]
+    fn test_flip_v() {
+        let mut img = RgbaImage::new(2, 2);
+        img.data[0..4].copy_from_slice(&[255, 0, 0, 255]); // (0,0)=R
+        img.data[4..8].copy_from_slice(&[0, 255, 0, 255]); // (1,0)=G
+        img.data[8..12].copy_from_slice(&[0, 0, 255, 255]); // (0,1)=B
+        img.data[12..16].copy_from_slice(&[255, 255, 0, 255]); // (1,1)=Y
+
+        let out = flip_v(img);
+        assert_eq!(out.dimensions(), (2, 2));
+        assert_eq!(pixel_at(&out, 0, 0), [0, 0, 255, 255]); // B (was bottom-left)
+        assert_eq!(pixel_at(&out, 1, 0), [255, 255, 0, 255]); // Y (was bottom-right)
+        assert_eq!(pixel_at(&out, 0, 1), [255, 0, 0, 255]); // R (was top-left)
+        assert_eq!(pixel_at(&out, 1, 1), [0, 255, 0, 255]); // G (was top-right)
+    }
+
+    #[test]
+    fn test_in_place_transforms_match_reference() {
+        // Odd sizes catch middle-row and middle-column mistakes
+        let mut img = RgbaImage::new(5, 3);
+        for (i, b) in img.data.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        let reference = |o: u32| match o {
+            2 => flip_h(img.clone()),
+            3 => rotate_180(img.clone()),
+            4 => flip_v(img.clone()),
+            5 => flip_h(rotate_90(img.clone())),
+            6 => rotate_90(img.clone()),
+            7 => flip_h(rotate_270(img.clone())),
+            8 => rotate_270(img.clone()),
+            _ => img.clone(),
+        };
+        let mut scratch = Vec::new();
+        for o in 1..=8 {
+            let mut out = img.clone();
+            orient_in_place(&mut out, o, &mut scratch);
+            let expected = reference(o);
+            assert_eq!(out.dimensions(), expected.dimensions(), "orientation {}", o);
+            assert_eq!(out.data, expected.data, "orientation {}", o);
+        }
+
+        // The scratch buffer is reused: after the first quarter turn it
+        // holds a frame-sized buffer and no further allocation is needed
+        let mut frames = vec![img.clone(), img.clone(), img.clone()];
+        let mut scratch = Vec::new();
+        rotate_quarter(&mut frames[0], true, &mut scratch);
+        let capacity = scratch.capacity();
+        for frame in &mut frames[1..] {
+            rotate_quarter(frame, true, &mut scratch);
+            assert_eq!(scratch.capacity(), capacity);
+        }
+        for frame in &frames {
+            assert_eq!(frame.data, rotate_90(img.clone()).data);
+        }
+    }
+
+    #[test]
+    fn test_crop_region() {
+        let img = make_2x3_image();
+        let out = crop_region(&img, 1, 1, 1, 2);
+        assert_eq!(out.dimensions(), (1, 2));
+        assert_eq!(pixel_at(&out, 0, 0), [255, 255, 255, 255]); // W
+        assert_eq!(pixel_at(&out, 0, 1), [0, 255, 255, 255]); // C
+
+        // Requests past the edge are clipped
+        let out = crop_region(&img, 1, 2, 10, 10);
+        assert_eq!(out.dimensions(), (1, 1));
+        assert_eq!(pixel_at(&out, 0, 0), [0, 255, 255, 255]); // C
+    }
+
+    #[test]
+    fn test_crop() {
+        let img = make_2x3_image();
+        let full = img.crop(0, 0, 2, 3).unwrap();
+        assert_eq!(full.dimensions(), (2, 3));
+        assert_eq!(full.data, img.data);
+
+        // Bottom-right corner and a full row
+        let corner = img.crop(1, 2, 1, 1).unwrap();
+        assert_eq!(corner.data, [0, 255, 255, 255]); // C
+        let row = img.crop(0, 1, 2, 1).unwrap();
+        assert_eq!(pixel_at(&row, 0, 0), [0, 0, 255, 255]); // B
+        assert_eq!(pixel_at(&row, 1, 0), [255, 255, 255, 255]); // W
+
+        // Empty regions are fine, even at the far edge
+        for (x, y, w, h) in [(0, 0, 0, 0), (2, 3, 0, 0), (1, 0, 0, 3), (0, 3, 2, 0)] {
+            let out = img.crop(x, y, w, h).unwrap();
+            assert_eq!(out.dimensions(), (w, h));
+            assert!(out.data.is_empty());
+        }
+
+        // Anything reaching past the image is rejected, not clipped
+        assert!(img.crop(1, 0, 2, 1).is_none());
+        assert!(img.crop(0, 1, 1, 3).is_none());
+        assert!(img.crop(3, 0, 0, 0).is_none());
+        assert!(img.crop(0, 4, 0, 0).is_none());
+        assert!(img.crop(1, 1, u32::MAX, 1).is_none());
+        assert!(img.crop(u32::MAX, 0, 2, 1).is_none());
+    }
+
+    /// 6x5 image: white border (with one near-white and one transparent
+    /// pixel) around a 2x2 block at (2, 1).
+    fn make_bordered_image() -> RgbaImage {
+        let mut img = RgbaImage::new(6, 5);
+        for px in img.data.chunks_exact_mut(4) {
+            px.copy_from_slice(&[255, 255, 255, 255]);
+        }
+        let mut set = |x: u32, y: u32, px: [u8; 4]| {
+            let i = ((y * 6 + x) * 4) as usize;
+            img.data[i..i + 4].copy_from_slice(&px);
+        };
+        set(2, 1, [255, 0, 0, 255]);
+        set(3, 1, [0, 255, 0, 255]);
+        set(2, 2, [0, 0, 255, 255]);
+        set(3, 2, [10, 10, 10, 255]);
+        set(0, 4, [250, 252, 255, 255]);
+        set(5, 0, [0, 0, 0, 0]);
+        img
+    }
+
+    #[test]
+    fn test_auto_crop_trims_border() {
+        let img = make_bordered_image();
+        assert_eq!(auto_crop_bounds(&img, 8), Some((2, 1, 2, 2)));
+        let out = crop_region(&img, 2, 1, 2, 2);
+        assert_eq!(out.dimensions(), (2, 2));
+        assert_eq!(pixel_at(&out, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel_at(&out, 1, 1), [10, 10, 10, 255]);
+
+        // Without tolerance the near-white pixel counts as content
+        assert_eq!(auto_crop_bounds(&img, 0), Some((0, 1, 4, 4)));
+    }
+
+    #[test]
+    fn test_auto_crop_nothing_to_trim() {
+        // Uniform image
+        let img = RgbaImage::new(3, 3);
+        assert_eq!(auto_crop_bounds(&img, 0), None);
+        // Content touches every edge
+        let img = make_2x3_image();
+        assert_eq!(auto_crop_bounds(&img, 0), None);
+    }
+
+    // ========== PNG encoder tests ==========
+
+    #[test]
+    fn test_png_encode_roundtrip() {
+        let mut img = make_2x3_image();
+        img.data[3] = 128; // semi-transparent R
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.png");
+        save_png(&img, &path).unwrap();
+
+        let decoded = load_image(&path).unwrap();
+        let out = decoded.first_frame();
+        assert_eq!(out.dimensions(), (2, 3));
+        assert_eq!(out.data, img.data);
+    }
+
+    /// Append a PNG chunk with its CRC.
+    fn push_png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], payload: &[u8]) {
+        let mut body = kind.to_vec();
+        body.extend_from_slice(payload);
+        let mut crc = !0u32;
+        for &b in &body {
+            crc ^= b as u32;
+            for _ in 0..8 {
+                crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+            }
+        }
+        out.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        out.extend_from_slice(&body);
+        out.extend_from_slice(&(!crc).to_be_bytes());
+    }
+
+    /// `png` with `chunks` (type, payload) inserted right after IHDR.
+    fn png_with_chunks(png: &[u8], chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let ihdr_end = 8 + 4 + 4 + 13 + 4;
+        let mut out = png[..ihdr_end].to_vec();
+        for (kind, payload) in chunks {
+            push_png_chunk(&mut out, kind, payload);
+        }
+        out.extend_from_slice(&png[ihdr_end..]);
+        out
+    }
+
+    /// A 16-bit RGBA PNG of `samples` (see `png_from_rows`).
+    fn png_rgba16(width: u32, height: u32, samples: &[u16]) -> Vec<u8> {
+        let rows: Vec<Vec<u8>> = samples
+            .chunks_exact(width as usize * 4)
+            .map(|row| row.iter().flat_map(|v| v.to_be_bytes()).collect())
+            .collect();
+        assert_eq!(rows.len(), height as usize);
+        png_from_rows(width, 16, 6, &rows, &[])
+    }
+
+    /// A PNG of the given bit depth and color type from packed, unfiltered
+    /// `rows`, with `chunks` (PLTE, tRNS) before the image data, stored
+    /// uncompressed (so it must stay under 64 KiB).
+    fn png_from_rows(
+        width: u32,
+        depth: u8,
+        color_type: u8,
+        rows: &[Vec<u8>],
+        chunks: &[(&[u8; 4], &[u8])],
+    ) -> Vec<u8> {
+        let mut raw = Vec::new();
+        for row in rows {
+            raw.push(0); // filter: none
+            raw.extend_from_slice(row);
+        }
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in &raw {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        let len = raw.len() as u16;
+        let mut zlib = vec![0x78, 0x01, 1];
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(&raw);
+        zlib.extend_from_slice(&((b << 16) | a).to_be_bytes());
+
+        let mut ihdr = width.to_be_bytes().to_vec();
+        ihdr.extend_from_slice(&(rows.len() as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[depth, color_type, 0, 0, 0]);
+        let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+        push_png_chunk(&mut out, b"IHDR", &ihdr);
+        for (kind, payload) in chunks {
+            push_png_chunk(&mut out, kind, payload);
+        }
+        push_png_chunk(&mut out, b"IDAT", &zlib);
+        push_png_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    #[test]
+    fn test_png_16bit_is_rounded_and_dithered() {
+        // Black, white, and a half-transparent color halfway between two
+        // 8-bit levels (0x8100 is about 128.5 * 257)
+        let mut samples = vec![0, 0, 0, 0xFFFF, 0xFFFF, 0xFFFF, 0xFFFF, 0xFFFF];
+        for _ in 0..14 {
+            samples.extend_from_slice(&[0x8100, 0x8100, 0x8100, 0x8000]);
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deep.png");
+        fs::write(&path, png_rgba16(4, 4, &samples)).unwrap();
+        let img = load_image(&path).unwrap().first_frame().clone();
+
+        assert_eq!(img.data[..8], [0, 0, 0, 255, 255, 255, 255, 255]);
+        let mid: Vec<&[u8]> = img.data.chunks_exact(4).skip(2).collect();
+        assert!(mid.iter().all(|px| px[3] == 128));
+        // Truncation would give 128 everywhere; the dither keeps the
+        // average by mixing 128 and 129
+        let highs = mid.iter().filter(|px| px[0] == 129).count();
+        assert!(mid.iter().all(|px| px[0] == 128 || px[0] == 129));
+        assert!((6..=8).contains(&highs), "{}", highs);
+    }
+
+    #[test]
+    fn test_png_palette_and_gray_transparency() {
+        let dir = tempfile::tempdir().unwrap();
+        let load = |rows: &[Vec<u8>], depth, color_type, chunks: &[(&[u8; 4], &[u8])]| {
+            let path = dir.path().join("t.png");
+            fs::write(&path, png_from_rows(4, depth, color_type, rows, chunks)).unwrap();
+            let img = load_image(&path).unwrap().first_frame().clone();
+            img.data
+                .chunks_exact(4)
+                .map(|px| px.to_vec())
+                .collect::<Vec<_>>()
+        };
+        let plte: &[u8] = &[255, 0, 0, 0, 255, 0, 0, 0, 255, 9, 9, 9];
+        // tRNS may be shorter than the palette: entry 3 stays opaque
+        let trns: &[u8] = &[0, 128, 255];
+        let expected = [
+            [255, 0, 0, 0],
+            [0, 255, 0, 128],
+            [0, 0, 255, 255],
+            [9, 9, 9, 255],
+        ];
+
+        // 8-bit and 2-bit palette with transparency
+        let px8 = load(
+            &[vec![0, 1, 2, 3]],
+            8,
+            3,
+            &[(b"PLTE", plte), (b"tRNS", trns)],
+        );
+        assert_eq!(px8, expected);
+        let px2 = load(
+            &[vec![0b0001_1011]],
+            2,
+            3,
+            &[(b"PLTE", plte), (b"tRNS", trns)],
+        );
+        assert_eq!(px2, expected);
+
+        // 2-bit gray with a transparent level (2 of 0..=3)
+        let gray = load(&[vec![0b0001_1011]], 2, 0, &[(b"tRNS", &[0, 2])]);
+        assert_eq!(
+            gray,
+            [
+                [0, 0, 0, 255],
+                [85, 85, 85, 255],
+                [170, 170, 170, 0],
+                [255, 255, 255, 255]
+            ]
+        );
+
+        // 8-bit gray + alpha
+        let gray_alpha = load(&[vec![10, 0, 20, 64, 30, 128, 40, 255]], 8, 4, &[]);
+        assert_eq!(
+            gray_alpha,
+            [
+                [10, 10, 10, 0],
+                [20, 20, 20, 64],
+                [30, 30, 30, 128],
+                [40, 40, 40, 255]
+            ]
+        );
+
+        // 16-bit gray with a transparent level
+        let row16 = [0u16, 0x1234, 0xFFFF, 0x1234]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        let gray16 = load(&[row16], 16, 0, &[(b"tRNS", &[0x12, 0x34])]);
+        assert_eq!(gray16[0], [0, 0, 0, 255]);
+        assert_eq!(gray16[1][3], 0);
+        assert_eq!(gray16[2], [255, 255, 255, 255]);
+        assert_eq!(gray16[3][3], 0);
+
+        // 8-bit RGB with a transparent color
+        let rgb = load(
+            &[vec![1, 2, 3, 4, 5, 6, 1, 2, 3, 7, 8, 9]],
+            8,
+            2,
+            &[(b"tRNS", &[0, 1, 0, 2, 0, 3])],
+        );
+        assert_eq!(
+            rgb,
+            [[1, 2, 3, 0], [4, 5, 6, 255], [1, 2, 3, 0], [7, 8, 9, 255]]
+        );
+        // 1-bit gray with a transparent white
+        let mono = load(&[vec![0b0101_0000]], 1, 0, &[(b"tRNS", &[0, 1])]);
+        assert_eq!(
+            mono,
+            [
+                [0, 0, 0, 255],
+                [255, 255, 255, 0],
+                [0, 0, 0, 255],
+                [255, 255, 255, 0]
+            ]
+        );
+
+        // With bKGD, transparency is composited over it. The background
+        // of low-depth gray is scaled like the samples (level 1 of 2 bits)
+        let chunks: &[(&[u8; 4], &[u8])] = &[(b"PLTE", plte), (b"tRNS", trns), (b"bKGD", &[2])];
+        let over_blue = load(&[vec![0, 1, 2, 3]], 8, 3, chunks);
+        assert_eq!(
+            over_blue,
+            [
+                [0, 0, 255, 255],
+                [0, 128, 127, 255],
+                [0, 0, 255, 255],
+                [9, 9, 9, 255]
+            ]
+        );
+        let chunks: &[(&[u8; 4], &[u8])] = &[(b"tRNS", &[0, 2]), (b"bKGD", &[0, 1])];
+        let gray = load(&[vec![0b0001_1011]], 2, 0, chunks);
+        assert_eq!(
+            gray,
+            [
+                [0, 0, 0, 255],
+                [85, 85, 85, 255],
+                [85, 85, 85, 255],
+                [255, 255, 255, 255]
+            ]
+        );
+        let gray_alpha = load(
+            &[vec![10, 0, 20, 64, 30, 128, 40, 255]],
+            8,
+            4,
+            &[(b"bKGD", &[0, 200])],
+        );
+        assert_eq!(
+            gray_alpha,
+            [
+                [200, 200, 200, 255],
+                [155, 155, 155, 255],
+                [115, 115, 115, 255],
+                [40, 40, 40, 255]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_png_gamma_and_background() {
+        let mut img = RgbaImage::new(2, 1);
+        img.data.copy_from_slice(&[128, 128, 128, 255, 0, 0, 0, 0]);
+        let png = encode_png(&img).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let load = |name: &str, chunks: &[(&[u8; 4], &[u8])]| {
+            let path = dir.path().join(name);
+            fs::write(&path, png_with_chunks(&png, chunks)).unwrap();
+            load_image(&path).unwrap().first_frame().data.clone()
+        };
+
+        // Linear (gamma 1.0) samples are brightened for the display
+        let linear = load("gamma.png", &[(b"gAMA", &100_000u32.to_be_bytes())]);
+        assert!((185..=188).contains(&linear[0]), "{}", linear[0]);
+        assert_eq!(linear[3], 255);
+
+        // The transparent pixel takes the bKGD color (16-bit RGB samples)
+        let bkgd = [0, 0xFF, 0, 0x80, 0, 0x10];
+        let composited = load("bkgd.png", &[(b"bKGD", &bkgd)]);
+        assert_eq!(composited, [128, 128, 128, 255, 0xFF, 0x80, 0x10, 255]);
+    }
+
+    #[test]
+    fn test_error_diffusion_breaks_up_flat_runs() {
+        // A 16-bit gradient across 4 8-bit levels over 256 pixels: plain
+        // rounding leaves runs of about 64 equal pixels
+        let width = 256;
+        let levels: Vec<u16> = (0..width)
+            .map(|x| 0x4000 + (x * 4 * 257 / width) as u16)
+            .collect();
+        let row: Vec<u16> = levels.iter().flat_map(|&v| [v, v, v, 0xFFFF]).collect();
+        let exact: Vec<f64> = levels.iter().map(|&v| v as f64 / 257.0).collect();
+
+        let mut reducer = DepthReducer::with_diffusion(width, u16::MAX as u32, true);
+        let mut out = vec![0u8; width * 4];
+        for _ in 0..8 {
+            reducer.reduce_row(&row, &mut out);
+            let reds: Vec<u8> = out.chunks_exact(4).map(|px| px[0]).collect();
+            assert!(out.chunks_exact(4).all(|px| px[3] == 255));
+            // Wherever the exact level is well between two 8-bit values,
+            // no six neighbours come out the same
+            for x in 0..width - 5 {
+                let between = exact[x..x + 6]
+                    .iter()
+                    .all(|v| (0.25..=0.75).contains(&v.fract()));
+                if between {
+                    let flat = reds[x..x + 6].iter().all(|&r| r == reds[x]);
+                    assert!(!flat, "flat at {}", x);
+                }
+            }
+            // and the average level is kept
+            let mean = reds.iter().map(|&r| r as f64).sum::<f64>() / width as f64;
+            let exact_mean = exact.iter().sum::<f64>() / width as f64;
+            let error = (mean - exact_mean).abs();
+            assert!(error < 0.05, "{} vs {}", mean, exact_mean);
+        }
+
+        // Deeper samples of other ranges (10-bit) map onto the same scale
+        let mut reducer = DepthReducer::with_diffusion(1, 1023, true);
+        let mut px = [0u8; 4];
+        reducer.reduce_row(&[1023, 0, 512, 1023], &mut px);
+        assert_eq!(px, [255, 0, 128, 255]);
+    }
+
+    #[test]
+    fn test_png_encode_empty() {
+        assert!(encode_png(&RgbaImage::new(0, 0)).is_err());
+    }
+
+    // ========== Pixel aspect tests ==========
+
+    #[test]
+    fn test_jpeg_limit_scale() {
+        use turbojpeg::ScalingFactor;
+        assert_eq!(jpeg_limit_scale(6000, 4000), Some(ScalingFactor::ONE));
+        assert_eq!(
+            jpeg_limit_scale(20000, 20000),
+            Some(ScalingFactor::ONE_HALF)
+        );
+        // 16384x16384 is exactly the limit
+        assert_eq!(
+            jpeg_limit_scale(65535, 65535),
+            Some(ScalingFactor::ONE_QUARTER)
+        );
+        assert_eq!(jpeg_limit_scale(200_000, 200_000), None);
+    }
+
+    #[test]
+    fn test_memory_pixel_limit() {
+        let meminfo = "MemTotal:        2000000 kB\n\
+                       MemFree:          100000 kB\n\
+                       MemAvailable:    1500000 kB\n";
+        assert_eq!(parse_mem_available(meminfo), Some(1_536_000_000));
+        assert_eq!(parse_mem_available("MemTotal: 2000000 kB\n"), None);
+        assert_eq!(parse_mem_available("MemAvailable: lots\n"), None);
+
+        // About 1.5 GB leaves room for 128 MP, well below a 200 MP image
+        assert_eq!(limit_for_memory(Some(1_536_000_000)), 128_000_000);
+        assert_eq!(limit_for_memory(Some(1 << 40)), MAX_PIXEL_COUNT);
+        assert_eq!(limit_for_memory(None), MAX_PIXEL_COUNT);
+        assert!(describe_limit(128_000_000).contains("available memory"));
+    }
+
+    #[test]
+    fn test_pixel_aspect_jfif() {
+        // SOI, APP0 len=16, "JFIF\0", v1.01, units=0, Xdensity=1, Ydensity=2
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10];
+        data.extend_from_slice(b"JFIF\0");
+        data.extend_from_slice(&[1, 1, 0, 0, 1, 0, 2, 0, 0]);
+        assert_eq!(read_pixel_aspect(&data, "jpg"), 2.0);
+
+        // Square density
+        data[15] = 2;
+        assert_eq!(read_pixel_aspect(&data, "jpg"), 1.0);
+    }
 
     #[test]
-    fn test_rotate_90() {
-        let img = make_2x3_image(); // 2x3
-        let out = rotate_90(img);
-        assert_eq!(out.dimensions(), (3, 2)); // width=old_h, height=old_w
-                                              // Original layout:
-                                              //   R G     Rotated 90 CW:
-                                              //   B W     Y B R
-                                              //   Y C     C W G
-        assert_eq!(pixel_at(&out, 0, 0), [255, 255, 0, 255]); // Y
-        assert_eq!(pixel_at(&out, 1, 0), [0, 0, 255, 255]); // B
-        assert_eq!(pixel_at(&out, 2, 0), [255, 0, 0, 255]); // R
-        assert_eq!(pixel_at(&out, 0, 1), [0, 255, 255, 255]); // C
-        assert_eq!(pixel_at(&out, 1, 1), [255, 255, 255, 255]); // W
-        assert_eq!(pixel_at(&out, 2, 1), [0, 255, 0, 255]); // G
+    fn test_pixel_aspect_png_phys() {
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+        data.extend_from_slice(&9u32.to_be_bytes());
+        data.extend_from_slice(b"pHYs");
+        data.extend_from_slice(&2000u32.to_be_bytes()); // X ppu
+        data.extend_from_slice(&1000u32.to_be_bytes()); // Y ppu
+        data.push(1);
+        data.extend_from_slice(&[0; 4]); // CRC
+        assert_eq!(read_pixel_aspect(&data, "png"), 0.5);
     }
 
     #[test]
-    fn test_rotate_180() {
-        let img = make_2x3_image();
-        let out = rotate_180(img);
-        assert_eq!(out.dimensions(), (2, 3));
-        // 180: reverse all pixels
-        //   C Y
-        //   W B
-        //   G R
-        assert_eq!(pixel_at(&out, 0, 0), [0, 255, 255, 255]); // C
-        assert_eq!(pixel_at(&out, 1, 0), [255, 255, 0, 255]); // Y
-        assert_eq!(pixel_at(&out, 0, 1), [255, 255, 255, 255]); // W
-        assert_eq!(pixel_at(&out, 1, 1), [0, 0, 255, 255]); // B
-        assert_eq!(pixel_at(&out, 0, 2), [0, 255, 0, 255]); // G
-        assert_eq!(pixel_at(&out, 1, 2), [255, 0, 0, 255]); // R
+    fn test_pixel_aspect_avif_pasp() {
+        fn bx(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+            let mut out = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+            out.extend_from_slice(kind);
+            out.extend_from_slice(payload);
+            out
+        }
+        let mut pasp_payload = 4u32.to_be_bytes().to_vec();
+        pasp_payload.extend_from_slice(&3u32.to_be_bytes());
+        let ipco = bx(b"ipco", &bx(b"pasp", &pasp_payload));
+        let mut meta_payload = vec![0u8; 4]; // version/flags
+        meta_payload.extend_from_slice(&bx(b"iprp", &ipco));
+        let mut data = bx(b"ftyp", b"avif");
+        data.extend_from_slice(&bx(b"meta", &meta_payload));
+        let aspect = read_pixel_aspect(&data, "avif");
+        assert!((aspect - 4.0 / 3.0).abs() < 1e-9);
+    }
+
+    /// A minimal AVIF/HEIF header whose ipco holds the given properties.
+    fn build_isobmff_properties(props: &[(&[u8; 4], u8)]) -> Vec<u8> {
+        fn bx(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+            let mut out = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+            out.extend_from_slice(kind);
+            out.extend_from_slice(payload);
+            out
+        }
+        let ipco: Vec<u8> = props.iter().flat_map(|(kind, v)| bx(kind, &[*v])).collect();
+        let mut meta_payload = vec![0u8; 4]; // version/flags
+        meta_payload.extend_from_slice(&bx(b"iprp", &bx(b"ipco", &ipco)));
+        let mut data = bx(b"ftyp", b"heic");
+        data.extend_from_slice(&bx(b"meta", &meta_payload));
+        data
     }
 
     #[test]
-    fn test_rotate_270() {
-        let img = make_2x3_image();
-        let out = rotate_270(img);
-        assert_eq!(out.dimensions(), (3, 2));
-        // 270 CW (= 90 CCW):
-        //   G W C
-        //   R B Y
-        assert_eq!(pixel_at(&out, 0, 0), [0, 255, 0, 255]); // G
-        assert_eq!(pixel_at(&out, 1, 0), [255, 255, 255, 255]); // W
-        assert_eq!(pixel_at(&out, 2, 0), [0, 255, 255, 255]); // C
-        assert_eq!(pixel_at(&out, 0, 1), [255, 0, 0, 255]); // R
-        assert_eq!(pixel_at(&out, 1, 1), [0, 0, 255, 255]); // B
-        assert_eq!(pixel_at(&out, 2, 1), [255, 255, 0, 255]); // Y
+    fn test_isobmff_container_orientation() {
+        let orientation = |props: &[(&[u8; 4], u8)]| {
+            isobmff_container_orientation(&build_isobmff_properties(props))
+        };
+        assert_eq!(orientation(&[]), None);
+        assert_eq!(orientation(&[(b"irot", 0)]), Some(1));
+        // irot counts anti-clockwise
+        assert_eq!(orientation(&[(b"irot", 1)]), Some(8));
+        assert_eq!(orientation(&[(b"irot", 3)]), Some(6));
+        assert_eq!(orientation(&[(b"imir", 0)]), Some(2));
+        assert_eq!(orientation(&[(b"imir", 1)]), Some(4));
+
+        // Rotation then mirroring matches the pixel transforms
+        let mut img = RgbaImage::new(3, 2);
+        for (i, px) in img.data.chunks_exact_mut(4).enumerate() {
+            px[0] = i as u8;
+        }
+        let net = orientation(&[(b"irot", 1), (b"imir", 0)]).unwrap();
+        let expected = flip_h(rotate_270(img.clone()));
+        assert_eq!(apply_orientation(img.clone(), net).data, expected.data);
+        let net = orientation(&[(b"irot", 1), (b"imir", 1)]).unwrap();
+        let expected = flip_v(rotate_270(img.clone()));
+        assert_eq!(apply_orientation(img, net).data, expected.data);
     }
 
     #[test]
-    fn test_flip_h() {
-        // Use a 2x2 image
-        let mut img = RgbaImage::new(2, 2);
-        img.data[0..4].copy_from_slice(&[255, 0, 0, 255]); // (0,0)=R
-        img.data[4..8].copy_from_slice(&[0, 255, 0, 255]); // (1,0)=G
-        img.data[8..12].copy_from_slice(&[0, 0, 255, 255]); // (0,1)=B
-        img.data[12..16].copy_from_slice(&[255, 255, 0, 255]); // (1,1)=Y
+    fn test_orientation_policy() {
+        use OrientationPolicy::*;
+        assert_eq!(OrientationPolicy::parse("container"), Some(Container));
+        assert_eq!(OrientationPolicy::parse("none"), Some(Off));
+        assert_eq!(OrientationPolicy::parse("sideways"), None);
 
-        let out = flip_h(img);
-        assert_eq!(out.dimensions(), (2, 2));
-        assert_eq!(pixel_at(&out, 0, 0), [0, 255, 0, 255]); // G (was right)
-        assert_eq!(pixel_at(&out, 1, 0), [255, 0, 0, 255]); // R (was left)
-        assert_eq!(pixel_at(&out, 0, 1), [255, 255, 0, 255]); // Y
-        assert_eq!(pixel_at(&out, 1, 1), [0, 0, 255, 255]); // B
+        // A file rotated by the container and tagged in EXIF
+        assert_eq!(Auto.resolve(Some(6), Some(8)), Some(6));
+        assert_eq!(Auto.resolve(None, Some(8)), Some(8));
+        assert_eq!(Container.resolve(None, Some(8)), None);
+        assert_eq!(Exif.resolve(Some(6), Some(8)), Some(8));
+        assert_eq!(Off.resolve(Some(6), Some(8)), None);
     }
 
     #[test]
-    fn test_flip_v() {
-        let mut img = RgbaImage::new(2, 2);
-        img.data[0..4].copy_from_slice(&[255, 0, 0, 255]); // (0,0)=R
-        img.data[4..8].copy_from_slice(&[0, 255, 0, 255]); // (1,0)=G
-        img.data[8..12].copy_from_slice(&[0, 0, 255, 255]); // (0,1)=B
-        img.data[12..16].copy_from_slice(&[255, 255, 0, 255]); // (1,1)=Y
-
-        let out = flip_v(img);
-        assert_eq!(out.dimensions(), (2, 2));
-        assert_eq!(pixel_at(&out, 0, 0), [0, 0, 255, 255]); // B (was bottom-left)
-        assert_eq!(pixel_at(&out, 1, 0), [255, 255, 0, 255]); // Y (was bottom-right)
-        assert_eq!(pixel_at(&out, 0, 1), [255, 0, 0, 255]); // R (was top-left)
-        assert_eq!(pixel_at(&out, 1, 1), [0, 255, 0, 255]); // G (was top-right)
+    fn test_pixel_aspect_default() {
+        assert_eq!(read_pixel_aspect(&[], "jpg"), 1.0);
+        assert_eq!(read_pixel_aspect(b"GIF89a", "gif"), 1.0);
     }
 
     // ========== BMP parser tests ==========
@@ -3678,6 +7063,119 @@ mod tests {
         assert_eq!(pixel_at(&img, 7, 0), [0, 0, 0, 255]); // 0=black
     }
 
+    #[test]
+    fn test_bmp_8bit_top_down() {
+        // 2x2 8-bit BMP with negative height: the first file row is the top
+        let palette: Vec<[u8; 4]> = vec![
+            [0, 0, 255, 0], // index 0: Red
+            [0, 255, 0, 0], // index 1: Green
+            [255, 0, 0, 0], // index 2: Blue
+        ];
+        let pixels = vec![0, 1, 0, 0, 2, 0, 0, 0]; // rows padded to 4 bytes
+
+        let bmp = build_bmp(2, -2, 8, 0, &palette, &pixels);
+        let LoadedImage::Static(img) = decode_bmp(&bmp, "test").unwrap() else {
+            panic!("Expected static image");
+        };
+        assert_eq!(img.dimensions(), (2, 2));
+        assert_eq!(pixel_at(&img, 0, 0), [255, 0, 0, 255]); // red
+        assert_eq!(pixel_at(&img, 1, 0), [0, 255, 0, 255]); // green
+        assert_eq!(pixel_at(&img, 0, 1), [0, 0, 255, 255]); // blue
+        assert_eq!(pixel_at(&img, 1, 1), [255, 0, 0, 255]); // red
+
+        // The same bytes stored bottom-up come out flipped
+        let bmp = build_bmp(2, 2, 8, 0, &palette, &pixels);
+        let LoadedImage::Static(img) = decode_bmp(&bmp, "test").unwrap() else {
+            panic!("Expected static image");
+        };
+        assert_eq!(pixel_at(&img, 0, 0), [0, 0, 255, 255]); // blue
+        assert_eq!(pixel_at(&img, 1, 1), [0, 255, 0, 255]); // green
+        assert_eq!((source_row(0, 3, false), source_row(0, 3, true)), (2, 0));
+    }
+
+    #[test]
+    fn test_bmp_2bit() {
+        // 5x1 2-bit BMP with 4-entry palette
+        // Row size: (5*2+31)/32 * 4 = 4 bytes
+        let palette: Vec<[u8; 4]> = vec![
+            [0, 0, 0, 0],       // index 0: Black
+            [0, 0, 255, 0],     // index 1: Red
+            [0, 255, 0, 0],     // index 2: Green
+            [255, 255, 255, 0], // index 3: White
+        ];
+        // 5 pixels: 3,2,1,0 = 0b11100100 = 0xE4, then 1 = 0b01000000 = 0x40
+        let pixels = vec![0xE4, 0x40, 0, 0];
+
+        let bmp = build_bmp(5, 1, 2, 0, &palette, &pixels);
+        let LoadedImage::Static(img) = decode_bmp(&bmp, "test").unwrap() else {
+            panic!("Expected static image");
+        };
+        assert_eq!(img.dimensions(), (5, 1));
+        assert_eq!(pixel_at(&img, 0, 0), [255, 255, 255, 255]);
+        assert_eq!(pixel_at(&img, 1, 0), [0, 255, 0, 255]);
+        assert_eq!(pixel_at(&img, 2, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel_at(&img, 3, 0), [0, 0, 0, 255]);
+        assert_eq!(pixel_at(&img, 4, 0), [255, 0, 0, 255]);
+    }
+
+    /// Build an OS/2 BMP with a 12-byte BITMAPCOREHEADER and a palette of
+    /// 3-byte BGR triples.
+    fn build_core_bmp(
+        width: u16,
+        height: u16,
+        bpp: u16,
+        color_table: &[[u8; 3]],
+        pixel_data: &[u8],
+    ) -> Vec<u8> {
+        let data_offset = 14 + 12 + color_table.len() as u32 * 3;
+        let file_size = data_offset + pixel_data.len() as u32;
+
+        let mut buf = Vec::with_capacity(file_size as usize);
+        buf.extend_from_slice(b"BM");
+        buf.extend_from_slice(&file_size.to_le_bytes());
+        buf.extend_from_slice(&[0u8; 4]); // reserved
+        buf.extend_from_slice(&data_offset.to_le_bytes());
+        buf.extend_from_slice(&12u32.to_le_bytes());
+        buf.extend_from_slice(&width.to_le_bytes());
+        buf.extend_from_slice(&height.to_le_bytes());
+        buf.extend_from_slice(&1u16.to_le_bytes()); // planes
+        buf.extend_from_slice(&bpp.to_le_bytes());
+        for entry in color_table {
+            buf.extend_from_slice(entry); // BGR
+        }
+        buf.extend_from_slice(pixel_data);
+        buf
+    }
+
+    #[test]
+    fn test_bmp_core_header() {
+        // 2x2 8-bit, bottom-up: the first file row is the bottom image row
+        let palette = [[0, 0, 255], [255, 0, 0], [0, 255, 0]]; // red, blue, green
+        let pixels = vec![2, 0, 0, 0, 1, 2, 0, 0];
+        let bmp = build_core_bmp(2, 2, 8, &palette, &pixels);
+        assert_eq!(bmp.len(), 14 + 12 + 9 + 8);
+        let LoadedImage::Static(img) = decode_bmp(&bmp, "test").unwrap() else {
+            panic!("Expected static image");
+        };
+        assert_eq!(img.dimensions(), (2, 2));
+        assert_eq!(pixel_at(&img, 0, 0), [0, 0, 255, 255]); // blue
+        assert_eq!(pixel_at(&img, 1, 0), [0, 255, 0, 255]); // green
+        assert_eq!(pixel_at(&img, 0, 1), [0, 255, 0, 255]); // green
+        assert_eq!(pixel_at(&img, 1, 1), [255, 0, 0, 255]); // red
+
+        // 24-bit core BMPs carry no palette
+        let bmp = build_core_bmp(1, 1, 24, &[], &[10, 20, 30, 0]);
+        let LoadedImage::Static(img) = decode_bmp(&bmp, "test").unwrap() else {
+            panic!("Expected static image");
+        };
+        assert_eq!(pixel_at(&img, 0, 0), [30, 20, 10, 255]);
+
+        // A palette cut short is rejected rather than read past the end
+        let mut bmp = build_core_bmp(2, 1, 8, &palette, &[]);
+        bmp.truncate(14 + 12 + 4);
+        assert!(decode_bmp(&bmp, "test").is_err());
+    }
+
     #[test]
     fn test_bmp_rle8_rejected() {
         let palette: Vec<[u8; 4]> = vec![[0, 0, 0, 0]; 2];
@@ -3756,6 +7254,37 @@ mod tests {
         buf
     }
 
+    /// Insert an EXIF APP1 segment tagged with `orientation` right after
+    /// the SOI marker of `jpeg`.
+    fn with_exif_orientation(jpeg: &[u8], le: bool, orientation: u16) -> Vec<u8> {
+        let tiff = build_tiff_with_orientation(le, orientation);
+        let mut out = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        out.extend_from_slice(&((2 + 6 + tiff.len()) as u16).to_be_bytes());
+        out.extend_from_slice(b"Exif\0\0");
+        out.extend_from_slice(&tiff);
+        out.extend_from_slice(&jpeg[2..]);
+        out
+    }
+
+    #[test]
+    fn test_exif_tags_for_ext_dispatch() {
+        let tiff = build_tiff_with_orientation(true, 6);
+        let mut webp = Vec::new();
+        webp.extend_from_slice(b"RIFF");
+        webp.extend_from_slice(&((4 + 8 + tiff.len()) as u32).to_le_bytes());
+        webp.extend_from_slice(b"WEBP");
+        webp.extend_from_slice(b"EXIF");
+        webp.extend_from_slice(&(tiff.len() as u32).to_le_bytes());
+        webp.extend_from_slice(&tiff);
+
+        let has_orientation =
+            |tags: Vec<(String, String)>| tags.iter().any(|(label, _)| label == "Orientation");
+        assert!(has_orientation(read_exif_tags_for_ext(&webp, "webp")));
+        assert!(has_orientation(read_exif_tags_for_ext(&tiff, "tif")));
+        assert!(read_exif_tags_for_ext(&webp, "jpg").is_empty());
+        assert!(read_exif_tags_for_ext(&tiff, "bmp").is_empty());
+    }
+
     #[test]
     fn test_exif_orientation_le() {
         let data = build_tiff_with_orientation(true, 6);
@@ -3770,6 +7299,484 @@ mod tests {
         assert_eq!(result, Some(3));
     }
 
+    #[test]
+    fn test_jpeg_orientation_reset_after_rotation() {
+        for le in [true, false] {
+            let mut jpeg = with_exif_orientation(&[0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x02], le, 6);
+            assert_eq!(read_exif_orientation(&jpeg), Some(6));
+            set_jpeg_orientation_normal(&mut jpeg);
+            assert_eq!(read_exif_orientation(&jpeg), Some(1));
+        }
+        // Stored rotation plus manual turns; mirrored orientations cannot
+        // be combined into a plain rotation
+        assert_eq!(orientation_turns(6).map(|t| (t + 3) % 4), Some(0));
+        assert_eq!(orientation_turns(8), Some(3));
+        assert_eq!(orientation_turns(5), None);
+    }
+
+    /// A `w`x`h` image, red on the left half and blue on the right.
+    fn red_blue_halves(w: u32, h: u32) -> RgbaImage {
+        let (red, blue) = ([255, 0, 0, 255], [0, 0, 255, 255]);
+        let data = (0..w * h)
+            .flat_map(|i| if i % w < w / 2 { red } else { blue })
+            .collect();
+        RgbaImage::from_raw(w, h, data).unwrap()
+    }
+
+    /// Whether the pixel at (`x`, `y`) is red rather than blue.
+    fn is_red(img: &RgbaImage, x: u32, y: u32) -> bool {
+        let i = ((y * img.width + x) * 4) as usize;
+        img.data[i] > 128 && img.data[i + 2] < 128
+    }
+
+    /// Decode JPEG data to its stored pixels, ignoring the orientation.
+    fn decode_jpeg_data(data: &[u8]) -> RgbaImage {
+        let image = turbojpeg::decompress(data, turbojpeg::PixelFormat::RGBA).unwrap();
+        RgbaImage::from_raw(image.width as u32, image.height as u32, image.pixels).unwrap()
+    }
+
+    #[test]
+    fn test_rotate_jpeg_lossless_and_reencoded() {
+        // 32x16 is a whole number of 4:2:0 MCUs, so the turn is lossless
+        let img = red_blue_halves(32, 16);
+        let jpeg = encode_jpeg(&img, 95).unwrap();
+        let (out, lossless) = rotate_jpeg(&jpeg, 1, &rotate_90(img)).unwrap();
+        assert!(lossless);
+        let rotated = decode_jpeg_data(&out);
+        assert_eq!(rotated.dimensions(), (16, 32));
+        // A clockwise turn moves the left half to the top
+        assert!(is_red(&rotated, 8, 4) && !is_red(&rotated, 8, 28));
+
+        // 20x12 leaves partial MCUs on the edges, so the displayed pixels
+        // are re-encoded instead
+        let img = red_blue_halves(20, 12);
+        let jpeg = encode_jpeg(&img, 95).unwrap();
+        assert!(transform_jpeg_lossless(&jpeg, 1).is_err());
+        let (out, lossless) = rotate_jpeg(&jpeg, 1, &rotate_90(img)).unwrap();
+        assert!(!lossless);
+        let rotated = decode_jpeg_data(&out);
+        assert_eq!(rotated.dimensions(), (12, 20));
+        assert!(is_red(&rotated, 6, 3) && !is_red(&rotated, 6, 17));
+
+        // The stored EXIF quarter turn adds to the manual ones, and the tag
+        // is reset since the pixels now stand upright
+        let jpeg = encode_jpeg(&red_blue_halves(32, 16), 95).unwrap();
+        let jpeg = with_exif_orientation(&jpeg, true, 6);
+        let (out, lossless) = rotate_jpeg(&jpeg, 2, &RgbaImage::new(16, 32)).unwrap();
+        assert!(lossless);
+        assert_eq!(read_exif_orientation(&out), Some(1));
+        let rotated = decode_jpeg_data(&out);
+        assert_eq!(rotated.dimensions(), (16, 32));
+        // Three turns clockwise move the left half to the bottom
+        assert!(!is_red(&rotated, 8, 4) && is_red(&rotated, 8, 28));
+    }
+
+    #[test]
+    fn test_portrait_jpeg_thumbnail_is_taller_than_wide() {
+        // A camera held upright stores landscape pixels tagged orientation 6
+        let jpeg = encode_jpeg(&red_blue_halves(400, 300), 90).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let portrait = dir.path().join("portrait.jpg");
+        std::fs::write(&portrait, with_exif_orientation(&jpeg, true, 6)).unwrap();
+
+        // Decoded at 1/2 scale, turned upright, then fit into 100x100
+        let thumb = load_jpeg_thumbnail(&portrait, 100).unwrap();
+        assert_eq!((thumb.width, thumb.height), (75, 100));
+        // The left half of the stored pixels is the top of the photo
+        assert!(is_red(&thumb, 37, 10) && !is_red(&thumb, 37, 90));
+
+        // Without an orientation tag the pixels are left alone
+        let plain = dir.path().join("plain.jpg");
+        std::fs::write(&plain, &jpeg).unwrap();
+        let thumb = load_jpeg_thumbnail(&plain, 100).unwrap();
+        assert_eq!((thumb.width, thumb.height), (100, 75));
+        assert!(is_red(&thumb, 10, 37) && !is_red(&thumb, 90, 37));
+    }
+
+    #[test]
+    fn test_probe_display_size() {
+        // EXIF APP1 tagged orientation 6, a DHT segment, then a 400x300 SOF2
+        let tiff = build_tiff_with_orientation(false, 6);
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&((2 + 6 + tiff.len()) as u16).to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(&tiff);
+        jpeg.extend_from_slice(&[0xFF, 0xC4, 0x00, 0x04, 0xAA, 0xBB]);
+        jpeg.extend_from_slice(&[0xFF, 0xFF, 0xC2, 0x00, 0x0B, 8, 0x01, 0x2C, 0x01, 0x90]);
+        jpeg.extend_from_slice(&[3, 1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1]);
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02]);
+        assert_eq!(jpeg_dimensions(&jpeg), Some((400, 300)));
+        // Cut off before the frame header, or no frame header at all
+        assert_eq!(jpeg_dimensions(&jpeg[..jpeg.len() - 16]), None);
+        assert_eq!(jpeg_dimensions(&[0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x02]), None);
+
+        let png = png_from_rows(3, 8, 0, &[vec![0; 3], vec![0; 3]], &[]);
+        assert_eq!(png_dimensions(&png), Some((3, 2)));
+        assert_eq!(png_dimensions(&png[..20]), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, data: &[u8]| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, data).unwrap();
+            path
+        };
+        // The quarter turn swaps the displayed width and height
+        assert_eq!(probe_display_size(&write("a.jpg", &jpeg)), Some((300, 400)));
+        assert_eq!(probe_display_size(&write("b.png", &png)), Some((3, 2)));
+        assert_eq!(probe_display_size(&write("c.jpg", &png)), None);
+        assert_eq!(probe_display_size(&write("d.gif", b"GIF89a")), None);
+        assert_eq!(probe_display_size(&write("e.webp", b"RIFF")), None);
+        assert_eq!(probe_display_size(&dir.path().join("missing.png")), None);
+    }
+
+    #[test]
+    fn test_image_dimensions() {
+        let dir = tempfile::tempdir().unwrap();
+        let dims = |name: &str, data: &[u8]| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, data).unwrap();
+            image_dimensions(&path)
+        };
+
+        // JPEG, with EXIF orientation ignored
+        let mut jpeg = vec![
+            0xFF, 0xD8, 0xFF, 0xC0, 0x00, 0x0B, 8, 0x00, 0x20, 0x00, 0x30,
+        ];
+        jpeg.extend_from_slice(&[1, 1, 0x11, 0, 0xFF, 0xDA, 0x00, 0x02]);
+        assert_eq!(dims("a.jpg", &jpeg), Some((48, 32)));
+        let png = png_from_rows(3, 8, 0, &[vec![0; 3], vec![0; 3]], &[]);
+        assert_eq!(dims("a.png", &png), Some((3, 2)));
+        assert_eq!(
+            dims("a.gif", b"GIF89a\x05\x00\x07\x00\x80\x00\x00"),
+            Some((5, 7))
+        );
+        assert_eq!(dims("b.gif", b"GIF8"), None);
+        // Top-down BMP
+        assert_eq!(
+            dims("a.bmp", &build_bmp(4, -3, 24, 0, &[], &[0; 36])),
+            Some((4, 3))
+        );
+
+        // WebP: lossless, lossy and extended
+        let encode = |lossless: bool, w: i32, h: i32| unsafe {
+            let pixels = vec![128u8; (w * h * 4) as usize];
+            let mut out: *mut u8 = std::ptr::null_mut();
+            let len = if lossless {
+                libwebp_sys::WebPEncodeLosslessRGBA(pixels.as_ptr(), w, h, w * 4, &mut out)
+            } else {
+                libwebp_sys::WebPEncodeRGBA(pixels.as_ptr(), w, h, w * 4, 80.0, &mut out)
+            };
+            assert!(len > 0, "WebP encoding failed");
+            let bytes = std::slice::from_raw_parts(out, len).to_vec();
+            libwebp_sys::WebPFree(out as *mut std::ffi::c_void);
+            bytes
+        };
+        assert_eq!(dims("a.webp", &encode(true, 5, 3)), Some((5, 3)));
+        assert_eq!(dims("b.webp", &encode(false, 37, 21)), Some((37, 21)));
+        let mut vp8x = b"RIFF\x16\0\0\0WEBPVP8X\x0a\0\0\0\0\0\0\0".to_vec();
+        vp8x.extend_from_slice(&[0x1F, 0x4E, 0x00, 0xDF, 0x2E, 0x00]);
+        assert_eq!(dims("c.webp", &vp8x), Some((20000, 12000)));
+
+        // TIFF with its IFD after the image data, in either byte order
+        let mut tiff = b"MM\0\x2a\0\0\0\x10".to_vec();
+        tiff.extend_from_slice(&[0xAB; 8]);
+        tiff.extend_from_slice(&[0, 2]);
+        tiff.extend_from_slice(&[0x01, 0x00, 0, 3, 0, 0, 0, 1, 0x02, 0x80, 0, 0]);
+        tiff.extend_from_slice(&[0x01, 0x01, 0, 4, 0, 0, 0, 1, 0, 0, 0x01, 0xE0]);
+        assert_eq!(dims("a.tif", &tiff), Some((640, 480)));
+        let mut tiff = b"II\x2a\0\x08\0\0\0\x01\0".to_vec();
+        tiff.extend_from_slice(&[0x00, 0x01, 3, 0, 1, 0, 0, 0, 0x80, 0x02, 0, 0]);
+        assert_eq!(dims("b.tiff", &tiff), None); // no ImageLength
+
+        // AVIF grid and HEIC; meta after the image data is found as well
+        let avif = include_bytes!("../test_images/grid.avif");
+        assert_eq!(dims("a.avif", avif), Some((288, 128)));
+        let mut late_meta = vec![0, 0, 0x03, 0xE8];
+        late_meta.extend_from_slice(b"mdat");
+        late_meta.resize(1000, 0);
+        late_meta.extend_from_slice(avif);
+        assert_eq!(dims("b.avif", &late_meta), Some((288, 128)));
+        let heic = include_bytes!("../test_images/alpha.heic");
+        assert_eq!(dims("a.heic", heic), Some((16, 16)));
+        assert_eq!(dims("c.avif", &avif[..40]), None);
+
+        // JPEG XL: small header 64x32, and 1000 rows at 16:9 in a container
+        assert_eq!(dims("a.jxl", &[0xFF, 0x0A, 0x07, 0x0E]), Some((64, 32)));
+        let size_header = 1u32 << 1 | 999 << 3 | 5 << 16;
+        let mut jxl = b"\0\0\0\x0cJXL \r\n\x87\n\0\0\0\x14ftypjxl \0\0\0\0jxl ".to_vec();
+        jxl.extend_from_slice(&[0, 0, 0, 0]);
+        jxl.extend_from_slice(b"jxlp\0\0\0\0\xFF\x0A");
+        jxl.extend_from_slice(&size_header.to_le_bytes()[..3]);
+        assert_eq!(dims("b.jxl", &jxl), Some((1777, 1000)));
+        assert_eq!(dims("c.jxl", &[0xFF, 0x0A]), None);
+
+        assert_eq!(dims("a.svg", b"<svg width='10' height='10'/>"), None);
+    }
+
+    #[test]
+    fn test_rotated_orientation_matches_pixels() {
+        let mut img = RgbaImage::new(3, 2);
+        for (i, px) in img.data.chunks_exact_mut(4).enumerate() {
+            px[0] = i as u8;
+        }
+        for orientation in 1..=8 {
+            let mut shown = apply_orientation(img.clone(), orientation);
+            for turns in 0..4 {
+                let net = rotated_orientation(orientation, turns);
+                assert_eq!(
+                    apply_orientation(img.clone(), net).data,
+                    shown.data,
+                    "orientation {} + {} turns",
+                    orientation,
+                    turns
+                );
+                shown = rotate_90(shown);
+            }
+        }
+        assert_eq!(rotated_orientation(6, 3), 1);
+        assert_eq!(orientation_from_name("Rotated 90 CW"), Some(6));
+        assert_eq!(orientation_from_name("Sideways"), None);
+    }
+
+    #[test]
+    fn test_decode_user_comment() {
+        let comment =
+            |code: &[u8], text: &[u8], le: bool| decode_user_comment(&[code, text].concat(), le);
+        assert_eq!(
+            comment(b"ASCII\0\0\0", b"Sunset\0\0", true).as_deref(),
+            Some("Sunset")
+        );
+        // UTF-16 in the TIFF byte order, or as marked by a BOM
+        let utf16_le: Vec<u8> = "Caf\u{e9}"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let utf16_be: Vec<u8> = "Caf\u{e9}"
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        assert_eq!(
+            comment(b"UNICODE\0", &utf16_le, true).as_deref(),
+            Some("Caf\u{e9}")
+        );
+        assert_eq!(
+            comment(b"UNICODE\0", &utf16_be, false).as_deref(),
+            Some("Caf\u{e9}")
+        );
+        let with_bom = [&[0xFE, 0xFF][..], &utf16_be].concat();
+        assert_eq!(
+            comment(b"UNICODE\0", &with_bom, true).as_deref(),
+            Some("Caf\u{e9}")
+        );
+        // Undefined code: UTF-8, blank padding trimmed
+        assert_eq!(
+            comment(&[0; 8], b"  note\n  ", true).as_deref(),
+            Some("note")
+        );
+        assert_eq!(comment(&[0; 8], b"        ", true).as_deref(), Some(""));
+        // JIS and short values are not decoded
+        assert_eq!(comment(b"JIS\0\0\0\0\0", b"\x30\x21", true), None);
+        assert_eq!(decode_user_comment(b"ASCII", true), None);
+        // Long comments are shortened
+        let long = comment(b"ASCII\0\0\0", &[b'x'; 100], true).unwrap();
+        assert_eq!(long.len(), MAX_USER_COMMENT_CHARS + 3);
+    }
+
+    #[test]
+    fn test_exif_description_and_user_comment() {
+        // Little-endian TIFF: IFD0 with ImageDescription and an EXIF
+        // sub-IFD pointer, then the sub-IFD with UserComment
+        let desc = b"Harbor at dawn\0";
+        let comment = [&b"ASCII\0\0\0"[..], b"Shot on film"].concat();
+        let ifd0 = 8;
+        let sub_ifd = ifd0 + 2 + 2 * 12 + 4;
+        let desc_off = sub_ifd + 2 + 12 + 4;
+        let comment_off = desc_off + desc.len();
+
+        let mut d = b"II\x2a\x00".to_vec();
+        d.extend_from_slice(&(ifd0 as u32).to_le_bytes());
+        let entry = |d: &mut Vec<u8>, tag: u16, typ: u16, count: usize, value: usize| {
+            d.extend_from_slice(&tag.to_le_bytes());
+            d.extend_from_slice(&typ.to_le_bytes());
+            d.extend_from_slice(&(count as u32).to_le_bytes());
+            d.extend_from_slice(&(value as u32).to_le_bytes());
+        };
+        d.extend_from_slice(&2u16.to_le_bytes());
+        entry(&mut d, 0x010E, 2, desc.len(), desc_off);
+        entry(&mut d, 0x8769, 4, 1, sub_ifd);
+        d.extend_from_slice(&[0; 4]);
+        d.extend_from_slice(&1u16.to_le_bytes());
+        entry(&mut d, 0x9286, 7, comment.len(), comment_off);
+        d.extend_from_slice(&[0; 4]);
+        d.extend_from_slice(desc);
+        d.extend_from_slice(&comment);
+
+        let tags = parse_all_exif_tags(&d, 0);
+        let get = |label: &str| {
+            tags.iter()
+                .find(|(l, _)| l == label)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(get("Description"), Some("Harbor at dawn"));
+        assert_eq!(get("User Comment"), Some("Shot on film"));
+    }
+
+    #[test]
+    fn test_exif_short_and_long_arrays() {
+        // YCbCrSubSampling (2 SHORTs) fits inline; BitsPerSample (3 SHORTs)
+        // and a 10-element LONG array are stored after the IFD
+        let entry = |d: &mut Vec<u8>, tag: u16, typ: u16, count: u32, value: [u8; 4]| {
+            d.extend_from_slice(&tag.to_be_bytes());
+            d.extend_from_slice(&typ.to_be_bytes());
+            d.extend_from_slice(&count.to_be_bytes());
+            d.extend_from_slice(&value);
+        };
+        let bits_off = 8 + 2 + 3 * 12 + 4;
+        let long_off = bits_off + 6;
+        let mut d = b"MM\x00\x2a\x00\x00\x00\x08".to_vec();
+        d.extend_from_slice(&3u16.to_be_bytes());
+        entry(&mut d, 0x0102, 3, 3, (bits_off as u32).to_be_bytes());
+        entry(&mut d, 0x0212, 3, 2, [0, 2, 0, 1]);
+        entry(&mut d, 0xA002, 4, 10, (long_off as u32).to_be_bytes());
+        d.extend_from_slice(&[0; 4]);
+        for bits in [8u16, 8, 16] {
+            d.extend_from_slice(&bits.to_be_bytes());
+        }
+        for n in 1..=10u32 {
+            d.extend_from_slice(&n.to_be_bytes());
+        }
+
+        let tags = parse_all_exif_tags(&d, 0);
+        let get = |label: &str| {
+            tags.iter()
+                .find(|(l, _)| l == label)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(get("YCbCr Subsampling"), Some("2, 1"));
+        assert_eq!(get("Bits per Sample"), Some("8, 8, 16"));
+        // Long arrays are cut after MAX_TAG_VALUES elements; Width is in the
+        // EXIF sub-IFD, so this checks the IFD0 list directly
+        let value = read_tag_value(&d, 8 + 2 + 2 * 12 + 8, 4, 10, false, 0xA002);
+        assert_eq!(value.as_deref(), Some("1, 2, 3, 4, 5, 6, 7, 8, ..."));
+    }
+
+    #[test]
+    fn test_exif_follows_ifd1_and_interop() {
+        // IFD0 -> EXIF sub-IFD -> Interop IFD, and IFD0 -> IFD1 (thumbnail),
+        // whose next pointer leads back to IFD0
+        fn entry(buf: &mut Vec<u8>, tag: u16, dtype: u16, count: u32, value: [u8; 4]) {
+            buf.extend_from_slice(&tag.to_le_bytes());
+            buf.extend_from_slice(&dtype.to_le_bytes());
+            buf.extend_from_slice(&count.to_le_bytes());
+            buf.extend_from_slice(&value);
+        }
+        let (ifd0, exif, interop, ifd1, thumb) = (8u32, 38u32, 56u32, 74u32, 104u32);
+        let mut t = b"II\x2a\x00".to_vec();
+        t.extend_from_slice(&ifd0.to_le_bytes());
+        t.extend_from_slice(&2u16.to_le_bytes());
+        entry(&mut t, 0x0112, 3, 1, [6, 0, 0, 0]);
+        entry(&mut t, 0x8769, 4, 1, exif.to_le_bytes());
+        t.extend_from_slice(&ifd1.to_le_bytes());
+        assert_eq!(t.len(), exif as usize);
+        t.extend_from_slice(&1u16.to_le_bytes());
+        entry(&mut t, 0xA005, 4, 1, interop.to_le_bytes());
+        t.extend_from_slice(&0u32.to_le_bytes());
+        t.extend_from_slice(&1u16.to_le_bytes());
+        entry(&mut t, 0x0001, 2, 4, *b"R98\0");
+        t.extend_from_slice(&0u32.to_le_bytes());
+        assert_eq!(t.len(), ifd1 as usize);
+        t.extend_from_slice(&2u16.to_le_bytes());
+        entry(&mut t, 0x0201, 4, 1, thumb.to_le_bytes());
+        entry(&mut t, 0x0202, 4, 1, 4u32.to_le_bytes());
+        t.extend_from_slice(&ifd0.to_le_bytes());
+        assert_eq!(t.len(), thumb as usize);
+        t.extend_from_slice(&[0xFF, 0xD8, 0xFF, 0xD9]);
+
+        let tags = parse_all_exif_tags(&t, 0);
+        let get = |label: &str| {
+            tags.iter()
+                .find(|(l, _)| l == label)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(get("Interoperability"), Some("R98"));
+        assert_eq!(get("Thumbnail"), Some("JPEG, 4 B"));
+        // The loop back to IFD0 does not repeat its tags
+        assert_eq!(tags.iter().filter(|(l, _)| l == "Orientation").count(), 1);
+    }
+
+    /// Deterministic xorshift bytes for the fuzz-style EXIF tests.
+    fn pseudo_random_bytes(seed: &mut u64, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                *seed ^= *seed << 13;
+                *seed ^= *seed >> 7;
+                *seed ^= *seed << 17;
+                *seed as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_exif_random_bytes_never_panic() {
+        let mut seed = 0x9E37_79B9_7F4A_7C15;
+        for i in 0..2000 {
+            let mut d = pseudo_random_bytes(&mut seed, 8 + i % 300);
+            // Mostly valid headers, so the IFD walk is reached
+            let header: &[u8] = if i % 2 == 0 {
+                b"II\x2a\x00"
+            } else {
+                b"MM\x00\x2a"
+            };
+            if i % 5 != 0 {
+                d[..4].copy_from_slice(header);
+            }
+            if i % 3 == 0 {
+                // Small offsets point back into the block
+                for chunk in d[4..].chunks_mut(4) {
+                    chunk[0] %= 64;
+                }
+            }
+            parse_all_exif_tags(&d, 0);
+            let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1, 0x00, 0x40];
+            jpeg.extend_from_slice(b"Exif\0\0");
+            jpeg.extend_from_slice(&d);
+            read_exif_tags(&jpeg);
+        }
+    }
+
+    #[test]
+    fn test_exif_mutated_block_never_panics() {
+        let mut seed = 42;
+        let base = build_tiff_with_orientation(true, 6);
+        for _ in 0..2000 {
+            let mut d = base.clone();
+            let noise = pseudo_random_bytes(&mut seed, 8);
+            for pair in noise.chunks(2) {
+                let idx = 4 + pair[0] as usize % (d.len() - 4);
+                d[idx] = pair[1];
+            }
+            parse_all_exif_tags(&d, 0);
+        }
+    }
+
+    #[test]
+    fn test_exif_rejects_values_outside_block() {
+        // Make points into the TIFF header, Model is valid and Artist
+        // overruns the block
+        let mut d = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+        d.extend_from_slice(&3u16.to_le_bytes());
+        for (tag, count, offset) in [(0x010Fu16, 8u32, 0u32), (0x0110, 5, 50), (0x013B, 8, 52)] {
+            d.extend_from_slice(&tag.to_le_bytes());
+            d.extend_from_slice(&2u16.to_le_bytes());
+            d.extend_from_slice(&count.to_le_bytes());
+            d.extend_from_slice(&offset.to_le_bytes());
+        }
+        d.extend_from_slice(&[0; 4]);
+        d.extend_from_slice(b"Cam1\0");
+        let tags = parse_all_exif_tags(&d, 0);
+        assert_eq!(tags, vec![("Model".to_string(), "Cam1".to_string())]);
+    }
+
     #[test]
     fn test_exif_tags_le() {
         let data = build_tiff_with_orientation(true, 6);
@@ -3787,6 +7794,21 @@ mod tests {
         assert!(orient.is_some(), "Orientation tag not found in {:?}", tags);
     }
 
+    #[test]
+    fn test_clamp_frame_duration() {
+        let ms = |d: Duration| d.as_millis() as u64;
+        // Zero delay runs at display rate, tiny delays at the minimum
+        assert_eq!(ms(clamp_frame_duration(0, 10, 60_000)), ZERO_DELAY_FRAME_MS);
+        assert_eq!(ms(clamp_frame_duration(1, 10, 60_000)), 10);
+        assert_eq!(ms(clamp_frame_duration(80, 10, 60_000)), 80);
+        // Absurd delays are capped
+        assert_eq!(ms(clamp_frame_duration(u64::MAX, 10, 60_000)), 60_000);
+        // A higher minimum also applies to zero delays
+        assert_eq!(ms(clamp_frame_duration(0, 100, 60_000)), 100);
+        // A maximum below the minimum does not win
+        assert_eq!(ms(clamp_frame_duration(500, 100, 50)), 100);
+    }
+
     #[test]
     fn test_exif_webp_extraction() {
         // Build a minimal RIFF/WEBP with an EXIF chunk containing a TIFF header
@@ -3810,6 +7832,115 @@ mod tests {
         assert_eq!(result, Some(8));
     }
 
+    #[test]
+    fn test_webp_alpha_is_straight() {
+        // Lossless WebP with semi-transparent pixels: decoding must return
+        // the original (non-premultiplied) values
+        let pixels: [u8; 8] = [200, 100, 50, 128, 10, 220, 30, 64];
+        let webp = unsafe {
+            let mut out: *mut u8 = std::ptr::null_mut();
+            let len = libwebp_sys::WebPEncodeLosslessRGBA(pixels.as_ptr(), 2, 1, 8, &mut out);
+            assert!(len > 0, "WebP encoding failed");
+            let bytes = std::slice::from_raw_parts(out, len).to_vec();
+            libwebp_sys::WebPFree(out as *mut std::ffi::c_void);
+            bytes
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("alpha.webp");
+        std::fs::write(&path, &webp).unwrap();
+
+        let LoadedImage::Static(img) = load_image(&path).unwrap() else {
+            panic!("expected a static image");
+        };
+        assert_eq!(img.dimensions(), (2, 1));
+        assert_eq!(img.as_raw(), &pixels);
+
+        // Straight alpha blends over the background as expected
+        let bg = 0x00000000;
+        let buf = crate::render::composite_centered(&img, 2, 1, 0, 0, bg);
+        let (r, g, b) = (200 * 128 / 255, 100 * 128 / 255, 50 * 128 / 255);
+        assert_eq!(buf[0], (r << 16) | (g << 8) | b);
+    }
+
+    #[test]
+    fn test_truncated_webp_keeps_decoded_rows() {
+        let (w, h) = (64u32, 256u32);
+        let pixels: Vec<u8> = (0..w * h)
+            .flat_map(|i| [(i % 251) as u8, 90, 160, 255])
+            .collect();
+        let webp = unsafe {
+            let mut out: *mut u8 = std::ptr::null_mut();
+            let stride = (w * 4) as std::ffi::c_int;
+            let len = libwebp_sys::WebPEncodeRGBA(
+                pixels.as_ptr(),
+                w as std::ffi::c_int,
+                h as std::ffi::c_int,
+                stride,
+                90.0,
+                &mut out,
+            );
+            assert!(len > 0, "WebP encoding failed");
+            let bytes = std::slice::from_raw_parts(out, len).to_vec();
+            libwebp_sys::WebPFree(out as *mut std::ffi::c_void);
+            bytes
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("partial.webp");
+        std::fs::write(&path, &webp[..webp.len() / 2]).unwrap();
+
+        let LoadedImage::Static(img) = load_image(&path).unwrap() else {
+            panic!("expected a static image");
+        };
+        assert_eq!(img.dimensions(), (w, h));
+        // The top of the image is decoded, the missing bottom is transparent
+        let raw = img.as_raw();
+        assert_eq!(raw[3], 255);
+        assert_eq!(raw[raw.len() - 1], 0);
+    }
+
+    #[test]
+    fn test_validate_webp_riff() {
+        let riff = |riff_size: u32, fourcc: &[u8], chunk_size: u32| {
+            let mut data = b"RIFF".to_vec();
+            data.extend_from_slice(&riff_size.to_le_bytes());
+            data.extend_from_slice(b"WEBP");
+            data.extend_from_slice(fourcc);
+            data.extend_from_slice(&chunk_size.to_le_bytes());
+            data.extend_from_slice(&[0; 10]);
+            data
+        };
+        assert!(validate_webp_riff(&riff(22, b"VP8L", 10)).is_ok());
+        assert!(validate_webp_riff(&riff(22, b"VP8X", 10)).is_ok());
+        // Declared larger than the buffer: a truncated file, left to the decoder
+        assert!(validate_webp_riff(&riff(4000, b"VP8 ", 3000)).is_ok());
+
+        let err = |data: &[u8]| validate_webp_riff(data).unwrap_err();
+        assert_eq!(err(&riff(22, b"VP8L", 10)[..16]), "truncated RIFF header");
+        assert_eq!(err(b""), "truncated RIFF header");
+        let mut wav = riff(22, b"VP8L", 10);
+        wav[8..12].copy_from_slice(b"WAVE");
+        assert_eq!(err(&wav), "missing RIFF/WEBP signature");
+        assert_eq!(err(&riff(4, b"VP8L", 10)), "RIFF size 4 is too small");
+        assert_eq!(err(&riff(22, b"ALPH", 10)), "unexpected first chunk 'ALPH'");
+        assert_eq!(
+            err(&riff(22, b"VP8 ", 11)),
+            "VP8 chunk of 11 bytes exceeds RIFF size 22"
+        );
+        assert_eq!(
+            err(&riff(22, b"VP8L", u32::MAX)),
+            "VP8L chunk of 4294967295 bytes exceeds RIFF size 22"
+        );
+
+        // load_webp rejects the file before handing it to libwebp
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bad.webp");
+        std::fs::write(&path, riff(22, b"VP8 ", 100)).unwrap();
+        let Err(msg) = load_webp(&path) else {
+            panic!("expected an error");
+        };
+        assert!(msg.starts_with("Invalid WebP "), "{}", msg);
+    }
+
     #[test]
     fn test_exif_png_extraction() {
         // Build a minimal PNG with an eXIf chunk containing a TIFF header
@@ -3847,6 +7978,7 @@ mod tests {
         assert_eq!(result, None);
     }
 
+    #[cfg(feature = "avif")]
     #[test]
     fn test_load_avif() {
         let path = std::path::Path::new("test_images/test.avif");
@@ -3864,6 +7996,70 @@ mod tests {
         }
     }
 
+    #[cfg(any(feature = "avif", feature = "heif"))]
+    #[test]
+    fn test_isobmff_grid_size() {
+        // 3x2 tiles of 96x64
+        let data = include_bytes!("../test_images/grid.avif");
+        assert_eq!(isobmff_grid_size(data), Some((288, 128)));
+
+        // The same file with the primary item retyped as a plain image
+        let mut plain = data.to_vec();
+        let at = plain.windows(4).position(|w| w == b"grid").unwrap();
+        plain[at..at + 4].copy_from_slice(b"av01");
+        assert_eq!(isobmff_grid_size(&plain), None);
+        assert_eq!(isobmff_grid_size(&data[..200]), None);
+    }
+
+    #[cfg(feature = "avif")]
+    #[test]
+    fn test_avif_decoder_layout() {
+        if libavif::load().is_err() {
+            return; // libavif not installed
+        }
+        let data = include_bytes!("../test_images/grid.avif");
+        unsafe {
+            let decoder = libavif::avifDecoderCreate();
+            assert!(avif_set_max_threads(decoder, 2).is_ok());
+            // No longer at the defaults
+            assert!(avif_set_max_threads(decoder, 2).is_err());
+            libavif::avifDecoderSetIOMemory(decoder, data.as_ptr(), data.len());
+            assert_eq!(libavif::avifDecoderParse(decoder), libavif::AVIF_RESULT_OK);
+            assert!(avif_decoder_image(decoder, -1).is_ok());
+            // Not yet advanced to the first frame
+            assert!(avif_decoder_image(decoder, 0).is_err());
+            assert_eq!(
+                libavif::avifDecoderNextImage(decoder),
+                libavif::AVIF_RESULT_OK
+            );
+            let image = avif_decoder_image(decoder, 0).unwrap();
+            assert_eq!(*(image as *const [u32; 2]), [288, 128]);
+            libavif::avifDecoderDestroy(decoder);
+        }
+    }
+
+    #[cfg(feature = "heif")]
+    #[test]
+    fn test_load_grid_composes_tiles() {
+        // libheif reads AVIF as well when built with an AV1 decoder
+        let path = std::path::Path::new("test_images/grid.avif");
+        let img = load_heic(path).unwrap().first_frame().clone();
+        assert_eq!((img.width, img.height), (288, 128));
+        assert!(check_grid_decoded(Some((288, 128)), 96, 64, path).is_err());
+    }
+
+    #[cfg(feature = "avif")]
+    #[test]
+    fn test_load_avif_grid() {
+        // 3x2 grid of 96x64 tiles
+        let path = std::path::Path::new("test_images/grid.avif");
+        let img = load_avif(path).unwrap().first_frame().clone();
+        assert_eq!((img.width, img.height), (288, 128));
+        // Converted as RGBA, so the image without alpha is opaque throughout
+        assert!(img.data.chunks_exact(4).all(|p| p[3] == 255));
+    }
+
+    #[cfg(feature = "heif")]
     #[test]
     fn test_load_heic() {
         let path = std::path::Path::new("test_images/test.heic");
@@ -3881,6 +8077,25 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "heif")]
+    #[test]
+    fn test_heic_alpha_reaches_composite() {
+        // 16x16 HEIC whose right half is fully transparent
+        let path = std::path::Path::new("test_images/alpha.heic");
+        let img = load_heic(path).unwrap().first_frame().clone();
+        assert_eq!(img.dimensions(), (16, 16));
+        let alpha = |x: u32, y: u32| img.as_raw()[((y * 16 + x) * 4 + 3) as usize];
+        assert!(alpha(2, 8) > 240);
+        assert!(alpha(13, 8) < 15);
+
+        // The transparent half shows the background
+        let bg = 0x00102030;
+        let buf = crate::render::composite_centered(&img, 16, 16, 0, 0, bg);
+        assert_eq!(buf[8 * 16 + 15], bg);
+        assert_ne!(buf[8 * 16], bg);
+    }
+
+    #[cfg(feature = "jxl")]
     #[test]
     fn test_load_jxl() {
         let path = std::path::Path::new("test_images/test.jxl");
@@ -3898,6 +8113,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_disabled_formats_match_extensions() {
+        // Each optional format is either supported or reported as not compiled in
+        for ext in ["svg", "avif", "heic", "heif", "jxl"] {
+            let supported = SUPPORTED_EXTENSIONS.contains(&ext);
+            assert_ne!(supported, not_compiled_in(ext).is_some(), "{}", ext);
+        }
+        assert_eq!(not_compiled_in("png"), None);
+    }
+
+    #[cfg(all(feature = "avif", feature = "heif", feature = "jxl"))]
     #[test]
     fn test_supported_extensions_include_new_formats() {
         assert!(is_supported_image(std::path::Path::new("test.avif")));
@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
 use xkbcommon_dl::keysyms;
 
 use crate::wayland::KeyEvent;
@@ -10,6 +13,90 @@ const KEY_L: u32 = 38;
 const KEY_W: u32 = 17;
 const KEY_0: u32 = 11;
 
+/// Default viewer bindings as (keys, description), shown by `--help` and
+/// the `?` overlay. Keep in sync with `map_viewer_key`.
+pub const VIEWER_HELP: &[(&str, &str)] = &[
+    ("n/Space", "Next image"),
+    ("p/Backspace", "Previous image"),
+    ("g/G", "First/last image"),
+    ("*", "Random image"),
+    ("+/-/0", "Zoom in/out/reset"),
+    ("%", "Zoom to a typed percentage of 1:1 (Enter applies)"),
+    (
+        "h/j/k/l",
+        "Pan when zoomed, h/l navigate otherwise (also arrows)",
+    ),
+    ("Shift+w", "Toggle fit-to-window for small images"),
+    ("Ctrl+0", "Display at actual size (1:1 pixels)"),
+    ("w", "Toggle between 1:1 and fit"),
+    ("r/R", "Rotate clockwise/counterclockwise"),
+    ("x", "Export visible region as PNG"),
+    ("X", "Save a rotated copy (lossless for JPEG)"),
+    ("F5", "Reload image from disk"),
+    ("c", "Auto-crop uniform borders"),
+    ("#", "Toggle composition grid"),
+    ("b/B", "Increase/decrease brightness"),
+    ("o/O", "Increase/decrease contrast"),
+    ("y/Y", "Increase/decrease gamma"),
+    ("a", "Reset brightness, contrast and gamma"),
+    ("i", "Cycle display filter (none/grayscale/invert/sepia)"),
+    ("t", "Cycle status bar (always/auto-hide/off)"),
+    ("]/[", "Play animations faster/slower"),
+    (
+        "v",
+        "Toggle vertical strip mode (j/k scroll through images)",
+    ),
+    ("d", "Open the image's directory in the gallery"),
+    ("e", "Toggle EXIF info"),
+    ("f", "Toggle fullscreen"),
+    ("s/S", "Cycle sort mode/reverse sort order"),
+    ("m", "Mark/unmark image (marked paths are printed on quit)"),
+    ("?", "Toggle keybinding help"),
+    ("Enter", "Toggle gallery mode"),
+    (
+        "Double-click",
+        "Toggle fullscreen (Alt+drag: move, Alt+right-drag: resize)",
+    ),
+    ("q/Escape", "Quit"),
+];
+
+/// Default gallery bindings as (keys, description). Keep in sync with
+/// `map_gallery_key`.
+pub const GALLERY_HELP: &[(&str, &str)] = &[
+    ("h/j/k/l", "Navigate thumbnails (also arrows)"),
+    ("g/G", "First/last image (also Home/End)"),
+    ("PgUp/PgDn", "Move a page of rows up/down"),
+    ("/", "Filter by name (ext:png for a format)"),
+    ("s/S", "Cycle sort mode/reverse sort order"),
+    ("m", "Mark/unmark selected image"),
+    ("?", "Toggle keybinding help"),
+    ("Enter", "Open selected image"),
+    ("Escape", "Return to viewer"),
+    ("q", "Quit"),
+];
+
+/// Maximum time between two clicks of a double-click.
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+/// Maximum pointer travel (in surface pixels) between two clicks of a double-click.
+const DOUBLE_CLICK_DISTANCE: f64 = 4.0;
+
+/// A left-button press, kept to detect double-clicks.
+#[derive(Debug, Clone, Copy)]
+pub struct Click {
+    pub time: Instant,
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Whether `click` completes a double-click started by `prev`.
+pub fn is_double_click(prev: Option<Click>, click: Click) -> bool {
+    prev.is_some_and(|p| {
+        click.time.saturating_duration_since(p.time) <= DOUBLE_CLICK_TIME
+            && (click.x - p.x).abs() <= DOUBLE_CLICK_DISTANCE
+            && (click.y - p.y).abs() <= DOUBLE_CLICK_DISTANCE
+    })
+}
+
 /// Pan direction indices.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PanDirection {
@@ -29,11 +116,15 @@ pub enum Action {
     // Viewer actions
     NextImage,
     PrevImage,
+    /// Jump to a random image other than the current one.
+    RandomImage,
     FirstImage,
     LastImage,
     ZoomIn,
     ZoomOut,
     ZoomReset,
+    /// Start typing a zoom percentage (`%`).
+    ZoomPercent,
     PanStart(PanDirection),
     PanStop(PanDirection),
     Fullscreen,
@@ -42,6 +133,30 @@ pub enum Action {
     ToggleExif,
     FitToWindow,
     ActualSize,
+    /// Switch between 1:1 and the previous fit mode.
+    ToggleActualSize,
+    ExportView,
+    SaveRotated,
+    Reload,
+    AutoCrop,
+    ToggleGrid,
+    ToggleStrip,
+    BrightnessUp,
+    BrightnessDown,
+    ContrastUp,
+    ContrastDown,
+    GammaUp,
+    GammaDown,
+    ResetTone,
+    CycleFilter,
+    /// Cycle the status bar between always shown, auto-hide and off.
+    CycleStatus,
+    /// Double or halve the playback speed of animated images.
+    AnimFaster,
+    AnimSlower,
+    /// Add the other images in the current image's directory and show
+    /// them in the gallery with the current one selected.
+    BrowseDirectory,
 
     // Gallery actions
     MoveLeft,
@@ -50,9 +165,44 @@ pub enum Action {
     MoveDown,
     GalleryFirst,
     GalleryLast,
+    GalleryPageUp,
+    GalleryPageDown,
+    /// Start typing a filter query (`/`).
+    GalleryFilter,
 
     // Global actions
     CycleSort,
+    ToggleSortDirection,
+    ToggleHelp,
+    /// Mark or unmark the current (viewer) or selected (gallery) image.
+    ToggleMark,
+}
+
+impl Action {
+    /// Whether holding the key should repeat this action. Pan is excluded:
+    /// it is driven by press/release state rather than repeated presses.
+    pub fn is_repeatable(&self) -> bool {
+        matches!(
+            self,
+            Action::NextImage
+                | Action::PrevImage
+                | Action::RandomImage
+                | Action::ZoomIn
+                | Action::ZoomOut
+                | Action::BrightnessUp
+                | Action::BrightnessDown
+                | Action::ContrastUp
+                | Action::ContrastDown
+                | Action::GammaUp
+                | Action::GammaDown
+                | Action::MoveLeft
+                | Action::MoveRight
+                | Action::MoveUp
+                | Action::MoveDown
+                | Action::GalleryPageUp
+                | Action::GalleryPageDown
+        )
+    }
 }
 
 /// Application mode.
@@ -62,6 +212,141 @@ pub enum Mode {
     Gallery,
 }
 
+/// User key bindings from the config file, keyed by keysym.
+#[derive(Debug, Clone, Default)]
+pub struct KeyBindings {
+    map: HashMap<u32, Action>,
+}
+
+impl KeyBindings {
+    pub fn insert(&mut self, keysym: u32, action: Action) {
+        self.map.insert(keysym, action);
+    }
+
+    pub fn get(&self, keysym: u32) -> Option<&Action> {
+        self.map.get(&keysym)
+    }
+}
+
+/// Map a key event, consulting user bindings before the built-in keys.
+/// Bindings only apply to key presses; global actions (quit, gallery toggle,
+/// sort) are honored in both modes, everything else only in the viewer.
+pub fn map_key_with_bindings(
+    event: &KeyEvent,
+    mode: Mode,
+    bindings: &KeyBindings,
+) -> Option<Action> {
+    if event.pressed {
+        if let Some(action) = bindings.get(event.keysym) {
+            let global = matches!(
+                action,
+                Action::Quit
+                    | Action::EscapeOrQuit
+                    | Action::ToggleMode
+                    | Action::CycleSort
+                    | Action::ToggleSortDirection
+                    | Action::ToggleHelp
+                    | Action::ToggleMark
+            );
+            if mode == Mode::Viewer || global {
+                return Some(action.clone());
+            }
+        }
+    }
+    map_key(event, mode)
+}
+
+/// Look up a bindable action by its config-file name.
+/// Pan actions are excluded because they need press/release pairs.
+pub fn action_from_name(name: &str) -> Option<Action> {
+    let action = match name {
+        "quit" => Action::Quit,
+        "gallery" => Action::ToggleMode,
+        "next" => Action::NextImage,
+        "prev" => Action::PrevImage,
+        "random" => Action::RandomImage,
+        "first" => Action::FirstImage,
+        "last" => Action::LastImage,
+        "zoom-in" => Action::ZoomIn,
+        "zoom-out" => Action::ZoomOut,
+        "zoom-reset" => Action::ZoomReset,
+        "zoom-percent" => Action::ZoomPercent,
+        "fullscreen" => Action::Fullscreen,
+        "rotate-cw" => Action::RotateCW,
+        "rotate-ccw" => Action::RotateCCW,
+        "exif" => Action::ToggleExif,
+        "fit-window" => Action::FitToWindow,
+        "actual-size" => Action::ActualSize,
+        "toggle-actual-size" => Action::ToggleActualSize,
+        "export-view" => Action::ExportView,
+        "save-rotated" => Action::SaveRotated,
+        "reload" => Action::Reload,
+        "auto-crop" => Action::AutoCrop,
+        "grid" => Action::ToggleGrid,
+        "strip" => Action::ToggleStrip,
+        "brightness-up" => Action::BrightnessUp,
+        "brightness-down" => Action::BrightnessDown,
+        "contrast-up" => Action::ContrastUp,
+        "contrast-down" => Action::ContrastDown,
+        "gamma-up" => Action::GammaUp,
+        "gamma-down" => Action::GammaDown,
+        "tone-reset" => Action::ResetTone,
+        "filter" => Action::CycleFilter,
+        "status" => Action::CycleStatus,
+        "anim-faster" => Action::AnimFaster,
+        "anim-slower" => Action::AnimSlower,
+        "browse-dir" => Action::BrowseDirectory,
+        "sort" => Action::CycleSort,
+        "sort-direction" => Action::ToggleSortDirection,
+        "help" => Action::ToggleHelp,
+        "mark" => Action::ToggleMark,
+        _ => return None,
+    };
+    Some(action)
+}
+
+/// Look up a keysym by config-file name: a single printable ASCII character
+/// (case-sensitive) or one of a few named keys.
+pub fn keysym_from_name(name: &str) -> Option<u32> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        // Latin-1 keysyms match their ASCII code points
+        return (c.is_ascii_graphic()).then_some(c as u32);
+    }
+    let sym = match name.to_ascii_lowercase().as_str() {
+        "space" => keysyms::space,
+        "return" | "enter" => keysyms::Return,
+        "backspace" => keysyms::BackSpace,
+        "tab" => keysyms::Tab,
+        "delete" => keysyms::Delete,
+        "home" => keysyms::Home,
+        "end" => keysyms::End,
+        "pageup" | "page_up" | "prior" => keysyms::Prior,
+        "pagedown" | "page_down" | "next" => keysyms::Next,
+        "left" => keysyms::Left,
+        "right" => keysyms::Right,
+        "up" => keysyms::Up,
+        "down" => keysyms::Down,
+        // Function keys F1-F12 have consecutive keysyms
+        f => match f.strip_prefix('f').and_then(|n| n.parse::<u32>().ok()) {
+            Some(n @ 1..=12) => keysyms::F1 + n - 1,
+            _ => return None,
+        },
+    };
+    Some(sym)
+}
+
+/// The character a keysym types, for text input such as the gallery filter.
+/// Covers Latin-1 and Unicode keysyms; `None` for function keys.
+pub fn keysym_to_char(sym: u32) -> Option<char> {
+    match sym {
+        // Latin-1 keysyms match their code points
+        0x20..=0x7e | 0xa0..=0xff => char::from_u32(sym),
+        0x0100_0000..=0x0110_ffff => char::from_u32(sym - 0x0100_0000),
+        _ => None,
+    }
+}
+
 /// Map a key event to an action based on the current mode.
 /// Returns None for unmapped keys.
 pub fn map_key(event: &KeyEvent, mode: Mode) -> Option<Action> {
@@ -81,6 +366,9 @@ pub fn map_key(event: &KeyEvent, mode: Mode) -> Option<Action> {
         keysyms::Escape => return Some(Action::EscapeOrQuit),
         keysyms::Return => return Some(Action::ToggleMode),
         keysyms::s => return Some(Action::CycleSort),
+        keysyms::S => return Some(Action::ToggleSortDirection),
+        keysyms::question => return Some(Action::ToggleHelp),
+        keysyms::m => return Some(Action::ToggleMark),
         _ => {}
     }
 
@@ -109,13 +397,34 @@ fn map_viewer_key(keycode: u32, sym: u32, ctrl: bool, shift: bool) -> Option<Act
         keysyms::p => Some(Action::PrevImage),
         keysyms::g => Some(Action::FirstImage),
         keysyms::G => Some(Action::LastImage),
+        keysyms::asterisk => Some(Action::RandomImage),
         keysyms::plus | keysyms::equal => Some(Action::ZoomIn),
         keysyms::minus => Some(Action::ZoomOut),
         keysyms::_0 => Some(Action::ZoomReset),
+        keysyms::percent => Some(Action::ZoomPercent),
         keysyms::e => Some(Action::ToggleExif),
+        keysyms::w => Some(Action::ToggleActualSize),
         keysyms::f => Some(Action::Fullscreen),
         keysyms::r => Some(Action::RotateCW),
         keysyms::R => Some(Action::RotateCCW),
+        keysyms::x => Some(Action::ExportView),
+        keysyms::X => Some(Action::SaveRotated),
+        keysyms::F5 => Some(Action::Reload),
+        keysyms::c => Some(Action::AutoCrop),
+        keysyms::numbersign => Some(Action::ToggleGrid),
+        keysyms::v => Some(Action::ToggleStrip),
+        keysyms::b => Some(Action::BrightnessUp),
+        keysyms::B => Some(Action::BrightnessDown),
+        keysyms::o => Some(Action::ContrastUp),
+        keysyms::O => Some(Action::ContrastDown),
+        keysyms::y => Some(Action::GammaUp),
+        keysyms::Y => Some(Action::GammaDown),
+        keysyms::a => Some(Action::ResetTone),
+        keysyms::i => Some(Action::CycleFilter),
+        keysyms::t => Some(Action::CycleStatus),
+        keysyms::bracketright => Some(Action::AnimFaster),
+        keysyms::bracketleft => Some(Action::AnimSlower),
+        keysyms::d => Some(Action::BrowseDirectory),
         keysyms::space => Some(Action::NextImage),
         keysyms::BackSpace => Some(Action::PrevImage),
         _ => None,
@@ -145,8 +454,11 @@ fn map_gallery_key(sym: u32) -> Option<Action> {
         keysyms::l | keysyms::Right => Some(Action::MoveRight),
         keysyms::k | keysyms::Up => Some(Action::MoveUp),
         keysyms::j | keysyms::Down => Some(Action::MoveDown),
-        keysyms::g => Some(Action::GalleryFirst),
-        keysyms::G => Some(Action::GalleryLast),
+        keysyms::g | keysyms::Home => Some(Action::GalleryFirst),
+        keysyms::G | keysyms::End => Some(Action::GalleryLast),
+        keysyms::Prior => Some(Action::GalleryPageUp),
+        keysyms::Next => Some(Action::GalleryPageDown),
+        keysyms::slash => Some(Action::GalleryFilter),
         _ => None,
     }
 }
@@ -208,6 +520,30 @@ mod tests {
         assert_eq!(action, Some(Action::CycleSort));
     }
 
+    #[test]
+    fn test_toggle_help() {
+        let action = map_key(&press(keysyms::question), Mode::Viewer);
+        assert_eq!(action, Some(Action::ToggleHelp));
+        let action = map_key(&press(keysyms::question), Mode::Gallery);
+        assert_eq!(action, Some(Action::ToggleHelp));
+    }
+
+    #[test]
+    fn test_toggle_sort_direction() {
+        let action = map_key(&press(keysyms::S), Mode::Viewer);
+        assert_eq!(action, Some(Action::ToggleSortDirection));
+        let action = map_key(&press(keysyms::S), Mode::Gallery);
+        assert_eq!(action, Some(Action::ToggleSortDirection));
+    }
+
+    #[test]
+    fn test_toggle_mark() {
+        let action = map_key(&press(keysyms::m), Mode::Viewer);
+        assert_eq!(action, Some(Action::ToggleMark));
+        let action = map_key(&press(keysyms::m), Mode::Gallery);
+        assert_eq!(action, Some(Action::ToggleMark));
+    }
+
     #[test]
     fn test_viewer_next_image() {
         let action = map_key(&press(keysyms::n), Mode::Viewer);
@@ -240,6 +576,36 @@ mod tests {
         assert_eq!(action, Some(Action::GalleryFirst));
         let action = map_key(&press(keysyms::G), Mode::Gallery);
         assert_eq!(action, Some(Action::GalleryLast));
+        let action = map_key(&press(keysyms::Home), Mode::Gallery);
+        assert_eq!(action, Some(Action::GalleryFirst));
+        let action = map_key(&press(keysyms::End), Mode::Gallery);
+        assert_eq!(action, Some(Action::GalleryLast));
+    }
+
+    #[test]
+    fn test_gallery_page() {
+        let action = map_key(&press(keysyms::Prior), Mode::Gallery);
+        assert_eq!(action, Some(Action::GalleryPageUp));
+        let action = map_key(&press(keysyms::Next), Mode::Gallery);
+        assert_eq!(action, Some(Action::GalleryPageDown));
+        assert!(Action::GalleryPageDown.is_repeatable());
+    }
+
+    #[test]
+    fn test_gallery_filter_key() {
+        let action = map_key(&press(keysyms::slash), Mode::Gallery);
+        assert_eq!(action, Some(Action::GalleryFilter));
+    }
+
+    #[test]
+    fn test_keysym_to_char() {
+        assert_eq!(keysym_to_char(keysyms::a), Some('a'));
+        assert_eq!(keysym_to_char(keysyms::colon), Some(':'));
+        assert_eq!(keysym_to_char(keysyms::space), Some(' '));
+        assert_eq!(keysym_to_char(keysyms::udiaeresis), Some('\u{fc}'));
+        assert_eq!(keysym_to_char(0x0100_20ac), Some('\u{20ac}'));
+        assert_eq!(keysym_to_char(keysyms::Return), None);
+        assert_eq!(keysym_to_char(keysyms::F1), None);
     }
 
     #[test]
@@ -258,6 +624,133 @@ mod tests {
         assert_eq!(action, Some(Action::RotateCCW));
     }
 
+    #[test]
+    fn test_double_click() {
+        let t = Instant::now();
+        let first = Click {
+            time: t,
+            x: 10.0,
+            y: 10.0,
+        };
+        let again = |ms, x| Click {
+            time: t + Duration::from_millis(ms),
+            x,
+            y: 10.0,
+        };
+        assert!(!is_double_click(None, first));
+        assert!(is_double_click(Some(first), again(200, 12.0)));
+        assert!(!is_double_click(Some(first), again(600, 10.0)));
+        assert!(!is_double_click(Some(first), again(200, 30.0)));
+    }
+
+    #[test]
+    fn test_viewer_toggle_grid() {
+        let action = map_key(&press(keysyms::numbersign), Mode::Viewer);
+        assert_eq!(action, Some(Action::ToggleGrid));
+        let action = map_key(&press(keysyms::numbersign), Mode::Gallery);
+        assert_eq!(action, None);
+    }
+
+    #[test]
+    fn test_viewer_toggle_strip() {
+        let action = map_key(&press(keysyms::v), Mode::Viewer);
+        assert_eq!(action, Some(Action::ToggleStrip));
+        assert_eq!(action_from_name("strip"), Some(Action::ToggleStrip));
+    }
+
+    #[test]
+    fn test_viewer_tone_keys() {
+        let action = map_key(&press(keysyms::b), Mode::Viewer);
+        assert_eq!(action, Some(Action::BrightnessUp));
+        let action = map_key(&press(keysyms::Y), Mode::Viewer);
+        assert_eq!(action, Some(Action::GammaDown));
+        assert!(Action::ContrastUp.is_repeatable());
+        assert_eq!(map_key(&press(keysyms::b), Mode::Gallery), None);
+        assert_eq!(action_from_name("tone-reset"), Some(Action::ResetTone));
+        let action = map_key(&press(keysyms::i), Mode::Viewer);
+        assert_eq!(action, Some(Action::CycleFilter));
+        let action = map_key(&press(keysyms::t), Mode::Viewer);
+        assert_eq!(action, Some(Action::CycleStatus));
+        assert_eq!(action_from_name("status"), Some(Action::CycleStatus));
+    }
+
+    #[test]
+    fn test_viewer_auto_crop() {
+        let action = map_key(&press(keysyms::c), Mode::Viewer);
+        assert_eq!(action, Some(Action::AutoCrop));
+        assert_eq!(action_from_name("auto-crop"), Some(Action::AutoCrop));
+    }
+
+    #[test]
+    fn test_viewer_reload() {
+        assert_eq!(
+            map_key(&press(keysyms::F5), Mode::Viewer),
+            Some(Action::Reload)
+        );
+        assert_eq!(map_key(&press(keysyms::F5), Mode::Gallery), None);
+        assert_eq!(action_from_name("reload"), Some(Action::Reload));
+        assert_eq!(keysym_from_name("F5"), Some(keysyms::F5));
+        assert_eq!(keysym_from_name("f12"), Some(keysyms::F12));
+        assert_eq!(keysym_from_name("f13"), None);
+        assert_eq!(keysym_from_name("f0"), None);
+    }
+
+    #[test]
+    fn test_viewer_save_rotated() {
+        let action = map_key(&press(keysyms::X), Mode::Viewer);
+        assert_eq!(action, Some(Action::SaveRotated));
+        assert_eq!(action_from_name("save-rotated"), Some(Action::SaveRotated));
+    }
+
+    #[test]
+    fn test_viewer_export_view() {
+        let action = map_key(&press(keysyms::x), Mode::Viewer);
+        assert_eq!(action, Some(Action::ExportView));
+        let action = map_key(&press(keysyms::x), Mode::Gallery);
+        assert_eq!(action, None);
+    }
+
+    #[test]
+    fn test_bindings_override_builtin() {
+        let mut bindings = KeyBindings::default();
+        bindings.insert(keysyms::x, Action::NextImage);
+        let action = map_key_with_bindings(&press(keysyms::x), Mode::Viewer, &bindings);
+        assert_eq!(action, Some(Action::NextImage));
+        // Unbound keys fall through to the defaults
+        let action = map_key_with_bindings(&press(keysyms::r), Mode::Viewer, &bindings);
+        assert_eq!(action, Some(Action::RotateCW));
+    }
+
+    #[test]
+    fn test_bindings_gallery_only_global() {
+        let mut bindings = KeyBindings::default();
+        bindings.insert(keysyms::x, Action::NextImage);
+        bindings.insert(keysyms::z, Action::Quit);
+        let action = map_key_with_bindings(&press(keysyms::x), Mode::Gallery, &bindings);
+        assert_eq!(action, None);
+        let action = map_key_with_bindings(&press(keysyms::z), Mode::Gallery, &bindings);
+        assert_eq!(action, Some(Action::Quit));
+    }
+
+    #[test]
+    fn test_keysym_from_name() {
+        assert_eq!(keysym_from_name("x"), Some(keysyms::x));
+        assert_eq!(keysym_from_name("X"), Some(keysyms::X));
+        assert_eq!(keysym_from_name("Space"), Some(keysyms::space));
+        assert_eq!(keysym_from_name("page_down"), Some(keysyms::Next));
+        assert_eq!(keysym_from_name("nope"), None);
+    }
+
+    #[test]
+    fn test_repeatable_actions() {
+        assert!(Action::NextImage.is_repeatable());
+        assert!(Action::ZoomIn.is_repeatable());
+        assert!(Action::MoveDown.is_repeatable());
+        assert!(!Action::PanStart(PanDirection::Left).is_repeatable());
+        assert!(!Action::ToggleMode.is_repeatable());
+        assert!(!Action::Quit.is_repeatable());
+    }
+
     #[test]
     fn test_unmapped_key() {
         let action = map_key(&press(keysyms::z), Mode::Viewer);
@@ -1,36 +1,224 @@
 mod app;
+mod bench;
+mod config;
+mod contact_sheet;
+mod convert;
+#[cfg(any(feature = "svg", feature = "avif", feature = "heif", feature = "jxl"))]
+mod dynlib;
 mod font;
 mod gallery;
 mod image_loader;
 mod input;
 mod protocols;
 mod render;
+mod signal;
 mod status;
 mod viewer;
+mod watch;
 mod wayland;
+mod window_state;
 
 use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 use std::process;
 
+use config::SortMode;
+use convert::ConvertFormat;
+use image_loader::OrientationPolicy;
+
 fn print_help() {
-    println!("Usage: rimg [options] <file>... | rimg [options] <directory>");
-    println!("  Supported formats: jpg, jpeg, png, gif, webp, bmp, tiff, tif, svg, avif, heic, heif, jxl");
+    println!("Usage: rimg [options] [--] <file>... | rimg [options] <directory>");
+    println!(
+        "  Supported formats: {}",
+        image_loader::SUPPORTED_EXTENSIONS.join(", ")
+    );
     println!();
     println!("Options:");
     println!("  -h, --help   Show this help message");
     println!("  -w           Set image as wallpaper (wlr-layer-shell)");
+    println!("  --output <name>  With -w, only set the wallpaper on this output (repeatable)");
+    println!("  --config <path>  Read configuration from <path>");
+    println!("  --bg <#rrggbb>  Background color around and behind images (default #1a1a1a)");
+    println!("  --no-animations  Step-wise pan, show only the first frame of animations");
+    println!("  --anim-speed <x>  Play animations at x times their speed (0.125-8, default 1)");
+    println!("  --strip      Start in vertical strip mode (images stacked, j/k scroll)");
+    println!("  --geometry <w>x<h>  Initial window size (default 800x600)");
+    println!("  --size-to-image  Open the window at the first image's size");
+    println!("  --remember-geometry  Reopen at the last window size and fullscreen state");
+    println!("  --downscale-oversized  Decode JPEGs over the pixel limit at reduced size");
+    println!("  --memory-limit  Also limit images to what fits in available memory");
+    println!("  --orientation <mode>  auto, container, exif or none (AVIF/HEIC transforms)");
+    println!("  --sort <key>[:desc]  Initial sort: name, size, date (EXIF), mtime or megapixels");
+    println!("  --no-sort    Keep the command-line order (directories in readdir order)");
+    println!("  --shuffle    Show the images in random order");
+    println!("  --proof      Show images on white with crop marks, like a print proof");
+    println!("  --watch      Reload the current image when it changes on disk");
+    println!("  --no-gamma   Ignore PNG gamma (gAMA) for pixel-exact values");
+    println!("  --dither     Error-diffusion dither for 16-bit PNG and 10/12-bit AVIF/HEIC");
+    println!("  --verbose    Print per-image decode times (and wallpaper outputs) to stderr");
+    println!("  --contact-sheet <out.png>  Write a thumbnail grid of all images and exit");
+    println!("  --cols <n>   Columns for --contact-sheet (default 6)");
+    println!("  --convert <png|jpg>  Write each image in another format next to it and exit");
+    println!("  --marks-out <file>  Write paths marked with m to <file> on quit");
+    println!("  --           Treat all following arguments as files, even if they start with -");
     println!();
     println!("Keys:");
-    println!("  n/Space      Next image");
-    println!("  p/Backspace  Previous image");
-    println!("  g/G          First/last image");
-    println!("  +/-/0        Zoom in/out/reset");
-    println!("  h/j/k/l      Pan when zoomed, h/l navigate otherwise (also arrows)");
-    println!("  Shift+w      Toggle fit-to-window for small images");
-    println!("  Ctrl+0       Display at actual size (1:1 pixels)");
-    println!("  r/R          Rotate clockwise/counterclockwise");
-    println!("  Enter        Toggle gallery mode");
-    println!("  q/Escape     Quit");
+    for (keys, desc) in input::VIEWER_HELP {
+        println!("  {:<12} {}", keys, desc);
+    }
+}
+
+/// Write the marked paths one per line to `out`, or to stdout when no
+/// file was given and anything was marked.
+fn write_marks(marks: &[&Path], out: Option<&Path>) -> Result<(), String> {
+    let mut list = Vec::new();
+    for path in marks {
+        list.extend_from_slice(path.as_os_str().as_bytes());
+        list.push(b'\n');
+    }
+    match out {
+        Some(out) => {
+            fs::write(out, &list).map_err(|e| format!("cannot write {}: {}", out.display(), e))
+        }
+        None if marks.is_empty() => Ok(()),
+        None => io::stdout()
+            .write_all(&list)
+            .map_err(|e| format!("cannot print marked paths: {}", e)),
+    }
+}
+
+/// Command-line options, before they are merged into the config.
+#[derive(Debug, Default)]
+struct Options {
+    help: bool,
+    wallpaper_mode: bool,
+    no_animations: bool,
+    anim_speed: Option<f64>,
+    strip: bool,
+    geometry: Option<(u32, u32)>,
+    size_to_image: bool,
+    remember_geometry: bool,
+    downscale_oversized: bool,
+    memory_limit: bool,
+    verbose: bool,
+    proof: bool,
+    watch: bool,
+    no_gamma: bool,
+    dither: bool,
+    orientation: Option<OrientationPolicy>,
+    config_path: Option<PathBuf>,
+    background: Option<u32>,
+    sort: Option<SortMode>,
+    no_sort: bool,
+    shuffle: bool,
+    contact_sheet: Option<PathBuf>,
+    sheet_cols: Option<usize>,
+    marks_out: Option<PathBuf>,
+    convert: Option<ConvertFormat>,
+    decode_only: bool,
+    decode_thumbnails: bool,
+    resize_only: bool,
+    wallpaper_outputs: Vec<String>,
+    file_args: Vec<String>,
+}
+
+/// Parse the command line in one pass. An option's value is taken before
+/// anything else, so `--output --` names an output; a bare `--` ends the
+/// options and everything after it is a file, even `-h`.
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut opts = Options::default();
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--" => {
+                opts.file_args.extend(iter);
+                break;
+            }
+            "-h" | "--help" => {
+                opts.help = true;
+                return Ok(opts);
+            }
+            "-w" => opts.wallpaper_mode = true,
+            "--no-animations" => opts.no_animations = true,
+            "--strip" => opts.strip = true,
+            "--size-to-image" => opts.size_to_image = true,
+            "--remember-geometry" => opts.remember_geometry = true,
+            "--downscale-oversized" => opts.downscale_oversized = true,
+            "--memory-limit" => opts.memory_limit = true,
+            "--verbose" => opts.verbose = true,
+            "--proof" => opts.proof = true,
+            "--watch" => opts.watch = true,
+            "--no-gamma" => opts.no_gamma = true,
+            "--dither" => opts.dither = true,
+            "--no-sort" => opts.no_sort = true,
+            "--shuffle" => opts.shuffle = true,
+            // Profiling aids, not listed in --help
+            "--decode-only" => opts.decode_only = true,
+            "--bench-resize" => opts.resize_only = true,
+            "--decode-thumbnails" => {
+                opts.decode_only = true;
+                opts.decode_thumbnails = true;
+            }
+            "--anim-speed" => match iter.next().as_deref().and_then(config::parse_anim_speed) {
+                Some(speed) => opts.anim_speed = Some(speed),
+                None => return Err("--anim-speed requires a multiplier from 0.125 to 8".into()),
+            },
+            "--geometry" => match iter.next().as_deref().and_then(config::parse_geometry) {
+                Some(size) => opts.geometry = Some(size),
+                None => return Err("--geometry requires a size such as 1280x720".into()),
+            },
+            "--orientation" => match iter.next().as_deref().and_then(OrientationPolicy::parse) {
+                Some(policy) => opts.orientation = Some(policy),
+                None => return Err("--orientation requires auto, container, exif or none".into()),
+            },
+            "--config" => match iter.next() {
+                Some(path) => opts.config_path = Some(PathBuf::from(path)),
+                None => return Err("--config requires a path".into()),
+            },
+            "--bg" => {
+                let value = iter.next().unwrap_or_default();
+                match config::parse_hex_color(&value) {
+                    Some(color) => opts.background = Some(color),
+                    None => eprintln!(
+                        "Warning: ignoring --bg '{}', expected a color such as #1a1a1a",
+                        value
+                    ),
+                }
+            }
+            "--sort" => match iter.next().as_deref().and_then(SortMode::parse) {
+                Some(mode) => opts.sort = Some(mode),
+                None => {
+                    let keys = "name, size, date, mtime or megapixels (optionally :desc)";
+                    return Err(format!("--sort requires {}", keys));
+                }
+            },
+            "--output" => match iter.next() {
+                Some(name) => opts.wallpaper_outputs.push(name),
+                None => return Err("--output requires an output name such as DP-1".into()),
+            },
+            "--contact-sheet" => match iter.next() {
+                Some(path) => opts.contact_sheet = Some(PathBuf::from(path)),
+                None => return Err("--contact-sheet requires an output path".into()),
+            },
+            "--marks-out" => match iter.next() {
+                Some(path) => opts.marks_out = Some(PathBuf::from(path)),
+                None => return Err("--marks-out requires an output path".into()),
+            },
+            "--convert" => match iter.next().as_deref().and_then(ConvertFormat::parse) {
+                Some(format) => opts.convert = Some(format),
+                None => return Err("--convert requires png or jpg".into()),
+            },
+            "--cols" => match iter.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) if n > 0 => opts.sheet_cols = Some(n),
+                _ => return Err("--cols requires a positive number".into()),
+            },
+            _ => opts.file_args.push(arg),
+        }
+    }
+    Ok(opts)
 }
 
 fn main() {
@@ -41,27 +229,220 @@ fn main() {
         process::exit(1);
     }
 
-    if args.iter().any(|a| a == "-h" || a == "--help") {
+    let opts = match parse_args(args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+    if opts.help {
         print_help();
         process::exit(0);
     }
-
-    // Parse -w flag
-    let wallpaper_mode = args.iter().any(|a| a == "-w");
-    let file_args: Vec<String> = args.into_iter().filter(|a| a != "-w").collect();
+    let Options {
+        wallpaper_mode,
+        no_animations,
+        anim_speed,
+        strip,
+        geometry,
+        size_to_image,
+        remember_geometry,
+        downscale_oversized,
+        memory_limit,
+        verbose,
+        proof,
+        watch,
+        no_gamma,
+        dither,
+        orientation,
+        config_path,
+        background,
+        sort,
+        no_sort,
+        shuffle,
+        contact_sheet,
+        sheet_cols,
+        marks_out,
+        convert,
+        decode_only,
+        decode_thumbnails,
+        resize_only,
+        wallpaper_outputs,
+        file_args,
+        ..
+    } = opts;
+    if sheet_cols.is_some() && contact_sheet.is_none() {
+        eprintln!("Warning: --cols only applies to --contact-sheet");
+    }
+    let sheet_cols = sheet_cols.unwrap_or(contact_sheet::DEFAULT_COLS);
 
     if file_args.is_empty() {
         eprintln!("Error: no image files specified");
         process::exit(1);
     }
 
-    let paths = image_loader::collect_paths(&file_args);
+    let mut config = config::load(config_path.as_deref());
+    if no_animations {
+        config.animations = false;
+    }
+    if let Some(speed) = anim_speed {
+        config.anim_speed = speed;
+    }
+    if let Some(sort) = sort {
+        config.sort = sort;
+    }
+    if no_sort {
+        config.sort = SortMode::UNSORTED;
+    }
+    if shuffle {
+        config.sort = SortMode::SHUFFLED;
+    }
+    if let Some(color) = background {
+        config.background = color;
+        config.custom_background = true;
+    }
+    if strip {
+        config.strip = true;
+    }
+    if !wallpaper_outputs.is_empty() && !wallpaper_mode {
+        eprintln!("Warning: --output only applies to wallpaper mode (-w)");
+    }
+    config.wallpaper_outputs = wallpaper_outputs;
+    if geometry.is_some() {
+        config.geometry = geometry;
+    }
+    if size_to_image {
+        config.size_to_image = true;
+    }
+    if remember_geometry {
+        config.remember_geometry = true;
+    }
+    if config.remember_geometry && !wallpaper_mode {
+        if let Some(saved) = window_state::load() {
+            // A size given on the command line wins over the remembered one
+            if geometry.is_none() {
+                config.geometry = Some(saved.size);
+            }
+            config.fullscreen = saved.fullscreen;
+        }
+    }
+    if let Some(orientation) = orientation {
+        config.orientation = orientation;
+    }
+    if downscale_oversized {
+        config.downscale_oversized = true;
+    }
+    if memory_limit {
+        config.memory_limit = true;
+    }
+    if proof {
+        config.proof = true;
+    }
+    if watch {
+        config.watch = true;
+    }
+    if no_gamma {
+        config.gamma = false;
+    }
+    if dither {
+        config.dither = true;
+    }
+    image_loader::set_orientation_policy(config.orientation);
+    image_loader::set_png_gamma(config.gamma);
+    image_loader::set_png_background(!config.custom_background);
+    image_loader::set_dither(config.dither);
+    image_loader::set_frame_duration_limits(config.min_frame_duration, config.max_frame_duration);
+    image_loader::set_downscale_oversized(config.downscale_oversized);
+    image_loader::set_memory_limit(config.memory_limit);
+    image_loader::set_verbose(verbose);
+
+    let sorted = config.sort != SortMode::UNSORTED;
+    let paths = image_loader::collect_paths(&file_args, config.natural_sort, sorted);
 
     if paths.is_empty() {
         eprintln!("Error: no supported image files found");
         process::exit(1);
     }
 
-    let mut app = app::App::new(paths, wallpaper_mode);
+    if decode_only {
+        let failed = bench::decode_only(&paths, decode_thumbnails);
+        process::exit(if failed > 0 { 1 } else { 0 });
+    }
+
+    if resize_only {
+        let failed = bench::resize_only(&paths);
+        process::exit(if failed > 0 { 1 } else { 0 });
+    }
+
+    if let Some(out) = contact_sheet {
+        match contact_sheet::write_contact_sheet(&paths, &out, sheet_cols, config.background) {
+            Ok(()) => {
+                println!("Wrote {} ({} images)", out.display(), paths.len());
+                return;
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(format) = convert {
+        let failed = convert::convert_all(&paths, format, config.background);
+        if failed > 0 {
+            eprintln!("{} of {} images failed to convert", failed, paths.len());
+            process::exit(1);
+        }
+        return;
+    }
+
+    let mut app = app::App::new(paths, wallpaper_mode, config);
     app.run();
+    if let Err(e) = write_marks(&app.marked_paths(), marks_out.as_deref()) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args_separator() {
+        let opts = parse_args(strings(&["-w", "--", "file.png"])).unwrap();
+        assert!(opts.wallpaper_mode);
+        assert_eq!(opts.file_args, strings(&["file.png"]));
+
+        let opts = parse_args(strings(&["--", "-weird.png"])).unwrap();
+        assert!(!opts.wallpaper_mode);
+        assert_eq!(opts.file_args, strings(&["-weird.png"]));
+
+        // Only the first separator ends the options; help after it is a file
+        let opts = parse_args(strings(&["-w", "a.png", "--", "--", "-h"])).unwrap();
+        assert!(opts.wallpaper_mode && !opts.help);
+        assert_eq!(opts.file_args, strings(&["a.png", "--", "-h"]));
+    }
+
+    #[test]
+    fn test_parse_args_values_before_separator_and_help() {
+        // Option values are consumed before `--` or help are recognized
+        let opts = parse_args(strings(&["-w", "--output", "--", "a.png"])).unwrap();
+        assert_eq!(opts.wallpaper_outputs, strings(&["--"]));
+        assert_eq!(opts.file_args, strings(&["a.png"]));
+
+        let opts = parse_args(strings(&["--marks-out", "-h", "a.png"])).unwrap();
+        assert!(!opts.help);
+        assert_eq!(opts.marks_out, Some(PathBuf::from("-h")));
+        assert_eq!(opts.file_args, strings(&["a.png"]));
+
+        assert!(parse_args(strings(&["a.png", "--help"])).unwrap().help);
+        let err = parse_args(strings(&["--cols", "0", "a.png"])).unwrap_err();
+        assert!(err.contains("--cols"), "{}", err);
+    }
 }
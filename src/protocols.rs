@@ -29,3 +29,29 @@ pub mod wlr_layer_shell {
 
     wayland_scanner::generate_client_code!("protocols/wlr-layer-shell-unstable-v1.xml");
 }
+
+pub mod cursor_shape {
+    use wayland_client;
+    use wayland_client::protocol::*;
+
+    pub mod __interfaces {
+        use wayland_client::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/cursor-shape-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_client_code!("protocols/cursor-shape-v1.xml");
+}
+
+pub mod presentation_time {
+    use wayland_client;
+    use wayland_client::protocol::*;
+
+    pub mod __interfaces {
+        use wayland_client::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/presentation-time.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_client_code!("protocols/presentation-time.xml");
+}
@@ -1,7 +1,18 @@
-use crate::image_loader::RgbaImage;
+use crate::image_loader::{LoadedImage, RgbaImage};
 
 /// Background color: #1a1a1a
 pub const BG_COLOR: u32 = 0x001a1a1a;
+/// Paper-white background of the `--proof` view.
+pub const PROOF_BG_COLOR: u32 = 0x00FFFFFF;
+/// Color of the `--proof` crop and center marks.
+const PROOF_MARK_COLOR: u32 = 0x00000000;
+/// Gap between the image edge and a crop mark, and the mark length.
+const PROOF_MARK_GAP: i32 = 6;
+const PROOF_MARK_LEN: i32 = 18;
+
+/// Rectangle `(x, y, w, h)` in window coordinates; it may reach past the
+/// window edges.
+pub type WindowRect = (i32, i32, u32, u32);
 
 /// Scale an RGBA image to fit within (max_w, max_h) preserving aspect ratio.
 pub fn scale_to_fit(img: &RgbaImage, max_w: u32, max_h: u32) -> RgbaImage {
@@ -10,11 +21,16 @@ pub fn scale_to_fit(img: &RgbaImage, max_w: u32, max_h: u32) -> RgbaImage {
         return RgbaImage::new(1, 1);
     }
 
+    let (dst_w, dst_h) = fit_size(src_w, src_h, max_w, max_h);
+    resize_rgba(img, dst_w, dst_h)
+}
+
+/// Size of a `src_w`x`src_h` image after `scale_to_fit` into (max_w, max_h).
+pub fn fit_size(src_w: u32, src_h: u32, max_w: u32, max_h: u32) -> (u32, u32) {
     let scale = (max_w as f64 / src_w as f64).min(max_h as f64 / src_h as f64);
     let dst_w = ((src_w as f64 * scale).round() as u32).max(1);
     let dst_h = ((src_h as f64 * scale).round() as u32).max(1);
-
-    resize_rgba(img, dst_w, dst_h)
+    (dst_w, dst_h)
 }
 
 /// Scale an RGBA image to fill (cover) the target dimensions, then center-crop.
@@ -64,47 +80,142 @@ pub fn scale_to_fill(img: &RgbaImage, target_w: u32, target_h: u32) -> RgbaImage
     }
 }
 
-/// Scale an RGBA image by a zoom factor.
-pub fn scale_by_factor(img: &RgbaImage, factor: f64) -> RgbaImage {
-    let (src_w, src_h) = img.dimensions();
-    let dst_w = ((src_w as f64 * factor).round() as u32).max(1);
-    let dst_h = ((src_h as f64 * factor).round() as u32).max(1);
+/// Scale an RGBA image by separate horizontal and vertical zoom factors
+/// (equal unless the image has non-square pixels).
+pub fn scale_by_factors(img: &RgbaImage, factor_x: f64, factor_y: f64) -> RgbaImage {
+    let (dst_w, dst_h) = scaled_size(img.width, img.height, factor_x, factor_y);
     resize_rgba(img, dst_w, dst_h)
 }
 
-/// Resize RGBA image using bilinear interpolation.
+/// Size of a `src_w`x`src_h` image after `scale_by_factors`.
+pub fn scaled_size(src_w: u32, src_h: u32, factor_x: f64, factor_y: f64) -> (u32, u32) {
+    (
+        ((src_w as f64 * factor_x).round() as u32).max(1),
+        ((src_h as f64 * factor_y).round() as u32).max(1),
+    )
+}
+
+/// The `rect = (x, y, w, h)` part of `scale_by_factors(img, factor_x,
+/// factor_y)`, computed without materializing the rest of the scaled
+/// image. `rect` must lie within the scaled size.
+pub fn scale_region(
+    img: &RgbaImage,
+    factor_x: f64,
+    factor_y: f64,
+    rect: (u32, u32, u32, u32),
+) -> RgbaImage {
+    let (dst_w, dst_h) = scaled_size(img.width, img.height, factor_x, factor_y);
+    let threads = resize_threads(rect.2, rect.3);
+    resize_region_threaded(img, dst_w, dst_h, rect, threads)
+}
+
+/// Destination pixels below which resizing stays on the calling thread;
+/// spawning threads costs more than it saves for thumbnails and small zooms.
+const PARALLEL_RESIZE_MIN_PIXELS: usize = 512 * 512;
+/// Upper bound on resize worker threads.
+const MAX_RESIZE_THREADS: usize = 8;
+
+/// Resize RGBA image using bilinear interpolation. Pixel centers are
+/// aligned (`sx = (dx + 0.5) * src_w / dst_w - 0.5`, clamped to the edges),
+/// like other viewers and image libraries, so scaling does not shift the
+/// image by a fraction of a pixel.
 fn resize_rgba(src: &RgbaImage, dst_w: u32, dst_h: u32) -> RgbaImage {
+    let threads = resize_threads(dst_w, dst_h);
+    resize_rgba_threaded(src, dst_w, dst_h, threads)
+}
+
+/// Worker threads for producing `w`x`h` resized pixels.
+pub fn resize_threads(w: u32, h: u32) -> usize {
+    if (w as usize).saturating_mul(h as usize) < PARALLEL_RESIZE_MIN_PIXELS {
+        1
+    } else {
+        std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(MAX_RESIZE_THREADS)
+    }
+}
+
+/// `resize_rgba` with destination rows split into `threads` bands. Rows are
+/// independent, so the output does not depend on the thread count.
+pub fn resize_rgba_threaded(src: &RgbaImage, dst_w: u32, dst_h: u32, threads: usize) -> RgbaImage {
+    resize_region_threaded(src, dst_w, dst_h, (0, 0, dst_w, dst_h), threads)
+}
+
+/// The `rect = (x, y, w, h)` part of a bilinear resize of `src` to
+/// `dst_w`x`dst_h`, with its rows split into `threads` bands.
+fn resize_region_threaded(
+    src: &RgbaImage,
+    dst_w: u32,
+    dst_h: u32,
+    rect: (u32, u32, u32, u32),
+    threads: usize,
+) -> RgbaImage {
+    let (x, y, w, h) = rect;
+    debug_assert!(
+        x + w <= dst_w && y + h <= dst_h,
+        "region outside the resized image"
+    );
     let (src_w, src_h) = src.dimensions();
     if src_w == dst_w && src_h == dst_h {
-        return src.clone();
+        return crate::image_loader::crop_region(src, x, y, w, h);
     }
 
-    let raw = src.as_raw();
-    let out_size = (dst_w as usize)
-        .checked_mul(dst_h as usize)
+    let out_size = (w as usize)
+        .checked_mul(h as usize)
         .and_then(|n| n.checked_mul(4))
         .expect("Resize dimensions too large");
     let mut out = vec![0u8; out_size];
 
-    let x_ratio = if dst_w > 1 {
-        (src_w - 1) as f64 / (dst_w - 1) as f64
-    } else {
-        0.0
-    };
-    let y_ratio = if dst_h > 1 {
-        (src_h - 1) as f64 / (dst_h - 1) as f64
+    let row_len = w as usize * 4;
+    let rows_per_band = (h as usize).div_ceil(threads.max(1)).max(1);
+    if rows_per_band >= h as usize {
+        resize_rows(src, dst_w, dst_h, (x, y), w, &mut out);
     } else {
-        0.0
-    };
+        std::thread::scope(|scope| {
+            for (band, rows) in out.chunks_mut(rows_per_band * row_len).enumerate() {
+                let first_row = y + (band * rows_per_band) as u32;
+                scope.spawn(move || resize_rows(src, dst_w, dst_h, (x, first_row), w, rows));
+            }
+        });
+    }
 
-    for dy in 0..dst_h {
-        let sy = y_ratio * dy as f64;
+    RgbaImage {
+        data: out,
+        width: w,
+        height: h,
+    }
+}
+
+/// Fill `out` with `cols`-wide destination rows, starting at destination
+/// pixel `(first_col, first_row)`, of a bilinear resize of `src` to
+/// `dst_w`x`dst_h`.
+fn resize_rows(
+    src: &RgbaImage,
+    dst_w: u32,
+    dst_h: u32,
+    (first_col, first_row): (u32, u32),
+    cols: u32,
+    out: &mut [u8],
+) {
+    let (src_w, src_h) = src.dimensions();
+    let raw = src.as_raw();
+
+    let x_ratio = src_w as f64 / dst_w as f64;
+    let y_ratio = src_h as f64 / dst_h as f64;
+    // Source coordinate of the center of destination pixel `d`
+    let source_pos =
+        |d: u32, ratio: f64, max: u32| ((d as f64 + 0.5) * ratio - 0.5).clamp(0.0, max as f64);
+
+    for (row, out_row) in out.chunks_exact_mut(cols as usize * 4).enumerate() {
+        let dy = first_row + row as u32;
+        let sy = source_pos(dy, y_ratio, src_h - 1);
         let y0 = sy as u32;
         let y1 = (y0 + 1).min(src_h - 1);
         let fy = sy - y0 as f64;
 
-        for dx in 0..dst_w {
-            let sx = x_ratio * dx as f64;
+        for col in 0..cols {
+            let dx = first_col + col;
+            let sx = source_pos(dx, x_ratio, src_w - 1);
             let x0 = sx as u32;
             let x1 = (x0 + 1).min(src_w - 1);
             let fx = sx - x0 as f64;
@@ -114,7 +225,7 @@ fn resize_rgba(src: &RgbaImage, dst_w: u32, dst_h: u32) -> RgbaImage {
             let i01 = ((y1 * src_w + x0) * 4) as usize;
             let i11 = ((y1 * src_w + x1) * 4) as usize;
 
-            let dst_idx = ((dy * dst_w + dx) * 4) as usize;
+            let dst_idx = (col * 4) as usize;
             for c in 0..4 {
                 let v00 = raw[i00 + c] as f64;
                 let v10 = raw[i10 + c] as f64;
@@ -124,19 +235,49 @@ fn resize_rgba(src: &RgbaImage, dst_w: u32, dst_h: u32) -> RgbaImage {
                     + v10 * fx * (1.0 - fy)
                     + v01 * (1.0 - fx) * fy
                     + v11 * fx * fy;
-                out[dst_idx + c] = v.round() as u8;
+                out_row[dst_idx + c] = v.round() as u8;
             }
         }
     }
+}
 
-    RgbaImage {
-        data: out,
-        width: dst_w,
-        height: dst_h,
+/// Largest pan offsets that keep the edges of a `scaled_w`x`scaled_h` image
+/// at or beyond the window edges; zero on axes where the image fits.
+pub fn max_pan(scaled_w: u32, scaled_h: u32, win_w: u32, win_h: u32) -> (i32, i32) {
+    (
+        ((scaled_w as i32 - win_w as i32) / 2).max(0),
+        ((scaled_h as i32 - win_h as i32) / 2).max(0),
+    )
+}
+
+/// Render frame `frame` of `loaded` into a `win_w`x`win_h` XRGB buffer, the
+/// way the viewer shows it: scaled by `zoom` (1.0 = one window pixel per
+/// image pixel), centered, shifted by `pan` (clamped like the viewer's
+/// pan) and letterboxed with `bg`. Holds no state or caches, so it suits
+/// one-off renders and tests; the viewer caches each stage instead.
+pub fn render_to_rgba(
+    loaded: &LoadedImage,
+    frame: usize,
+    zoom: f64,
+    pan: (i32, i32),
+    win_w: u32,
+    win_h: u32,
+    bg: u32,
+) -> Vec<u32> {
+    let len = (win_w as usize)
+        .checked_mul(win_h as usize)
+        .expect("Render dimensions too large");
+    let img = loaded.frame(frame);
+    if len == 0 || img.width == 0 || img.height == 0 || !(zoom > 0.0 && zoom.is_finite()) {
+        return vec![bg; len];
     }
+    let scaled = scale_by_factors(img, zoom, zoom);
+    let (max_x, max_y) = max_pan(scaled.width, scaled.height, win_w, win_h);
+    let (pan_x, pan_y) = (pan.0.clamp(-max_x, max_x), pan.1.clamp(-max_y, max_y));
+    composite_centered(&scaled, win_w, win_h, pan_x, pan_y, bg)
 }
 
-/// Composite a scaled image centered on a background buffer of given dimensions.
+/// Composite a scaled image centered on a `bg_color` buffer of given dimensions.
 /// Returns the XRGB pixel buffer.
 pub fn composite_centered(
     img: &RgbaImage,
@@ -144,49 +285,435 @@ pub fn composite_centered(
     win_h: u32,
     offset_x: i32,
     offset_y: i32,
+    bg_color: u32,
+) -> Vec<u32> {
+    let size = img.dimensions();
+    composite_region(
+        img,
+        (0, 0),
+        size,
+        win_w,
+        win_h,
+        offset_x,
+        offset_y,
+        bg_color,
+    )
+}
+
+/// Like `composite_centered` for a scaled image of `scaled_size`, of which
+/// only `region` (with its top-left corner at `at` in the scaled image) was
+/// computed. Window pixels outside the region show `bg_color`.
+#[allow(clippy::too_many_arguments)]
+pub fn composite_region(
+    region: &RgbaImage,
+    at: (u32, u32),
+    scaled_size: (u32, u32),
+    win_w: u32,
+    win_h: u32,
+    offset_x: i32,
+    offset_y: i32,
+    bg_color: u32,
 ) -> Vec<u32> {
-    let (img_w, img_h) = img.dimensions();
     let buf_len = (win_w as usize)
         .checked_mul(win_h as usize)
         .expect("Composite dimensions too large");
-    let mut buf = vec![BG_COLOR; buf_len];
+    let mut buf = vec![bg_color; buf_len];
 
     // Center position plus pan offset
-    let cx = (win_w as i32 - img_w as i32) / 2 + offset_x;
-    let cy = (win_h as i32 - img_h as i32) / 2 + offset_y;
+    let cx = (win_w as i32 - scaled_size.0 as i32) / 2 + offset_x;
+    let cy = (win_h as i32 - scaled_size.1 as i32) / 2 + offset_y;
+    let (x, y) = (cx + at.0 as i32, cy + at.1 as i32);
+    blit_over(&mut buf, win_w, win_h, region, x, y, bg_color);
+    buf
+}
+
+/// Turn `buf`, the `composite_region` result at pan offset `from`, into
+/// the one at `to` for the same region. The pixels that stay in view are
+/// shifted and only the newly exposed strips along the edges are
+/// composited, which keeps small pan steps cheap in large windows.
+#[allow(clippy::too_many_arguments)]
+pub fn recomposite_panned(
+    buf: &mut [u32],
+    region: &RgbaImage,
+    at: (u32, u32),
+    scaled_size: (u32, u32),
+    win_w: u32,
+    win_h: u32,
+    from: (i32, i32),
+    to: (i32, i32),
+    bg_color: u32,
+) {
+    let (w, h) = (win_w as i64, win_h as i64);
+    let dx = to.0 as i64 - from.0 as i64;
+    let dy = to.1 as i64 - from.1 as i64;
+    let cx = (w - scaled_size.0 as i64) / 2 + to.0 as i64 + at.0 as i64;
+    let cy = (h - scaled_size.1 as i64) / 2 + to.1 as i64 + at.1 as i64;
+    let (x, y) = (cx as i32, cy as i32);
+    let redraw = |buf: &mut [u32], clip: (i64, i64, i64, i64)| {
+        for row in clip.1..clip.3 {
+            let start = (row * w) as usize;
+            buf[start + clip.0 as usize..start + clip.2 as usize].fill(bg_color);
+        }
+        blit_over_clipped(buf, win_w, clip, region, x, y, bg_color);
+    };
+    if dx.abs() >= w || dy.abs() >= h {
+        redraw(buf, (0, 0, w, h));
+        return;
+    }
 
+    // Move the rows still in view, in an order that never overwrites a
+    // row before it was copied
+    let len = (w - dx.abs()) as usize;
+    let (src_x, dst_x) = ((-dx).max(0) as usize, dx.max(0) as usize);
+    let rows = dy.max(0)..h + dy.min(0);
+    let mut shift = |row: i64| {
+        let src = ((row - dy) * w) as usize + src_x;
+        let dst = (row * w) as usize + dst_x;
+        buf.copy_within(src..src + len, dst);
+    };
+    if dy > 0 {
+        rows.rev().for_each(&mut shift);
+    } else {
+        rows.for_each(&mut shift);
+    }
+
+    // Exposed rows across the full width, then the exposed columns
+    if dy > 0 {
+        redraw(buf, (0, 0, w, dy));
+    } else if dy < 0 {
+        redraw(buf, (0, h + dy, w, h));
+    }
+    let (top, bottom) = (dy.max(0), h + dy.min(0));
+    if dx > 0 {
+        redraw(buf, (0, top, dx, bottom));
+    } else if dx < 0 {
+        redraw(buf, (w + dx, top, w, bottom));
+    }
+}
+
+/// Rectangle `(x, y, w, h)` of a `scaled_w`x`scaled_h` image, centered and
+/// offset as by `composite_centered`, that is visible in the window,
+/// widened by `margin` on each side and clipped to the image. `None` if
+/// no part of the image is visible.
+pub fn visible_scaled_rect(
+    (scaled_w, scaled_h): (u32, u32),
+    win_w: u32,
+    win_h: u32,
+    (offset_x, offset_y): (i32, i32),
+    (margin_x, margin_y): (u32, u32),
+) -> Option<(u32, u32, u32, u32)> {
+    let span = |scaled: u32, win: u32, offset: i32, margin: u32| {
+        let origin = (win as i64 - scaled as i64) / 2 + offset as i64;
+        let start = (-origin - margin as i64).max(0);
+        let end = (win as i64 - origin + margin as i64).min(scaled as i64);
+        // Fully off-screen spans stay empty even with a margin
+        let visible = (-origin).max(0) < (win as i64 - origin).min(scaled as i64);
+        (visible && start < end).then_some((start as u32, (end - start) as u32))
+    };
+    let (x, w) = span(scaled_w, win_w, offset_x, margin_x)?;
+    let (y, h) = span(scaled_h, win_h, offset_y, margin_y)?;
+    Some((x, y, w, h))
+}
+
+/// Draw `img` into an XRGB buffer with its top-left corner at `(x, y)`,
+/// clipped to the buffer. Translucent pixels are blended over `bg_color`.
+pub fn blit_over(
+    buf: &mut [u32],
+    buf_w: u32,
+    buf_h: u32,
+    img: &RgbaImage,
+    x: i32,
+    y: i32,
+    bg_color: u32,
+) {
+    let clip = (0, 0, buf_w as i64, buf_h as i64);
+    blit_over_clipped(buf, buf_w, clip, img, x, y, bg_color);
+}
+
+/// `blit_over` limited to the buffer rectangle `clip` (x0, y0, x1, y1),
+/// which must lie within the buffer.
+fn blit_over_clipped(
+    buf: &mut [u32],
+    buf_w: u32,
+    clip: (i64, i64, i64, i64),
+    img: &RgbaImage,
+    x: i32,
+    y: i32,
+    bg_color: u32,
+) {
+    let (img_w, img_h) = img.dimensions();
     let raw = img.as_raw();
-    for iy in 0..img_h as i32 {
-        let dy = cy + iy;
-        if dy < 0 || dy >= win_h as i32 {
-            continue;
-        }
-        for ix in 0..img_w as i32 {
-            let dx = cx + ix;
-            if dx < 0 || dx >= win_w as i32 {
-                continue;
-            }
-            let src_idx = (iy as u32 * img_w + ix as u32) as usize * 4;
-            let r = raw[src_idx] as u32;
-            let g = raw[src_idx + 1] as u32;
-            let b = raw[src_idx + 2] as u32;
-            let a = raw[src_idx + 3] as u32;
+    // Clip once, in i64 so offsets near i32::MAX cannot overflow
+    let x0 = (x as i64).max(clip.0);
+    let x1 = (x as i64 + img_w as i64).min(clip.2);
+    let y0 = (y as i64).max(clip.1);
+    let y1 = (y as i64 + img_h as i64).min(clip.3);
+    if x0 >= x1 || y0 >= y1 {
+        return;
+    }
+    let len = (x1 - x0) as usize;
+    let src_x = (x0 - x as i64) as usize;
 
-            let dst_idx = (dy as u32 * win_w + dx as u32) as usize;
+    let bg_r = (bg_color >> 16) & 0xFF;
+    let bg_g = (bg_color >> 8) & 0xFF;
+    let bg_b = bg_color & 0xFF;
+    for dy in y0..y1 {
+        let src_start = ((dy - y as i64) as usize * img_w as usize + src_x) * 4;
+        let src_row = &raw[src_start..src_start + len * 4];
+        let dst_start = dy as usize * buf_w as usize + x0 as usize;
+        let dst_row = &mut buf[dst_start..dst_start + len];
+
+        for (px, out) in src_row.chunks_exact(4).zip(dst_row) {
+            let (r, g, b, a) = (px[0] as u32, px[1] as u32, px[2] as u32, px[3] as u32);
             if a == 255 {
-                buf[dst_idx] = (r << 16) | (g << 8) | b;
+                *out = (r << 16) | (g << 8) | b;
             } else if a > 0 {
-                let bg_r = (BG_COLOR >> 16) & 0xFF;
-                let bg_g = (BG_COLOR >> 8) & 0xFF;
-                let bg_b = BG_COLOR & 0xFF;
                 let out_r = (r * a + bg_r * (255 - a)) / 255;
                 let out_g = (g * a + bg_g * (255 - a)) / 255;
                 let out_b = (b * a + bg_b * (255 - a)) / 255;
-                buf[dst_idx] = (out_r << 16) | (out_g << 8) | out_b;
+                *out = (out_r << 16) | (out_g << 8) | out_b;
             }
         }
     }
-    buf
+}
+
+/// Map the part of an image that is visible in the window back to source
+/// pixel coordinates. `scale_x`/`scale_y` are the display scale factors and
+/// `offset_x`/`offset_y` the pan offset, as passed to `composite_centered`.
+/// Returns `(x, y, w, h)` in source pixels, or `None` if nothing is visible.
+#[allow(clippy::too_many_arguments)]
+pub fn visible_source_rect(
+    src_w: u32,
+    src_h: u32,
+    scale_x: f64,
+    scale_y: f64,
+    win_w: u32,
+    win_h: u32,
+    offset_x: i32,
+    offset_y: i32,
+) -> Option<(u32, u32, u32, u32)> {
+    let valid = |s: f64| s.is_finite() && s > 0.0;
+    if src_w == 0 || src_h == 0 || win_w == 0 || win_h == 0 || !valid(scale_x) || !valid(scale_y) {
+        return None;
+    }
+    let (scaled_w, scaled_h) = scaled_size(src_w, src_h, scale_x, scale_y);
+
+    let (x0, x1) = visible_span(src_w, scaled_w, win_w, offset_x)?;
+    let (y0, y1) = visible_span(src_h, scaled_h, win_h, offset_y)?;
+    Some((x0, y0, x1 - x0, y1 - y0))
+}
+
+/// One axis of `visible_source_rect`: returns the visible source range `[start, end)`.
+fn visible_span(src: u32, scaled: u32, win: u32, offset: i32) -> Option<(u32, u32)> {
+    let origin = (win as i64 - scaled as i64) / 2 + offset as i64;
+    let start = (-origin).max(0);
+    let end = (win as i64 - origin).min(scaled as i64);
+    if end <= start {
+        return None;
+    }
+    let ratio = src as f64 / scaled as f64;
+    let src_start = ((start as f64 * ratio).floor() as u32).min(src);
+    let src_end = ((end as f64 * ratio).ceil() as u32).min(src);
+    if src_end <= src_start {
+        return None;
+    }
+    Some((src_start, src_end))
+}
+
+/// Convert an XRGB8888 buffer to an opaque RgbaImage.
+pub fn xrgb_to_rgba(buf: &[u32], width: u32, height: u32) -> RgbaImage {
+    let mut img = RgbaImage::new(width, height);
+    for (dst, &px) in img.data.chunks_exact_mut(4).zip(buf) {
+        dst.copy_from_slice(&[(px >> 16) as u8, (px >> 8) as u8, px as u8, 255]);
+    }
+    img
+}
+
+/// Display tone adjustments, applied to the color channels in the order
+/// gamma, contrast, brightness.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    /// Added to each channel, in -1.0..=1.0 of full scale.
+    pub brightness: f64,
+    /// Scales the distance from mid grey; 1.0 leaves it unchanged.
+    pub contrast: f64,
+    /// Output = input^(1/gamma); 1.0 leaves it unchanged.
+    pub gamma: f64,
+}
+
+impl Tone {
+    pub const NEUTRAL: Tone = Tone {
+        brightness: 0.0,
+        contrast: 1.0,
+        gamma: 1.0,
+    };
+
+    /// Lookup table mapping each 8-bit channel value through the adjustments.
+    pub fn lut(&self) -> [u8; 256] {
+        let mut lut = [0u8; 256];
+        for (i, out) in lut.iter_mut().enumerate() {
+            let v = (i as f64 / 255.0).powf(1.0 / self.gamma);
+            let v = (v - 0.5) * self.contrast + 0.5 + self.brightness;
+            *out = (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        lut
+    }
+}
+
+/// Copy of `img` with `tone` applied to the color channels (alpha is kept).
+pub fn apply_tone(img: &RgbaImage, tone: Tone) -> RgbaImage {
+    let lut = tone.lut();
+    let mut out = img.clone();
+    for px in out.data.chunks_exact_mut(4) {
+        px[0] = lut[px[0] as usize];
+        px[1] = lut[px[1] as usize];
+        px[2] = lut[px[2] as usize];
+    }
+    out
+}
+
+/// Non-destructive display filter, cycled with a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    None,
+    Grayscale,
+    Invert,
+    Sepia,
+}
+
+impl Filter {
+    /// The next filter in the cycle none -> grayscale -> invert -> sepia.
+    pub fn next(self) -> Filter {
+        match self {
+            Filter::None => Filter::Grayscale,
+            Filter::Grayscale => Filter::Invert,
+            Filter::Invert => Filter::Sepia,
+            Filter::Sepia => Filter::None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Filter::None => "None",
+            Filter::Grayscale => "Grayscale",
+            Filter::Invert => "Invert",
+            Filter::Sepia => "Sepia",
+        }
+    }
+}
+
+/// Copy of `img` with `filter` applied to each pixel (alpha is kept).
+pub fn apply_filter(img: &RgbaImage, filter: Filter) -> RgbaImage {
+    let mut out = img.clone();
+    for px in out.data.chunks_exact_mut(4) {
+        let (r, g, b) = (px[0] as u32, px[1] as u32, px[2] as u32);
+        match filter {
+            Filter::None => {}
+            Filter::Grayscale => {
+                // Rec. 601 luma in 8-bit fixed point (weights sum to 256)
+                let y = ((77 * r + 150 * g + 29 * b) >> 8) as u8;
+                px[0] = y;
+                px[1] = y;
+                px[2] = y;
+            }
+            Filter::Invert => {
+                px[0] = 255 - px[0];
+                px[1] = 255 - px[1];
+                px[2] = 255 - px[2];
+            }
+            Filter::Sepia => {
+                // Classic sepia matrix, weights in thousandths
+                px[0] = ((393 * r + 769 * g + 189 * b) / 1000).min(255) as u8;
+                px[1] = ((349 * r + 686 * g + 168 * b) / 1000).min(255) as u8;
+                px[2] = ((272 * r + 534 * g + 131 * b) / 1000).min(255) as u8;
+            }
+        }
+    }
+    out
+}
+
+/// Draw a `cols`x`rows` grid (e.g. rule of thirds) over the image area
+/// `rect = (x, y, w, h)` in window coordinates, clipped to the window.
+/// Each line is a light 1px line with a dark 1px shadow so it shows on any content.
+pub fn draw_grid(
+    buf: &mut [u32],
+    win_w: u32,
+    win_h: u32,
+    rect: (i32, i32, u32, u32),
+    cols: u32,
+    rows: u32,
+) {
+    let (rx, ry, rw, rh) = rect;
+    // Clip the image rect to the window
+    let x0 = rx.max(0);
+    let y0 = ry.max(0);
+    let x1 = (rx + rw as i32).min(win_w as i32);
+    let y1 = (ry + rh as i32).min(win_h as i32);
+    if x1 <= x0 || y1 <= y0 {
+        return;
+    }
+    let (span_w, span_h) = ((x1 - x0) as u32, (y1 - y0) as u32);
+
+    for i in 1..cols {
+        let lx = rx + (rw as u64 * i as u64 / cols as u64) as i32;
+        if lx >= x0 && lx + 1 < x1 {
+            blend_rect(buf, win_w, lx as u32, y0 as u32, 1, span_h, 0x00FFFFFF, 110);
+            blend_rect(buf, win_w, lx as u32 + 1, y0 as u32, 1, span_h, 0, 70);
+        }
+    }
+    for i in 1..rows {
+        let ly = ry + (rh as u64 * i as u64 / rows as u64) as i32;
+        if ly >= y0 && ly + 1 < y1 {
+            blend_rect(buf, win_w, x0 as u32, ly as u32, span_w, 1, 0x00FFFFFF, 110);
+            blend_rect(buf, win_w, x0 as u32, ly as u32 + 1, span_w, 1, 0, 70);
+        }
+    }
+}
+
+/// Draw print-style crop marks at the corners and center marks at the
+/// middle of each side, just outside the image area `rect = (x, y, w, h)`
+/// in window coordinates. Marks falling outside the window are clipped.
+pub fn draw_crop_marks(buf: &mut [u32], win_w: u32, win_h: u32, rect: (i32, i32, u32, u32)) {
+    let (rx, ry, rw, rh) = rect;
+    let (right, bottom) = (rx + rw as i32, ry + rh as i32);
+    let (gap, len) = (PROOF_MARK_GAP, PROOF_MARK_LEN);
+    let mut line = |x: i32, y: i32, w: i32, h: i32| {
+        let (x0, y0) = (x.max(0), y.max(0));
+        let (x1, y1) = ((x + w).min(win_w as i32), (y + h).min(win_h as i32));
+        if x1 > x0 && y1 > y0 {
+            let (w, h) = ((x1 - x0) as u32, (y1 - y0) as u32);
+            fill_rect(buf, win_w, x0 as u32, y0 as u32, w, h, PROOF_MARK_COLOR);
+        }
+    };
+
+    // Corner marks: extensions of the image edges, away from the image
+    for (x, y) in [
+        (rx, ry),
+        (right - 1, ry),
+        (rx, bottom - 1),
+        (right - 1, bottom - 1),
+    ] {
+        let hx = if x == rx { rx - gap - len } else { right + gap };
+        let vy = if y == ry {
+            ry - gap - len
+        } else {
+            bottom + gap
+        };
+        line(hx, y, len, 1);
+        line(x, vy, 1, len);
+    }
+
+    // Center marks: short ticks on each side with a crossbar
+    let (cx, cy) = (rx + rw as i32 / 2, ry + rh as i32 / 2);
+    let half = len / 3;
+    for vy in [ry - gap - len, bottom + gap] {
+        line(cx, vy, 1, len);
+        line(cx - half, vy + len / 2, 2 * half + 1, 1);
+    }
+    for hx in [rx - gap - len, right + gap] {
+        line(hx, cy, len, 1);
+        line(hx + len / 2, cy - half, 1, 2 * half + 1);
+    }
 }
 
 /// Generate a thumbnail: scale image to fit within thumb_size x thumb_size.
@@ -197,7 +724,7 @@ pub fn generate_thumbnail(img: &RgbaImage, thumb_size: u32) -> RgbaImage {
 /// Draw a filled rectangle with a given XRGB color onto the buffer.
 pub fn fill_rect(buf: &mut [u32], buf_w: u32, x: u32, y: u32, w: u32, h: u32, color: u32) {
     for row in y..y.saturating_add(h) {
-        if row >= buf.len() as u32 / buf_w {
+        if row >= buf.len() as u32 / buf_w.max(1) {
             break;
         }
         for col in x..x.saturating_add(w) {
@@ -258,12 +785,42 @@ pub fn fill_rect_rounded(
     }
 }
 
-/// Draw a semi-transparent dark overlay (for status bar background).
-/// Blends a dark color at given alpha over existing pixels.
-pub fn draw_overlay(buf: &mut [u32], buf_w: u32, x: u32, y: u32, w: u32, h: u32, alpha: u32) {
-    let ov_r: u32 = 0;
-    let ov_g: u32 = 0;
-    let ov_b: u32 = 0;
+/// Place a `w`x`h` overlay `margin` pixels in from the top-right corner of
+/// a `win_w`x`win_h` window, shrunk to fit inside the margins. Returns
+/// `(x, y, w, h)`, or `None` if less than `min_w`x`min_h` would remain
+/// (e.g. not even one line of text), so tiny windows skip the overlay.
+pub fn fit_overlay_top_right(
+    w: u32,
+    h: u32,
+    min_w: u32,
+    min_h: u32,
+    margin: u32,
+    win_w: u32,
+    win_h: u32,
+) -> Option<(u32, u32, u32, u32)> {
+    let w = w.min(win_w.checked_sub(margin * 2)?);
+    let h = h.min(win_h.checked_sub(margin * 2)?);
+    if w < min_w.max(1) || h < min_h.max(1) {
+        return None;
+    }
+    Some((win_w - margin - w, margin, w, h))
+}
+
+/// Blend `color` over a rectangle with the given alpha (0-255).
+#[allow(clippy::too_many_arguments)]
+pub fn blend_rect(
+    buf: &mut [u32],
+    buf_w: u32,
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    color: u32,
+    alpha: u32,
+) {
+    let ov_r = (color >> 16) & 0xFF;
+    let ov_g = (color >> 8) & 0xFF;
+    let ov_b = color & 0xFF;
     for row in y..y.saturating_add(h) {
         if row >= buf.len() as u32 / buf_w.max(1) {
             break;
@@ -288,8 +845,10 @@ pub fn draw_overlay(buf: &mut [u32], buf_w: u32, x: u32, y: u32, w: u32, h: u32,
     }
 }
 
-/// Draw a semi-transparent dark overlay with rounded corners.
-/// Same blending as `draw_overlay` but skips pixels outside the corner radius.
+/// Draw a semi-transparent dark overlay with rounded corners, for the status
+/// bar and info panels. Blends black at the given alpha over existing pixels;
+/// on the corner arcs the alpha is scaled by how much of each pixel lies
+/// inside the radius, so the edges are antialiased.
 pub fn draw_overlay_rounded(
     buf: &mut [u32],
     buf_w: u32,
@@ -301,7 +860,6 @@ pub fn draw_overlay_rounded(
     radius: u32,
 ) {
     let r = radius.min(w / 2).min(h / 2);
-    let r_sq = (r * r) as i64;
 
     for row in y..y.saturating_add(h) {
         if row >= buf.len() as u32 / buf_w.max(1) {
@@ -314,21 +872,18 @@ pub fn draw_overlay_rounded(
             }
             let rx = col - x; // col within the rect
 
-            // Check if pixel is in a corner region and outside the radius
-            if r > 0 {
-                let in_corner = (rx < r && ry < r)
-                    || (rx >= w - r && ry < r)
-                    || (rx < r && ry >= h - r)
-                    || (rx >= w - r && ry >= h - r);
-                if in_corner {
-                    // Distance from the nearest corner's circle center
-                    let cx = if rx < r { r - 1 } else { w - r };
-                    let cy = if ry < r { r - 1 } else { h - r };
-                    let dx = rx as i64 - cx as i64;
-                    let dy = ry as i64 - cy as i64;
-                    if dx * dx + dy * dy > r_sq {
-                        continue; // Outside rounded corner
-                    }
+            // Corner pixels are blended by their coverage of the arc
+            let mut a = alpha;
+            if r > 0 && (rx < r || rx >= w - r) && (ry < r || ry >= h - r) {
+                // Pixel center relative to the nearest corner's circle center
+                let cx = if rx < r { r } else { w - r };
+                let cy = if ry < r { r } else { h - r };
+                let dx = rx as f32 + 0.5 - cx as f32;
+                let dy = ry as f32 + 0.5 - cy as f32;
+                let coverage = (r as f32 + 0.5 - dx.hypot(dy)).clamp(0.0, 1.0);
+                a = (alpha as f32 * coverage).round() as u32;
+                if a == 0 {
+                    continue; // Outside rounded corner
                 }
             }
 
@@ -340,9 +895,9 @@ pub fn draw_overlay_rounded(
             let bg_r = (existing >> 16) & 0xFF;
             let bg_g = (existing >> 8) & 0xFF;
             let bg_b = existing & 0xFF;
-            let out_r = (bg_r * (255 - alpha)) / 255;
-            let out_g = (bg_g * (255 - alpha)) / 255;
-            let out_b = (bg_b * (255 - alpha)) / 255;
+            let out_r = (bg_r * (255 - a)) / 255;
+            let out_g = (bg_g * (255 - a)) / 255;
+            let out_b = (bg_b * (255 - a)) / 255;
             buf[idx] = (out_r << 16) | (out_g << 8) | out_b;
         }
     }
@@ -406,6 +961,7 @@ pub fn blit_thumbnail(
 mod tests {
     use super::*;
     use crate::image_loader::RgbaImage;
+    use std::time::Duration;
 
     #[test]
     fn test_scale_to_fit_dimensions() {
@@ -449,7 +1005,7 @@ mod tests {
             img.data[i * 4 + 3] = 255; // A
         }
 
-        let buf = composite_centered(&img, 4, 4, 0, 0);
+        let buf = composite_centered(&img, 4, 4, 0, 0, BG_COLOR);
         assert_eq!(buf.len(), 16);
         // Center of 4x4 with 2x2: at (1,1)
         let red = (255 << 16) | (0 << 8) | 0;
@@ -462,6 +1018,22 @@ mod tests {
         assert_eq!(xrgb_at(&buf, 4, 3, 3), BG_COLOR);
     }
 
+    #[test]
+    fn test_blit_over_clips_negative_offset() {
+        // 2x3 image whose top row is scrolled off the top of a 3x3 buffer
+        let mut img = RgbaImage::new(2, 3);
+        for px in img.data.chunks_exact_mut(4) {
+            px.copy_from_slice(&[0, 255, 0, 255]);
+        }
+        let mut buf = vec![BG_COLOR; 9];
+        blit_over(&mut buf, 3, 3, &img, 2, -1, BG_COLOR);
+        let green = 0x0000FF00;
+        assert_eq!(xrgb_at(&buf, 3, 2, 0), green);
+        assert_eq!(xrgb_at(&buf, 3, 2, 1), green);
+        assert_eq!(xrgb_at(&buf, 3, 2, 2), BG_COLOR);
+        assert_eq!(xrgb_at(&buf, 3, 1, 0), BG_COLOR);
+    }
+
     #[test]
     fn test_composite_centered_alpha_blend() {
         // 1x1 semi-transparent image (alpha=128)
@@ -471,7 +1043,7 @@ mod tests {
         img.data[2] = 0; // B
         img.data[3] = 128; // A (about 50%)
 
-        let buf = composite_centered(&img, 1, 1, 0, 0);
+        let buf = composite_centered(&img, 1, 1, 0, 0, BG_COLOR);
         // Should be a blend of red over BG_COLOR (#1a1a1a)
         let pixel = buf[0];
         let r = (pixel >> 16) & 0xFF;
@@ -494,11 +1066,491 @@ mod tests {
     #[test]
     fn test_draw_overlay_darkens() {
         let mut buf = vec![0x00FFFFFF; 4]; // 2x2 white
-        draw_overlay(&mut buf, 2, 0, 0, 2, 2, 128); // ~50% dark overlay
+        draw_overlay_rounded(&mut buf, 2, 0, 0, 2, 2, 128, 0); // ~50% dark overlay
         for &px in &buf {
             let r = (px >> 16) & 0xFF;
             // White (255) with 50% dark overlay: ~127
             assert!(r < 160 && r > 90, "Expected dimmed, got r={}", r);
         }
     }
+
+    #[test]
+    fn test_draw_overlay_rounded_antialiased() {
+        let mut buf = vec![0x00FFFFFF; 16 * 16];
+        draw_overlay_rounded(&mut buf, 16, 0, 0, 16, 16, 255, 6);
+        let red = |x: u32, y: u32| (xrgb_at(&buf, 16, x, y) >> 16) & 0xFF;
+        // Fully covered center and straight edges, untouched outermost corner
+        assert_eq!(red(8, 8), 0);
+        assert_eq!(red(0, 8), 0);
+        assert_eq!(red(0, 0), 255);
+        // Pixels on the arc are partially blended, the same in every corner
+        let edge = red(1, 1);
+        assert!(
+            edge > 0 && edge < 255,
+            "expected partial blend, got {}",
+            edge
+        );
+        assert_eq!(red(14, 1), edge);
+        assert_eq!(red(1, 14), edge);
+        assert_eq!(red(14, 14), edge);
+    }
+
+    #[test]
+    fn test_xrgb_to_rgba() {
+        let img = xrgb_to_rgba(&[0x00FF8001, 0x00000000], 2, 1);
+        assert_eq!(img.as_raw(), &[0xFF, 0x80, 0x01, 255, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_draw_grid_thirds() {
+        // 9x9 image area filling a 9x9 black window: lines at x=3,6 and y=3,6
+        let mut buf = vec![0u32; 81];
+        draw_grid(&mut buf, 9, 9, (0, 0, 9, 9), 3, 3);
+        assert_ne!(xrgb_at(&buf, 9, 3, 0), 0);
+        assert_ne!(xrgb_at(&buf, 9, 6, 8), 0);
+        assert_ne!(xrgb_at(&buf, 9, 0, 3), 0);
+        assert_eq!(xrgb_at(&buf, 9, 1, 1), 0);
+    }
+
+    #[test]
+    fn test_draw_grid_clipped_offscreen() {
+        // Image entirely left of the window: nothing drawn
+        let mut buf = vec![0u32; 16];
+        draw_grid(&mut buf, 4, 4, (-20, 0, 10, 4), 3, 3);
+        assert!(buf.iter().all(|&p| p == 0));
+    }
+
+    #[test]
+    fn test_visible_source_rect_whole_image() {
+        // 100x50 shown at 1:1 in a larger window -> whole image visible
+        assert_eq!(
+            visible_source_rect(100, 50, 1.0, 1.0, 200, 200, 0, 0),
+            Some((0, 0, 100, 50))
+        );
+    }
+
+    #[test]
+    fn test_visible_source_rect_zoomed_center() {
+        // 100x100 at 2x (200x200) in a 100x100 window -> center 50x50
+        assert_eq!(
+            visible_source_rect(100, 100, 2.0, 2.0, 100, 100, 0, 0),
+            Some((25, 25, 50, 50))
+        );
+    }
+
+    #[test]
+    fn test_visible_source_rect_panned() {
+        // Panning right by 50 display pixels shows 25 source pixels further left
+        assert_eq!(
+            visible_source_rect(100, 100, 2.0, 2.0, 100, 100, 50, 0),
+            Some((0, 25, 50, 50))
+        );
+    }
+
+    #[test]
+    fn test_visible_source_rect_anamorphic() {
+        // 100x100 stretched 2x horizontally (200x100) in a 100x100 window
+        assert_eq!(
+            visible_source_rect(100, 100, 2.0, 1.0, 100, 100, 0, 0),
+            Some((25, 0, 50, 100))
+        );
+    }
+
+    #[test]
+    fn test_visible_source_rect_degenerate() {
+        assert_eq!(visible_source_rect(0, 100, 1.0, 1.0, 100, 100, 0, 0), None);
+        assert_eq!(visible_source_rect(100, 100, 1.0, 1.0, 0, 100, 0, 0), None);
+        assert_eq!(
+            visible_source_rect(100, 100, 0.0, 0.0, 100, 100, 0, 0),
+            None
+        );
+        // Panned completely out of view
+        assert_eq!(
+            visible_source_rect(10, 10, 1.0, 1.0, 100, 100, 500, 0),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tone_lut() {
+        let lut = Tone::NEUTRAL.lut();
+        assert!(lut.iter().enumerate().all(|(i, &v)| v as usize == i));
+
+        let brighter = Tone {
+            brightness: 0.1,
+            ..Tone::NEUTRAL
+        }
+        .lut();
+        assert_eq!(brighter[0], 26);
+        assert_eq!(brighter[255], 255);
+
+        // Contrast pivots around mid grey
+        let flat = Tone {
+            contrast: 0.0,
+            ..Tone::NEUTRAL
+        }
+        .lut();
+        assert!(flat.iter().all(|&v| v == 128));
+
+        // Gamma > 1 lifts the midtones, keeps the end points
+        let lifted = Tone {
+            gamma: 2.0,
+            ..Tone::NEUTRAL
+        }
+        .lut();
+        assert_eq!((lifted[0], lifted[255]), (0, 255));
+        assert!(lifted[64] > 64);
+    }
+
+    #[test]
+    fn test_apply_tone_keeps_alpha() {
+        let img = RgbaImage::from_raw(1, 1, vec![10, 20, 30, 40]).unwrap();
+        let out = apply_tone(
+            &img,
+            Tone {
+                brightness: 1.0,
+                ..Tone::NEUTRAL
+            },
+        );
+        assert_eq!(out.as_raw(), &[255, 255, 255, 40]);
+    }
+
+    #[test]
+    fn test_apply_filter() {
+        let mut img = RgbaImage::new(2, 1);
+        img.data.copy_from_slice(&[255, 0, 0, 255, 10, 200, 30, 40]);
+
+        let gray = apply_filter(&img, Filter::Grayscale);
+        assert_eq!(&gray.data[..4], &[76, 76, 76, 255]);
+
+        let inverted = apply_filter(&img, Filter::Invert);
+        assert_eq!(inverted.data, [0, 255, 255, 255, 245, 55, 225, 40]);
+
+        let sepia = apply_filter(&img, Filter::Sepia);
+        assert_eq!(&sepia.data[..4], &[100, 88, 69, 255]);
+        assert_eq!(sepia.data[7], 40);
+
+        assert_eq!(apply_filter(&img, Filter::None).data, img.data);
+        let mut filter = Filter::None;
+        for _ in 0..4 {
+            filter = filter.next();
+        }
+        assert_eq!(filter, Filter::None);
+    }
+
+    #[test]
+    fn test_threaded_resize_matches_single_thread() {
+        let mut img = RgbaImage::new(37, 23);
+        for (i, v) in img.data.iter_mut().enumerate() {
+            *v = (i * 31 % 251) as u8;
+        }
+        for (w, h) in [(100, 61), (13, 7), (37, 1), (5, 40)] {
+            let single = resize_rgba_threaded(&img, w, h, 1);
+            for threads in [2, 3, 8, 64] {
+                let multi = resize_rgba_threaded(&img, w, h, threads);
+                assert_eq!(
+                    multi.data, single.data,
+                    "{}x{} with {} threads",
+                    w, h, threads
+                );
+            }
+        }
+    }
+
+    /// The straightforward per-pixel blit, as a reference for `blit_over`.
+    fn blit_over_reference(
+        buf: &mut [u32],
+        buf_w: u32,
+        buf_h: u32,
+        img: &RgbaImage,
+        x: i32,
+        y: i32,
+        bg_color: u32,
+    ) {
+        let raw = img.as_raw();
+        for iy in 0..img.height as i32 {
+            for ix in 0..img.width as i32 {
+                let (dx, dy) = (x + ix, y + iy);
+                if dx < 0 || dy < 0 || dx >= buf_w as i32 || dy >= buf_h as i32 {
+                    continue;
+                }
+                let i = (iy as u32 * img.width + ix as u32) as usize * 4;
+                let a = raw[i + 3] as u32;
+                if a == 0 {
+                    continue;
+                }
+                let mut out = 0;
+                for (c, shift) in [(0, 16), (1, 8), (2, 0)] {
+                    let bg = (bg_color >> shift) & 0xFF;
+                    out |= ((raw[i + c] as u32 * a + bg * (255 - a)) / 255) << shift;
+                }
+                buf[(dy as u32 * buf_w + dx as u32) as usize] = out;
+            }
+        }
+    }
+
+    #[test]
+    fn test_blit_over_matches_reference() {
+        let mut opaque = RgbaImage::new(7, 5);
+        for (i, v) in opaque.data.iter_mut().enumerate() {
+            *v = if i % 4 == 3 {
+                255
+            } else {
+                (i * 37 % 256) as u8
+            };
+        }
+        let mut mixed = opaque.clone();
+        for (i, px) in mixed.data.chunks_exact_mut(4).enumerate() {
+            px[3] = [255, 0, 128, 1][i % 4];
+        }
+        for img in [&opaque, &mixed] {
+            for (x, y) in [
+                (0, 0),
+                (-3, 2),
+                (4, -4),
+                (6, 3),
+                (-7, 0),
+                (9, 9),
+                (-20, -20),
+            ] {
+                let mut fast = vec![BG_COLOR; 8 * 6];
+                let mut slow = fast.clone();
+                blit_over(&mut fast, 8, 6, img, x, y, BG_COLOR);
+                blit_over_reference(&mut slow, 8, 6, img, x, y, BG_COLOR);
+                assert_eq!(fast, slow, "offset ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fit_overlay_top_right() {
+        // Fits: right-aligned inside the margin
+        assert_eq!(
+            fit_overlay_top_right(50, 20, 10, 10, 10, 200, 100),
+            Some((140, 10, 50, 20))
+        );
+        // Shrunk to the space between the margins
+        assert_eq!(
+            fit_overlay_top_right(500, 20, 10, 10, 10, 200, 100),
+            Some((10, 10, 180, 20))
+        );
+        // Tiny windows skip the overlay instead of underflowing
+        for (win_w, win_h) in [(0, 0), (1, 1), (10, 10), (25, 25), (1, 300), (300, 1)] {
+            assert_eq!(
+                fit_overlay_top_right(50, 20, 10, 10, 10, win_w, win_h),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn test_fill_rect_zero_width_buffer() {
+        let mut buf: Vec<u32> = Vec::new();
+        fill_rect(&mut buf, 0, 0, 0, 5, 5, 0xFFFFFF);
+        fill_rect_rounded(&mut buf, 0, 0, 0, 5, 5, 0xFFFFFF, 2);
+        blend_rect(&mut buf, 0, 0, 0, 5, 5, 0xFFFFFF, 128);
+    }
+
+    #[test]
+    fn test_draw_crop_marks() {
+        let (w, h) = (120, 100);
+        let mut buf = vec![PROOF_BG_COLOR; (w * h) as usize];
+        // 40x30 image at (40, 35)
+        draw_crop_marks(&mut buf, w, h, (40, 35, 40, 30));
+        let black = |x: u32, y: u32| xrgb_at(&buf, w, x, y) == PROOF_MARK_COLOR;
+        // Top-left corner: horizontal mark left of the top edge, vertical
+        // mark above the left edge, the corner gap itself stays white
+        assert!(black(40 - 6 - 1, 35));
+        assert!(black(40, 35 - 6 - 1));
+        assert!(!black(39, 34));
+        // Bottom-right corner
+        assert!(black(80 + 6, 64));
+        assert!(black(79, 65 + 6));
+        // Center tick above the image
+        assert!(black(60, 35 - 6 - 1));
+        // Nothing is drawn over the image itself
+        for y in 35..65 {
+            for x in 40..80 {
+                assert!(!black(x, y), "({}, {})", x, y);
+            }
+        }
+        // Marks off the window edge are clipped without panicking
+        draw_crop_marks(&mut buf, w, h, (-50, -50, 300, 300));
+        draw_crop_marks(&mut buf, w, h, (0, 0, w, h));
+    }
+
+    fn solid_frame(w: u32, h: u32, rgba: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_raw(w, h, rgba.repeat((w * h) as usize)).unwrap()
+    }
+
+    #[test]
+    fn test_render_to_rgba_centered() {
+        // 2x2 image with distinct corners, centered in a 6x4 window
+        let data = [
+            [255, 0, 0, 255],
+            [0, 255, 0, 255],
+            [0, 0, 255, 255],
+            [255, 255, 255, 255],
+        ]
+        .concat();
+        let loaded = LoadedImage::Static(RgbaImage::from_raw(2, 2, data).unwrap());
+        let bg = 0x00123456;
+        let buf = render_to_rgba(&loaded, 0, 1.0, (0, 0), 6, 4, bg);
+        assert_eq!(buf.len(), 24);
+        let at = |x: usize, y: usize| buf[y * 6 + x];
+        assert_eq!(at(2, 1), 0x00FF0000);
+        assert_eq!(at(3, 1), 0x0000FF00);
+        assert_eq!(at(2, 2), 0x000000FF);
+        assert_eq!(at(3, 2), 0x00FFFFFF);
+        for (x, y) in [(0, 0), (1, 1), (4, 2), (5, 3), (2, 0), (3, 3)] {
+            assert_eq!(at(x, y), bg, "({}, {})", x, y);
+        }
+
+        // Pan is clamped to zero when the image fits the window
+        assert_eq!(render_to_rgba(&loaded, 0, 1.0, (2, -2), 6, 4, bg), buf);
+    }
+
+    #[test]
+    fn test_render_to_rgba_zoom_pan_and_frames() {
+        let delay = Duration::from_millis(100);
+        let loaded = LoadedImage::Animated {
+            frames: vec![
+                (solid_frame(2, 2, [255, 0, 0, 255]), delay),
+                (solid_frame(2, 2, [0, 0, 255, 255]), delay),
+            ],
+        };
+        // Zoomed 2x the image covers the whole 4x4 window
+        let buf = render_to_rgba(&loaded, 1, 2.0, (0, 0), 4, 4, 0);
+        assert!(buf.iter().all(|&p| p == 0x000000FF));
+        // Out-of-range frames clamp to the last one
+        assert_eq!(render_to_rgba(&loaded, 9, 2.0, (0, 0), 4, 4, 0), buf);
+
+        // An 8x2 image in a 4x2 window can pan two pixels each way
+        let wide = LoadedImage::Static(
+            RgbaImage::from_raw(
+                8,
+                2,
+                [[255, 0, 0, 255], [0, 255, 0, 255]]
+                    .iter()
+                    .flat_map(|&px| px.repeat(4))
+                    .collect::<Vec<u8>>()
+                    .repeat(2),
+            )
+            .unwrap(),
+        );
+        let left = render_to_rgba(&wide, 0, 1.0, (100, 0), 4, 2, 0);
+        assert!(left.iter().all(|&p| p == 0x00FF0000));
+        let right = render_to_rgba(&wide, 0, 1.0, (-100, 0), 4, 2, 0);
+        assert!(right.iter().all(|&p| p == 0x0000FF00));
+
+        // Degenerate sizes and zooms give an empty or background buffer
+        assert!(render_to_rgba(&wide, 0, 1.0, (0, 0), 0, 5, 7).is_empty());
+        assert_eq!(render_to_rgba(&wide, 0, 0.0, (0, 0), 2, 2, 7), vec![7; 4]);
+        assert_eq!(
+            render_to_rgba(&wide, 0, f64::NAN, (0, 0), 2, 2, 7),
+            vec![7; 4]
+        );
+    }
+
+    #[test]
+    fn test_scale_region_matches_full_scale() {
+        let mut img = RgbaImage::new(23, 17);
+        for (i, b) in img.data.iter_mut().enumerate() {
+            *b = (i * 37 % 251) as u8;
+        }
+        for (fx, fy) in [(3.7, 3.7), (0.5, 0.5), (1.0, 1.0), (2.0, 1.0)] {
+            let full = scale_by_factors(&img, fx, fy);
+            let (w, h) = full.dimensions();
+            for rect in [(0, 0, w, h), (w / 3, h / 4, w / 2, h / 2), (w - 1, 0, 1, 2)] {
+                let region = scale_region(&img, fx, fy, rect);
+                let (x, y, rw, rh) = rect;
+                let expected = crate::image_loader::crop_region(&full, x, y, rw, rh);
+                assert_eq!(region.dimensions(), (rect.2, rect.3));
+                assert!(region.data == expected.data, "{}x{} {:?}", fx, fy, rect);
+                // Banding does not change the result
+                let banded = resize_region_threaded(&img, w, h, rect, 3);
+                assert!(banded.data == region.data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_visible_scaled_rect() {
+        // 100x80 image in a 40x20 window, centered: x 30..70, y 30..50
+        let rect = |size, offset, margin| visible_scaled_rect(size, 40, 20, offset, margin);
+        assert_eq!(rect((100, 80), (0, 0), (0, 0)), Some((30, 30, 40, 20)));
+        // Margin widens the rect but stays within the image
+        assert_eq!(rect((100, 80), (0, 0), (50, 10)), Some((0, 20, 100, 40)));
+        // Panning right shows the left part
+        assert_eq!(rect((100, 80), (30, 0), (0, 0)), Some((0, 30, 40, 20)));
+        // Smaller than the window: the whole image
+        assert_eq!(rect((10, 8), (0, 0), (5, 5)), Some((0, 0, 10, 8)));
+        // Off-screen stays None regardless of margin
+        assert_eq!(rect((10, 8), (100, 0), (500, 500)), None);
+
+        // Compositing the visible region matches compositing the whole image
+        let mut img = RgbaImage::new(100, 80);
+        for (i, b) in img.data.iter_mut().enumerate() {
+            *b = (i % 256) as u8 | 1;
+        }
+        let (x, y, w, h) = rect((100, 80), (7, -5), (3, 3)).unwrap();
+        let region = crate::image_loader::crop_region(&img, x, y, w, h);
+        let bg = 0x00112233;
+        let full = composite_centered(&img, 40, 20, 7, -5, bg);
+        let part = composite_region(&region, (x, y), (100, 80), 40, 20, 7, -5, bg);
+        assert!(full == part);
+    }
+
+    #[test]
+    fn test_recomposite_panned_matches_full_composite() {
+        // Translucent pixels blend over the background, so those must come
+        // out the same in shifted and redrawn parts alike
+        let mut img = RgbaImage::new(60, 50);
+        for (i, b) in img.data.iter_mut().enumerate() {
+            *b = (i * 7 % 256) as u8;
+        }
+        let (at, scaled, bg) = ((5, 4), (80, 70), 0x00112233);
+        // Small steps in every direction, a half-window jump, and one past
+        // the window that falls back to a full redraw
+        let pans = [
+            (0, 0),
+            (3, 0),
+            (3, -2),
+            (-6, 5),
+            (0, 9),
+            (-20, 1),
+            (100, 0),
+            (2, 2),
+        ];
+        let mut buf = composite_region(&img, at, scaled, 40, 30, 0, 0, bg);
+        for pair in pans.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            recomposite_panned(&mut buf, &img, at, scaled, 40, 30, from, to, bg);
+            let full = composite_region(&img, at, scaled, 40, 30, to.0, to.1, bg);
+            assert!(buf == full, "pan {:?} -> {:?}", from, to);
+        }
+    }
+
+    #[test]
+    fn test_resize_samples_pixel_centers() {
+        // Red channel of row 0 after resizing a one-row pattern
+        let reds = |values: &[u8], dst_w: u32| {
+            let data = values.iter().flat_map(|&v| [v, 0, 0, 255]).collect();
+            let src = RgbaImage::from_raw(values.len() as u32, 1, data).unwrap();
+            let out = resize_rgba(&src, dst_w, 1);
+            out.data.chunks(4).map(|px| px[0]).collect::<Vec<u8>>()
+        };
+        // 2x upscale: destination centers fall a quarter pixel either side of
+        // each source center; the outermost ones clamp to the edge pixels
+        assert_eq!(reds(&[0, 200], 4), vec![0, 50, 150, 200]);
+        // 2x downscale: each output pixel is the mean of the two it covers
+        assert_eq!(reds(&[0, 100, 200, 100], 2), vec![50, 150]);
+
+        // Vertically the same, and a symmetric pattern stays symmetric
+        let data = [0u8, 200, 0].iter().flat_map(|&v| [v, v, v, 255]).collect();
+        let src = RgbaImage::from_raw(1, 3, data).unwrap();
+        let out = resize_rgba(&src, 1, 6);
+        let col: Vec<u8> = out.data.chunks(4).map(|px| px[0]).collect();
+        assert_eq!(col, vec![0, 50, 150, 150, 50, 0]);
+    }
 }
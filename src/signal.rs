@@ -0,0 +1,97 @@
+use rustix::pipe::{pipe_with, PipeFlags};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd};
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+const SIGINT: c_int = 2;
+const SIGTERM: c_int = 15;
+const SIG_DFL: usize = 0;
+
+extern "C" {
+    fn signal(signum: c_int, handler: usize) -> usize;
+}
+
+/// Write end of the installed pipe, or -1. A static because the handler
+/// has no other way to reach it.
+static WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_signal(_signum: c_int) {
+    let fd = WRITE_FD.load(Ordering::Relaxed);
+    if fd >= 0 {
+        // SAFETY: the fd stays open until SignalPipe::drop resets WRITE_FD.
+        // A full pipe already has a wakeup pending, so errors are ignored.
+        let _ = rustix::io::write(unsafe { BorrowedFd::borrow_raw(fd) }, &[0]);
+    }
+}
+
+/// Self-pipe for SIGINT and SIGTERM: the handler only writes a byte, and the
+/// main loop polls the read end next to the Wayland fd and shuts down
+/// normally, so Drop cleanup runs instead of the process dying mid-frame.
+pub struct SignalPipe {
+    read: OwnedFd,
+    _write: OwnedFd,
+}
+
+impl SignalPipe {
+    /// Create the pipe and install the handlers. Only one may exist at a time.
+    pub fn install() -> Result<Self, String> {
+        let (read, write) = pipe_with(PipeFlags::CLOEXEC | PipeFlags::NONBLOCK)
+            .map_err(|e| format!("pipe failed: {}", e))?;
+        WRITE_FD.store(write.as_raw_fd(), Ordering::Relaxed);
+        let handler = on_signal as extern "C" fn(c_int) as usize;
+        unsafe {
+            signal(SIGINT, handler);
+            signal(SIGTERM, handler);
+        }
+        Ok(Self {
+            read,
+            _write: write,
+        })
+    }
+
+    /// File descriptor that becomes readable once a signal arrived.
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        self.read.as_fd()
+    }
+
+    /// Drain the pipe and return whether a signal arrived since the last call.
+    pub fn received(&self) -> bool {
+        let mut buf = [0u8; 16];
+        let mut received = false;
+        while let Ok(n) = rustix::io::read(&self.read, &mut buf) {
+            if n == 0 {
+                break;
+            }
+            received = true;
+        }
+        received
+    }
+}
+
+impl Drop for SignalPipe {
+    fn drop(&mut self) {
+        unsafe {
+            signal(SIGINT, SIG_DFL);
+            signal(SIGTERM, SIG_DFL);
+        }
+        WRITE_FD.store(-1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C" {
+        fn raise(signum: c_int) -> c_int;
+    }
+
+    #[test]
+    fn test_signal_wakes_pipe() {
+        let pipe = SignalPipe::install().unwrap();
+        assert!(!pipe.received());
+        unsafe { raise(SIGTERM) };
+        assert!(pipe.received());
+        assert!(!pipe.received());
+    }
+}
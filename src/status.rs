@@ -2,35 +2,190 @@ use crate::font;
 use crate::render;
 use std::fs;
 use std::path::Path;
+use std::time::Duration;
 
-/// Format the status text for a given image file.
-/// Format: "filename.jpg | 1920x1080 | 2.4 MB | 2025-01-15 14:30 | [3/42]"
-pub fn format_status(path: &Path, img_w: u32, img_h: u32, index: usize, total: usize) -> String {
-    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("?");
+/// Window edge the status bar is drawn at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusPosition {
+    Top,
+    Bottom,
+}
 
-    let size_str = match fs::metadata(path) {
-        Ok(meta) => format_file_size(meta.len()),
-        Err(_) => "? B".to_string(),
-    };
+impl StatusPosition {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "top" => Some(Self::Top),
+            "bottom" => Some(Self::Bottom),
+            _ => None,
+        }
+    }
+}
 
-    let mtime_str = match fs::metadata(path) {
-        Ok(meta) => match meta.modified() {
-            Ok(t) => format_system_time(t),
-            Err(_) => "?".to_string(),
-        },
-        Err(_) => "?".to_string(),
+/// When the status bar is shown in the viewer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusMode {
+    Always,
+    /// Shown for a few seconds after a key press or image change.
+    Auto,
+    /// Hidden except while an error message is pending.
+    Off,
+}
+
+impl StatusMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "always" => Some(Self::Always),
+            "auto" => Some(Self::Auto),
+            "off" => Some(Self::Off),
+            _ => None,
+        }
+    }
+
+    /// The mode after this one when cycling with `t`.
+    pub fn next(self) -> Self {
+        match self {
+            Self::Always => Self::Auto,
+            Self::Auto => Self::Off,
+            Self::Off => Self::Always,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Always => "always",
+            Self::Auto => "auto",
+            Self::Off => "off",
+        }
+    }
+}
+
+/// One piece of the status text, in the order given by the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusField {
+    /// File name, e.g. "photo.jpg".
+    Name,
+    /// Pixel size, e.g. "1920x1080".
+    Dimensions,
+    /// File size, e.g. "2.4 MB".
+    Size,
+    /// Modification time, e.g. "2025-01-15 14:30".
+    Date,
+    /// Position in the image list, e.g. "[3/42]".
+    Index,
+    /// Display scale relative to the image pixels, e.g. "50%".
+    Zoom,
+    /// Image format from the file extension, e.g. "JPEG".
+    Format,
+}
+
+/// Fields shown when the config does not set `status-fields`.
+pub const DEFAULT_STATUS_FIELDS: &[StatusField] = &[
+    StatusField::Name,
+    StatusField::Dimensions,
+    StatusField::Size,
+    StatusField::Date,
+    StatusField::Index,
+];
+
+impl StatusField {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "name" => Some(Self::Name),
+            "dimensions" => Some(Self::Dimensions),
+            "size" => Some(Self::Size),
+            "date" => Some(Self::Date),
+            "index" => Some(Self::Index),
+            "zoom" => Some(Self::Zoom),
+            "format" => Some(Self::Format),
+            _ => None,
+        }
+    }
+
+    /// Parse a comma-separated field list such as `name,dimensions,zoom`.
+    /// `none` (or an empty list) hides the file details; errors still show.
+    pub fn parse_list(s: &str) -> Option<Vec<Self>> {
+        let s = s.trim();
+        if s.is_empty() || s == "none" {
+            return Some(Vec::new());
+        }
+        s.split(',').map(|f| Self::parse(f.trim())).collect()
+    }
+}
+
+/// Format the status text for a given image file from a list of fields.
+/// The default fields give "filename.jpg | 1920x1080 | 2.4 MB | 2025-01-15 14:30 | [3/42]".
+/// `zoom` is the display scale (1.0 = one screen pixel per image pixel).
+pub fn format_status(
+    fields: &[StatusField],
+    path: &Path,
+    img_w: u32,
+    img_h: u32,
+    index: usize,
+    total: usize,
+    zoom: f64,
+) -> String {
+    // Only stat the file when a field needs it
+    let meta = if fields.contains(&StatusField::Size) || fields.contains(&StatusField::Date) {
+        fs::metadata(path).ok()
+    } else {
+        None
     };
 
-    format!(
-        "{} | {}x{} | {} | {} | [{}/{}]",
-        name,
-        img_w,
-        img_h,
-        size_str,
-        mtime_str,
-        index + 1,
-        total
-    )
+    let parts: Vec<String> = fields
+        .iter()
+        .map(|field| match field {
+            StatusField::Name => path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("?")
+                .to_string(),
+            StatusField::Dimensions => format!("{}x{}", img_w, img_h),
+            StatusField::Size => match &meta {
+                Some(meta) => format_file_size(meta.len()),
+                None => "? B".to_string(),
+            },
+            StatusField::Date => match meta.as_ref().map(|m| m.modified()) {
+                Some(Ok(t)) => format_system_time(t),
+                _ => "?".to_string(),
+            },
+            StatusField::Index => format!("[{}/{}]", index + 1, total),
+            StatusField::Zoom => format!("{:.0}%", zoom * 100.0),
+            StatusField::Format => format_name(path),
+        })
+        .collect();
+    parts.join(" | ")
+}
+
+/// Position in an animation, appended to the status text of animated
+/// images: "frame 3/24 | 2.4s loop", plus "| 2x speed" when played at
+/// another speed than recorded. The loop length is the one at that speed.
+/// `frame` is zero-based.
+pub fn format_animation(frame: usize, count: usize, loop_duration: Duration, speed: f64) -> String {
+    let text = format!(
+        "frame {}/{} | {:.1}s loop",
+        frame + 1,
+        count,
+        loop_duration.as_secs_f64() / speed
+    );
+    if speed == 1.0 {
+        text
+    } else {
+        format!("{} | {}x speed", text, speed)
+    }
+}
+
+/// Human-readable format name from the file extension.
+fn format_name(path: &Path) -> String {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("?")
+        .to_ascii_uppercase();
+    match ext.as_str() {
+        "JPG" | "JPE" => "JPEG".to_string(),
+        "TIF" => "TIFF".to_string(),
+        _ => ext,
+    }
 }
 
 pub(crate) fn format_file_size(bytes: u64) -> String {
@@ -84,19 +239,47 @@ pub(crate) fn days_to_date(days: u64) -> (u64, u64, u64) {
     (y, m, d)
 }
 
-/// Draw the status bar overlay onto an XRGB buffer.
-pub fn draw_status_bar(buf: &mut [u32], buf_w: u32, buf_h: u32, text: &str) {
-    if buf_w == 0 || buf_h == 0 {
+/// Convert (year, month, day) to days since Unix epoch; the inverse of
+/// `days_to_date`. Dates before 1970 are not supported.
+pub(crate) fn date_to_days(year: u64, month: u64, day: u64) -> u64 {
+    // days_from_civil, from the same source as days_to_date
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12; // March = 0
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Draw the status bar overlay onto an XRGB buffer, at the bottom or top
+/// edge of the window. Empty text draws nothing.
+pub fn draw_status_bar(
+    buf: &mut [u32],
+    buf_w: u32,
+    buf_h: u32,
+    text: &str,
+    position: StatusPosition,
+) {
+    if buf_w == 0 || buf_h == 0 || text.is_empty() {
         return;
     }
 
     let bar_h = font::GLYPH_H + 6; // 3px padding top and bottom
-    let bar_y = buf_h.saturating_sub(bar_h);
+
+    // Skip the bar when the window cannot fit one character of it
+    if buf_h < bar_h || buf_w < font::GLYPH_W + 12 {
+        return;
+    }
+    let bar_y = match position {
+        StatusPosition::Top => 0,
+        StatusPosition::Bottom => buf_h - bar_h,
+    };
 
     // Draw semi-transparent dark overlay
     let text_pixel_width = text.len() as u32 * font::GLYPH_W + 12; // 6px padding each side
     let bar_w = text_pixel_width.min(buf_w);
-    render::draw_overlay(buf, buf_w, 0, bar_y, bar_w, bar_h, 160);
+    render::draw_overlay_rounded(buf, buf_w, 0, bar_y, bar_w, bar_h, 160, 4);
 
     // Draw text
     let text_x = 6;
@@ -108,6 +291,18 @@ pub fn draw_status_bar(buf: &mut [u32], buf_w: u32, buf_h: u32, text: &str) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_status_mode_cycle() {
+        assert_eq!(StatusMode::parse("auto"), Some(StatusMode::Auto));
+        assert_eq!(StatusMode::parse("hidden"), None);
+        let mut mode = StatusMode::Always;
+        for expected in ["auto", "off", "always"] {
+            mode = mode.next();
+            assert_eq!(mode.name(), expected);
+            assert_eq!(StatusMode::parse(mode.name()), Some(mode));
+        }
+    }
+
     #[test]
     fn test_format_file_size_bytes() {
         assert_eq!(format_file_size(0), "0 B");
@@ -157,4 +352,82 @@ mod tests {
         let (y, m, d) = days_to_date(364);
         assert_eq!((y, m, d), (1970, 12, 31));
     }
+
+    #[test]
+    fn test_date_to_days_known() {
+        assert_eq!(date_to_days(1970, 1, 1), 0);
+        assert_eq!(date_to_days(2000, 1, 1), 10957);
+        assert_eq!(date_to_days(2000, 2, 29), 11016);
+        assert_eq!(date_to_days(1970, 12, 31), 364);
+    }
+
+    #[test]
+    fn test_date_to_days_roundtrip() {
+        for days in (0..40_000).step_by(97) {
+            let (y, m, d) = days_to_date(days);
+            assert_eq!(date_to_days(y, m, d), days);
+        }
+    }
+
+    #[test]
+    fn test_status_tiny_dimensions() {
+        let path = Path::new("/nonexistent/x.png");
+        let fields = DEFAULT_STATUS_FIELDS;
+        assert_eq!(
+            format_status(fields, path, 0, 0, 0, 1, 1.0),
+            "x.png | 0x0 | ? B | ? | [1/1]"
+        );
+        assert_eq!(
+            format_status(fields, path, 1, 1, 0, 1, 1.0),
+            "x.png | 1x1 | ? B | ? | [1/1]"
+        );
+
+        // Too small for the bar: the buffer is left untouched
+        for (w, h) in [(0, 0), (1, 1), (10, 10), (300, 5), (5, 300)] {
+            let mut buf = vec![0x00FFFFFF; (w * h) as usize];
+            draw_status_bar(&mut buf, w, h, "x.png | 1x1", StatusPosition::Bottom);
+            assert!(buf.iter().all(|&p| p == 0x00FFFFFF), "{}x{}", w, h);
+        }
+        let mut buf = vec![0x00FFFFFF; 40 * 30];
+        draw_status_bar(&mut buf, 40, 30, "x.png | 1x1", StatusPosition::Bottom);
+        assert_ne!(buf[29 * 40 + 20], 0x00FFFFFF);
+    }
+
+    #[test]
+    fn test_status_fields() {
+        let path = Path::new("/nonexistent/photo.jpg");
+        let fields = StatusField::parse_list("zoom, format,index,dimensions").unwrap();
+        assert_eq!(
+            format_status(&fields, path, 640, 480, 2, 9, 0.5),
+            "50% | JPEG | [3/9] | 640x480"
+        );
+        assert_eq!(format_status(&[], path, 640, 480, 2, 9, 0.5), "");
+        assert_eq!(
+            format_animation(2, 24, Duration::from_millis(2400), 1.0),
+            "frame 3/24 | 2.4s loop"
+        );
+        assert_eq!(
+            format_animation(0, 2, Duration::from_millis(200), 0.25),
+            "frame 1/2 | 0.8s loop | 0.25x speed"
+        );
+        assert_eq!(StatusField::parse_list("none"), Some(vec![]));
+        assert_eq!(StatusField::parse_list("name,bogus"), None);
+        assert_eq!(StatusField::parse_list("name,"), None);
+        assert_eq!(StatusPosition::parse("top"), Some(StatusPosition::Top));
+        assert_eq!(StatusPosition::parse("left"), None);
+    }
+
+    #[test]
+    fn test_status_bar_position() {
+        let (w, h) = (60, 60);
+        let mut buf = vec![0x00FFFFFF; w * h];
+        draw_status_bar(&mut buf, w as u32, h as u32, "x", StatusPosition::Top);
+        assert_ne!(buf[font::GLYPH_W as usize], 0x00FFFFFF);
+        assert_eq!(buf[(h - 1) * w + font::GLYPH_W as usize], 0x00FFFFFF);
+
+        // Empty text leaves the buffer alone
+        let mut buf = vec![0x00FFFFFF; w * h];
+        draw_status_bar(&mut buf, w as u32, h as u32, "", StatusPosition::Bottom);
+        assert!(buf.iter().all(|&p| p == 0x00FFFFFF));
+    }
 }
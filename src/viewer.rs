@@ -1,27 +1,58 @@
+use crate::config::ScaleMode;
 use crate::font;
-use crate::image_loader::LoadedImage;
-use crate::image_loader::RgbaImage;
+use crate::image_loader::{self, LoadedImage, RgbaImage};
 use crate::input::PanDirection;
 use crate::render;
-use crate::status;
-use std::path::Path;
+use crate::status::{self, StatusField, StatusPosition};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
 /// Zoom step factor.
 const ZOOM_STEP: f64 = 1.25;
+/// Largest magnification, in window pixels per image pixel.
+const MAX_DISPLAY_SCALE: f64 = 64.0;
+/// Largest zoomed image in pixels: no more than the loader allows for a
+/// decoded one, so zooming in can never allocate an absurd buffer.
+const MAX_SCALED_PIXELS: f64 = image_loader::MAX_PIXEL_COUNT as f64;
 
 /// Constant pan speed in pixels per second.
 const PAN_SPEED: f64 = 600.0;
+/// Pan distance per key press when animations are disabled.
+const PAN_STEP: f64 = 100.0;
+/// How far elastic panning lets the image run past its edges, in pixels.
+const ELASTIC_OVERSCROLL: f64 = 80.0;
+/// Rate (per second) at which an elastic overshoot decays back to the edge.
+const ELASTIC_SPRING: f64 = 12.0;
 /// Target frame interval for pan animation (~60fps).
 const PAN_FRAME_INTERVAL: Duration = Duration::from_millis(16);
+/// Range of the animation speed multiplier (`[`/`]`, `--anim-speed`).
+pub const ANIM_SPEED_MIN: f64 = 0.125;
+pub const ANIM_SPEED_MAX: f64 = 8.0;
+/// Scaled images up to this many window areas are scaled whole, so panning
+/// only re-composites. Larger ones are scaled around the visible part only.
+const FULL_SCALE_MAX_WINDOWS: u64 = 4;
+/// Shown instead of a blank frame when the image misses the window.
+const OFFSCREEN_HINT: &str = "Image off-screen - press 0 to reset";
 
 /// Cache key for the scaled image: (actual_scale_bits, win_w, win_h, frame_index).
 /// We store scale as u64 bits to get exact equality checks.
 type ScaleCacheKey = (u64, u32, u32, usize);
 
+/// A fit-to-window scaled image taken out of the viewer, so the app can keep
+/// it for an image it navigates away from and hand it back on return.
+pub struct FitScaled {
+    image: RgbaImage,
+    key: ScaleCacheKey,
+    rect: (u32, u32, u32, u32),
+}
+
 pub struct Viewer {
     /// Current zoom level (1.0 = fit-to-window).
     zoom: f64,
+    /// Upper bound for `zoom` given the current image and window, as of
+    /// the last render.
+    max_zoom: f64,
     /// Pan offset from center (integer, for rendering).
     pan_x: i32,
     pan_y: i32,
@@ -32,46 +63,131 @@ pub struct Viewer {
     pan_active: [bool; 4],
     /// Timestamp of last pan animation tick.
     last_pan_tick: Option<Instant>,
+    /// Let panning overshoot the edges and spring back (`elastic-pan`).
+    elastic_pan: bool,
+    /// Largest pan offsets that keep the image edges in the window, as of
+    /// the last render.
+    max_pan: (i32, i32),
+    /// Window rectangle `(x, y, w, h)` the image was drawn at by the last
+    /// render; `None` if it was not drawn.
+    image_rect: Option<render::WindowRect>,
     /// Fit-to-window scale factor for current image + window size.
     fit_scale: f64,
 
     /// Cached scaled image to avoid re-scaling every frame during panning.
+    /// At high zoom it holds only `scaled_rect` of the scaled image: the
+    /// visible part plus a margin, so memory stays bounded by the window.
     scaled_cache: Option<RgbaImage>,
     scaled_cache_key: ScaleCacheKey,
+    scaled_rect: (u32, u32, u32, u32),
+    /// Cached composited window buffer (image on background, no overlays),
+    /// valid for `scaled_cache` at the stored pan offset. Lets overlay-only
+    /// redraws (toast/error dismissal) skip compositing.
+    composite_cache: Option<Vec<u32>>,
+    composite_pan: (i32, i32),
+    /// Display tone adjustments and `scaled_cache` with them applied.
+    tone: render::Tone,
+    toned_cache: Option<RgbaImage>,
+    /// Display filter and the current frame (by index) with it applied,
+    /// the source for `scaled_cache`. Kept across navigation.
+    filter: render::Filter,
+    filtered_cache: Option<(usize, RgbaImage)>,
 
     // Animation state
     pub current_frame: usize,
     pub next_frame_time: Option<Instant>,
+    /// Playback speed multiplier; frame delays are divided by it.
+    anim_speed: f64,
 
     /// Whether to scale small images up to fit the window.
     fit_to_window: bool,
     /// Flag: next render should set zoom to display at 1:1 pixel size.
     actual_size: bool,
+    /// Open every image at 1:1 pixel size (`scale = actual` in the config).
+    default_actual_size: bool,
+    /// `fit_to_window` before `toggle_actual_size` switched to 1:1, restored
+    /// when it switches back.
+    fit_before_actual: Option<bool>,
+    /// Background color behind the image.
+    bg_color: u32,
+    /// Edge and contents of the status bar.
+    status_position: StatusPosition,
+    status_fields: Vec<StatusField>,
+    /// Print proofing view: white letterbox with crop marks (`--proof`).
+    proof: bool,
+    /// Pixel aspect ratio (width / height) of the current image; 1.0 for square pixels.
+    pixel_aspect: f64,
+    /// Smooth panning and animated image playback. When false, pan moves in
+    /// fixed steps and animated images show only their first frame.
+    animations: bool,
+    /// Composition grid over the displayed image, and its columns x rows.
+    show_grid: bool,
+    grid_divisions: (u32, u32),
+
+    // Vertical strip state: images stacked top to bottom at window width,
+    // scrolled continuously across image boundaries.
+    strip_mode: bool,
+    /// Distance from the top of the current image to the top of the window.
+    strip_offset: f64,
+    /// Width-fitted images of the visible strip, scaled for `strip_cache_w`.
+    strip_cache: HashMap<PathBuf, RgbaImage>,
+    strip_cache_w: u32,
 
     // EXIF overlay state
     show_exif: bool,
     exif_lines: Vec<String>,
+    exif_tags: Vec<(String, String)>,
+    /// Manual rotation of the shown image in quarter turns clockwise.
+    exif_turns: u32,
 }
 
 impl Viewer {
     pub fn new() -> Self {
         Self {
             zoom: 1.0,
+            max_zoom: f64::INFINITY,
             pan_x: 0,
             pan_y: 0,
             pan_x_f: 0.0,
             pan_y_f: 0.0,
             pan_active: [false; 4],
             last_pan_tick: None,
+            elastic_pan: false,
+            max_pan: (0, 0),
+            image_rect: None,
             fit_scale: 1.0,
             scaled_cache: None,
             scaled_cache_key: (0, 0, 0, 0),
+            scaled_rect: (0, 0, 0, 0),
+            composite_cache: None,
+            composite_pan: (0, 0),
+            tone: render::Tone::NEUTRAL,
+            toned_cache: None,
+            filter: render::Filter::None,
+            filtered_cache: None,
             current_frame: 0,
             next_frame_time: None,
+            anim_speed: 1.0,
             fit_to_window: false,
             actual_size: false,
+            default_actual_size: false,
+            fit_before_actual: None,
+            bg_color: render::BG_COLOR,
+            status_position: StatusPosition::Bottom,
+            status_fields: status::DEFAULT_STATUS_FIELDS.to_vec(),
+            proof: false,
+            pixel_aspect: 1.0,
+            animations: true,
+            show_grid: false,
+            grid_divisions: (3, 3),
+            strip_mode: false,
+            strip_offset: 0.0,
+            strip_cache: HashMap::new(),
+            strip_cache_w: 0,
             show_exif: false,
             exif_lines: Vec::new(),
+            exif_tags: Vec::new(),
+            exif_turns: 0,
         }
     }
 
@@ -83,10 +199,252 @@ impl Viewer {
         self.pan_y_f = 0.0;
         self.pan_active = [false; 4];
         self.last_pan_tick = None;
-        self.scaled_cache = None;
+        self.strip_offset = 0.0;
+        self.tone = render::Tone::NEUTRAL;
+        self.invalidate_cache();
         self.current_frame = 0;
         self.next_frame_time = None;
         self.show_exif = false;
+        self.actual_size = self.default_actual_size;
+    }
+
+    /// Apply the configured initial scale mode.
+    pub fn set_scale_mode(&mut self, mode: ScaleMode) {
+        self.fit_to_window = mode == ScaleMode::FitWindow;
+        self.default_actual_size = mode == ScaleMode::Actual;
+        self.actual_size = self.default_actual_size;
+        self.invalidate_cache();
+    }
+
+    /// Set the pixel aspect ratio of the current image.
+    pub fn set_pixel_aspect(&mut self, aspect: f64) {
+        if aspect != self.pixel_aspect {
+            self.pixel_aspect = aspect;
+            self.invalidate_cache();
+        }
+    }
+
+    /// Flip the pixel aspect ratio after a 90° rotation.
+    pub fn rotate_pixel_aspect(&mut self) {
+        self.set_pixel_aspect(1.0 / self.pixel_aspect);
+    }
+
+    pub fn set_animations(&mut self, enabled: bool) {
+        self.animations = enabled;
+    }
+
+    pub fn animations_enabled(&self) -> bool {
+        self.animations
+    }
+
+    pub fn set_anim_speed(&mut self, speed: f64) {
+        self.anim_speed = speed.clamp(ANIM_SPEED_MIN, ANIM_SPEED_MAX);
+    }
+
+    /// Double (`faster`) or halve the animation speed within its range and
+    /// return the new multiplier. The frame already waiting keeps its delay.
+    pub fn step_anim_speed(&mut self, faster: bool) -> f64 {
+        let factor = if faster { 2.0 } else { 0.5 };
+        self.set_anim_speed(self.anim_speed * factor);
+        self.anim_speed
+    }
+
+    /// A frame's delay at the current playback speed, no shorter than the
+    /// configured `min-frame-duration`.
+    fn scaled_delay(&self, delay: Duration) -> Duration {
+        delay
+            .div_f64(self.anim_speed)
+            .max(image_loader::min_frame_duration())
+    }
+
+    pub fn set_elastic_pan(&mut self, enabled: bool) {
+        self.elastic_pan = enabled;
+    }
+
+    /// Elastic edges need the continuous pan animation to spring back.
+    fn elastic(&self) -> bool {
+        self.elastic_pan && self.animations && !self.strip_mode
+    }
+
+    pub fn set_background(&mut self, color: u32) {
+        self.bg_color = color;
+        self.composite_cache = None;
+    }
+
+    /// Choose where the status bar is drawn and which fields it shows.
+    pub fn set_status_layout(&mut self, position: StatusPosition, fields: Vec<StatusField>) {
+        self.status_position = position;
+        self.status_fields = fields;
+    }
+
+    pub fn status_position(&self) -> StatusPosition {
+        self.status_position
+    }
+
+    /// Status text for an image, with the current frame of an animation
+    /// (`animation` is its frame count and loop duration) and
+    /// `error_message` appended if present.
+    #[allow(clippy::too_many_arguments)]
+    fn status_text(
+        &self,
+        path: &Path,
+        img_w: u32,
+        img_h: u32,
+        index: usize,
+        total: usize,
+        zoom: f64,
+        animation: Option<(usize, Duration)>,
+        error_message: Option<&str>,
+    ) -> String {
+        let mut text =
+            status::format_status(&self.status_fields, path, img_w, img_h, index, total, zoom);
+        // An animation without frames has nothing to show
+        let animation = animation.filter(|&(count, _)| count > 0 && !text.is_empty());
+        if let Some((count, loop_duration)) = animation {
+            let frame = self.current_frame.min(count - 1);
+            text = format!(
+                "{} | {}",
+                text,
+                status::format_animation(frame, count, loop_duration, self.anim_speed)
+            );
+        }
+        match error_message {
+            Some(err) if text.is_empty() => err.to_string(),
+            Some(err) => format!("{} | {}", text, err),
+            None => text,
+        }
+    }
+
+    /// Show single images on white with crop and center marks, as on a
+    /// print proof. The strip and gallery keep the normal background.
+    pub fn set_proof(&mut self, enabled: bool) {
+        self.proof = enabled;
+        self.composite_cache = None;
+    }
+
+    /// Letterbox color of the single-image view.
+    fn letterbox_color(&self) -> u32 {
+        if self.proof {
+            render::PROOF_BG_COLOR
+        } else {
+            self.bg_color
+        }
+    }
+
+    /// Drop the cached scaled and composited image, e.g. after the image
+    /// data changed in place (rotation).
+    pub fn invalidate_cache(&mut self) {
+        self.scaled_cache = None;
+        self.toned_cache = None;
+        self.filtered_cache = None;
+        self.composite_cache = None;
+    }
+
+    /// Take the scaled image of the last render if it was at the default
+    /// fit zoom and unfiltered; `None` otherwise.
+    pub fn take_fit_scaled(&mut self) -> Option<FitScaled> {
+        if self.zoom != 1.0 || self.filter != render::Filter::None {
+            return None;
+        }
+        Some(FitScaled {
+            image: self.scaled_cache.take()?,
+            key: self.scaled_cache_key,
+            rect: self.scaled_rect,
+        })
+    }
+
+    /// Reuse a scaled image from `take_fit_scaled` for the next render. It
+    /// is only used if the image, window size and scale still match.
+    pub fn restore_fit_scaled(&mut self, scaled: FitScaled) {
+        if self.filter != render::Filter::None {
+            return;
+        }
+        self.invalidate_cache();
+        self.scaled_cache = Some(scaled.image);
+        self.scaled_cache_key = scaled.key;
+        self.scaled_rect = scaled.rect;
+    }
+
+    /// Switch to the next display filter and return its name.
+    pub fn cycle_filter(&mut self) -> &'static str {
+        self.filter = self.filter.next();
+        self.invalidate_cache();
+        self.filter.name()
+    }
+
+    /// Change brightness, contrast and gamma by the given steps (clamped to
+    /// sane ranges); all zero resets them.
+    pub fn adjust_tone(&mut self, brightness: f64, contrast: f64, gamma: f64) {
+        self.tone = if brightness == 0.0 && contrast == 0.0 && gamma == 0.0 {
+            render::Tone::NEUTRAL
+        } else {
+            render::Tone {
+                brightness: (self.tone.brightness + brightness).clamp(-1.0, 1.0),
+                contrast: (self.tone.contrast + contrast).clamp(0.0, 4.0),
+                gamma: (self.tone.gamma + gamma).clamp(0.1, 4.0),
+            }
+        };
+        self.toned_cache = None;
+        self.composite_cache = None;
+    }
+
+    /// Current tone adjustments for the toast, e.g.
+    /// "Brightness +0.10  Contrast 1.00  Gamma 1.00".
+    pub fn tone_label(&self) -> String {
+        format!(
+            "Brightness {:+.2}  Contrast {:.2}  Gamma {:.2}",
+            self.tone.brightness, self.tone.contrast, self.tone.gamma
+        )
+    }
+
+    pub fn toggle_grid(&mut self) {
+        self.show_grid = !self.show_grid;
+    }
+
+    pub fn set_grid_divisions(&mut self, cols: u32, rows: u32) {
+        self.grid_divisions = (cols.max(1), rows.max(1));
+    }
+
+    /// Switch between single-image display and the vertical strip.
+    pub fn set_strip_mode(&mut self, enabled: bool) {
+        self.strip_mode = enabled;
+        self.strip_cache.clear();
+        self.next_frame_time = None;
+        self.reset_view();
+    }
+
+    pub fn is_strip_mode(&self) -> bool {
+        self.strip_mode
+    }
+
+    pub fn strip_offset(&self) -> f64 {
+        self.strip_offset
+    }
+
+    pub fn set_strip_offset(&mut self, offset: f64) {
+        self.strip_offset = offset;
+    }
+
+    /// Drop the strip's scaled copy of `path` (e.g. after rotating it).
+    pub fn invalidate_strip_image(&mut self, path: &Path) {
+        self.strip_cache.remove(path);
+    }
+
+    /// Scale of a `src_w` wide image in the strip: fit the window width,
+    /// enlarging small images only with fit-to-window.
+    fn strip_scale(&self, src_w: u32, win_w: u32) -> f64 {
+        let scale = win_w as f64 / src_w.max(1) as f64;
+        if self.fit_to_window {
+            scale
+        } else {
+            scale.min(1.0)
+        }
+    }
+
+    /// Displayed height of `frame` in the strip.
+    pub fn strip_height(&self, frame: &RgbaImage, win_w: u32) -> u32 {
+        let scale = self.strip_scale(frame.width, win_w);
+        ((frame.height as f64 * scale).round() as u32).max(1)
     }
 
     pub fn toggle_exif(&mut self) {
@@ -102,42 +460,141 @@ impl Viewer {
     }
 
     pub fn set_exif_data(&mut self, tags: Vec<(String, String)>) {
-        self.exif_lines = if tags.is_empty() {
-            vec!["No EXIF data".to_string()]
-        } else {
-            tags.into_iter()
-                .map(|(label, value)| format!("{}: {}", label, value))
-                .collect()
-        };
+        self.exif_tags = tags;
+        self.exif_turns = 0;
+        self.update_exif_lines();
+    }
+
+    /// Show the Orientation tag as the net orientation after the user rotated
+    /// the image by `quarter_turns` clockwise.
+    pub fn set_exif_rotation(&mut self, quarter_turns: u32) {
+        self.exif_turns = quarter_turns % 4;
+        self.update_exif_lines();
+    }
+
+    fn update_exif_lines(&mut self) {
+        if self.exif_tags.is_empty() {
+            self.exif_lines = vec!["No EXIF data".to_string()];
+            return;
+        }
+        let turns = self.exif_turns;
+        self.exif_lines = self
+            .exif_tags
+            .iter()
+            .map(|(label, value)| {
+                let orientation = image_loader::orientation_from_name(value);
+                match orientation {
+                    Some(orientation) if label == "Orientation" && turns != 0 => {
+                        let net = image_loader::rotated_orientation(orientation, turns);
+                        let net_name = image_loader::orientation_name(net).unwrap_or("?");
+                        format!("{}: {} (file: {})", label, net_name, value)
+                    }
+                    _ => format!("{}: {}", label, value),
+                }
+            })
+            .collect();
     }
 
     pub fn zoom_in(&mut self) {
-        self.zoom *= ZOOM_STEP;
+        self.set_zoom_keeping_center((self.zoom * ZOOM_STEP).min(self.max_zoom));
     }
 
     pub fn zoom_out(&mut self) {
-        self.zoom = (self.zoom / ZOOM_STEP).max(1.0);
+        self.set_zoom_keeping_center((self.zoom / ZOOM_STEP).max(1.0));
         if self.zoom <= 1.0 {
             self.stop_all_pan();
         }
     }
 
+    /// Change the zoom around the window center: the pan offset scales with
+    /// the zoom, so the part of the image being inspected stays in view.
+    fn set_zoom_keeping_center(&mut self, zoom: f64) {
+        let ratio = zoom / self.zoom;
+        self.zoom = zoom;
+        self.pan_x_f *= ratio;
+        self.pan_y_f *= ratio;
+        self.pan_x = self.pan_x_f.round() as i32;
+        self.pan_y = self.pan_y_f.round() as i32;
+    }
+
     pub fn zoom_reset(&mut self) {
         self.zoom = 1.0;
         self.stop_all_pan();
     }
 
+    /// Zoom around the window center so `frame` shows at `percent` of its
+    /// pixel size in a `win_w`x`win_h` window, but not below fitting the
+    /// window or above the zoom limit. Returns the percentage actually
+    /// applied.
+    pub fn set_zoom_percent(
+        &mut self,
+        frame: &RgbaImage,
+        percent: f64,
+        win_w: u32,
+        win_h: u32,
+    ) -> f64 {
+        // The window or image may have changed since the last render
+        let (src_w, src_h) = frame.dimensions();
+        if src_w > 0 && src_h > 0 && win_w > 0 && win_h > 0 {
+            self.fit_scale = self.fit_scale_for(src_w, src_h, win_w, win_h);
+            let display_w = src_w as f64 * self.pixel_aspect;
+            self.max_zoom = max_zoom(display_w, src_h as f64, self.fit_scale);
+        }
+        self.actual_size = false;
+        self.set_zoom_keeping_center(zoom_for_percent(percent, self.fit_scale, self.max_zoom));
+        if self.zoom <= 1.0 {
+            self.stop_all_pan();
+        }
+        self.fit_scale * self.zoom * 100.0
+    }
+
+    /// Fit-to-window scale of a `src_w`x`src_h` image. Non-square pixels
+    /// are stretched horizontally by pixel_aspect on top of this scale.
+    fn fit_scale_for(&self, src_w: u32, src_h: u32, win_w: u32, win_h: u32) -> f64 {
+        let display_w = src_w as f64 * self.pixel_aspect;
+        let scale = (win_w as f64 / display_w).min(win_h as f64 / src_h as f64);
+        if self.fit_to_window {
+            scale
+        } else {
+            scale.min(1.0)
+        }
+    }
+
     pub fn toggle_fit_to_window(&mut self) {
         self.fit_to_window = !self.fit_to_window;
         self.zoom = 1.0;
         self.stop_all_pan();
-        self.scaled_cache = None;
+        self.invalidate_cache();
     }
 
     pub fn zoom_actual_size(&mut self) {
         self.fit_to_window = false;
         self.actual_size = true;
-        self.scaled_cache = None;
+        self.invalidate_cache();
+    }
+
+    /// Switch between 1:1 and fitting the image to the window, returning to
+    /// the fit mode (with or without upscaling) in use before 1:1. Returns
+    /// the new mode for the toast.
+    pub fn toggle_actual_size(&mut self) -> &'static str {
+        let at_actual = self.actual_size || (self.fit_scale * self.zoom - 1.0).abs() < 1e-9;
+        if !at_actual {
+            self.fit_before_actual = Some(self.fit_to_window);
+            self.zoom_actual_size();
+            return "1:1";
+        }
+        if let Some(fit_to_window) = self.fit_before_actual.take() {
+            self.fit_to_window = fit_to_window;
+        }
+        self.actual_size = false;
+        self.zoom = 1.0;
+        self.stop_all_pan();
+        self.invalidate_cache();
+        if self.fit_to_window {
+            "fit to window"
+        } else {
+            "fit"
+        }
     }
 
     /// Start panning in the given direction.
@@ -145,6 +602,34 @@ impl Viewer {
         if self.zoom <= 1.0 {
             return;
         }
+        if !self.animations {
+            // Jump by a fixed step instead of panning continuously
+            match dir {
+                PanDirection::Left => self.pan_x_f += PAN_STEP,
+                PanDirection::Right => self.pan_x_f -= PAN_STEP,
+                PanDirection::Up => self.pan_y_f += PAN_STEP,
+                PanDirection::Down => self.pan_y_f -= PAN_STEP,
+            }
+            self.pan_x = self.pan_x_f.round() as i32;
+            self.pan_y = self.pan_y_f.round() as i32;
+            return;
+        }
+        self.pan_active[dir as usize] = true;
+        if self.last_pan_tick.is_none() {
+            self.last_pan_tick = Some(Instant::now());
+        }
+    }
+
+    /// Start scrolling the strip up or down.
+    pub fn strip_scroll_start(&mut self, dir: PanDirection) {
+        if !self.animations {
+            match dir {
+                PanDirection::Up => self.strip_offset -= PAN_STEP,
+                PanDirection::Down => self.strip_offset += PAN_STEP,
+                _ => {}
+            }
+            return;
+        }
         self.pan_active[dir as usize] = true;
         if self.last_pan_tick.is_none() {
             self.last_pan_tick = Some(Instant::now());
@@ -169,7 +654,7 @@ impl Viewer {
     /// Update pan position at constant speed based on which keys are held.
     /// Returns true if any pan key is active (needs continued redraws).
     pub fn update_pan(&mut self) -> bool {
-        if !self.is_pan_animating() {
+        if !self.animations || !self.is_pan_animating() {
             self.last_pan_tick = None;
             return false;
         }
@@ -179,15 +664,7 @@ impl Viewer {
             return false;
         }
 
-        let now = Instant::now();
-        let dt = if let Some(last) = self.last_pan_tick {
-            let elapsed = now.duration_since(last).as_secs_f64();
-            elapsed.min(0.1) // Cap to avoid huge jumps if the app stalls
-        } else {
-            0.0
-        };
-        self.last_pan_tick = Some(now);
-
+        let dt = self.pan_tick();
         if dt <= 0.0 {
             return true;
         }
@@ -216,8 +693,14 @@ impl Viewer {
         }
 
         // Move at constant speed
-        self.pan_x_f += dx * PAN_SPEED * dt;
-        self.pan_y_f += dy * PAN_SPEED * dt;
+        if self.elastic() {
+            let (max_x, max_y) = (self.max_pan.0 as f64, self.max_pan.1 as f64);
+            self.pan_x_f = elastic_step(self.pan_x_f, dx * PAN_SPEED * dt, max_x, dt);
+            self.pan_y_f = elastic_step(self.pan_y_f, dy * PAN_SPEED * dt, max_y, dt);
+        } else {
+            self.pan_x_f += dx * PAN_SPEED * dt;
+            self.pan_y_f += dy * PAN_SPEED * dt;
+        }
 
         // Convert to integer for rendering
         self.pan_x = self.pan_x_f.round() as i32;
@@ -226,20 +709,88 @@ impl Viewer {
         true
     }
 
+    /// Scroll the strip at constant speed while j/k are held. Returns true if
+    /// scrolling is active (needs continued redraws).
+    pub fn update_strip_scroll(&mut self) -> bool {
+        if !self.animations || !self.is_pan_animating() {
+            self.last_pan_tick = None;
+            return false;
+        }
+
+        let dt = self.pan_tick();
+        let mut dy: f64 = 0.0;
+        if self.pan_active[PanDirection::Up as usize] {
+            dy -= 1.0;
+        }
+        if self.pan_active[PanDirection::Down as usize] {
+            dy += 1.0;
+        }
+        self.strip_offset += dy * PAN_SPEED * dt;
+        true
+    }
+
+    /// Seconds since the last pan animation tick, and start a new tick.
+    fn pan_tick(&mut self) -> f64 {
+        let now = Instant::now();
+        let dt = if let Some(last) = self.last_pan_tick {
+            let elapsed = now.duration_since(last).as_secs_f64();
+            elapsed.min(0.1) // Cap to avoid huge jumps if the app stalls
+        } else {
+            0.0
+        };
+        self.last_pan_tick = Some(now);
+        dt
+    }
+
     /// Returns true if the image is zoomed in beyond fit-to-window.
     pub fn is_zoomed(&self) -> bool {
         self.zoom > 1.0
     }
 
-    /// Returns true if any pan key is currently held.
+    /// Source rectangle `(x, y, w, h)` of `frame` visible in a `win_w`x`win_h`
+    /// window with the current zoom and pan, as of the last render.
+    pub fn visible_region(
+        &self,
+        frame: &RgbaImage,
+        win_w: u32,
+        win_h: u32,
+    ) -> Option<(u32, u32, u32, u32)> {
+        let (src_w, src_h) = frame.dimensions();
+        let scale = self.fit_scale * self.zoom;
+        render::visible_source_rect(
+            src_w,
+            src_h,
+            scale * self.pixel_aspect,
+            scale,
+            win_w,
+            win_h,
+            self.pan_x,
+            self.pan_y,
+        )
+    }
+
+    /// Where the last render drew the image in the window, as `(x, y, w, h)`.
+    pub fn image_rect(&self) -> Option<render::WindowRect> {
+        self.image_rect
+    }
+
+    /// Returns true if any pan key is currently held, or an elastic
+    /// overshoot is springing back to the edge.
     pub fn is_pan_animating(&self) -> bool {
-        self.pan_active.iter().any(|&a| a)
+        self.pan_active.iter().any(|&a| a) || self.is_springing()
     }
 
-    /// Returns the deadline for the next pan animation frame, if animating.
-    pub fn pan_deadline(&self) -> Option<Instant> {
+    /// Whether the view is past an image edge and has to spring back.
+    fn is_springing(&self) -> bool {
+        let (max_x, max_y) = (self.max_pan.0 as f64, self.max_pan.1 as f64);
+        self.elastic() && (self.pan_x_f.abs() > max_x || self.pan_y_f.abs() > max_y)
+    }
+
+    /// Returns the deadline for the next pan animation frame, if animating:
+    /// the next display refresh when known, else `PAN_FRAME_INTERVAL` away.
+    pub fn pan_deadline(&self, next_refresh: Option<Instant>) -> Option<Instant> {
         if self.is_pan_animating() {
-            Some(Instant::now() + PAN_FRAME_INTERVAL)
+            Some(next_refresh.unwrap_or_else(|| Instant::now() + PAN_FRAME_INTERVAL))
         } else {
             None
         }
@@ -248,9 +799,12 @@ impl Viewer {
     /// Start animation for a new animated image.
     pub fn start_animation(&mut self, loaded: &LoadedImage) {
         self.current_frame = 0;
+        if !self.animations || self.strip_mode {
+            return;
+        }
         if let LoadedImage::Animated { frames } = loaded {
             if !frames.is_empty() {
-                self.next_frame_time = Some(Instant::now() + frames[0].1);
+                self.next_frame_time = Some(Instant::now() + self.scaled_delay(frames[0].1));
             }
         }
     }
@@ -258,11 +812,14 @@ impl Viewer {
     /// Advance animation frame if the timer has elapsed.
     /// Returns true if a frame was advanced (needs redraw).
     pub fn advance_frame(&mut self, loaded: &LoadedImage) -> bool {
+        if !self.animations {
+            return false;
+        }
         if let LoadedImage::Animated { frames } = loaded {
             if let Some(deadline) = self.next_frame_time {
                 if Instant::now() >= deadline {
                     self.current_frame = (self.current_frame + 1) % frames.len();
-                    let delay = frames[self.current_frame].1;
+                    let delay = self.scaled_delay(frames[self.current_frame].1);
                     self.next_frame_time = Some(Instant::now() + delay);
                     return true;
                 }
@@ -276,7 +833,9 @@ impl Viewer {
         self.next_frame_time
     }
 
-    /// Render the current view into an XRGB pixel buffer.
+    /// Render the current view into an XRGB pixel buffer. The status bar is
+    /// left out unless `show_status` is set.
+    #[allow(clippy::too_many_arguments)]
     pub fn render(
         &mut self,
         loaded: &LoadedImage,
@@ -287,72 +846,211 @@ impl Viewer {
         total: usize,
         error_message: Option<&str>,
         toast_message: Option<&str>,
+        show_status: bool,
     ) -> Vec<u32> {
+        self.image_rect = None;
         if win_w == 0 || win_h == 0 {
             return vec![];
         }
 
-        // Get the current frame
-        let frame: &RgbaImage = match loaded {
-            LoadedImage::Static(img) => img,
-            LoadedImage::Animated { frames } => &frames[self.current_frame.min(frames.len() - 1)].0,
-        };
+        let frame = loaded.frame(self.current_frame);
 
         let (src_w, src_h) = frame.dimensions();
         if src_w == 0 || src_h == 0 {
-            return vec![render::BG_COLOR; (win_w * win_h) as usize];
+            return vec![self.letterbox_color(); (win_w * win_h) as usize];
         }
 
-        // Calculate fit-to-window scale
-        let scale = (win_w as f64 / src_w as f64).min(win_h as f64 / src_h as f64);
-        self.fit_scale = if self.fit_to_window {
-            scale
-        } else {
-            scale.min(1.0)
-        };
+        let display_w = src_w as f64 * self.pixel_aspect;
+        self.fit_scale = self.fit_scale_for(src_w, src_h, win_w, win_h);
         if self.actual_size {
             self.zoom = 1.0 / self.fit_scale;
             self.stop_all_pan();
             self.actual_size = false;
         }
+        self.max_zoom = max_zoom(display_w, src_h as f64, self.fit_scale);
+        self.zoom = self.zoom.min(self.max_zoom);
         let actual_scale = self.fit_scale * self.zoom;
 
-        // Scale image (cached — only recompute when zoom/window/frame changes)
+        let (factor_x, factor_y) = (actual_scale * self.pixel_aspect, actual_scale);
+        let scaled_size = render::scaled_size(src_w, src_h, factor_x, factor_y);
+        let (scaled_w, scaled_h) = scaled_size;
+
+        // Clamp pan to keep image edges within window (elastic panning may
+        // overshoot them a little; update_pan() springs it back)
+        let (max_pan_x, max_pan_y) = render::max_pan(scaled_w, scaled_h, win_w, win_h);
+        self.max_pan = (max_pan_x, max_pan_y);
+        let overscroll = if self.elastic() {
+            ELASTIC_OVERSCROLL as i32
+        } else {
+            0
+        };
+        let (limit_x, limit_y) = (max_pan_x + overscroll, max_pan_y + overscroll);
+        self.pan_x = self.pan_x.clamp(-limit_x, limit_x);
+        self.pan_y = self.pan_y.clamp(-limit_y, limit_y);
+        // Keep floating-point in sync with clamped integer values
+        self.pan_x_f = self.pan_x_f.clamp(-limit_x as f64, limit_x as f64);
+        self.pan_y_f = self.pan_y_f.clamp(-limit_y as f64, limit_y as f64);
+        let pan = (self.pan_x, self.pan_y);
+
+        // The clamp keeps part of the image in view; should it still miss
+        // the window, tell the user how to get back instead of going blank
+        if render::visible_scaled_rect(scaled_size, win_w, win_h, pan, (0, 0)).is_none() {
+            let mut buf = vec![self.letterbox_color(); (win_w * win_h) as usize];
+            Self::draw_hint(&mut buf, win_w, win_h, &[OFFSCREEN_HINT]);
+            return buf;
+        }
+        self.image_rect = Some((
+            (win_w as i32 - scaled_w as i32) / 2 + pan.0,
+            (win_h as i32 - scaled_h as i32) / 2 + pan.1,
+            scaled_w,
+            scaled_h,
+        ));
+
+        // Part of the scaled image to compute: all of it while that is
+        // cheap, otherwise what is visible (with a margin, see below)
+        let full_rect = (0, 0, scaled_w, scaled_h);
+        let whole = scaled_w as u64 * scaled_h as u64
+            <= FULL_SCALE_MAX_WINDOWS * win_w as u64 * win_h as u64;
+        let needed = if whole {
+            full_rect
+        } else {
+            render::visible_scaled_rect(scaled_size, win_w, win_h, pan, (0, 0))
+                .unwrap_or((0, 0, 1, 1))
+        };
+
+        // Scale image (cached — only recompute when zoom/window/frame
+        // changes, or when panning leaves the computed region)
         let frame_idx = match loaded {
             LoadedImage::Static(_) => 0,
             LoadedImage::Animated { .. } => self.current_frame,
         };
         let cache_key: ScaleCacheKey = (actual_scale.to_bits(), win_w, win_h, frame_idx);
-        if self.scaled_cache.is_none() || self.scaled_cache_key != cache_key {
-            self.scaled_cache = Some(render::scale_by_factor(frame, actual_scale));
-            self.scaled_cache_key = cache_key;
-        }
-        let scaled = self.scaled_cache.as_ref().unwrap();
-        let (scaled_w, scaled_h) = scaled.dimensions();
-
-        // Clamp pan to keep image edges within window
-        let max_pan_x = ((scaled_w as i32 - win_w as i32) / 2).max(0);
-        let max_pan_y = ((scaled_h as i32 - win_h as i32) / 2).max(0);
-        self.pan_x = self.pan_x.clamp(-max_pan_x, max_pan_x);
-        self.pan_y = self.pan_y.clamp(-max_pan_y, max_pan_y);
-        // Keep floating-point in sync with clamped integer values
-        self.pan_x_f = self.pan_x_f.clamp(-max_pan_x as f64, max_pan_x as f64);
-        self.pan_y_f = self.pan_y_f.clamp(-max_pan_y as f64, max_pan_y as f64);
+        let letterbox = self.letterbox_color();
+        // A frame of an animation that fits the window is shown once and
+        // cannot be panned, so the stage caches have nothing to reuse
+        let one_shot = matches!(loaded, LoadedImage::Animated { .. })
+            && self.filter == render::Filter::None
+            && self.tone == render::Tone::NEUTRAL
+            && self.pixel_aspect == 1.0
+            && self.max_pan == (0, 0)
+            && pan == (0, 0);
+        if one_shot {
+            if self.scaled_cache.is_some()
+                || self.scaled_cache_key != cache_key
+                || self.composite_cache.is_none()
+            {
+                self.composite_cache = Some(render::render_to_rgba(
+                    loaded,
+                    frame_idx,
+                    actual_scale,
+                    pan,
+                    win_w,
+                    win_h,
+                    letterbox,
+                ));
+                self.scaled_cache = None;
+                self.toned_cache = None;
+                self.scaled_cache_key = cache_key;
+                self.composite_pan = pan;
+            }
+        } else {
+            if self.scaled_cache.is_none()
+                || self.scaled_cache_key != cache_key
+                || !rect_contains(self.scaled_rect, needed)
+            {
+                // Only the displayed frame is filtered, so animations stay cheap
+                let mut source = frame;
+                if self.filter != render::Filter::None {
+                    if self.filtered_cache.as_ref().map(|(idx, _)| *idx) != Some(frame_idx) {
+                        let filtered = render::apply_filter(frame, self.filter);
+                        self.filtered_cache = Some((frame_idx, filtered));
+                    }
+                    source = &self.filtered_cache.as_ref().unwrap().1;
+                }
+                // Half a window of margin on each side lets short pans reuse
+                // the region instead of re-scaling on every tick
+                let rect = if whole {
+                    full_rect
+                } else {
+                    let margin = (win_w / 2, win_h / 2);
+                    render::visible_scaled_rect(scaled_size, win_w, win_h, pan, margin)
+                        .unwrap_or(needed)
+                };
+                self.scaled_cache = Some(render::scale_region(source, factor_x, factor_y, rect));
+                self.scaled_cache_key = cache_key;
+                self.scaled_rect = rect;
+                self.toned_cache = None;
+                self.composite_cache = None;
+            }
+            let mut scaled = self.scaled_cache.as_ref().unwrap();
+            if self.tone != render::Tone::NEUTRAL {
+                scaled = self
+                    .toned_cache
+                    .get_or_insert_with(|| render::apply_tone(scaled, self.tone));
+            }
+
+            // Composite onto background (cached — only recompute when the scaled
+            // image or pan offset changes; overlays are drawn on a copy). A pan
+            // over the same scaled image only redraws the newly exposed edges.
+            let at = (self.scaled_rect.0, self.scaled_rect.1);
+            if self.composite_pan != pan {
+                if let Some(cached) = self.composite_cache.as_mut() {
+                    render::recomposite_panned(
+                        cached,
+                        scaled,
+                        at,
+                        scaled_size,
+                        win_w,
+                        win_h,
+                        self.composite_pan,
+                        pan,
+                        letterbox,
+                    );
+                    self.composite_pan = pan;
+                }
+            }
+            if self.composite_cache.is_none() {
+                self.composite_cache = Some(render::composite_region(
+                    scaled,
+                    at,
+                    scaled_size,
+                    win_w,
+                    win_h,
+                    self.pan_x,
+                    self.pan_y,
+                    letterbox,
+                ));
+                self.composite_pan = pan;
+            }
+        }
+        let mut buf = self.composite_cache.as_ref().unwrap().clone();
 
-        // Composite onto background
-        let mut buf = render::composite_centered(&scaled, win_w, win_h, self.pan_x, self.pan_y);
+        // Draw composition grid and proof marks around the displayed image
+        let img_x = (win_w as i32 - scaled_w as i32) / 2 + self.pan_x;
+        let img_y = (win_h as i32 - scaled_h as i32) / 2 + self.pan_y;
+        let img_rect = (img_x, img_y, scaled_w, scaled_h);
+        if self.show_grid {
+            let (cols, rows) = self.grid_divisions;
+            render::draw_grid(&mut buf, win_w, win_h, img_rect, cols, rows);
+        }
+        if self.proof {
+            render::draw_crop_marks(&mut buf, win_w, win_h, img_rect);
+        }
 
         // Draw status bar (with error message appended if present)
-        let status_text = if let Some(err) = error_message {
-            format!(
-                "{} | {}",
-                status::format_status(path, src_w, src_h, index, total),
-                err
-            )
-        } else {
-            status::format_status(path, src_w, src_h, index, total)
-        };
-        status::draw_status_bar(&mut buf, win_w, win_h, &status_text);
+        if show_status {
+            let status_text = self.status_text(
+                path,
+                src_w,
+                src_h,
+                index,
+                total,
+                actual_scale,
+                loaded.animation_length(),
+                error_message,
+            );
+            status::draw_status_bar(&mut buf, win_w, win_h, &status_text, self.status_position);
+        }
 
         // Draw EXIF overlay
         if self.show_exif && !self.exif_lines.is_empty() {
@@ -367,18 +1065,86 @@ impl Viewer {
         buf
     }
 
+    /// Render the vertical strip: `images` are the current image followed by
+    /// the ones below it, drawn from `strip_offset` above the window top
+    /// until the window is filled.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render_strip(
+        &mut self,
+        images: &[(&Path, &RgbaImage)],
+        win_w: u32,
+        win_h: u32,
+        index: usize,
+        total: usize,
+        error_message: Option<&str>,
+        toast_message: Option<&str>,
+        show_status: bool,
+    ) -> Vec<u32> {
+        if win_w == 0 || win_h == 0 {
+            return vec![];
+        }
+        if self.strip_cache_w != win_w {
+            self.strip_cache.clear();
+            self.strip_cache_w = win_w;
+        }
+
+        let mut buf = vec![self.bg_color; (win_w * win_h) as usize];
+        let mut y = -self.strip_offset.round() as i32;
+        for &(path, frame) in images {
+            if y >= win_h as i32 {
+                break;
+            }
+            if !self.strip_cache.contains_key(path) {
+                let scale = self.strip_scale(frame.width, win_w);
+                let scaled = render::scale_by_factors(frame, scale, scale);
+                self.strip_cache.insert(path.to_path_buf(), scaled);
+            }
+            let scaled = &self.strip_cache[path];
+            let x = (win_w as i32 - scaled.width as i32) / 2;
+            render::blit_over(&mut buf, win_w, win_h, scaled, x, y, self.bg_color);
+            y += scaled.height as i32;
+        }
+        // Keep only the images that are on screen
+        self.strip_cache
+            .retain(|p, _| images.iter().any(|&(path, _)| path == p.as_path()));
+
+        if let Some(&(path, frame)) = images.first().filter(|_| show_status) {
+            let (w, h) = frame.dimensions();
+            let scale = self.strip_scale(w, win_w);
+            let status_text =
+                self.status_text(path, w, h, index, total, scale, None, error_message);
+            status::draw_status_bar(&mut buf, win_w, win_h, &status_text, self.status_position);
+        }
+
+        if self.show_exif && !self.exif_lines.is_empty() {
+            self.draw_exif_overlay(&mut buf, win_w, win_h);
+        }
+        if let Some(msg) = toast_message {
+            Self::draw_toast(&mut buf, win_w, win_h, msg);
+        }
+
+        buf
+    }
+
     /// Draw a small toast notification at the top-right corner.
     pub(crate) fn draw_toast(buf: &mut [u32], win_w: u32, win_h: u32, message: &str) {
         let padding: u32 = 6;
         let margin: u32 = 10;
         let radius: u32 = 4;
 
-        let text_w = message.len() as u32 * font::GLYPH_W;
-        let overlay_w = text_w + padding * 2;
+        let text_w = message.chars().count() as u32 * font::GLYPH_W;
         let overlay_h = font::GLYPH_H + padding * 2;
-
-        let overlay_x = win_w.saturating_sub(overlay_w + margin);
-        let overlay_y = margin;
+        let Some((overlay_x, overlay_y, overlay_w, overlay_h)) = render::fit_overlay_top_right(
+            text_w + padding * 2,
+            overlay_h,
+            font::GLYPH_W + padding * 2,
+            overlay_h,
+            margin,
+            win_w,
+            win_h,
+        ) else {
+            return;
+        };
 
         render::draw_overlay_rounded(
             buf, win_w, overlay_x, overlay_y, overlay_w, overlay_h, 180, radius,
@@ -389,6 +1155,70 @@ impl Viewer {
         font::draw_string(buf, win_w, win_h, message, text_x, text_y, 0x00DDDDDD);
     }
 
+    /// Draw `lines` of text in a rounded box centered in the window.
+    pub(crate) fn draw_hint(buf: &mut [u32], win_w: u32, win_h: u32, lines: &[&str]) {
+        let padding: u32 = 8;
+        let line_h = font::GLYPH_H + 2;
+        let cols = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as u32;
+        let box_w = (cols * font::GLYPH_W + padding * 2).min(win_w);
+        let box_h = ((lines.len() as u32 * line_h).saturating_sub(2) + padding * 2).min(win_h);
+        let (x, y) = ((win_w - box_w) / 2, (win_h - box_h) / 2);
+        render::draw_overlay_rounded(buf, win_w, x, y, box_w, box_h, 180, 4);
+        for (i, line) in lines.iter().enumerate() {
+            let ty = y + padding + i as u32 * line_h;
+            font::draw_string(buf, win_w, win_h, line, x + padding, ty, 0x00DDDDDD);
+        }
+    }
+
+    /// Draw a centered keybinding help panel listing `entries` as
+    /// (keys, description) rows under `title`.
+    pub(crate) fn draw_help(
+        buf: &mut [u32],
+        win_w: u32,
+        win_h: u32,
+        title: &str,
+        entries: &[(&str, &str)],
+    ) {
+        let padding: u32 = 12;
+        let margin: u32 = 10;
+        let line_h = font::GLYPH_H + 2;
+        let radius: u32 = 6;
+
+        let key_cols = entries.iter().map(|(k, _)| k.len()).max().unwrap_or(0) as u32 + 2;
+        let desc_cols = entries.iter().map(|(_, d)| d.len()).max().unwrap_or(0) as u32;
+        let text_w = (key_cols + desc_cols).max(title.len() as u32) * font::GLYPH_W;
+        // Title plus a blank line, then one line per entry
+        let lines = entries.len() as u32 + 2;
+
+        let overlay_w = (text_w + padding * 2).min(win_w.saturating_sub(margin * 2));
+        let overlay_h = (lines * line_h - 2 + padding * 2).min(win_h.saturating_sub(margin * 2));
+        // Skip the panel when not even the title fits
+        if overlay_w < font::GLYPH_W + padding * 2 || overlay_h < font::GLYPH_H + padding * 2 {
+            return;
+        }
+        let overlay_x = (win_w - overlay_w) / 2;
+        let overlay_y = (win_h - overlay_h) / 2;
+
+        render::draw_overlay_rounded(
+            buf, win_w, overlay_x, overlay_y, overlay_w, overlay_h, 200, radius,
+        );
+
+        let text_x = overlay_x + padding;
+        let bottom = overlay_y + overlay_h;
+        let mut text_y = overlay_y + padding;
+        font::draw_string(buf, win_w, win_h, title, text_x, text_y, 0x00FFFFFF);
+        text_y += line_h * 2;
+        for (keys, desc) in entries {
+            if text_y + font::GLYPH_H > bottom {
+                break;
+            }
+            font::draw_string(buf, win_w, win_h, keys, text_x, text_y, 0x00E0B050);
+            let desc_x = text_x + key_cols * font::GLYPH_W;
+            font::draw_string(buf, win_w, win_h, desc, desc_x, text_y, 0x00DDDDDD);
+            text_y += line_h;
+        }
+    }
+
     fn draw_exif_overlay(&self, buf: &mut [u32], win_w: u32, win_h: u32) {
         let padding: u32 = 8;
         let margin: u32 = 10;
@@ -400,13 +1230,19 @@ impl Viewer {
         let overlay_w = max_line_len * font::GLYPH_W + padding * 2;
         let overlay_h = self.exif_lines.len() as u32 * line_h + padding * 2 - 2; // -2: no trailing spacing
 
-        // Position at top-right
-        let overlay_x = win_w.saturating_sub(overlay_w + margin);
-        let overlay_y = margin;
-
-        // Clamp to window
-        let overlay_w = overlay_w.min(win_w.saturating_sub(margin));
-        let overlay_h = overlay_h.min(win_h.saturating_sub(margin * 2));
+        // Position at top-right, clamped to the window; skipped if not even
+        // one line fits
+        let Some((overlay_x, overlay_y, overlay_w, overlay_h)) = render::fit_overlay_top_right(
+            overlay_w,
+            overlay_h,
+            font::GLYPH_W + padding * 2,
+            font::GLYPH_H + padding * 2,
+            margin,
+            win_w,
+            win_h,
+        ) else {
+            return;
+        };
 
         // Draw rounded dark overlay (same style as status bar: alpha 160)
         render::draw_overlay_rounded(
@@ -425,3 +1261,490 @@ impl Viewer {
         }
     }
 }
+
+/// Normalize a strip scroll position. `idx` is the image at the window top
+/// and `offset` the distance from its top edge to the window top; `height(i)`
+/// is the displayed height of image `i` of `total`. Returns the image at the
+/// window top and the offset into it, clamped so the strip neither starts
+/// below the window top nor ends above the window bottom, plus the index one
+/// past the last visible image.
+pub fn strip_position(
+    mut height: impl FnMut(usize) -> f64,
+    total: usize,
+    mut idx: usize,
+    mut offset: f64,
+    win_h: f64,
+) -> (usize, f64, usize) {
+    if total == 0 {
+        return (0, 0.0, 0);
+    }
+    idx = idx.min(total - 1);
+    loop {
+        if offset < 0.0 && idx > 0 {
+            idx -= 1;
+            offset += height(idx);
+        } else if idx + 1 < total && offset >= height(idx) {
+            offset -= height(idx);
+            idx += 1;
+        } else {
+            break;
+        }
+    }
+
+    // Don't scroll past the bottom of the last image
+    let mut bottom = -offset;
+    let mut end = idx;
+    while end < total && bottom < win_h {
+        bottom += height(end);
+        end += 1;
+    }
+    if bottom < win_h {
+        offset -= win_h - bottom;
+        while offset < 0.0 && idx > 0 {
+            idx -= 1;
+            offset += height(idx);
+        }
+    }
+    (idx, offset.max(0.0), end)
+}
+
+/// Whether rectangle `outer` covers all of `inner`; both are `(x, y, w, h)`.
+fn rect_contains(outer: (u32, u32, u32, u32), inner: (u32, u32, u32, u32)) -> bool {
+    inner.0 >= outer.0
+        && inner.1 >= outer.1
+        && inner.0 + inner.2 <= outer.0 + outer.2
+        && inner.1 + inner.3 <= outer.1 + outer.3
+}
+
+/// Advance one pan axis by `delta` with elastic edges at `-max..=max`:
+/// pushing further out past an edge meets more and more resistance up to
+/// `ELASTIC_OVERSCROLL`, and without input an overshoot decays back,
+/// landing exactly on the edge.
+fn elastic_step(pan: f64, delta: f64, max: f64, dt: f64) -> f64 {
+    let edge = pan.clamp(-max, max);
+    let over = pan - edge;
+    if delta == 0.0 {
+        if over == 0.0 {
+            return pan;
+        }
+        let over = over * (-ELASTIC_SPRING * dt).exp();
+        return if over.abs() < 0.5 { edge } else { edge + over };
+    }
+    let delta = if over * delta > 0.0 {
+        delta * (1.0 - over.abs() / ELASTIC_OVERSCROLL).max(0.0)
+    } else {
+        delta
+    };
+    let limit = max + ELASTIC_OVERSCROLL;
+    (pan + delta).clamp(-limit, limit)
+}
+
+/// Zoom (relative to `fit_scale`) that shows an image at `percent` of its
+/// pixel size, clamped to between 1.0 (fit) and `max_zoom`.
+fn zoom_for_percent(percent: f64, fit_scale: f64, max_zoom: f64) -> f64 {
+    (percent / 100.0 / fit_scale).clamp(1.0, max_zoom.max(1.0))
+}
+
+/// Largest zoom (relative to `fit_scale`) at which a `display_w`x`src_h`
+/// image stays within `MAX_SCALED_PIXELS` and `MAX_DISPLAY_SCALE`. Never
+/// below 1.0, so fit-to-window always works.
+fn max_zoom(display_w: f64, src_h: f64, fit_scale: f64) -> f64 {
+    let max_scale = (MAX_SCALED_PIXELS / (display_w * src_h))
+        .sqrt()
+        .min(MAX_DISPLAY_SCALE);
+    (max_scale / fit_scale).max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(heights: &[f64], idx: usize, offset: f64, win_h: f64) -> (usize, f64, usize) {
+        strip_position(|i| heights[i], heights.len(), idx, offset, win_h)
+    }
+
+    #[test]
+    fn test_strip_position_crosses_boundaries() {
+        let heights = [100.0, 200.0, 50.0];
+        // Scrolling down past the first image
+        assert_eq!(position(&heights, 0, 120.0, 150.0), (1, 20.0, 2));
+        // Scrolling up from the last image into the middle one
+        assert_eq!(position(&heights, 2, -10.0, 50.0), (1, 190.0, 3));
+        // Already inside an image
+        assert_eq!(position(&heights, 1, 30.0, 150.0), (1, 30.0, 2));
+    }
+
+    #[test]
+    fn test_strip_position_clamps_ends() {
+        let heights = [100.0, 200.0, 50.0];
+        // Above the first image
+        assert_eq!(position(&heights, 0, -30.0, 150.0), (0, 0.0, 2));
+        // Past the end: the last image's bottom stays at the window bottom
+        assert_eq!(position(&heights, 0, 400.0, 150.0), (1, 100.0, 3));
+        assert_eq!(position(&heights, 2, -60.0, 150.0), (1, 100.0, 3));
+        // A strip shorter than the window stays at the top
+        assert_eq!(position(&[50.0, 50.0], 1, 20.0, 150.0), (0, 0.0, 2));
+    }
+
+    #[test]
+    fn test_render_tiny_windows() {
+        let mut img = RgbaImage::new(40, 30);
+        img.data.fill(200);
+        let loaded = LoadedImage::Static(img.clone());
+        let path = Path::new("/nonexistent/a.png");
+        for (w, h) in [(1, 1), (2, 2), (10, 10), (1, 300), (300, 1), (15, 25)] {
+            let mut viewer = Viewer::new();
+            viewer.exif_lines = vec!["Camera: Example".to_string(), "ISO: 100".to_string()];
+            viewer.show_exif = true;
+            viewer.show_grid = true;
+            let (error, toast) = (Some("error"), Some("toast"));
+            let mut buf = viewer.render(&loaded, w, h, path, 0, 3, error, toast, true);
+            assert_eq!(buf.len(), (w * h) as usize, "{}x{}", w, h);
+            Viewer::draw_help(&mut buf, w, h, "Help", crate::input::VIEWER_HELP);
+            let strip = viewer.render_strip(&[(path, &img)], w, h, 0, 1, None, Some("toast"), true);
+            assert_eq!(strip.len(), (w * h) as usize, "{}x{}", w, h);
+        }
+    }
+
+    #[test]
+    fn test_render_matches_render_to_rgba() {
+        // A gradient, so scaling and placement differences would show
+        let data = (0..40 * 30)
+            .flat_map(|i| [(i % 40 * 6) as u8, (i / 40 * 8) as u8, 90, 255])
+            .collect();
+        let loaded = LoadedImage::Static(RgbaImage::from_raw(40, 30, data).unwrap());
+        let path = Path::new("/nonexistent/a.png");
+        let mut viewer = Viewer::new();
+        // Without status fields or overlays only the image is drawn
+        viewer.set_status_layout(StatusPosition::Bottom, Vec::new());
+        for (w, h) in [(20, 15), (64, 48), (33, 17)] {
+            let buf = viewer.render(&loaded, w, h, path, 0, 1, None, None, true);
+            let scale = viewer.fit_scale * viewer.zoom;
+            let bg = render::BG_COLOR;
+            let expected = render::render_to_rgba(&loaded, 0, scale, (0, 0), w, h, bg);
+            assert!(buf == expected, "{}x{}", w, h);
+        }
+        // A hidden status bar leaves the image alone too
+        let fields = status::DEFAULT_STATUS_FIELDS.to_vec();
+        viewer.set_status_layout(StatusPosition::Bottom, fields);
+        let shown = viewer.render(&loaded, 64, 48, path, 0, 1, None, None, true);
+        let hidden = viewer.render(&loaded, 64, 48, path, 0, 1, None, None, false);
+        let scale = viewer.fit_scale * viewer.zoom;
+        let bg = render::BG_COLOR;
+        assert!(hidden == render::render_to_rgba(&loaded, 0, scale, (0, 0), 64, 48, bg));
+        assert!(shown != hidden);
+    }
+
+    #[test]
+    fn test_fitted_animation_frames_use_render_to_rgba() {
+        let frame = |red: u8| {
+            let data = (0..96).flat_map(|i| [red, i as u8, 40, 255]).collect();
+            let delay = Duration::from_millis(50);
+            (RgbaImage::from_raw(12, 8, data).unwrap(), delay)
+        };
+        let loaded = LoadedImage::Animated {
+            frames: vec![frame(10), frame(200)],
+        };
+        let path = Path::new("/nonexistent/a.gif");
+        let mut viewer = Viewer::new();
+        let bg = render::BG_COLOR;
+        for i in 0..2 {
+            viewer.current_frame = i;
+            let buf = viewer.render(&loaded, 30, 20, path, 0, 1, None, None, false);
+            let scale = viewer.fit_scale * viewer.zoom;
+            assert!(buf == render::render_to_rgba(&loaded, i, scale, (0, 0), 30, 20, bg));
+            // Nothing is kept for panning
+            assert!(viewer.scaled_cache.is_none());
+        }
+
+        // Zoomed in, the frame can be panned and goes through the stages
+        viewer.zoom = 3.0;
+        let buf = viewer.render(&loaded, 30, 20, path, 0, 1, None, None, false);
+        assert!(viewer.scaled_cache.is_some());
+        let scale = viewer.fit_scale * viewer.zoom;
+        assert!(buf == render::render_to_rgba(&loaded, 1, scale, (0, 0), 30, 20, bg));
+    }
+
+    #[test]
+    fn test_max_zoom_bounds_scaled_size() {
+        // Huge image fitted into a small window: the cap keeps the zoomed
+        // image within the pixel budget
+        let (w, h, fit) = (16000.0, 16000.0, 0.05);
+        let zoom = max_zoom(w, h, fit);
+        let scale = fit * zoom;
+        assert!((w * scale) * (h * scale) <= MAX_SCALED_PIXELS * 1.0001);
+        // Tiny image: limited by magnification instead
+        assert_eq!(max_zoom(4.0, 4.0, 16.0), MAX_DISPLAY_SCALE / 16.0);
+        // Fit-to-window is always allowed, even past the limits
+        assert_eq!(max_zoom(4.0, 4.0, 100.0), 1.0);
+        assert_eq!(max_zoom(1e6, 1e6, 1.0), 1.0);
+    }
+
+    #[test]
+    fn test_anim_speed_scales_frame_delays() {
+        let ms = Duration::from_millis;
+        let min = image_loader::min_frame_duration();
+        let mut viewer = Viewer::new();
+        assert_eq!(viewer.scaled_delay(ms(100)), ms(100));
+        viewer.set_anim_speed(2.0);
+        assert_eq!(viewer.scaled_delay(ms(100)), ms(50));
+        viewer.set_anim_speed(0.5);
+        assert_eq!(viewer.scaled_delay(ms(100)), ms(200));
+        // Sped up, short frames stop at min-frame-duration
+        viewer.set_anim_speed(8.0);
+        assert_eq!(viewer.scaled_delay(ms(160)), ms(20).max(min));
+        assert_eq!(viewer.scaled_delay(min), min);
+        // The multiplier itself stays within its range
+        viewer.set_anim_speed(100.0);
+        assert_eq!(viewer.anim_speed, ANIM_SPEED_MAX);
+        assert_eq!(viewer.step_anim_speed(false), ANIM_SPEED_MAX / 2.0);
+
+        // The next frame is scheduled with its scaled delay
+        let frame = |delay| (RgbaImage::new(1, 1), ms(delay));
+        let loaded = LoadedImage::Animated {
+            frames: vec![frame(100), frame(600)],
+        };
+        viewer.set_anim_speed(2.0);
+        viewer.next_frame_time = Some(Instant::now());
+        let before = Instant::now();
+        assert!(viewer.advance_frame(&loaded));
+        let wait = viewer.next_frame_deadline().unwrap() - before;
+        assert!(wait >= ms(300) && wait < ms(400), "{:?}", wait);
+    }
+
+    #[test]
+    fn test_status_shows_animation_frame() {
+        let path = Path::new("/nonexistent/a.gif");
+        let mut viewer = Viewer::new();
+        viewer.set_status_layout(StatusPosition::Bottom, vec![StatusField::Name]);
+        viewer.current_frame = 1;
+        let anim = Some((3, Duration::from_millis(400)));
+        assert_eq!(
+            viewer.status_text(path, 1, 1, 0, 1, 1.0, anim, Some("err")),
+            "a.gif | frame 2/3 | 0.4s loop | err"
+        );
+        assert_eq!(
+            viewer.status_text(path, 1, 1, 0, 1, 1.0, None, None),
+            "a.gif"
+        );
+        let empty = Some((0, Duration::ZERO));
+        assert_eq!(
+            viewer.status_text(path, 1, 1, 0, 1, 1.0, empty, None),
+            "a.gif"
+        );
+        viewer.step_anim_speed(true);
+        assert_eq!(
+            viewer.status_text(path, 1, 1, 0, 1, 1.0, anim, None),
+            "a.gif | frame 2/3 | 0.2s loop | 2x speed"
+        );
+        // Hidden along with the other fields
+        viewer.set_status_layout(StatusPosition::Bottom, Vec::new());
+        assert_eq!(viewer.status_text(path, 1, 1, 0, 1, 1.0, anim, None), "");
+    }
+
+    #[test]
+    fn test_zoom_for_percent() {
+        // Image twice the window size: fit is 50%
+        assert_eq!(zoom_for_percent(100.0, 0.5, 8.0), 2.0);
+        assert_eq!(zoom_for_percent(150.0, 0.5, 8.0), 3.0);
+        // Below the fitted size, and above the limit
+        assert_eq!(zoom_for_percent(25.0, 0.5, 8.0), 1.0);
+        assert_eq!(zoom_for_percent(1000.0, 0.5, 8.0), 8.0);
+
+        // 200x100 image in a 100x50 window fits at 50%, whatever fit_scale
+        // the last render left behind
+        let frame = RgbaImage::new(200, 100);
+        let mut viewer = Viewer::new();
+        viewer.fit_scale = 0.25;
+        assert_eq!(viewer.set_zoom_percent(&frame, 150.0, 100, 50), 150.0);
+        assert_eq!(viewer.zoom, 3.0);
+        assert_eq!(viewer.set_zoom_percent(&frame, 10.0, 100, 50), 50.0);
+        assert!(!viewer.is_zoomed());
+        // After the window grew to the image size, 150% is zoom 1.5
+        assert_eq!(viewer.set_zoom_percent(&frame, 150.0, 200, 100), 150.0);
+        assert_eq!(viewer.zoom, 1.5);
+    }
+
+    #[test]
+    fn test_zoom_keeps_panned_center() {
+        let mut viewer = Viewer::new();
+        viewer.max_zoom = 100.0;
+        viewer.zoom = 2.0;
+        (viewer.pan_x_f, viewer.pan_y_f) = (100.0, -40.0);
+
+        viewer.zoom_in();
+        assert!((viewer.pan_x_f - 100.0 * ZOOM_STEP).abs() < 1e-9);
+        assert!((viewer.pan_y_f + 40.0 * ZOOM_STEP).abs() < 1e-9);
+        assert_eq!(viewer.pan_x, (100.0 * ZOOM_STEP).round() as i32);
+
+        viewer.zoom_out();
+        assert!((viewer.pan_x_f - 100.0).abs() < 1e-9);
+        assert_eq!((viewer.pan_x, viewer.pan_y), (100, -40));
+
+        // At the cap the pan does not move
+        viewer.max_zoom = 2.0;
+        viewer.zoom_in();
+        assert_eq!((viewer.pan_x, viewer.pan_y), (100, -40));
+
+        // Back at fit there is nothing to pan
+        while viewer.zoom > 1.0 {
+            viewer.zoom_out();
+        }
+        assert_eq!((viewer.pan_x, viewer.pan_y), (0, 0));
+    }
+
+    #[test]
+    fn test_elastic_step_overshoots_and_settles_on_edge() {
+        let dt = 0.016;
+        // Inside the edges panning is unaffected
+        assert_eq!(elastic_step(10.0, 5.0, 100.0, dt), 15.0);
+        // Past the edge the same push moves less, and never beyond the limit
+        let past = elastic_step(140.0, 10.0, 100.0, dt);
+        assert!(past > 140.0 && past < 150.0);
+        let limit = 100.0 + ELASTIC_OVERSCROLL;
+        assert_eq!(elastic_step(limit - 1.0, 500.0, 100.0, dt), limit);
+        // Pulling back in is not resisted
+        assert_eq!(elastic_step(140.0, -10.0, 100.0, dt), 130.0);
+
+        // Released, the overshoot springs back and lands exactly on the edge
+        let mut pan = -170.0;
+        for _ in 0..200 {
+            pan = elastic_step(pan, 0.0, 100.0, dt);
+        }
+        assert_eq!(pan, -100.0);
+        assert_eq!(elastic_step(-100.0, 0.0, 100.0, dt), -100.0);
+    }
+
+    #[test]
+    fn test_zoom_in_stops_at_cap() {
+        let loaded = LoadedImage::Static(RgbaImage::new(4, 3));
+        let path = Path::new("/nonexistent/a.png");
+        let mut viewer = Viewer::new();
+        viewer.render(&loaded, 40, 30, path, 0, 1, None, None, true);
+        for _ in 0..1000 {
+            viewer.zoom_in();
+        }
+        assert!(viewer.zoom.is_finite());
+        let buf = viewer.render(&loaded, 40, 30, path, 0, 1, None, None, true);
+        assert_eq!(buf.len(), 40 * 30);
+        let scaled = viewer.scaled_cache.as_ref().unwrap();
+        assert!(scaled.width <= (4.0 * MAX_DISPLAY_SCALE) as u32);
+        assert!(scaled.height <= (3.0 * MAX_DISPLAY_SCALE) as u32);
+
+        // Actual size and a window resize are clamped at render time too
+        viewer.zoom = 1e12;
+        viewer.render(&loaded, 8, 6, path, 0, 1, None, None, true);
+        assert!(viewer.fit_scale * viewer.zoom <= MAX_DISPLAY_SCALE);
+    }
+
+    #[test]
+    fn test_toggle_actual_size_returns_to_fit_mode() {
+        let path = Path::new("/nonexistent/a.png");
+        let mut viewer = Viewer::new();
+        let scale = |viewer: &mut Viewer, loaded: &LoadedImage| {
+            viewer.render(loaded, 40, 30, path, 0, 1, None, None, true);
+            viewer.fit_scale * viewer.zoom
+        };
+
+        // A large image shrinks to fit, and w toggles 1:1 and back
+        let large = LoadedImage::Static(RgbaImage::new(80, 60));
+        assert_eq!(scale(&mut viewer, &large), 0.5);
+        assert_eq!(viewer.toggle_actual_size(), "1:1");
+        assert_eq!(scale(&mut viewer, &large), 1.0);
+        assert_eq!(viewer.toggle_actual_size(), "fit");
+        assert_eq!(scale(&mut viewer, &large), 0.5);
+
+        // Fit-to-window is restored after 1:1 turned it off
+        let small = LoadedImage::Static(RgbaImage::new(4, 3));
+        viewer.toggle_fit_to_window();
+        assert_eq!(scale(&mut viewer, &small), 10.0);
+        assert_eq!(viewer.toggle_actual_size(), "1:1");
+        assert_eq!(scale(&mut viewer, &small), 1.0);
+        assert_eq!(viewer.toggle_actual_size(), "fit to window");
+        assert_eq!(scale(&mut viewer, &small), 10.0);
+    }
+
+    #[test]
+    fn test_high_zoom_scales_visible_region_only() {
+        let data = (0..40 * 30)
+            .flat_map(|i| [(i % 40 * 6) as u8, (i / 40 * 8) as u8, 90, 255])
+            .collect();
+        let loaded = LoadedImage::Static(RgbaImage::from_raw(40, 30, data).unwrap());
+        let path = Path::new("/nonexistent/a.png");
+        let (w, h) = (20, 15);
+        let mut viewer = Viewer::new();
+        viewer.set_status_layout(StatusPosition::Bottom, Vec::new());
+        viewer.render(&loaded, w, h, path, 0, 1, None, None, true);
+        for _ in 0..12 {
+            viewer.zoom_in();
+        }
+
+        let mut rescales = 0;
+        for pan in [(0, 0), (3, -2), (40, 25), (-400, 400), (-401, 400)] {
+            (viewer.pan_x, viewer.pan_y) = pan;
+            let before = viewer.scaled_rect;
+            let buf = viewer.render(&loaded, w, h, path, 0, 1, None, None, true);
+            rescales += (viewer.scaled_rect != before) as u32;
+
+            // Same pixels as scaling the whole image
+            let scale = viewer.fit_scale * viewer.zoom;
+            let pan = (viewer.pan_x, viewer.pan_y);
+            let bg = render::BG_COLOR;
+            let expected = render::render_to_rgba(&loaded, 0, scale, pan, w, h, bg);
+            assert!(buf == expected, "pan {:?}", pan);
+
+            // Only about the window plus its margin is held in memory
+            let cached = viewer.scaled_cache.as_ref().unwrap();
+            assert!(cached.width <= 2 * w && cached.height <= 2 * h);
+        }
+        // Small pans and clamped pans reuse the cached region
+        assert!(rescales < 5, "{}", rescales);
+    }
+
+    #[test]
+    fn test_fit_scaled_round_trip() {
+        let data = (0..40 * 30)
+            .flat_map(|i| [(i % 40 * 6) as u8, (i / 40 * 8) as u8, 90, 255])
+            .collect();
+        let loaded = LoadedImage::Static(RgbaImage::from_raw(40, 30, data).unwrap());
+        let path = Path::new("/nonexistent/a.png");
+        let mut viewer = Viewer::new();
+        let first = viewer.render(&loaded, 20, 15, path, 0, 1, None, None, true);
+
+        // Navigating away and back hands the same scaled image back
+        let scaled = viewer.take_fit_scaled().unwrap();
+        let ptr = scaled.image.data.as_ptr();
+        viewer.reset_view();
+        viewer.restore_fit_scaled(scaled);
+        let again = viewer.render(&loaded, 20, 15, path, 0, 1, None, None, true);
+        assert!(again == first);
+        assert_eq!(viewer.scaled_cache.as_ref().unwrap().data.as_ptr(), ptr);
+
+        // A different window size rescales instead of reusing it
+        let scaled = viewer.take_fit_scaled().unwrap();
+        viewer.restore_fit_scaled(scaled);
+        viewer.render(&loaded, 30, 20, path, 0, 1, None, None, true);
+        assert_ne!(viewer.scaled_cache.as_ref().unwrap().data.as_ptr(), ptr);
+
+        // Zoomed views are not kept
+        viewer.zoom_in();
+        viewer.render(&loaded, 30, 20, path, 0, 1, None, None, true);
+        assert!(viewer.take_fit_scaled().is_none());
+    }
+
+    #[test]
+    fn test_offscreen_hint_is_centered() {
+        let (w, h) = (400, 100);
+        let mut buf = vec![render::BG_COLOR; (w * h) as usize];
+        Viewer::draw_hint(&mut buf, w, h, &[OFFSCREEN_HINT]);
+        let row = |y: u32| &buf[(y * w) as usize..((y + 1) * w) as usize];
+        // The box covers the middle row but not the top and bottom edges
+        assert!(row(h / 2).iter().any(|&px| px != render::BG_COLOR));
+        assert!(row(0).iter().all(|&px| px == render::BG_COLOR));
+        assert!(row(h - 1).iter().all(|&px| px == render::BG_COLOR));
+        // Tiny windows clip the box instead of panicking
+        let mut tiny = vec![0; 20];
+        Viewer::draw_hint(&mut tiny, 5, 4, &[OFFSCREEN_HINT, "second line"]);
+        Viewer::draw_hint(&mut tiny, 5, 4, &[]);
+    }
+}
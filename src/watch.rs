@@ -0,0 +1,137 @@
+use rustix::fs::inotify::{self, CreateFlags, WatchFlags};
+use std::fs;
+use std::mem::MaybeUninit;
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Watches the directory of one file (`--watch`) and reports when that file
+/// was rewritten. The directory rather than the file itself is watched, so
+/// editors that save by writing a temporary file and renaming it over the
+/// original are noticed too.
+pub struct FileWatcher {
+    fd: OwnedFd,
+    /// Current watch descriptor and the directory it covers.
+    dir_watch: Option<(i32, PathBuf)>,
+    /// The watched file and its size and mtime when last seen.
+    file: Option<(PathBuf, Option<FileStamp>)>,
+}
+
+/// Size and modification time, compared to tell whether a file changed.
+type FileStamp = (u64, SystemTime);
+
+fn stamp(path: &Path) -> Option<FileStamp> {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.len(), meta.modified().ok()?))
+}
+
+impl FileWatcher {
+    pub fn new() -> Result<Self, String> {
+        let fd = inotify::init(CreateFlags::CLOEXEC | CreateFlags::NONBLOCK)
+            .map_err(|e| format!("inotify_init failed: {}", e))?;
+        Ok(Self {
+            fd,
+            dir_watch: None,
+            file: None,
+        })
+    }
+
+    /// File descriptor that becomes readable when the directory changes.
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+
+    /// Watch `path` instead of the previous file. Cheap when it is already
+    /// the watched file.
+    pub fn watch(&mut self, path: &Path) {
+        if self.file.as_ref().is_some_and(|(p, _)| p == path) {
+            return;
+        }
+        self.file = Some((path.to_path_buf(), stamp(path)));
+
+        let dir = match path.parent() {
+            Some(d) if !d.as_os_str().is_empty() => d.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        if self.dir_watch.as_ref().is_some_and(|(_, d)| *d == dir) {
+            return;
+        }
+        if let Some((wd, _)) = self.dir_watch.take() {
+            let _ = inotify::remove_watch(&self.fd, wd);
+        }
+        let flags = WatchFlags::CLOSE_WRITE | WatchFlags::MOVED_TO | WatchFlags::CREATE;
+        match inotify::add_watch(&self.fd, &dir, flags) {
+            Ok(wd) => self.dir_watch = Some((wd, dir)),
+            Err(e) => eprintln!("Warning: cannot watch {}: {}", dir.display(), e),
+        }
+    }
+
+    /// Drain pending events and return whether the watched file now differs
+    /// (in size or mtime) from when it was last seen.
+    pub fn file_changed(&mut self) -> bool {
+        let name = self
+            .file
+            .as_ref()
+            .and_then(|(path, _)| path.file_name())
+            .map(|n| n.as_bytes().to_vec());
+        if !self.drain_for(name.as_deref()) {
+            return false;
+        }
+        let Some((path, last)) = &mut self.file else {
+            return false;
+        };
+        let now = stamp(path);
+        if now.is_none() || now == *last {
+            return false;
+        }
+        *last = now;
+        true
+    }
+
+    /// Read all queued events; true if one names `name`.
+    fn drain_for(&mut self, name: Option<&[u8]>) -> bool {
+        let mut buf = [MaybeUninit::uninit(); 4096];
+        let mut reader = inotify::Reader::new(&self.fd, &mut buf);
+        let mut matched = false;
+        while let Ok(event) = reader.next() {
+            let event_name = event.file_name().map(|n| n.to_bytes());
+            if name.is_some() && event_name == name {
+                matched = true;
+            }
+        }
+        matched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watcher_reports_rewrites_of_the_watched_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.png");
+        let other = dir.path().join("b.png");
+        fs::write(&file, b"one").unwrap();
+
+        let mut watcher = FileWatcher::new().unwrap();
+        watcher.watch(&file);
+        assert!(!watcher.file_changed());
+
+        // Another file in the directory does not count
+        fs::write(&other, b"other").unwrap();
+        assert!(!watcher.file_changed());
+
+        // Rewritten in place
+        fs::write(&file, b"two!").unwrap();
+        assert!(watcher.file_changed());
+        assert!(!watcher.file_changed());
+
+        // Replaced by renaming a temporary file over it
+        let tmp = dir.path().join(".a.png.tmp");
+        fs::write(&tmp, b"three, longer").unwrap();
+        fs::rename(&tmp, &file).unwrap();
+        assert!(watcher.file_changed());
+    }
+}
@@ -1,20 +1,27 @@
-use std::os::fd::{AsFd, OwnedFd};
+use std::io::{Read, Write};
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
 use rustix::fs::{memfd_create, MemfdFlags};
 use rustix::mm::{mmap, munmap, MapFlags, ProtFlags};
+use rustix::pipe::{pipe_with, PipeFlags};
 
 use wayland_client::protocol::{
-    wl_buffer, wl_callback, wl_compositor, wl_keyboard, wl_output, wl_registry, wl_seat, wl_shm,
-    wl_shm_pool, wl_surface,
+    wl_buffer, wl_callback, wl_compositor, wl_data_device, wl_data_device_manager, wl_data_offer,
+    wl_keyboard, wl_output, wl_pointer, wl_registry, wl_seat, wl_shm, wl_shm_pool, wl_surface,
 };
-use wayland_client::{delegate_noop, Connection, Dispatch, QueueHandle, WEnum};
+use wayland_client::{delegate_noop, event_created_child, Connection, Dispatch, Proxy};
+use wayland_client::{QueueHandle, WEnum};
 
+use crate::protocols::cursor_shape::{wp_cursor_shape_device_v1, wp_cursor_shape_manager_v1};
+use crate::protocols::presentation_time::{wp_presentation, wp_presentation_feedback};
 use crate::protocols::xdg_shell::{xdg_surface, xdg_toplevel, xdg_wm_base};
 use crate::protocols::wlr_layer_shell::{zwlr_layer_shell_v1, zwlr_layer_surface_v1};
 
 /// Keyboard event data passed to the application.
 pub struct KeyEvent {
-    #[allow(dead_code)]
     pub keycode: u32,
     pub keysym: u32,
     pub pressed: bool,
@@ -22,45 +29,196 @@ pub struct KeyEvent {
     pub shift: bool,
 }
 
+/// Linux evdev button codes (linux/input-event-codes.h).
+pub const BTN_LEFT: u32 = 0x110;
+pub const BTN_RIGHT: u32 = 0x111;
+
+/// Pointer button event data passed to the application.
+pub struct PointerButton {
+    pub button: u32,
+    pub pressed: bool,
+    /// Surface-local pointer position at the time of the click.
+    pub x: f64,
+    pub y: f64,
+    /// Alt was held (modifier for interactive move/resize).
+    pub alt: bool,
+}
+
 /// Events produced by the Wayland state for the application to handle.
 pub enum WaylandEvent {
     Configure { width: u32, height: u32 },
     Close,
     Key(KeyEvent),
+    /// Keyboard focus left the window; held keys should be treated as released.
+    KeyboardLeave,
+    PointerButton(PointerButton),
     FrameCallback,
+    /// Files were dropped onto the window (local paths from a `text/uri-list`).
+    FilesDropped(Vec<PathBuf>),
     /// A wallpaper layer surface has been configured with output dimensions.
-    WallpaperConfigure { output_idx: usize, width: u32, height: u32 },
+    /// `output_name` is the registry name of the output's `wl_output` global.
+    WallpaperConfigure {
+        output_name: u32,
+        width: u32,
+        height: u32,
+    },
 }
 
 /// Tracked output information.
 struct OutputInfo {
-    #[allow(dead_code)]
+    /// Registry name of the `wl_output` global.
     name: u32,
     output: wl_output::WlOutput,
     width: u32,
     height: u32,
+    /// Connector name such as `DP-1` (`wl_output` v4).
+    connector: Option<String>,
+    /// Human-readable description such as the monitor model (v4).
+    description: Option<String>,
+}
+
+/// Name to show for an output in messages: the connector, else the
+/// description, else the registry name.
+fn output_label(connector: Option<&str>, description: Option<&str>, name: u32) -> String {
+    match connector.filter(|c| !c.is_empty()).or(description) {
+        Some(label) if !label.is_empty() => label.to_string(),
+        _ => format!("output {}", name),
+    }
+}
+
+/// Whether an output gets a wallpaper: every output when no `--output`
+/// names were given, else only those whose connector name is listed.
+fn wants_wallpaper(selected: &[String], connector: Option<&str>) -> bool {
+    selected.is_empty() || connector.is_some_and(|c| selected.iter().any(|s| s == c))
+}
+
+/// Keysym for the few keys that still work when no xkb keymap could be
+/// loaded, by evdev keycode and assuming a US layout: enough to navigate
+/// and quit.
+fn fallback_keysym(keycode: u32) -> Option<u32> {
+    use xkbcommon_dl::keysyms;
+    let sym = match keycode {
+        1 => keysyms::Escape,
+        14 => keysyms::BackSpace,
+        16 => keysyms::q,
+        28 => keysyms::Return,
+        49 => keysyms::n,
+        25 => keysyms::p,
+        57 => keysyms::space,
+        103 => keysyms::Up,
+        105 => keysyms::Left,
+        106 => keysyms::Right,
+        108 => keysyms::Down,
+        _ => return None,
+    };
+    Some(sym)
+}
+
+/// Report a keymap that could not be loaded. A previous keymap stays in
+/// use; without one, keys go through `fallback_keysym`.
+fn warn_keymap_failure(state: &WaylandState, reason: &str) {
+    if state.xkb_state.is_null() {
+        eprintln!(
+            "Warning: {}; only q, Escape, Enter, Space, Backspace, n, p and the arrow keys work",
+            reason
+        );
+    } else {
+        eprintln!("Warning: {}; keeping the previous keymap", reason);
+    }
 }
 
 /// Per-output wallpaper surface with its own wl_surface, SHM buffer, and layer surface.
 pub(crate) struct WallpaperSurface {
+    /// Registry name of the output this surface is shown on.
+    output_name: u32,
     surface: wl_surface::WlSurface,
-    #[allow(dead_code)]
     layer_surface: zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
     shm_buf: ShmBuffer,
     pub width: u32,
     pub height: u32,
 }
 
-/// SHM double-buffer management.
+/// MIME type of a dropped file list.
+const URI_LIST_MIME: &str = "text/uri-list";
+
+/// Maximum number of SHM buffers per surface. Two are enough normally; a
+/// third is allocated only when the compositor still holds both.
+const MAX_SHM_BUFFERS: usize = 3;
+
+/// Window size when the compositor lets the client choose and no
+/// `--geometry` was given.
+const DEFAULT_WINDOW_SIZE: (u32, u32) = (800, 600);
+/// Largest share of the biggest output a window sized to its image may take.
+const MAX_OUTPUT_FRACTION: f64 = 0.9;
+
+/// Pointer idle time after which the cursor is hidden in fullscreen.
+const CURSOR_HIDE_DELAY: Duration = Duration::from_secs(3);
+
+/// `clk_id` of CLOCK_MONOTONIC in `wp_presentation.clock_id`.
+const CLOCK_MONOTONIC: u32 = 1;
+/// Presentation feedback older than this no longer predicts refreshes.
+const PRESENTATION_MAX_AGE: Duration = Duration::from_secs(1);
+/// Arrow cursor bitmap: `X` outline, `.` fill, space transparent.
+/// The hotspot is the tip at (0, 0).
+const CURSOR_ARROW: &[&str] = &[
+    "X",
+    "XX",
+    "X.X",
+    "X..X",
+    "X...X",
+    "X....X",
+    "X.....X",
+    "X......X",
+    "X.......X",
+    "X........X",
+    "X.....XXXXX",
+    "X..X..X",
+    "X.X X..X",
+    "XX  X..X",
+    "X    X..X",
+    "     X..X",
+    "      XX",
+];
+
+/// Build the ARGB8888 arrow cursor from `CURSOR_ARROW`.
+/// Returns (width, height, pixels).
+fn cursor_image() -> (u32, u32, Vec<u32>) {
+    let width = CURSOR_ARROW.iter().map(|row| row.len()).max().unwrap_or(0);
+    let height = CURSOR_ARROW.len();
+    let mut pixels = vec![0u32; width * height];
+    for (y, row) in CURSOR_ARROW.iter().enumerate() {
+        for (x, c) in row.bytes().enumerate() {
+            pixels[y * width + x] = match c {
+                b'X' => 0xFF000000,
+                b'.' => 0xFFFFFFFF,
+                _ => 0,
+            };
+        }
+    }
+    (width as u32, height as u32, pixels)
+}
+
+/// Surface showing the arrow cursor image.
+struct Cursor {
+    surface: wl_surface::WlSurface,
+    buffer: wl_buffer::WlBuffer,
+}
+
+/// SHM buffer management. Buffers are handed out round-robin, skipping any
+/// the compositor has not yet released.
 struct ShmBuffer {
     fd: OwnedFd,
     pool: Option<wl_shm_pool::WlShmPool>,
-    buffers: [Option<wl_buffer::WlBuffer>; 2],
+    buffers: Vec<wl_buffer::WlBuffer>,
+    /// Per-buffer flag: attached and not yet released by the compositor.
+    busy: Vec<bool>,
     mmap_ptr: *mut u8,
     mmap_len: usize,
     width: u32,
     height: u32,
     current: usize, // which buffer index to draw into
+    /// Queue handle for creating extra buffers on demand.
+    qh: Option<QueueHandle<WaylandState>>,
 }
 
 impl ShmBuffer {
@@ -69,15 +227,22 @@ impl ShmBuffer {
         Self {
             fd,
             pool: None,
-            buffers: [None, None],
+            buffers: Vec::new(),
+            busy: Vec::new(),
             mmap_ptr: std::ptr::null_mut(),
             mmap_len: 0,
             width: 0,
             height: 0,
             current: 0,
+            qh: None,
         }
     }
 
+    /// Size in bytes of one buffer.
+    fn buf_size(&self) -> usize {
+        self.width as usize * 4 * self.height as usize
+    }
+
     fn resize(
         &mut self,
         width: u32,
@@ -90,37 +255,51 @@ impl ShmBuffer {
         }
 
         // Destroy old buffers
-        for buf in &mut self.buffers {
-            if let Some(b) = buf.take() {
-                b.destroy();
-            }
+        for b in self.buffers.drain(..) {
+            b.destroy();
         }
+        self.busy.clear();
         if let Some(pool) = self.pool.take() {
             pool.destroy();
         }
-
-        // Unmap old memory
-        if !self.mmap_ptr.is_null() && self.mmap_len > 0 {
-            unsafe {
-                let _ = munmap(self.mmap_ptr as *mut std::ffi::c_void, self.mmap_len);
-            }
-            self.mmap_ptr = std::ptr::null_mut();
-            self.mmap_len = 0;
-        }
+        self.unmap();
 
         // Use checked arithmetic to prevent overflow in buffer size calculations
         let stride = (width as usize).checked_mul(4).expect("SHM stride overflow");
         let buf_size = stride.checked_mul(height as usize).expect("SHM buffer size overflow");
         let pool_size = buf_size.checked_mul(2).expect("SHM pool size overflow"); // double buffer
 
+        // wl_shm_pool sizes are i32; make sure the pool can grow to its maximum
+        let max_pool_size = buf_size
+            .checked_mul(MAX_SHM_BUFFERS)
+            .expect("SHM pool size overflow");
+        assert!(max_pool_size <= i32::MAX as usize, "SHM pool size overflow");
+
+        self.width = width;
+        self.height = height;
+        self.map(pool_size);
+
+        // Create new pool
+        let pool = shm.create_pool(self.fd.as_fd(), pool_size as i32, qh, ());
+        self.pool = Some(pool);
+        self.qh = Some(qh.clone());
+
+        // Start with two buffers
+        self.push_buffer();
+        self.push_buffer();
+        self.current = 0;
+    }
+
+    /// Size the memfd to `len` bytes and map it.
+    fn map(&mut self, len: usize) {
         // Resize the memfd
-        rustix::fs::ftruncate(&self.fd, pool_size as u64).expect("ftruncate failed");
+        rustix::fs::ftruncate(&self.fd, len as u64).expect("ftruncate failed");
 
         // Mmap it
         let ptr = unsafe {
             mmap(
                 std::ptr::null_mut(),
-                pool_size,
+                len,
                 ProtFlags::READ | ProtFlags::WRITE,
                 MapFlags::SHARED,
                 self.fd.as_fd(),
@@ -130,71 +309,122 @@ impl ShmBuffer {
         };
 
         self.mmap_ptr = ptr as *mut u8;
-        self.mmap_len = pool_size;
-        self.width = width;
-        self.height = height;
+        self.mmap_len = len;
+    }
 
-        // Create new pool
-        let pool = shm.create_pool(self.fd.as_fd(), pool_size as i32, qh, ());
+    fn unmap(&mut self) {
+        if !self.mmap_ptr.is_null() && self.mmap_len > 0 {
+            unsafe {
+                let _ = munmap(self.mmap_ptr as *mut std::ffi::c_void, self.mmap_len);
+            }
+        }
+        self.mmap_ptr = std::ptr::null_mut();
+        self.mmap_len = 0;
+    }
 
-        // Create two buffers
-        let b0 = pool.create_buffer(
-            0,
-            width as i32,
-            height as i32,
-            stride as i32,
-            wl_shm::Format::Xrgb8888,
-            qh,
-            (),
-        );
-        let b1 = pool.create_buffer(
-            buf_size as i32,
-            width as i32,
-            height as i32,
-            stride as i32,
+    /// Create a wl_buffer for the next slot in the pool.
+    fn push_buffer(&mut self) {
+        let (pool, qh) = match (&self.pool, &self.qh) {
+            (Some(p), Some(q)) => (p, q),
+            _ => return,
+        };
+        let stride = self.width as i32 * 4;
+        let offset = self.buffers.len() * self.buf_size();
+        let buffer = pool.create_buffer(
+            offset as i32,
+            self.width as i32,
+            self.height as i32,
+            stride,
             wl_shm::Format::Xrgb8888,
             qh,
             (),
         );
+        self.buffers.push(buffer);
+        self.busy.push(false);
+    }
 
-        self.pool = Some(pool);
-        self.buffers = [Some(b0), Some(b1)];
-        self.current = 0;
+    /// Grow the pool by one buffer. Returns false if the limit is reached.
+    fn grow(&mut self) -> bool {
+        if self.buffers.len() >= MAX_SHM_BUFFERS || self.pool.is_none() {
+            return false;
+        }
+        let new_len = (self.buffers.len() + 1) * self.buf_size();
+        self.unmap();
+        self.map(new_len);
+        if let Some(pool) = &self.pool {
+            pool.resize(new_len as i32);
+        }
+        self.push_buffer();
+        true
     }
 
-    /// Get a mutable slice to the current back buffer pixel data.
-    fn back_buffer_mut(&mut self) -> &mut [u32] {
-        let stride = self.width as usize * 4;
-        let buf_size = stride * self.height as usize;
-        let offset = self.current * buf_size;
+    /// Pick a buffer the compositor is not using, preferring `current`.
+    /// Allocates an extra buffer if all are busy. Returns None if no buffer
+    /// is free and the limit has been reached.
+    fn acquire(&mut self) -> Option<usize> {
+        let n = self.buffers.len();
+        if n == 0 {
+            return None;
+        }
+        if let Some(idx) = (0..n)
+            .map(|i| (self.current + i) % n)
+            .find(|&i| !self.busy[i])
+        {
+            self.current = idx;
+            return Some(idx);
+        }
+        if self.grow() {
+            self.current = self.buffers.len() - 1;
+            return Some(self.current);
+        }
+        None
+    }
+
+    /// Get a mutable slice to the pixel data of buffer `idx`.
+    fn buffer_mut(&mut self, idx: usize) -> &mut [u32] {
+        let offset = idx * self.buf_size();
         let ptr = unsafe { self.mmap_ptr.add(offset) as *mut u32 };
         let len = (self.width * self.height) as usize;
         unsafe { std::slice::from_raw_parts_mut(ptr, len) }
     }
 
-    /// Get the current back buffer wl_buffer and swap.
-    fn swap(&mut self) -> Option<&wl_buffer::WlBuffer> {
-        let buf = self.buffers[self.current].as_ref();
-        self.current = 1 - self.current;
-        buf
+    /// Mark buffer `idx` as attached and advance to the next buffer.
+    fn submit(&mut self, idx: usize) -> &wl_buffer::WlBuffer {
+        self.busy[idx] = true;
+        self.current = (idx + 1) % self.buffers.len();
+        &self.buffers[idx]
+    }
+
+    /// Handle `wl_buffer.release`. Returns true if the buffer belongs to this pool.
+    fn release(&mut self, buffer: &wl_buffer::WlBuffer) -> bool {
+        match self.buffers.iter().position(|b| b == buffer) {
+            Some(idx) => {
+                self.busy[idx] = false;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Copy `pixels` into a free buffer and return it, marked busy.
+    fn fill(&mut self, pixels: &[u32]) -> Option<&wl_buffer::WlBuffer> {
+        let idx = self.acquire()?;
+        let back = self.buffer_mut(idx);
+        let len = back.len().min(pixels.len());
+        back[..len].copy_from_slice(&pixels[..len]);
+        Some(self.submit(idx))
     }
 }
 
 impl Drop for ShmBuffer {
     fn drop(&mut self) {
-        for buf in &mut self.buffers {
-            if let Some(b) = buf.take() {
-                b.destroy();
-            }
+        for b in self.buffers.drain(..) {
+            b.destroy();
         }
         if let Some(pool) = self.pool.take() {
             pool.destroy();
         }
-        if !self.mmap_ptr.is_null() && self.mmap_len > 0 {
-            unsafe {
-                let _ = munmap(self.mmap_ptr as *mut std::ffi::c_void, self.mmap_len);
-            }
-        }
+        self.unmap();
     }
 }
 
@@ -203,18 +433,55 @@ pub struct WaylandState {
     pub running: bool,
     compositor: Option<wl_compositor::WlCompositor>,
     shm: Option<wl_shm::WlShm>,
-    #[allow(dead_code)]
     seat: Option<wl_seat::WlSeat>,
     wm_base: Option<xdg_wm_base::XdgWmBase>,
     surface: Option<wl_surface::WlSurface>,
     xdg_surface: Option<xdg_surface::XdgSurface>,
     toplevel: Option<xdg_toplevel::XdgToplevel>,
     keyboard: Option<wl_keyboard::WlKeyboard>,
+    pointer: Option<wl_pointer::WlPointer>,
+    data_device_manager: Option<wl_data_device_manager::WlDataDeviceManager>,
+    data_device: Option<wl_data_device::WlDataDevice>,
+    /// Offer of the drag currently over the window, if it carries a file list.
+    drag_offer: Option<wl_data_offer::WlDataOffer>,
+    /// File list of a drop that the drag source is still writing.
+    pending_drop: Option<PendingDrop>,
+    /// Last surface-local pointer position over the main window.
+    pointer_pos: (f64, f64),
+    /// Serial of the last pointer button press, needed for move/resize.
+    pointer_serial: u32,
+    /// Serial of the pointer enter while the pointer is over the window,
+    /// needed to set the cursor.
+    pointer_enter_serial: Option<u32>,
+    /// Themed cursors through `wp_cursor_shape_manager_v1`, when the
+    /// compositor has it; `cursor` below is the fallback.
+    cursor_shape_manager: Option<wp_cursor_shape_manager_v1::WpCursorShapeManagerV1>,
+    cursor_shape_device: Option<wp_cursor_shape_device_v1::WpCursorShapeDeviceV1>,
+    /// Presentation feedback through `wp_presentation`, when the compositor
+    /// has it, to pace animations to the display refresh.
+    presentation: Option<wp_presentation::WpPresentation>,
+    /// The presentation clock is CLOCK_MONOTONIC, the clock `Instant` uses;
+    /// timestamps of other clocks cannot be compared and are ignored.
+    presentation_monotonic: bool,
+    /// When the last frame reached the screen, and the refresh interval.
+    last_presented: Option<(Instant, Duration)>,
+    /// Arrow cursor, created on the first pointer enter.
+    cursor: Option<Cursor>,
+    cursor_hidden: bool,
+    /// Last pointer motion over the window, for hiding an idle cursor.
+    last_pointer_motion: Instant,
     shm_buf: ShmBuffer,
     configured: bool,
     pending_configure_size: Option<(u32, u32)>,
+    /// Window size used when the compositor leaves the choice to us.
+    default_size: (u32, u32),
+    /// Image size to open the window at (`--size-to-image`), shrunk to fit
+    /// the largest output. Takes precedence over `default_size`.
+    preferred_size: Option<(u32, u32)>,
     pub events: Vec<WaylandEvent>,
     fullscreen: bool,
+    /// Ask for fullscreen before the first commit (`--remember-geometry`).
+    start_fullscreen: bool,
     frame_pending: bool,
 
     // xkbcommon state
@@ -223,12 +490,75 @@ pub struct WaylandState {
     xkb_state: *mut xkbcommon_dl::xkb_state,
     ctrl_pressed: bool,
     shift_pressed: bool,
+    alt_pressed: bool,
+    /// Key repeat rate in characters per second (0 disables repeat).
+    repeat_rate: i32,
+    /// Delay in milliseconds before key repeat starts.
+    repeat_delay: i32,
 
     // Wallpaper mode
     pub wallpaper_mode: bool,
     outputs: Vec<OutputInfo>,
     layer_shell: Option<zwlr_layer_shell_v1::ZwlrLayerShellV1>,
     pub wallpaper_surfaces: Vec<WallpaperSurface>,
+    /// Connector names given with `--output`; empty means all outputs.
+    wallpaper_outputs: Vec<String>,
+    /// Set once the initial wallpaper surfaces exist; outputs added after
+    /// this point get their surface created on the fly.
+    wallpaper_started: bool,
+}
+
+/// Pick the resize edge for a pointer position: the window is split into
+/// thirds on each axis, so the center picks the nearest corner.
+fn resize_edge(x: f64, y: f64, width: u32, height: u32) -> xdg_toplevel::ResizeEdge {
+    use xdg_toplevel::ResizeEdge;
+    let third = |pos: f64, size: u32| -> i32 {
+        let size = size.max(1) as f64;
+        if pos < size / 3.0 {
+            -1
+        } else if pos >= size * 2.0 / 3.0 {
+            1
+        } else {
+            0
+        }
+    };
+    let mut h = third(x, width);
+    let mut v = third(y, height);
+    if h == 0 && v == 0 {
+        h = if x < width as f64 / 2.0 { -1 } else { 1 };
+        v = if y < height as f64 / 2.0 { -1 } else { 1 };
+    }
+    match (h, v) {
+        (-1, -1) => ResizeEdge::TopLeft,
+        (0, -1) => ResizeEdge::Top,
+        (1, -1) => ResizeEdge::TopRight,
+        (-1, 0) => ResizeEdge::Left,
+        (1, 0) => ResizeEdge::Right,
+        (-1, 1) => ResizeEdge::BottomLeft,
+        (0, 1) => ResizeEdge::Bottom,
+        _ => ResizeEdge::BottomRight,
+    }
+}
+
+/// First refresh strictly after `now` on the grid of `refresh` intervals
+/// starting at `last`.
+fn next_refresh_after(last: Instant, refresh: Duration, now: Instant) -> Instant {
+    let elapsed = now.saturating_duration_since(last).as_nanos();
+    let periods = elapsed / refresh.as_nanos().max(1) + 1;
+    last + refresh * periods as u32
+}
+
+/// Shrink `size` to fit within `MAX_OUTPUT_FRACTION` of `output`, keeping
+/// its aspect ratio. Sizes that already fit are returned unchanged.
+fn fit_window_size(size: (u32, u32), output: (u32, u32)) -> (u32, u32) {
+    let (w, h) = (size.0.max(1) as f64, size.1.max(1) as f64);
+    let max_w = output.0 as f64 * MAX_OUTPUT_FRACTION;
+    let max_h = output.1 as f64 * MAX_OUTPUT_FRACTION;
+    let scale = (max_w / w).min(max_h / h).min(1.0);
+    (
+        ((w * scale).round() as u32).max(1),
+        ((h * scale).round() as u32).max(1),
+    )
 }
 
 // Safety: WaylandState is only used from the main thread.
@@ -251,31 +581,90 @@ impl WaylandState {
             xdg_surface: None,
             toplevel: None,
             keyboard: None,
+            pointer: None,
+            data_device_manager: None,
+            data_device: None,
+            drag_offer: None,
+            pending_drop: None,
+            pointer_pos: (0.0, 0.0),
+            pointer_serial: 0,
+            pointer_enter_serial: None,
+            cursor_shape_manager: None,
+            cursor_shape_device: None,
+            presentation: None,
+            presentation_monotonic: false,
+            last_presented: None,
+            cursor: None,
+            cursor_hidden: false,
+            last_pointer_motion: Instant::now(),
             shm_buf: ShmBuffer::new(),
             configured: false,
             pending_configure_size: None,
+            default_size: DEFAULT_WINDOW_SIZE,
+            preferred_size: None,
             events: Vec::new(),
             fullscreen: false,
+            start_fullscreen: false,
             frame_pending: false,
             xkb_context,
             xkb_keymap: std::ptr::null_mut(),
             xkb_state: std::ptr::null_mut(),
             ctrl_pressed: false,
             shift_pressed: false,
+            alt_pressed: false,
+            // Common compositor defaults until RepeatInfo arrives
+            repeat_rate: 25,
+            repeat_delay: 600,
             wallpaper_mode,
             outputs: Vec::new(),
             layer_shell: None,
             wallpaper_surfaces: Vec::new(),
+            wallpaper_outputs: Vec::new(),
+            wallpaper_started: false,
+        }
+    }
+
+    /// Create the data device for drag and drop once both the seat and the
+    /// data device manager are bound.
+    fn init_data_device(&mut self, qh: &QueueHandle<WaylandState>) {
+        if self.data_device.is_some() {
+            return;
+        }
+        if let (Some(manager), Some(seat)) = (&self.data_device_manager, &self.seat) {
+            self.data_device = Some(manager.get_data_device(seat, qh, ()));
+        }
+    }
+
+    /// Create the cursor shape device once both the pointer and the cursor
+    /// shape manager are bound.
+    fn init_cursor_shape_device(&mut self, qh: &QueueHandle<WaylandState>) {
+        if self.cursor_shape_device.is_some() {
+            return;
+        }
+        if let (Some(manager), Some(pointer)) = (&self.cursor_shape_manager, &self.pointer) {
+            self.cursor_shape_device = Some(manager.get_pointer(pointer, qh, ()));
+        }
+    }
+
+    /// Create the toplevel window. Called once the globals are bound and the
+    /// output modes are known, so the first configure can be fitted to them.
+    pub fn create_window(&mut self, qh: &QueueHandle<WaylandState>) {
+        if self.xdg_surface.is_none() {
+            self.init_xdg_surface(qh);
         }
     }
 
     fn init_xdg_surface(&mut self, qh: &QueueHandle<WaylandState>) {
-        let wm_base = self.wm_base.as_ref().unwrap();
-        let surface = self.surface.as_ref().unwrap();
+        let (Some(wm_base), Some(surface)) = (&self.wm_base, &self.surface) else {
+            return;
+        };
 
         let xdg_surface = wm_base.get_xdg_surface(surface, qh, ());
         let toplevel = xdg_surface.get_toplevel(qh, ());
         toplevel.set_title("rimg".into());
+        if self.start_fullscreen {
+            toplevel.set_fullscreen(None);
+        }
 
         surface.commit();
 
@@ -283,6 +672,79 @@ impl WaylandState {
         self.toplevel = Some(toplevel);
     }
 
+    /// Set the window size to use when the compositor sends a 0x0 configure.
+    /// Later compositor-chosen sizes still take precedence.
+    pub fn set_default_size(&mut self, width: u32, height: u32) {
+        self.default_size = (width, height);
+    }
+
+    /// Open the window at `width`x`height` (shrunk to fit the largest output)
+    /// when the compositor sends a 0x0 configure.
+    pub fn set_preferred_size(&mut self, width: u32, height: u32) {
+        self.preferred_size = Some((width, height));
+    }
+
+    /// Restrict wallpaper mode to the outputs with these connector names.
+    pub fn set_wallpaper_outputs(&mut self, outputs: Vec<String>) {
+        self.wallpaper_outputs = outputs;
+    }
+
+    /// Open the window fullscreen.
+    pub fn set_start_fullscreen(&mut self, fullscreen: bool) {
+        self.start_fullscreen = fullscreen;
+    }
+
+    /// Whether the compositor last configured the window as fullscreen.
+    pub fn is_fullscreen(&self) -> bool {
+        self.fullscreen
+    }
+
+    /// File descriptor that becomes readable while a dropped file list is
+    /// being transferred; `read_drop` drains it.
+    pub fn drop_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.pending_drop.as_ref().map(|d| d.pipe.as_fd())
+    }
+
+    /// Read what the drag source has written of a dropped file list. Once it
+    /// closes its end, the list is parsed into `FilesDropped`.
+    pub fn read_drop(&mut self) {
+        let Some(pending) = &mut self.pending_drop else {
+            return;
+        };
+        // Bytes read before WouldBlock stay in `data` for the next call
+        match pending.pipe.read_to_end(&mut pending.data) {
+            Ok(_) => {
+                let text = String::from_utf8_lossy(&pending.data);
+                let paths = crate::image_loader::parse_uri_list(&text);
+                if !paths.is_empty() {
+                    self.events.push(WaylandEvent::FilesDropped(paths));
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return,
+            Err(e) => eprintln!("Warning: failed to read dropped files: {}", e),
+        }
+        if let Some(pending) = self.pending_drop.take() {
+            finish_offer(pending.offer);
+        }
+    }
+
+    /// Size for a configure that leaves the choice to the client.
+    fn initial_size(&self) -> (u32, u32) {
+        let Some(size) = self.preferred_size else {
+            return self.default_size;
+        };
+        let largest = self
+            .outputs
+            .iter()
+            .filter(|o| o.width > 0 && o.height > 0)
+            .max_by_key(|o| o.width as u64 * o.height as u64)
+            .map(|o| (o.width, o.height));
+        match largest {
+            Some(output) => fit_window_size(size, output),
+            None => size,
+        }
+    }
+
     /// Set the window title.
     pub fn set_title(&self, title: &str) {
         if let Some(toplevel) = &self.toplevel {
@@ -301,34 +763,144 @@ impl WaylandState {
         }
     }
 
-    /// Write pixel data to the back buffer and present.
-    pub fn present(&mut self, pixels: &[u32]) {
-        if self.shm_buf.width == 0 || self.shm_buf.height == 0 {
+    /// Start an interactive window move, following the pointer button
+    /// press that triggered it.
+    pub fn start_move(&self) {
+        if let (Some(toplevel), Some(seat)) = (&self.toplevel, &self.seat) {
+            toplevel._move(seat, self.pointer_serial);
+        }
+    }
+
+    /// Start an interactive resize from the window edge or corner nearest
+    /// to the pointer.
+    pub fn start_resize(&self) {
+        if self.fullscreen {
             return;
         }
+        if let (Some(toplevel), Some(seat)) = (&self.toplevel, &self.seat) {
+            let (x, y) = self.pointer_pos;
+            let edge = resize_edge(x, y, self.shm_buf.width, self.shm_buf.height);
+            toplevel.resize(seat, self.pointer_serial, edge);
+        }
+    }
 
-        let back = self.shm_buf.back_buffer_mut();
-        let len = back.len().min(pixels.len());
-        back[..len].copy_from_slice(&pixels[..len]);
+    /// Show the arrow cursor over the window: the theme's default cursor
+    /// if the compositor offers cursor shapes, our own arrow otherwise.
+    fn show_cursor(&mut self, qh: &QueueHandle<WaylandState>) {
+        let (Some(pointer), Some(serial)) = (self.pointer.clone(), self.pointer_enter_serial)
+        else {
+            return;
+        };
+        if let Some(device) = &self.cursor_shape_device {
+            device.set_shape(serial, wp_cursor_shape_device_v1::Shape::Default);
+            self.cursor_hidden = false;
+            return;
+        }
+        if self.cursor.is_none() {
+            self.cursor = self.create_cursor(qh);
+        }
+        if let Some(cursor) = &self.cursor {
+            pointer.set_cursor(serial, Some(&cursor.surface), 0, 0);
+            self.cursor_hidden = false;
+        }
+    }
+
+    /// Upload the arrow cursor image into its own surface.
+    fn create_cursor(&self, qh: &QueueHandle<WaylandState>) -> Option<Cursor> {
+        let (compositor, shm) = (self.compositor.as_ref()?, self.shm.as_ref()?);
+        let (width, height, pixels) = cursor_image();
+        let bytes: Vec<u8> = pixels.iter().flat_map(|p| p.to_ne_bytes()).collect();
+
+        let fd = memfd_create(c"rimg-cursor", MemfdFlags::CLOEXEC).ok()?;
+        let mut file = std::fs::File::from(fd);
+        file.write_all(&bytes).ok()?;
+        let pool = shm.create_pool(file.as_fd(), bytes.len() as i32, qh, ());
+        let buffer = pool.create_buffer(
+            0,
+            width as i32,
+            height as i32,
+            width as i32 * 4,
+            wl_shm::Format::Argb8888,
+            qh,
+            (),
+        );
+        // The buffer keeps the pool's memory alive
+        pool.destroy();
+
+        let surface = compositor.create_surface(qh, ());
+        surface.attach(Some(&buffer), 0, 0);
+        surface.damage_buffer(0, 0, width as i32, height as i32);
+        surface.commit();
+        Some(Cursor { surface, buffer })
+    }
+
+    /// When the cursor should be hidden for inactivity: only in fullscreen,
+    /// while the pointer is over the window.
+    pub fn cursor_hide_deadline(&self) -> Option<Instant> {
+        (self.fullscreen && self.pointer_enter_serial.is_some() && !self.cursor_hidden)
+            .then(|| self.last_pointer_motion + CURSOR_HIDE_DELAY)
+    }
+
+    /// Hide the cursor once the pointer has been idle long enough.
+    pub fn hide_idle_cursor(&mut self) {
+        let idle = self
+            .cursor_hide_deadline()
+            .is_some_and(|deadline| Instant::now() >= deadline);
+        if !idle {
+            return;
+        }
+        if let (Some(pointer), Some(serial)) = (&self.pointer, self.pointer_enter_serial) {
+            pointer.set_cursor(serial, None, 0, 0);
+            self.cursor_hidden = true;
+        }
+    }
+
+    /// Write pixel data to a free buffer and present.
+    /// Returns false if every buffer is still held by the compositor; the
+    /// caller should retry after the next `wl_buffer.release`.
+    pub fn present(&mut self, pixels: &[u32]) -> bool {
+        if self.shm_buf.width == 0 || self.shm_buf.height == 0 {
+            return true;
+        }
 
+        let (width, height) = (self.shm_buf.width as i32, self.shm_buf.height as i32);
         let surface = self.surface.as_ref().unwrap();
-        if let Some(buffer) = self.shm_buf.swap() {
-            surface.attach(Some(buffer), 0, 0);
-            surface.damage_buffer(0, 0, self.shm_buf.width as i32, self.shm_buf.height as i32);
-            surface.commit();
+        match self.shm_buf.fill(pixels) {
+            Some(buffer) => {
+                surface.attach(Some(buffer), 0, 0);
+                surface.damage_buffer(0, 0, width, height);
+                surface.commit();
+                true
+            }
+            None => false,
         }
     }
 
-    /// Request a frame callback for animation.
+    /// Request a frame callback for animation, and presentation feedback
+    /// for the same commit.
     pub fn request_frame(&mut self, qh: &QueueHandle<WaylandState>) {
         if !self.frame_pending {
             if let Some(surface) = &self.surface {
                 surface.frame(qh, ());
+                if let Some(presentation) = &self.presentation {
+                    presentation.feedback(surface, qh, ());
+                }
                 self.frame_pending = true;
             }
         }
     }
 
+    /// The next display refresh after now, predicted from the last
+    /// presentation feedback. `None` without recent feedback.
+    pub fn next_refresh(&self) -> Option<Instant> {
+        let (last, refresh) = self.last_presented?;
+        let now = Instant::now();
+        if now.duration_since(last) > PRESENTATION_MAX_AGE {
+            return None;
+        }
+        Some(next_refresh_after(last, refresh, now))
+    }
+
     /// Resize SHM buffers (called after configure).
     pub fn resize_buffers(&mut self, width: u32, height: u32, qh: &QueueHandle<WaylandState>) {
         if let Some(shm) = &self.shm.clone() {
@@ -336,6 +908,11 @@ impl WaylandState {
         }
     }
 
+    /// Key repeat settings as (rate per second, delay in ms).
+    pub fn repeat_info(&self) -> (i32, i32) {
+        (self.repeat_rate, self.repeat_delay)
+    }
+
     #[allow(dead_code)]
     pub fn width(&self) -> u32 {
         self.shm_buf.width
@@ -351,8 +928,40 @@ impl WaylandState {
         self.layer_shell.is_some()
     }
 
-    /// Create wallpaper layer surfaces for all discovered outputs.
+    /// Name of the output with the given registry name for messages, e.g.
+    /// `DP-1`.
+    pub fn output_label(&self, output_name: u32) -> String {
+        let info = self.outputs.iter().find(|o| o.name == output_name);
+        output_label(
+            info.and_then(|o| o.connector.as_deref()),
+            info.and_then(|o| o.description.as_deref()),
+            output_name,
+        )
+    }
+
+    /// Create wallpaper layer surfaces for the discovered outputs, limited to
+    /// the `--output` names if any were given.
     pub fn create_wallpaper_surfaces(&mut self, qh: &QueueHandle<WaylandState>) {
+        for wanted in &self.wallpaper_outputs {
+            let mut known = self.outputs.iter().filter_map(|o| o.connector.as_deref());
+            if !known.any(|c| c == wanted) {
+                eprintln!("Warning: no output named '{}'", wanted);
+            }
+        }
+        let names: Vec<u32> = self
+            .outputs
+            .iter()
+            .filter(|o| wants_wallpaper(&self.wallpaper_outputs, o.connector.as_deref()))
+            .map(|o| o.name)
+            .collect();
+        for name in names {
+            self.create_wallpaper_surface(name, qh);
+        }
+        self.wallpaper_started = true;
+    }
+
+    /// Create a wallpaper layer surface for the output with the given registry name.
+    fn create_wallpaper_surface(&mut self, output_name: u32, qh: &QueueHandle<WaylandState>) {
         let layer_shell = match &self.layer_shell {
             Some(ls) => ls.clone(),
             None => return,
@@ -361,50 +970,70 @@ impl WaylandState {
             Some(c) => c.clone(),
             None => return,
         };
+        let output = match self.outputs.iter().find(|o| o.name == output_name) {
+            Some(info) => info.output.clone(),
+            None => return,
+        };
 
-        for (idx, output_info) in self.outputs.iter().enumerate() {
-            let surface = compositor.create_surface(qh, ());
-            let layer_surface = layer_shell.get_layer_surface(
-                &surface,
-                Some(&output_info.output),
-                zwlr_layer_shell_v1::Layer::Background,
-                "wallpaper".into(),
-                qh,
-                idx,
-            );
-
-            // Anchor to all four edges
-            layer_surface.set_anchor(
-                zwlr_layer_surface_v1::Anchor::Top
-                    | zwlr_layer_surface_v1::Anchor::Bottom
-                    | zwlr_layer_surface_v1::Anchor::Left
-                    | zwlr_layer_surface_v1::Anchor::Right,
-            );
-            // Exclusive zone -1: extend under panels
-            layer_surface.set_exclusive_zone(-1);
-            // No keyboard interactivity
-            layer_surface.set_keyboard_interactivity(
-                zwlr_layer_surface_v1::KeyboardInteractivity::None,
-            );
-            // Size 0,0: let compositor assign output dimensions
-            layer_surface.set_size(0, 0);
-
-            // Initial commit without buffer to trigger configure
-            surface.commit();
-
-            self.wallpaper_surfaces.push(WallpaperSurface {
-                surface,
-                layer_surface,
-                shm_buf: ShmBuffer::new(),
-                width: 0,
-                height: 0,
-            });
+        let surface = compositor.create_surface(qh, ());
+        let layer_surface = layer_shell.get_layer_surface(
+            &surface,
+            Some(&output),
+            zwlr_layer_shell_v1::Layer::Background,
+            "wallpaper".into(),
+            qh,
+            output_name,
+        );
+
+        // Anchor to all four edges
+        layer_surface.set_anchor(
+            zwlr_layer_surface_v1::Anchor::Top
+                | zwlr_layer_surface_v1::Anchor::Bottom
+                | zwlr_layer_surface_v1::Anchor::Left
+                | zwlr_layer_surface_v1::Anchor::Right,
+        );
+        // Exclusive zone -1: extend under panels
+        layer_surface.set_exclusive_zone(-1);
+        // No keyboard interactivity
+        layer_surface
+            .set_keyboard_interactivity(zwlr_layer_surface_v1::KeyboardInteractivity::None);
+        // Size 0,0: let compositor assign output dimensions
+        layer_surface.set_size(0, 0);
+
+        // Initial commit without buffer to trigger configure
+        surface.commit();
+
+        self.wallpaper_surfaces.push(WallpaperSurface {
+            output_name,
+            surface,
+            layer_surface,
+            shm_buf: ShmBuffer::new(),
+            width: 0,
+            height: 0,
+        });
+    }
+
+    /// Tear down the wallpaper surface shown on the given output, if any.
+    fn destroy_wallpaper_surface(&mut self, output_name: u32) {
+        if let Some(pos) = self
+            .wallpaper_surfaces
+            .iter()
+            .position(|ws| ws.output_name == output_name)
+        {
+            let ws = self.wallpaper_surfaces.remove(pos);
+            ws.layer_surface.destroy();
+            ws.surface.destroy();
+            // ws.shm_buf is released by its Drop impl
         }
     }
 
     /// Write pixel data to a wallpaper surface's back buffer and present.
-    pub fn present_wallpaper(&mut self, output_idx: usize, pixels: &[u32]) {
-        let ws = match self.wallpaper_surfaces.get_mut(output_idx) {
+    pub fn present_wallpaper(&mut self, output_name: u32, pixels: &[u32]) {
+        let ws = match self
+            .wallpaper_surfaces
+            .iter_mut()
+            .find(|ws| ws.output_name == output_name)
+        {
             Some(ws) => ws,
             None => return,
         };
@@ -418,11 +1047,7 @@ impl WaylandState {
             return;
         }
 
-        let back = ws.shm_buf.back_buffer_mut();
-        let len = back.len().min(pixels.len());
-        back[..len].copy_from_slice(&pixels[..len]);
-
-        if let Some(buffer) = ws.shm_buf.swap() {
+        if let Some(buffer) = ws.shm_buf.fill(pixels) {
             ws.surface.attach(Some(buffer), 0, 0);
             ws.surface.damage_buffer(0, 0, ws.width as i32, ws.height as i32);
             ws.surface.commit();
@@ -432,7 +1057,7 @@ impl WaylandState {
     /// Resize a wallpaper surface's SHM buffers.
     pub fn resize_wallpaper_buffers(
         &mut self,
-        output_idx: usize,
+        output_name: u32,
         width: u32,
         height: u32,
         qh: &QueueHandle<WaylandState>,
@@ -441,7 +1066,11 @@ impl WaylandState {
             Some(s) => s.clone(),
             None => return,
         };
-        if let Some(ws) = self.wallpaper_surfaces.get_mut(output_idx) {
+        if let Some(ws) = self
+            .wallpaper_surfaces
+            .iter_mut()
+            .find(|ws| ws.output_name == output_name)
+        {
             ws.width = width;
             ws.height = height;
             ws.shm_buf.resize(width, height, &shm, qh);
@@ -461,6 +1090,19 @@ impl Drop for WaylandState {
         if !self.xkb_context.is_null() {
             unsafe { (xkb.xkb_context_unref)(self.xkb_context) };
         }
+        if let Some(cursor) = self.cursor.take() {
+            cursor.surface.destroy();
+            cursor.buffer.destroy();
+        }
+        if let Some(device) = self.cursor_shape_device.take() {
+            device.destroy();
+        }
+        if let Some(manager) = self.cursor_shape_manager.take() {
+            manager.destroy();
+        }
+        if let Some(presentation) = self.presentation.take() {
+            presentation.destroy();
+        }
     }
 }
 
@@ -475,6 +1117,21 @@ impl Dispatch<wl_registry::WlRegistry, ()> for WaylandState {
         _: &Connection,
         qh: &QueueHandle<Self>,
     ) {
+        if let wl_registry::Event::GlobalRemove { name } = event {
+            // Outputs are the only globals we expect to go away at runtime
+            if let Some(pos) = state.outputs.iter().position(|o| o.name == name) {
+                if state.wallpaper_mode && crate::image_loader::verbose() {
+                    eprintln!("Output {} removed", state.output_label(name));
+                }
+                state.destroy_wallpaper_surface(name);
+                let info = state.outputs.remove(pos);
+                if info.output.version() >= 3 {
+                    info.output.release();
+                }
+            }
+            return;
+        }
+
         if let wl_registry::Event::Global {
             name,
             interface,
@@ -490,13 +1147,6 @@ impl Dispatch<wl_registry::WlRegistry, ()> for WaylandState {
                         state.surface = Some(surface);
                     }
                     state.compositor = Some(compositor);
-
-                    if !state.wallpaper_mode
-                        && state.wm_base.is_some()
-                        && state.xdg_surface.is_none()
-                    {
-                        state.init_xdg_surface(qh);
-                    }
                 }
                 "wl_shm" => {
                     let shm = registry.bind::<wl_shm::WlShm, _, _>(name, 1, qh, ());
@@ -504,30 +1154,66 @@ impl Dispatch<wl_registry::WlRegistry, ()> for WaylandState {
                 }
                 "wl_seat" => {
                     if !state.wallpaper_mode {
-                        registry.bind::<wl_seat::WlSeat, _, _>(name, 4.min(version), qh, ());
+                        let seat =
+                            registry.bind::<wl_seat::WlSeat, _, _>(name, 4.min(version), qh, ());
+                        state.seat = Some(seat);
+                        state.init_data_device(qh);
                     }
                 }
+                "wl_data_device_manager" if !state.wallpaper_mode => {
+                    let manager = registry
+                        .bind::<wl_data_device_manager::WlDataDeviceManager, _, _>(
+                            name,
+                            3.min(version),
+                            qh,
+                            (),
+                        );
+                    state.data_device_manager = Some(manager);
+                    state.init_data_device(qh);
+                }
+                "wp_cursor_shape_manager_v1" if !state.wallpaper_mode => {
+                    let manager = registry
+                        .bind::<wp_cursor_shape_manager_v1::WpCursorShapeManagerV1, _, _>(
+                            name,
+                            1,
+                            qh,
+                            (),
+                        );
+                    state.cursor_shape_manager = Some(manager);
+                    state.init_cursor_shape_device(qh);
+                }
+                "wp_presentation" if !state.wallpaper_mode => {
+                    let presentation =
+                        registry.bind::<wp_presentation::WpPresentation, _, _>(name, 1, qh, ());
+                    state.presentation = Some(presentation);
+                }
                 "xdg_wm_base" => {
                     if !state.wallpaper_mode {
                         let wm_base =
                             registry.bind::<xdg_wm_base::XdgWmBase, _, _>(name, 1, qh, ());
                         state.wm_base = Some(wm_base);
-
-                        if state.surface.is_some() && state.xdg_surface.is_none() {
-                            state.init_xdg_surface(qh);
-                        }
                     }
                 }
                 "wl_output" => {
-                    if state.wallpaper_mode {
-                        let output = registry
-                            .bind::<wl_output::WlOutput, _, _>(name, 2.min(version), qh, ());
-                        state.outputs.push(OutputInfo {
-                            name,
-                            output,
-                            width: 0,
-                            height: 0,
-                        });
+                    // Tracked in windowed mode too, to size the window to its image
+                    // Version 4 adds the connector name and description
+                    let output =
+                        registry.bind::<wl_output::WlOutput, _, _>(name, 4.min(version), qh, ());
+                    state.outputs.push(OutputInfo {
+                        name,
+                        output,
+                        width: 0,
+                        height: 0,
+                        connector: None,
+                        description: None,
+                    });
+                    // Hotplugged output: give it a wallpaper right away. With
+                    // --output that waits for its name event instead.
+                    if state.wallpaper_mode
+                        && state.wallpaper_started
+                        && state.wallpaper_outputs.is_empty()
+                    {
+                        state.create_wallpaper_surface(name, qh);
                     }
                 }
                 "zwlr_layer_shell_v1" => {
@@ -576,15 +1262,17 @@ impl Dispatch<xdg_surface::XdgSurface, ()> for WaylandState {
         state.configured = true;
 
         // If we got a pending size from the toplevel configure, emit it now
+        let (default_w, default_h) = state.initial_size();
         if let Some((w, h)) = state.pending_configure_size.take() {
-            let width = if w == 0 { 800 } else { w };
-            let height = if h == 0 { 600 } else { h };
+            let width = if w == 0 { default_w } else { w };
+            let height = if h == 0 { default_h } else { h };
             state.events.push(WaylandEvent::Configure { width, height });
         } else if state.shm_buf.width == 0 {
             // First configure with no size hint — use default
-            state
-                .events
-                .push(WaylandEvent::Configure { width: 800, height: 600 });
+            state.events.push(WaylandEvent::Configure {
+                width: default_w,
+                height: default_h,
+            });
         }
     }
 }
@@ -638,10 +1326,143 @@ impl Dispatch<wl_seat::WlSeat, ()> for WaylandState {
                 let kb = seat.get_keyboard(qh, ());
                 state.keyboard = Some(kb);
             }
+            // Pointer input only drives the toplevel window
+            if caps.contains(wl_seat::Capability::Pointer)
+                && state.pointer.is_none()
+                && !state.wallpaper_mode
+            {
+                state.pointer = Some(seat.get_pointer(qh, ()));
+                state.init_cursor_shape_device(qh);
+            }
         }
     }
 }
 
+impl Dispatch<wl_data_device::WlDataDevice, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &wl_data_device::WlDataDevice,
+        event: wl_data_device::Event,
+        _: &(),
+        conn: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_data_device::Event::Enter { serial, id, .. } => {
+                if let Some(old) = state.drag_offer.take() {
+                    old.destroy();
+                }
+                let Some(offer) = id else {
+                    return;
+                };
+                let has_uris = offer
+                    .data::<AtomicBool>()
+                    .is_some_and(|uris| uris.load(Ordering::Relaxed));
+                if has_uris {
+                    offer.accept(serial, Some(URI_LIST_MIME.to_string()));
+                    if offer.version() >= 3 {
+                        let copy = wl_data_device_manager::DndAction::Copy;
+                        offer.set_actions(copy, copy);
+                    }
+                    state.drag_offer = Some(offer);
+                } else {
+                    offer.accept(serial, None);
+                    offer.destroy();
+                }
+            }
+            wl_data_device::Event::Leave => {
+                if let Some(offer) = state.drag_offer.take() {
+                    offer.destroy();
+                }
+            }
+            wl_data_device::Event::Drop => {
+                if let Some(offer) = state.drag_offer.take() {
+                    // The source writes the list asynchronously; the main
+                    // loop reads it through drop_fd/read_drop
+                    match receive_offer(conn, &offer, URI_LIST_MIME) {
+                        Ok(pipe) => {
+                            let pending = PendingDrop {
+                                offer,
+                                pipe,
+                                data: Vec::new(),
+                            };
+                            if let Some(old) = state.pending_drop.replace(pending) {
+                                finish_offer(old.offer);
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("Warning: failed to read dropped files: {}", e);
+                            finish_offer(offer);
+                        }
+                    }
+                }
+            }
+            wl_data_device::Event::Selection { id: Some(offer) } => {
+                // Clipboard contents are not used
+                offer.destroy();
+            }
+            _ => {}
+        }
+    }
+
+    // New offers start out without a file list until their Offer events
+    event_created_child!(WaylandState, wl_data_device::WlDataDevice, [
+        wl_data_device::EVT_DATA_OFFER_OPCODE => (
+            wl_data_offer::WlDataOffer,
+            AtomicBool::new(false)
+        ),
+    ]);
+}
+
+/// Whether the offer lists `text/uri-list` among its MIME types.
+impl Dispatch<wl_data_offer::WlDataOffer, AtomicBool> for WaylandState {
+    fn event(
+        _: &mut Self,
+        _: &wl_data_offer::WlDataOffer,
+        event: wl_data_offer::Event,
+        has_uris: &AtomicBool,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_data_offer::Event::Offer { mime_type } = event {
+            if mime_type == URI_LIST_MIME {
+                has_uris.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// A dropped offer whose data is still arriving through `pipe`.
+struct PendingDrop {
+    offer: wl_data_offer::WlDataOffer,
+    pipe: std::fs::File,
+    data: Vec<u8>,
+}
+
+/// Ask for the offer's data in `mime_type` and return the non-blocking read
+/// end of the pipe it is written to.
+fn receive_offer(
+    conn: &Connection,
+    offer: &wl_data_offer::WlDataOffer,
+    mime_type: &str,
+) -> Result<std::fs::File, String> {
+    let (read_fd, write_fd) = pipe_with(PipeFlags::CLOEXEC).map_err(|e| e.to_string())?;
+    // Only the read end: the source client writes with blocking semantics
+    rustix::fs::fcntl_setfl(&read_fd, rustix::fs::OFlags::NONBLOCK).map_err(|e| e.to_string())?;
+    offer.receive(mime_type.to_string(), write_fd.as_fd());
+    drop(write_fd);
+    conn.flush().map_err(|e| e.to_string())?;
+    Ok(std::fs::File::from(read_fd))
+}
+
+/// Tell the source the drop is done and release the offer.
+fn finish_offer(offer: wl_data_offer::WlDataOffer) {
+    if offer.version() >= 3 {
+        offer.finish();
+    }
+    offer.destroy();
+}
+
 impl Dispatch<wl_keyboard::WlKeyboard, ()> for WaylandState {
     fn event(
         state: &mut Self,
@@ -668,33 +1489,48 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for WaylandState {
                         )
                     };
 
-                    if let Ok(ptr) = map {
-                        let keymap = unsafe {
-                            (xkb.xkb_keymap_new_from_string)(
-                                state.xkb_context,
-                                ptr as *const std::os::raw::c_char,
-                                xkbcommon_dl::xkb_keymap_format::XKB_KEYMAP_FORMAT_TEXT_V1,
-                                xkbcommon_dl::xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
-                            )
-                        };
-
-                        unsafe {
-                            let _ = munmap(ptr, size as usize);
+                    let ptr = match map {
+                        Ok(ptr) => ptr,
+                        Err(e) => {
+                            warn_keymap_failure(state, &format!("cannot map keymap: {}", e));
+                            return;
                         }
+                    };
+                    let keymap = unsafe {
+                        (xkb.xkb_keymap_new_from_string)(
+                            state.xkb_context,
+                            ptr as *const std::os::raw::c_char,
+                            xkbcommon_dl::xkb_keymap_format::XKB_KEYMAP_FORMAT_TEXT_V1,
+                            xkbcommon_dl::xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
+                        )
+                    };
 
-                        if !keymap.is_null() {
-                            // Clean up old state/keymap
-                            if !state.xkb_state.is_null() {
-                                unsafe { (xkb.xkb_state_unref)(state.xkb_state) };
-                            }
-                            if !state.xkb_keymap.is_null() {
-                                unsafe { (xkb.xkb_keymap_unref)(state.xkb_keymap) };
-                            }
+                    unsafe {
+                        let _ = munmap(ptr, size as usize);
+                    }
 
-                            state.xkb_keymap = keymap;
-                            state.xkb_state = unsafe { (xkb.xkb_state_new)(keymap) };
-                        }
+                    if keymap.is_null() {
+                        warn_keymap_failure(state, "cannot compile keymap");
+                        return;
+                    }
+                    let xkb_state = unsafe { (xkb.xkb_state_new)(keymap) };
+                    if xkb_state.is_null() {
+                        unsafe { (xkb.xkb_keymap_unref)(keymap) };
+                        warn_keymap_failure(state, "cannot create keyboard state");
+                        return;
                     }
+
+                    // Only replace the old keymap once the new one is usable
+                    if !state.xkb_state.is_null() {
+                        unsafe { (xkb.xkb_state_unref)(state.xkb_state) };
+                    }
+                    if !state.xkb_keymap.is_null() {
+                        unsafe { (xkb.xkb_keymap_unref)(state.xkb_keymap) };
+                    }
+                    state.xkb_keymap = keymap;
+                    state.xkb_state = xkb_state;
+                } else {
+                    warn_keymap_failure(state, "unsupported keymap format");
                 }
             }
             wl_keyboard::Event::Key {
@@ -702,14 +1538,16 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for WaylandState {
                 state: key_state,
                 ..
             } => {
-                if state.xkb_state.is_null() {
-                    return;
-                }
-
+                let keysym = if state.xkb_state.is_null() {
+                    match fallback_keysym(key) {
+                        Some(sym) => sym,
+                        None => return,
+                    }
+                } else {
+                    // Wayland keycodes are evdev keycodes; xkb expects evdev + 8
+                    unsafe { (xkb.xkb_state_key_get_one_sym)(state.xkb_state, key + 8) }
+                };
                 let pressed = matches!(key_state, WEnum::Value(wl_keyboard::KeyState::Pressed));
-                // Wayland keycodes are evdev keycodes; xkb expects evdev + 8
-                let keycode = key + 8;
-                let keysym = unsafe { (xkb.xkb_state_key_get_one_sym)(state.xkb_state, keycode) };
 
                 state.events.push(WaylandEvent::Key(KeyEvent {
                     keycode: key,
@@ -752,8 +1590,84 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for WaylandState {
                             xkbcommon_dl::xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
                         )
                     } == 1;
+                    state.alt_pressed = unsafe {
+                        (xkb.xkb_state_mod_name_is_active)(
+                            state.xkb_state,
+                            xkbcommon_dl::XKB_MOD_NAME_ALT.as_ptr().cast(),
+                            xkbcommon_dl::xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
+                        )
+                    } == 1;
                 }
             }
+            wl_keyboard::Event::RepeatInfo { rate, delay } => {
+                state.repeat_rate = rate.max(0);
+                state.repeat_delay = delay.max(0);
+            }
+            wl_keyboard::Event::Leave { .. } => {
+                state.events.push(WaylandEvent::KeyboardLeave);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_pointer::WlPointer, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &wl_pointer::WlPointer,
+        event: wl_pointer::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_pointer::Event::Enter {
+                serial,
+                surface_x,
+                surface_y,
+                ..
+            } => {
+                state.pointer_pos = (surface_x, surface_y);
+                state.pointer_enter_serial = Some(serial);
+                state.last_pointer_motion = Instant::now();
+                state.show_cursor(qh);
+            }
+            wl_pointer::Event::Leave { .. } => {
+                state.pointer_enter_serial = None;
+            }
+            wl_pointer::Event::Motion {
+                surface_x,
+                surface_y,
+                ..
+            } => {
+                state.pointer_pos = (surface_x, surface_y);
+                state.last_pointer_motion = Instant::now();
+                if state.cursor_hidden {
+                    state.show_cursor(qh);
+                }
+            }
+            wl_pointer::Event::Button {
+                serial,
+                button,
+                state: button_state,
+                ..
+            } => {
+                let pressed =
+                    matches!(button_state, WEnum::Value(wl_pointer::ButtonState::Pressed));
+                if pressed {
+                    state.pointer_serial = serial;
+                }
+                let (x, y) = state.pointer_pos;
+                state
+                    .events
+                    .push(WaylandEvent::PointerButton(PointerButton {
+                        button,
+                        pressed,
+                        x,
+                        y,
+                        alt: state.alt_pressed,
+                    }));
+            }
             _ => {}
         }
     }
@@ -782,25 +1696,39 @@ impl Dispatch<wl_output::WlOutput, ()> for WaylandState {
         event: wl_output::Event,
         _: &(),
         _: &Connection,
-        _: &QueueHandle<Self>,
+        qh: &QueueHandle<Self>,
     ) {
-        if let wl_output::Event::Mode {
-            flags: WEnum::Value(flags),
-            width,
-            height,
-            ..
-        } = event
-        {
-            if flags.contains(wl_output::Mode::Current) {
-                // Find and update the matching output
-                for info in &mut state.outputs {
-                    if info.output == *output {
-                        info.width = width as u32;
-                        info.height = height as u32;
-                        break;
-                    }
+        let Some(info) = state.outputs.iter_mut().find(|o| o.output == *output) else {
+            return;
+        };
+        let name = info.name;
+        match event {
+            wl_output::Event::Mode {
+                flags: WEnum::Value(flags),
+                width,
+                height,
+                ..
+            } if flags.contains(wl_output::Mode::Current) => {
+                info.width = width as u32;
+                info.height = height as u32;
+            }
+            wl_output::Event::Name { name: connector } => {
+                // A hotplugged output picked with --output gets its wallpaper
+                // once its connector name is known
+                let mut surfaces = state.wallpaper_surfaces.iter();
+                let has_surface = surfaces.any(|s| s.output_name == name);
+                let hotplugged = state.wallpaper_mode
+                    && state.wallpaper_started
+                    && !state.wallpaper_outputs.is_empty()
+                    && wants_wallpaper(&state.wallpaper_outputs, Some(&connector))
+                    && !has_surface;
+                info.connector = Some(connector);
+                if hotplugged {
+                    state.create_wallpaper_surface(name, qh);
                 }
             }
+            wl_output::Event::Description { description } => info.description = Some(description),
+            _ => {}
         }
     }
 }
@@ -818,13 +1746,13 @@ impl Dispatch<zwlr_layer_shell_v1::ZwlrLayerShellV1, ()> for WaylandState {
     }
 }
 
-/// The usize user data is the output index.
-impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, usize> for WaylandState {
+/// The u32 user data is the registry name of the surface's output.
+impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, u32> for WaylandState {
     fn event(
         state: &mut Self,
         layer_surface: &zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
         event: zwlr_layer_surface_v1::Event,
-        output_idx: &usize,
+        output_name: &u32,
         _: &Connection,
         _: &QueueHandle<Self>,
     ) {
@@ -836,14 +1764,83 @@ impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, usize> for WaylandState
             } => {
                 layer_surface.ack_configure(serial);
                 state.events.push(WaylandEvent::WallpaperConfigure {
-                    output_idx: *output_idx,
+                    output_name: *output_name,
                     width,
                     height,
                 });
             }
             zwlr_layer_surface_v1::Event::Closed => {
-                state.running = false;
-                state.events.push(WaylandEvent::Close);
+                // The compositor closes a layer surface when its output goes
+                // away; drop just that surface and keep serving the others.
+                if crate::image_loader::verbose() {
+                    eprintln!("Wallpaper on {} closed", state.output_label(*output_name));
+                }
+                state.destroy_wallpaper_surface(*output_name);
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_buffer::WlBuffer, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        buffer: &wl_buffer::WlBuffer,
+        event: wl_buffer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_buffer::Event::Release = event {
+            if !state.shm_buf.release(buffer) {
+                for ws in &mut state.wallpaper_surfaces {
+                    if ws.shm_buf.release(buffer) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Dispatch<wp_presentation::WpPresentation, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &wp_presentation::WpPresentation,
+        event: wp_presentation::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let wp_presentation::Event::ClockId { clk_id } = event;
+        state.presentation_monotonic = clk_id == CLOCK_MONOTONIC;
+    }
+}
+
+impl Dispatch<wp_presentation_feedback::WpPresentationFeedback, ()> for WaylandState {
+    fn event(
+        state: &mut Self,
+        _: &wp_presentation_feedback::WpPresentationFeedback,
+        event: wp_presentation_feedback::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wp_presentation_feedback::Event::Presented {
+            tv_sec_hi,
+            tv_sec_lo,
+            tv_nsec,
+            refresh,
+            ..
+        } = event
+        {
+            if !state.presentation_monotonic || refresh == 0 {
+                return;
+            }
+            let shown = Duration::new(((tv_sec_hi as u64) << 32) | tv_sec_lo as u64, tv_nsec);
+            let now = rustix::time::clock_gettime(rustix::time::ClockId::Monotonic);
+            let now = Duration::new(now.tv_sec as u64, now.tv_nsec as u32);
+            if let Some(at) = Instant::now().checked_sub(now.saturating_sub(shown)) {
+                state.last_presented = Some((at, Duration::from_nanos(refresh as u64)));
             }
         }
     }
@@ -854,4 +1851,92 @@ delegate_noop!(WaylandState: ignore wl_compositor::WlCompositor);
 delegate_noop!(WaylandState: ignore wl_surface::WlSurface);
 delegate_noop!(WaylandState: ignore wl_shm::WlShm);
 delegate_noop!(WaylandState: ignore wl_shm_pool::WlShmPool);
-delegate_noop!(WaylandState: ignore wl_buffer::WlBuffer);
+delegate_noop!(WaylandState: ignore wl_data_device_manager::WlDataDeviceManager);
+delegate_noop!(WaylandState: ignore wp_cursor_shape_manager_v1::WpCursorShapeManagerV1);
+delegate_noop!(WaylandState: ignore wp_cursor_shape_device_v1::WpCursorShapeDeviceV1);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xdg_toplevel::ResizeEdge;
+
+    #[test]
+    fn test_resize_edge_regions() {
+        assert_eq!(resize_edge(5.0, 5.0, 300, 300), ResizeEdge::TopLeft);
+        assert_eq!(resize_edge(150.0, 5.0, 300, 300), ResizeEdge::Top);
+        assert_eq!(resize_edge(295.0, 150.0, 300, 300), ResizeEdge::Right);
+        assert_eq!(resize_edge(5.0, 295.0, 300, 300), ResizeEdge::BottomLeft);
+    }
+
+    #[test]
+    fn test_cursor_image() {
+        let (w, h, pixels) = cursor_image();
+        assert_eq!((w, h), (11, 17));
+        assert_eq!(pixels.len(), (w * h) as usize);
+        // Opaque outline at the hotspot, white fill inside, transparent outside
+        assert_eq!(pixels[0], 0xFF000000);
+        assert_eq!(pixels[(3 * w + 1) as usize], 0xFFFFFFFF);
+        assert_eq!(pixels[1], 0);
+    }
+
+    #[test]
+    fn test_fit_window_size() {
+        // Fits already
+        assert_eq!(fit_window_size((640, 480), (1920, 1080)), (640, 480));
+        // Too tall: limited to 90% of the output height, aspect kept
+        assert_eq!(fit_window_size((3000, 4000), (1920, 1080)), (729, 972));
+        // Too wide
+        assert_eq!(fit_window_size((8000, 1000), (2000, 1000)), (1800, 225));
+    }
+
+    #[test]
+    fn test_output_label() {
+        assert_eq!(output_label(Some("DP-1"), Some("Dell U2720Q"), 7), "DP-1");
+        assert_eq!(output_label(None, Some("Dell U2720Q"), 7), "Dell U2720Q");
+        assert_eq!(output_label(None, None, 7), "output 7");
+        assert_eq!(output_label(Some(""), None, 7), "output 7");
+        assert_eq!(output_label(Some(""), Some("Dell"), 7), "Dell");
+    }
+
+    #[test]
+    fn test_fallback_keysym() {
+        use xkbcommon_dl::keysyms;
+        assert_eq!(fallback_keysym(16), Some(keysyms::q));
+        assert_eq!(fallback_keysym(1), Some(keysyms::Escape));
+        assert_eq!(fallback_keysym(106), Some(keysyms::Right));
+        // Keys outside the fallback set are dropped
+        assert_eq!(fallback_keysym(30), None);
+    }
+
+    #[test]
+    fn test_wants_wallpaper() {
+        let selected = vec!["DP-1".to_string(), "HDMI-A-1".to_string()];
+        assert!(wants_wallpaper(&[], Some("eDP-1")));
+        assert!(wants_wallpaper(&[], None));
+        assert!(wants_wallpaper(&selected, Some("HDMI-A-1")));
+        assert!(!wants_wallpaper(&selected, Some("eDP-1")));
+        // Without a name (wl_output before v4) it cannot be picked
+        assert!(!wants_wallpaper(&selected, None));
+    }
+
+    #[test]
+    fn test_next_refresh_after() {
+        let last = Instant::now();
+        let refresh = Duration::from_micros(16_667);
+        // Between refreshes: the next one on the same grid
+        let now = last + Duration::from_millis(40);
+        assert_eq!(next_refresh_after(last, refresh, now), last + refresh * 3);
+        // Exactly on a refresh: the one after it
+        assert_eq!(next_refresh_after(last, refresh, last), last + refresh);
+        assert_eq!(
+            next_refresh_after(last, refresh, last + refresh),
+            last + refresh * 2
+        );
+    }
+
+    #[test]
+    fn test_resize_edge_center_picks_nearest_corner() {
+        assert_eq!(resize_edge(140.0, 160.0, 300, 300), ResizeEdge::BottomLeft);
+        assert_eq!(resize_edge(160.0, 140.0, 300, 300), ResizeEdge::TopRight);
+    }
+}
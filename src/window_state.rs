@@ -0,0 +1,132 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config;
+
+/// Window size and fullscreen state kept between runs
+/// (`--remember-geometry`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowState {
+    /// Last windowed (not fullscreen) size.
+    pub size: (u32, u32),
+    pub fullscreen: bool,
+}
+
+impl WindowState {
+    /// Parse the state file. Anything unexpected makes the whole file
+    /// invalid, so a stale or damaged one is simply ignored.
+    fn parse(text: &str) -> Option<Self> {
+        let mut size = None;
+        let mut fullscreen = false;
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (key, value) = line.split_once('=')?;
+            match key.trim() {
+                "size" => size = Some(config::parse_geometry(value.trim())?),
+                "fullscreen" => fullscreen = config::parse_bool(value.trim())?,
+                _ => return None,
+            }
+        }
+        Some(Self {
+            size: size?,
+            fullscreen,
+        })
+    }
+
+    fn serialize(&self) -> String {
+        format!(
+            "size = {}x{}\nfullscreen = {}\n",
+            self.size.0, self.size.1, self.fullscreen
+        )
+    }
+}
+
+/// `$XDG_STATE_HOME/rimg/window`, or `~/.local/state/rimg/window`.
+fn state_path(xdg_state_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    // Per the XDG spec, relative values of XDG_STATE_HOME are ignored
+    if let Some(dir) = xdg_state_home
+        .map(PathBuf::from)
+        .filter(|d| d.is_absolute())
+    {
+        return Some(dir.join("rimg").join("window"));
+    }
+    home.filter(|h| !h.is_empty())
+        .map(|h| PathBuf::from(h).join(".local/state/rimg/window"))
+}
+
+fn default_path() -> Option<PathBuf> {
+    state_path(env::var_os("XDG_STATE_HOME"), env::var_os("HOME"))
+}
+
+fn load_from(path: &Path) -> Option<WindowState> {
+    let text = fs::read_to_string(path).ok()?;
+    let state = WindowState::parse(&text);
+    if state.is_none() {
+        eprintln!("Warning: ignoring invalid window state {}", path.display());
+    }
+    state
+}
+
+fn save_to(path: &Path, state: &WindowState) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    }
+    fs::write(path, state.serialize())
+        .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+/// The state saved by the last run, if any.
+pub fn load() -> Option<WindowState> {
+    load_from(&default_path()?)
+}
+
+/// Save `state` for the next run.
+pub fn save(state: &WindowState) -> Result<(), String> {
+    let path = default_path().ok_or("cannot locate the state directory")?;
+    save_to(&path, state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_path() {
+        let home = Some(OsString::from("/home/u"));
+        assert_eq!(
+            state_path(Some("/xdg".into()), home.clone()),
+            Some(PathBuf::from("/xdg/rimg/window"))
+        );
+        // Relative XDG_STATE_HOME is ignored
+        assert_eq!(
+            state_path(Some("rel".into()), home),
+            Some(PathBuf::from("/home/u/.local/state/rimg/window"))
+        );
+        assert_eq!(state_path(None, None), None);
+    }
+
+    #[test]
+    fn test_round_trip_and_invalid_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join("window");
+        let state = WindowState {
+            size: (1280, 720),
+            fullscreen: true,
+        };
+        save_to(&path, &state).unwrap();
+        assert_eq!(load_from(&path), Some(state));
+
+        assert_eq!(load_from(&dir.path().join("missing")), None);
+        assert_eq!(WindowState::parse("size = 0x720\n"), None);
+        assert_eq!(WindowState::parse("fullscreen = true\n"), None);
+        assert_eq!(WindowState::parse("size = 800x600\nzoom = 2\n"), None);
+        assert_eq!(
+            WindowState::parse("size = 800x600\n"),
+            Some(WindowState {
+                size: (800, 600),
+                fullscreen: false
+            })
+        );
+    }
+}